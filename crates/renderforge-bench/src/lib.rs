@@ -0,0 +1,161 @@
+//! Standardized rendering workloads for catching performance regressions in the state manager and
+//! batching. The harness doesn't create a window: callers must create one, make its gl context
+//! current and load the gl function pointers before calling run_bench, and pass a `present`
+//! callback that swaps buffers.
+
+use std::fmt;
+use std::time::Instant;
+
+use anyhow::Result;
+use renderforge_core::engine::Engine;
+
+mod workloads;
+pub use workloads::{InstancedMeshWorkload, SpriteWorkload, TextWorkload};
+
+/// A scene rendered the same way every frame
+pub trait Workload {
+    fn name(&self) -> &str;
+    /// Creates the gl objects of the workload, called once before the first frame
+    fn setup(&mut self, engine: &mut Engine) -> Result<()>;
+    /// Renders one frame, `frame` counts from 0 including warmup frames
+    fn render(&mut self, engine: &mut Engine, frame: u32) -> Result<()>;
+    fn destroy(&mut self, engine: &mut Engine);
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BenchConfig {
+    /// frames rendered before measuring, to let drivers and caches settle
+    pub warmup_frames: u32,
+    pub measured_frames: u32,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            warmup_frames: 30,
+            measured_frames: 300,
+        }
+    }
+}
+
+/// Per-frame averages of GlStateManager::get_stats over the measured frames
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AverageStats {
+    pub program_binds: f64,
+    pub vao_binds: f64,
+    pub fbo_binds: f64,
+    pub texture_binds: f64,
+    pub uniform_uploads: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub name: String,
+    pub frames: u32,
+    /// cpu time from the start of rendering to after present, in milliseconds
+    pub cpu_average_ms: f64,
+    pub cpu_min_ms: f64,
+    pub cpu_max_ms: f64,
+    pub cpu_p95_ms: f64,
+    /// average gpu time of the workload, None if no timer results came back
+    pub gpu_average_ms: Option<f64>,
+    pub stats: AverageStats,
+}
+
+/// Runs a workload for the configured number of frames and measures it
+pub fn run_bench(engine: &mut Engine, workload: &mut dyn Workload, config: BenchConfig, mut present: impl FnMut()) -> Result<BenchReport> {
+    let timer_name = format!("bench:{}", workload.name());
+    workload.setup(engine)?;
+
+    let mut cpu_times = Vec::with_capacity(config.measured_frames as usize);
+    let mut gpu_times = Vec::new();
+    let mut stats = AverageStats::default();
+
+    let total = config.warmup_frames + config.measured_frames;
+    let result = (|| {
+        for frame in 0..total {
+            let start = Instant::now();
+            engine.gl_state.frame_begin();
+            engine.gl_state.begin_gpu_timer(&timer_name);
+            workload.render(engine, frame)?;
+            engine.gl_state.end_gpu_timer();
+            let frame_stats = engine.gl_state.get_stats();
+            engine.gl_state.frame_end();
+            present();
+
+            if frame < config.warmup_frames {
+                continue;
+            }
+            cpu_times.push(start.elapsed().as_secs_f64() * 1000.);
+            // timer results lag a frame or more behind, so this is the time of an earlier frame
+            if let Some(gpu) = engine.gl_state.get_gpu_timings().get(&timer_name) {
+                gpu_times.push(*gpu);
+            }
+            stats.program_binds += frame_stats.program_binds as f64;
+            stats.vao_binds += frame_stats.vao_binds as f64;
+            stats.fbo_binds += frame_stats.fbo_binds as f64;
+            stats.texture_binds += frame_stats.texture_binds as f64;
+            stats.uniform_uploads += frame_stats.uniform_uploads as f64;
+        }
+        Ok::<_, anyhow::Error>(())
+    })();
+    workload.destroy(engine);
+    result?;
+
+    let frames = cpu_times.len().max(1) as f64;
+    stats.program_binds /= frames;
+    stats.vao_binds /= frames;
+    stats.fbo_binds /= frames;
+    stats.texture_binds /= frames;
+    stats.uniform_uploads /= frames;
+
+    let average = |times: &[f64]| times.iter().sum::<f64>() / times.len().max(1) as f64;
+    let mut sorted = cpu_times.clone();
+    sorted.sort_by(f64::total_cmp);
+    let p95 = sorted.get(((sorted.len() as f64 * 0.95) as usize).min(sorted.len().saturating_sub(1))).copied().unwrap_or(0.);
+
+    Ok(BenchReport {
+        name: workload.name().to_string(),
+        frames: cpu_times.len() as u32,
+        cpu_average_ms: average(&cpu_times),
+        cpu_min_ms: sorted.first().copied().unwrap_or(0.),
+        cpu_max_ms: sorted.last().copied().unwrap_or(0.),
+        cpu_p95_ms: p95,
+        gpu_average_ms: (!gpu_times.is_empty()).then(|| average(&gpu_times)),
+        stats,
+    })
+}
+
+/// Runs the standard workloads (10k sprites, 1k instanced meshes, heavy text) one after another
+pub fn run_standard_benches(engine: &mut Engine, config: BenchConfig, mut present: impl FnMut()) -> Result<Vec<BenchReport>> {
+    let mut workloads: Vec<Box<dyn Workload>> = vec![
+        Box::new(SpriteWorkload::new(10_000)),
+        Box::new(InstancedMeshWorkload::new(1_000)),
+        Box::new(TextWorkload::new(20_000)),
+    ];
+    let mut reports = Vec::new();
+    for workload in &mut workloads {
+        reports.push(run_bench(engine, workload.as_mut(), config, &mut present)?);
+    }
+    Ok(reports)
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} ({} frames)", self.name, self.frames)?;
+        writeln!(
+            f,
+            "  cpu: avg {:.3} ms, min {:.3} ms, p95 {:.3} ms, max {:.3} ms",
+            self.cpu_average_ms, self.cpu_min_ms, self.cpu_p95_ms, self.cpu_max_ms
+        )?;
+        match self.gpu_average_ms {
+            Some(gpu) => writeln!(f, "  gpu: avg {gpu:.3} ms")?,
+            None => writeln!(f, "  gpu: no timer results")?,
+        }
+        write!(
+            f,
+            "  per frame: {:.1} program binds, {:.1} vao binds, {:.1} fbo binds, {:.1} texture binds, {:.1} uniform uploads",
+            self.stats.program_binds, self.stats.vao_binds, self.stats.fbo_binds, self.stats.texture_binds, self.stats.uniform_uploads
+        )
+    }
+}
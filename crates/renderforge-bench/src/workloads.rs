@@ -0,0 +1,257 @@
+use anyhow::Result;
+use gl::types::GLuint;
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+use image::{DynamicImage, GrayImage, Luma, Rgba, RgbaImage};
+use renderforge_core::csg::CsgMesh;
+use renderforge_core::data::{Color, GLUniform};
+use renderforge_core::engine::Engine;
+use renderforge_core::mesh::{
+    compile_standard_instanced_program, standard_instance_layout, standard_mesh_layout, write_standard_instance,
+    InstancedMesh, InstancedMeshData, MeshController, MeshLayout,
+};
+use renderforge_core::renderer::{Sprite, SpriteBatch};
+use renderforge_core::semantics::{U_COLOR, U_LIGHT_DIRECTION, U_TEXTURE, U_VIEW_PROJ};
+use renderforge_core::texture::{upload_image, upload_image_swizzled, ImageFormat, MagFilter, MinFilter, Swizzle, TextureWrap, WrapMode};
+
+use crate::Workload;
+
+/// Moving sprites spread over a handful of textures, exercising sorting and texture batching
+#[derive(Debug)]
+pub struct SpriteWorkload {
+    count: u32,
+    batch: Option<SpriteBatch>,
+    textures: Vec<GLuint>,
+}
+
+/// One instanced cube mesh drawn many times with changing transforms
+#[derive(Debug)]
+pub struct InstancedMeshWorkload {
+    count: u32,
+    mesh: Option<InstancedMesh<BenchInstance, BenchMeshController>>,
+    program: GLuint,
+    texture: GLuint,
+}
+
+/// Many small glyph quads sampled from one glyph sheet, the way a text renderer batches text
+#[derive(Debug)]
+pub struct TextWorkload {
+    glyphs: u32,
+    batch: Option<SpriteBatch>,
+    sheet: GLuint,
+}
+
+#[derive(Debug)]
+pub struct BenchInstance {
+    transform: Mat4,
+}
+
+#[derive(Debug)]
+pub struct BenchMeshController {
+    view_proj: Mat4,
+    texture: GLuint,
+}
+
+/// glyph cells per row and column of the text workload's glyph sheet
+const SHEET_GRID: u32 = 16;
+const GLYPH_SIZE: u32 = 8;
+
+fn ortho_for_viewport(engine: &Engine) -> (Mat4, Vec2) {
+    let viewport = engine.gl_state.get_viewport();
+    let size = Vec2::new(viewport[2].max(1) as f32, viewport[3].max(1) as f32);
+    (Mat4::orthographic_rh_gl(0., size.x, size.y, 0., -1., 1.), size)
+}
+
+fn solid_texture(color: [u8; 4]) -> GLuint {
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba(color)));
+    upload_image(&img, MinFilter::Nearest, MagFilter::Nearest, TextureWrap::new(WrapMode::ClampToEdge, WrapMode::ClampToEdge)).0
+}
+
+impl SpriteWorkload {
+    pub fn new(count: u32) -> Self {
+        Self { count, batch: None, textures: Vec::new() }
+    }
+}
+
+impl Workload for SpriteWorkload {
+    fn name(&self) -> &str {
+        "sprites"
+    }
+
+    fn setup(&mut self, engine: &mut Engine) -> Result<()> {
+        let mut batch = SpriteBatch::new()?;
+        batch.set_projection(ortho_for_viewport(engine).0);
+        self.batch = Some(batch);
+        self.textures = [[255, 80, 80, 255], [80, 255, 80, 255], [80, 80, 255, 255], [255, 255, 80, 200]]
+            .into_iter()
+            .map(solid_texture)
+            .collect();
+        Ok(())
+    }
+
+    fn render(&mut self, engine: &mut Engine, frame: u32) -> Result<()> {
+        let (_, size) = ortho_for_viewport(engine);
+        let batch = self.batch.as_mut().unwrap();
+        let t = frame as f32 * 0.016;
+        for i in 0..self.count {
+            let f = i as f32;
+            let position = Vec2::new(
+                (f * 37.1 + t * 60.).rem_euclid(size.x),
+                (f * 17.3 + (t + f).sin() * 20.).rem_euclid(size.y),
+            );
+            // runs of the same texture, like sprites drawn per object type
+            let texture = self.textures[(i / 64) as usize % self.textures.len()];
+            batch.draw(Sprite::new(texture, position, Vec2::splat(12.)).with_order((i % 8) as i32));
+        }
+        batch.render(&mut engine.gl_state)
+    }
+
+    fn destroy(&mut self, engine: &mut Engine) {
+        if let Some(mut batch) = self.batch.take() {
+            batch.destroy(&mut engine.gl_state);
+        }
+        for texture in self.textures.drain(..) {
+            engine.gl_state.destroy_texture(texture);
+        }
+    }
+}
+
+impl InstancedMeshData for BenchInstance {
+    fn get_transform(&self) -> &Mat4 {
+        &self.transform
+    }
+
+    fn write_data(&self, buffer: &mut Vec<f32>) {
+        write_standard_instance(&self.transform, Vec4::ONE, buffer);
+    }
+
+    fn write_mesh(buffer: &mut Vec<f32>) {
+        CsgMesh::cuboid(Vec3::ZERO, Vec3::splat(0.5)).write_standard_mesh(buffer);
+    }
+
+    fn setup_shader(_engine: &mut Engine, _program: GLuint) {}
+}
+
+impl MeshController<BenchInstance> for BenchMeshController {
+    fn write_mesh(&mut self, buffer: &mut Vec<f32>) {
+        BenchInstance::write_mesh(buffer);
+    }
+
+    fn setup_shader(&mut self, engine: &mut Engine, _program: GLuint) {
+        engine.gl_state.set_uniform(U_VIEW_PROJ, GLUniform::Mat4(self.view_proj));
+        engine.gl_state.set_uniform(U_TEXTURE, GLUniform::I32(0));
+        engine.gl_state.set_uniform(U_COLOR, GLUniform::Vec4(Vec4::ONE));
+        engine.gl_state.set_uniform(U_LIGHT_DIRECTION, GLUniform::Vec3(Vec3::new(-0.4, -1., -0.3).normalize()));
+        engine.gl_state.bind_texture(0, self.texture);
+    }
+}
+
+impl InstancedMeshWorkload {
+    pub fn new(count: u32) -> Self {
+        Self { count, mesh: None, program: 0, texture: 0 }
+    }
+}
+
+impl Workload for InstancedMeshWorkload {
+    fn name(&self) -> &str {
+        "instanced meshes"
+    }
+
+    fn setup(&mut self, engine: &mut Engine) -> Result<()> {
+        self.program = compile_standard_instanced_program(None)?;
+        self.texture = solid_texture([200, 200, 200, 255]);
+        let (_, size) = ortho_for_viewport(engine);
+        let side = (self.count as f32).sqrt().ceil();
+        let projection = Mat4::perspective_rh_gl(60f32.to_radians(), size.x / size.y, 0.1, side * 4.);
+        let view = Mat4::look_at_rh(Vec3::new(0., side * 0.8, side * 1.2), Vec3::ZERO, Vec3::Y);
+        let controller = BenchMeshController { view_proj: projection * view, texture: self.texture };
+
+        let mut vertices = Vec::new();
+        let vertex_count = CsgMesh::cuboid(Vec3::ZERO, Vec3::splat(0.5)).write_standard_mesh(&mut vertices);
+        let layout = MeshLayout::new(standard_mesh_layout(), standard_instance_layout());
+        self.mesh = Some(InstancedMesh::new(&engine.gl_state, self.program, vertex_count, layout, Some(controller)));
+        Ok(())
+    }
+
+    fn render(&mut self, engine: &mut Engine, frame: u32) -> Result<()> {
+        let mesh = self.mesh.as_mut().unwrap();
+        let side = (self.count as f32).sqrt().ceil() as u32;
+        let t = frame as f32 * 0.016;
+        for i in 0..self.count {
+            let position = Vec3::new((i % side) as f32 - side as f32 / 2., 0., (i / side) as f32 - side as f32 / 2.) * 1.5;
+            let rotation = Quat::from_rotation_y(t + i as f32 * 0.1);
+            mesh.draw(BenchInstance { transform: Mat4::from_rotation_translation(rotation, position) });
+        }
+        engine.gl_state.depth_test(true);
+        mesh.flush(engine)
+    }
+
+    fn destroy(&mut self, engine: &mut Engine) {
+        if let Some(mut mesh) = self.mesh.take() {
+            mesh.destroy(&mut engine.gl_state);
+        }
+        engine.gl_state.destroy_program(self.program);
+        engine.gl_state.destroy_texture(self.texture);
+        engine.gl_state.depth_test(false);
+    }
+}
+
+impl TextWorkload {
+    pub fn new(glyphs: u32) -> Self {
+        Self { glyphs, batch: None, sheet: 0 }
+    }
+}
+
+impl Workload for TextWorkload {
+    fn name(&self) -> &str {
+        "text"
+    }
+
+    fn setup(&mut self, engine: &mut Engine) -> Result<()> {
+        // stand-in glyphs, a different bit pattern per cell, stored as coverage like a real font sheet
+        let size = SHEET_GRID * GLYPH_SIZE;
+        let sheet = GrayImage::from_fn(size, size, |x, y| {
+            let cell = (y / GLYPH_SIZE) * SHEET_GRID + x / GLYPH_SIZE;
+            let bit = (y % GLYPH_SIZE) * GLYPH_SIZE + x % GLYPH_SIZE;
+            let on = (cell.wrapping_mul(2_654_435_761) >> (bit % 32)) & 1 == 1;
+            Luma([if on { 255 } else { 0 }])
+        });
+        self.sheet = upload_image_swizzled(
+            &DynamicImage::ImageLuma8(sheet),
+            ImageFormat::R8,
+            Swizzle::WHITE_FROM_RED,
+            MinFilter::Nearest,
+            MagFilter::Nearest,
+            TextureWrap::new(WrapMode::ClampToEdge, WrapMode::ClampToEdge),
+        ).0;
+        let mut batch = SpriteBatch::new()?;
+        batch.set_projection(ortho_for_viewport(engine).0);
+        self.batch = Some(batch);
+        Ok(())
+    }
+
+    fn render(&mut self, engine: &mut Engine, frame: u32) -> Result<()> {
+        let (_, size) = ortho_for_viewport(engine);
+        let batch = self.batch.as_mut().unwrap();
+        let columns = (size.x / GLYPH_SIZE as f32).max(1.) as u32;
+        let cell = 1. / SHEET_GRID as f32;
+        let color = Color::rgb(0.9, 0.9, 0.9);
+        for i in 0..self.glyphs {
+            let glyph = (i + frame) % (SHEET_GRID * SHEET_GRID);
+            let (u, v) = ((glyph % SHEET_GRID) as f32 * cell, (glyph / SHEET_GRID) as f32 * cell);
+            let position = Vec2::new((i % columns) as f32, (i / columns) as f32) * GLYPH_SIZE as f32;
+            batch.draw(
+                Sprite::new(self.sheet, position, Vec2::splat(GLYPH_SIZE as f32))
+                    .with_uvs((u, v, u + cell, v + cell))
+                    .with_color(color),
+            );
+        }
+        batch.render(&mut engine.gl_state)
+    }
+
+    fn destroy(&mut self, engine: &mut Engine) {
+        if let Some(mut batch) = self.batch.take() {
+            batch.destroy(&mut engine.gl_state);
+        }
+        engine.gl_state.destroy_texture(self.sheet);
+    }
+}
@@ -0,0 +1,268 @@
+//! Keyframed animation of material uniforms. Entities with a MaterialAnimation and a
+//! MaterialInstance have the animated uniforms written into their own copy of the material every
+//! frame by animate_materials, so effects like dissolves need no per-frame uniform code.
+
+use std::ops::Deref;
+
+use hecs::World;
+
+use crate::data::GLUniform;
+use crate::material::Material;
+
+/// How values are interpolated between two keyframes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// holds each keyframe's value until the next keyframe
+    Step,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps linear progress between two keyframes in [0, 1] to the eased progress
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::Step => if t < 1. { 0. } else { 1. },
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2. - t),
+            Easing::EaseInOut => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframe {
+    /// seconds from the start of the animation
+    pub time: f32,
+    pub value: GLUniform,
+}
+
+/// Animates one uniform of a material by name. Float and float vector/matrix uniforms are
+/// interpolated, every other kind of uniform steps from keyframe to keyframe.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyTrack {
+    uniform: String,
+    /// sorted by time
+    keyframes: Vec<Keyframe>,
+    easing: Easing,
+}
+
+impl PropertyTrack {
+    pub fn new(uniform: impl ToString) -> Self {
+        Self {
+            uniform: uniform.to_string(),
+            keyframes: Vec::new(),
+            easing: Easing::Linear,
+        }
+    }
+
+    /// A track going from `from` to `to` over `duration` seconds, e.g.
+    /// `PropertyTrack::from_to("u_dissolve", GLUniform::F32(0.), GLUniform::F32(1.), 0.5)`
+    pub fn from_to(uniform: impl ToString, from: GLUniform, to: GLUniform, duration: f32) -> Self {
+        Self::new(uniform)
+            .with_keyframe(0., from)
+            .with_keyframe(duration, to)
+    }
+
+    pub fn with_keyframe(mut self, time: f32, value: GLUniform) -> Self {
+        self.add_keyframe(time, value);
+        self
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// Adds a keyframe, replacing the keyframe at the same time if there is one
+    pub fn add_keyframe(&mut self, time: f32, value: GLUniform) {
+        match self.keyframes.binary_search_by(|keyframe| keyframe.time.total_cmp(&time)) {
+            Ok(i) => self.keyframes[i].value = value,
+            Err(i) => self.keyframes.insert(i, Keyframe { time, value }),
+        }
+    }
+
+    pub fn get_uniform(&self) -> &str {
+        &self.uniform
+    }
+
+    pub fn get_keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// # Returns
+    /// the time of the last keyframe
+    pub fn get_duration(&self) -> f32 {
+        self.keyframes.last().map_or(0., |keyframe| keyframe.time)
+    }
+
+    /// # Returns
+    /// the value at `time`, held before the first and after the last keyframe. None without keyframes
+    pub fn sample(&self, time: f32) -> Option<GLUniform> {
+        let next = self.keyframes.partition_point(|keyframe| keyframe.time <= time);
+        if next == 0 {
+            return self.keyframes.first().map(|keyframe| keyframe.value.clone());
+        }
+        let previous = &self.keyframes[next - 1];
+        let Some(next) = self.keyframes.get(next) else {
+            return Some(previous.value.clone());
+        };
+        let t = (time - previous.time) / (next.time - previous.time);
+        Some(interpolate(&previous.value, &next.value, self.easing.apply(t)))
+    }
+}
+
+fn interpolate(from: &GLUniform, to: &GLUniform, t: f32) -> GLUniform {
+    match (from, to) {
+        (GLUniform::F32(a), GLUniform::F32(b)) => GLUniform::F32(a + (b - a) * t),
+        (GLUniform::Vec2(a), GLUniform::Vec2(b)) => GLUniform::Vec2(a.lerp(*b, t)),
+        (GLUniform::Vec3(a), GLUniform::Vec3(b)) => GLUniform::Vec3(a.lerp(*b, t)),
+        (GLUniform::Vec4(a), GLUniform::Vec4(b)) => GLUniform::Vec4(a.lerp(*b, t)),
+        (GLUniform::Mat3(a), GLUniform::Mat3(b)) => GLUniform::Mat3(*a + (*b - *a) * t),
+        (GLUniform::Mat4(a), GLUniform::Mat4(b)) => GLUniform::Mat4(*a + (*b - *a) * t),
+        _ => if t < 1. { from.clone() } else { to.clone() },
+    }
+}
+
+/// Plays PropertyTracks on the MaterialInstance of the same entity, see animate_materials
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialAnimation {
+    tracks: Vec<PropertyTrack>,
+    time: f32,
+    /// playback rate, 1 is real time
+    pub speed: f32,
+    pub looping: bool,
+    pub paused: bool,
+}
+
+impl MaterialAnimation {
+    pub fn new() -> Self {
+        Self {
+            tracks: Vec::new(),
+            time: 0.,
+            speed: 1.,
+            looping: false,
+            paused: false,
+        }
+    }
+
+    pub fn with_track(mut self, track: PropertyTrack) -> Self {
+        self.tracks.push(track);
+        self
+    }
+
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn add_track(&mut self, track: PropertyTrack) {
+        self.tracks.push(track);
+    }
+
+    pub fn get_tracks(&self) -> &[PropertyTrack] {
+        &self.tracks
+    }
+
+    /// # Returns
+    /// the duration of the longest track
+    pub fn get_duration(&self) -> f32 {
+        self.tracks.iter().map(PropertyTrack::get_duration).fold(0., f32::max)
+    }
+
+    pub fn get_time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time.max(0.);
+    }
+
+    pub fn restart(&mut self) {
+        self.time = 0.;
+        self.paused = false;
+    }
+
+    /// Whether a non-looping animation has played to its end
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.time >= self.get_duration()
+    }
+
+    /// Moves the animation forward by `delta_time` seconds, scaled by the speed
+    pub fn advance(&mut self, delta_time: f32) {
+        if self.paused {
+            return;
+        }
+        let duration = self.get_duration();
+        self.time += delta_time * self.speed;
+        self.time = if self.looping && duration > 0. {
+            self.time.rem_euclid(duration)
+        } else {
+            self.time.clamp(0., duration)
+        };
+    }
+
+    /// Writes the value of every track at the current time into `material`
+    pub fn apply(&self, material: &mut Material) {
+        for track in &self.tracks {
+            if let Some(value) = track.sample(self.time) {
+                material.set_uniform(&track.uniform, value);
+            }
+        }
+    }
+}
+
+impl Default for MaterialAnimation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An entity's own copy of a shared material, so animating one entity's uniforms doesn't affect
+/// every other entity drawn with the material
+#[derive(Debug, Clone)]
+pub struct MaterialInstance {
+    material: Material,
+}
+
+impl MaterialInstance {
+    pub fn new(material: &Material) -> Self {
+        Self {
+            material: material.clone(),
+        }
+    }
+
+    pub fn get_material(&self) -> &Material {
+        &self.material
+    }
+
+    pub fn get_material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+}
+
+impl Deref for MaterialInstance {
+    type Target = Material;
+
+    fn deref(&self) -> &Material {
+        &self.material
+    }
+}
+
+/// Advances every MaterialAnimation in `world` and applies it to the MaterialInstance of the same
+/// entity. Run once per frame, the engine's main loop does so with its delta time.
+pub fn animate_materials(world: &mut World, delta_time: f32) {
+    for (_, (animation, instance)) in world.query_mut::<(&mut MaterialAnimation, &mut MaterialInstance)>() {
+        animation.advance(delta_time);
+        animation.apply(&mut instance.material);
+    }
+}
@@ -1,22 +1,165 @@
+use std::cell::{Cell, RefCell};
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt;
+use std::fs;
 use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::Result;
 use gl::types::GLuint;
-use image::{imageops, DynamicImage, GenericImageView, RgbaImage};
-use rect_packer::{Config, Packer};
+use glam::{Mat3, Vec3, Vec4};
+use image::{imageops, DynamicImage, GenericImageView, ImageFormat, Rgba, RgbaImage};
+use rect_packer::{Config, Packer, Rect};
+use serde::{Deserialize, Serialize};
 
+use crate::data::GlStateManager;
 use crate::errors::AtlasError;
-use crate::texture::{upload_image, MagFilter, MinFilter, TextureWrap, WrapMode};
+use crate::frame::GlObject;
+use crate::texture::{upload_image, MagFilter, MinFilter, Texture, TextureArrayBuilder, TextureTarget, TextureWrap, WrapMode};
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AtlasTextureIdentifier(String);
 
+/// Border widths in pixels of a texture drawn as a nine-slice: corners keep their size, edges
+/// stretch along one axis and the center stretches both ways, see Sprite::nine_slice
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct NineSlice {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+/// A pre-baked sprite sheet of equally sized cells, named in row major order. Cells past the end
+/// of `names` are skipped.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GridSheet {
+    /// sheet image, relative to the description file, for Atlas::load_sprite_sheet
+    #[serde(default)]
+    pub image: Option<String>,
+    pub cell_width: u32,
+    pub cell_height: u32,
+    /// pixels around the outside of the grid
+    #[serde(default)]
+    pub margin: u32,
+    /// pixels between cells
+    #[serde(default)]
+    pub spacing: u32,
+    pub names: Vec<String>,
+}
+
+/// TexturePacker's JSON "hash" format, only the fields used for importing
+#[derive(Debug, Deserialize)]
+struct TexturePackerSheet {
+    frames: HashMap<String, TexturePackerFrame>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TexturePackerFrame {
+    frame: TexturePackerRect,
+    #[serde(default)]
+    rotated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct TexturePackerRect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct AtlasRect {
+    /// as laid out on the atlas, so width and height are swapped for rotated textures
     rect: (u32, u32, u32, u32),
     size: (f32, f32),
+    /// stored rotated 90 degrees clockwise
+    rotated: bool,
+    /// position of the packed pixels within the image as it was added, non-zero when trimmed
+    trim_offset: (u32, u32),
+    /// size of the image as it was added, before trimming
+    source_size: (u32, u32),
+}
+
+/// The frames of an animated sprite in playback order, see Atlas::get_animation
+#[derive(Debug, Clone)]
+pub struct AtlasAnimation {
+    frames: Vec<(AtlasRect, Duration)>,
+}
+
+/// Frames of animations added with AtlasBuilder::add_animation, by animation id
+type AnimationFrames = HashMap<AtlasTextureIdentifier, Vec<(AtlasTextureIdentifier, Duration)>>;
+
+/// frame duration of animations found by name, e.g. `run_0..run_7`
+const DEFAULT_FRAME_DURATION: Duration = Duration::from_millis(100);
+
+/// Packer state kept after building, so try_insert can use the remaining space
+pub(crate) struct AtlasPacker {
+    packer: Packer,
+    /// padded size of every packed rect in packing order. Packing is deterministic, so replaying
+    /// these restores the packer of a loaded atlas.
+    packed: Vec<(u32, u32)>,
+    /// set for atlases imported from sprite sheets, whose free space is unknown
+    sealed: bool,
+    allow_rotation: bool,
+    /// space added by Atlas::grow, tried in order once the original area is full
+    regions: Vec<GrowthRegion>,
+}
+
+/// Free space added to a grown atlas, packed separately so existing rects stay where they are
+struct GrowthRegion {
+    offset: (u32, u32),
+    packer: Packer,
+    packed: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GrowthRegionMetadata {
+    offset: (u32, u32),
+    size: (u32, u32),
+    packed: Vec<(u32, u32)>,
+}
+
+/// The metadata file written next to the image by Atlas::save
+#[derive(Debug, Serialize, Deserialize)]
+struct AtlasMetadata {
+    size: (u32, u32),
+    border_padding: u32,
+    rectangle_padding: u32,
+    min_filter: MinFilter,
+    mag_filter: MagFilter,
+    extrude: u32,
+    packed: Vec<(u32, u32)>,
+    #[serde(default)]
+    growth_regions: Vec<GrowthRegionMetadata>,
+    #[serde(default)]
+    max_size: Option<(u32, u32)>,
+    #[serde(default)]
+    sealed: bool,
+    #[serde(default)]
+    allow_rotation: bool,
+    /// textures stored rotated
+    #[serde(default)]
+    rotated: Vec<AtlasTextureIdentifier>,
+    #[serde(default)]
+    trims: HashMap<AtlasTextureIdentifier, TrimMetadata>,
+    #[serde(default)]
+    animations: AnimationFrames,
+    #[serde(default = "default_frame_duration")]
+    frame_duration: Duration,
+    #[serde(default)]
+    nine_slices: HashMap<AtlasTextureIdentifier, NineSlice>,
+    /// (x, y, width, height) of every texture
+    textures: HashMap<AtlasTextureIdentifier, (u32, u32, u32, u32)>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TrimMetadata {
+    offset: (u32, u32),
+    source_size: (u32, u32),
 }
 
 #[derive(Debug)]
@@ -24,48 +167,167 @@ pub struct Atlas {
     tex_id: GLuint,
     position_data: HashMap<AtlasTextureIdentifier, AtlasRect>,
     size: (u32, u32),
+    packer: AtlasPacker,
+    min_filter: MinFilter,
+    mag_filter: MagFilter,
+    extrude: u32,
+    animations: AnimationFrames,
+    frame_duration: Duration,
+    nine_slices: HashMap<AtlasTextureIdentifier, NineSlice>,
+    /// try_insert grows the atlas up to this size when full, None disables growth
+    max_size: Option<(u32, u32)>,
+    /// times the atlas has grown, see get_generation
+    generation: u32,
+    usage: AtlasUsage,
+}
+
+/// How often each texture's rect was fetched, see Atlas::set_usage_tracking
+#[derive(Debug, Default)]
+struct AtlasUsage {
+    tracking: Cell<bool>,
+    counts: RefCell<HashMap<AtlasTextureIdentifier, u64>>,
 }
 
 #[derive(Debug)]
 pub struct AtlasBuilder {
     size: (u32, u32),
-    texture: RgbaImage,
     texture_queue: Vec<(AtlasTextureIdentifier, DynamicImage)>,
+    animations: AnimationFrames,
+    frame_duration: Duration,
+    nine_slices: HashMap<AtlasTextureIdentifier, NineSlice>,
     border_padding: u32,
     rectangle_padding: u32,
     min_filter: MinFilter,
     mag_filter: MagFilter,
+    extrude: u32,
+    allow_rotation: bool,
+    trim: bool,
+    max_size: Option<(u32, u32)>,
 }
 
 #[derive(Debug)]
 pub struct AtlasSet {
-    atlases: Vec<Atlas>
+    atlases: Vec<Atlas>,
+    /// index of the atlas holding each texture
+    lookup: HashMap<AtlasTextureIdentifier, usize>,
+}
+
+/// How full one page of an AtlasSet is, see AtlasSet::get_page_stats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasPageStats {
+    /// gl texture id of the page
+    pub atlas_id: GLuint,
+    pub size: (u32, u32),
+    pub texture_count: usize,
+    /// pixels covered by textures
+    pub used_area: u64,
+    /// fraction of the page covered by textures, see Atlas::get_occupancy
+    pub occupancy: f32,
+}
+
+/// Where a texture moved when its AtlasSet was rebuilt, see AtlasSet::rebuild
+#[derive(Debug, Clone, Copy)]
+pub struct AtlasRemap {
+    pub old_texture: GLuint,
+    pub old_rect: AtlasRect,
+    pub new_texture: GLuint,
+    pub new_rect: AtlasRect,
+}
+
+/// Every page of an AtlasSet as a layer of one TEXTURE_2D_ARRAY, so sprites from different pages
+/// are drawn without rebinding textures, see AtlasSet::into_array
+#[derive(Debug)]
+pub struct AtlasArray {
+    texture: Texture,
+    rects: HashMap<AtlasTextureIdentifier, (u32, AtlasRect)>,
+    nine_slices: HashMap<AtlasTextureIdentifier, NineSlice>,
 }
 
 #[derive(Debug)]
 pub struct AtlasSetBuilder {
     texture_queue: Vec<(AtlasTextureIdentifier, DynamicImage)>,
+    nine_slices: HashMap<AtlasTextureIdentifier, NineSlice>,
     size: (u32, u32),
     border_padding: u32,
     rectangle_padding: u32,
     min_filter: MinFilter,
     mag_filter: MagFilter,
+    extrude: u32,
+    allow_rotation: bool,
+    trim: bool,
 }
 
 
 impl AtlasRect {
-    fn new(size: (u32, u32), rect: (u32, u32, u32, u32)) -> Self {
+    pub(crate) fn new(size: (u32, u32), rect: (u32, u32, u32, u32), rotated: bool) -> Self {
+        let source_size = if rotated { (rect.3, rect.2) } else { (rect.2, rect.3) };
         Self {
             rect,
-            size: (size.0 as f32, size.1 as f32)
+            size: (size.0 as f32, size.1 as f32),
+            rotated,
+            trim_offset: (0, 0),
+            source_size,
         }
     }
 
-    fn coords(&self) -> (u32, u32, u32, u32) {
+    fn with_trim(mut self, trim_offset: (u32, u32), source_size: (u32, u32)) -> Self {
+        self.trim_offset = trim_offset;
+        self.source_size = source_size;
+        self
+    }
+
+    /// Whether transparent borders were cut off the texture when packing, see AtlasBuilder::with_trim
+    pub fn is_trimmed(&self) -> bool {
+        self.get_size() != self.source_size
+    }
+
+    /// # Returns
+    /// where the packed pixels start within the image as it was added, (0, 0) if it wasn't trimmed
+    pub fn get_trim_offset(&self) -> (u32, u32) {
+        self.trim_offset
+    }
+
+    /// # Returns
+    /// the pixel size of the image as it was added, before trimming
+    pub fn get_source_size(&self) -> (u32, u32) {
+        self.source_size
+    }
+
+    /// Whether the texture is stored rotated 90 degrees clockwise, in which case uvs have to be
+    /// swapped per corner, see corner_uvs
+    pub fn is_rotated(&self) -> bool {
+        self.rotated
+    }
+
+    /// # Returns
+    /// the pixel size of the packed texture, unaffected by rotation. Smaller than get_source_size
+    /// for trimmed textures.
+    pub fn get_size(&self) -> (u32, u32) {
+        if self.rotated { (self.rect.3, self.rect.2) } else { (self.rect.2, self.rect.3) }
+    }
+
+    /// # Returns
+    /// the atlas uvs of the texture's top left, top right, bottom right and bottom left corners,
+    /// accounting for rotation
+    pub fn corner_uvs(&self) -> [(f32, f32); 4] {
+        let (u, v, w, h) = self.uvs();
+        [(0., 0.), (1., 0.), (1., 1.), (0., 1.)].map(|(s, t)| {
+            // rotating clockwise moves the texture's left edge to the top
+            let (s, t) = if self.rotated { (1. - t, s) } else { (s, t) };
+            (u + s * w, v + t * h)
+        })
+    }
+
+    /// # Returns
+    /// (x, y, width, height) in atlas pixels, as laid out on the atlas so width and height are
+    /// swapped for rotated textures
+    pub fn coords(&self) -> (u32, u32, u32, u32) {
         self.rect
     }
 
-    fn uvs(&self) -> (f32, f32, f32, f32) {
+    /// # Returns
+    /// (u, v, width, height) of the rect in atlas uvs, as laid out on the atlas
+    pub fn uvs(&self) -> (f32, f32, f32, f32) {
         (
             self.rect.0 as f32 / self.size.0,
             self.rect.1 as f32 / self.size.1,
@@ -74,6 +336,140 @@ impl AtlasRect {
         )
     }
 
+    /// # Returns
+    /// (u0, v0, u1, v1), the opposite corners of the rect in atlas uvs
+    pub fn uv_bounds(&self) -> (f32, f32, f32, f32) {
+        let (u, v, w, h) = self.uvs();
+        (u, v, u + w, v + h)
+    }
+
+    /// Like uv_bounds, with the corners swapped to mirror the texture. For rotated textures the
+    /// texture's horizontal axis runs along v, so flipping horizontally swaps v0 and v1.
+    pub fn flipped_uvs(&self, horizontal: bool, vertical: bool) -> (f32, f32, f32, f32) {
+        let (mut u0, mut v0, mut u1, mut v1) = self.uv_bounds();
+        let (flip_u, flip_v) = if self.rotated { (vertical, horizontal) } else { (horizontal, vertical) };
+        if flip_u {
+            mem::swap(&mut u0, &mut u1);
+        }
+        if flip_v {
+            mem::swap(&mut v0, &mut v1);
+        }
+        (u0, v0, u1, v1)
+    }
+
+    /// A part of the texture, e.g. one tile of a tile sheet packed as a single texture.
+    /// (x, y, width, height) are pixels of the packed texture, unaffected by rotation, and are
+    /// clamped to it.
+    pub fn sub_rect(&self, x: u32, y: u32, width: u32, height: u32) -> AtlasRect {
+        let (tex_width, tex_height) = self.get_size();
+        let x = x.min(tex_width);
+        let y = y.min(tex_height);
+        let width = width.min(tex_width - x);
+        let height = height.min(tex_height - y);
+        let rect = if self.rotated {
+            // rotating clockwise moves texture pixel (x, y) to (height - 1 - y, x)
+            (self.rect.0 + tex_height - y - height, self.rect.1 + x, height, width)
+        } else {
+            (self.rect.0 + x, self.rect.1 + y, width, height)
+        };
+        AtlasRect::new((self.size.0 as u32, self.size.1 as u32), rect, self.rotated)
+    }
+
+    /// # Returns
+    /// (u offset, v offset, u scale, v scale) mapping uvs in [0, 1] over the texture to atlas uvs
+    /// as `offset + uv * scale`, a single vec4 per instance. Doesn't include rotation, use
+    /// uv_matrix for atlases packed with rotation.
+    pub fn uv_transform(&self) -> Vec4 {
+        let (u, v, w, h) = self.uvs();
+        Vec4::new(u, v, w, h)
+    }
+
+    /// # Returns
+    /// a matrix taking (s, t, 1), with uvs in [0, 1] over the texture, to atlas uvs, including
+    /// rotation
+    pub fn uv_matrix(&self) -> Mat3 {
+        let (u, v, w, h) = self.uvs();
+        if self.rotated {
+            // (s, t) -> (1 - t, s), see corner_uvs
+            Mat3::from_cols(Vec3::new(0., h, 0.), Vec3::new(-w, 0., 0.), Vec3::new(u + w, v, 1.))
+        } else {
+            Mat3::from_cols(Vec3::new(w, 0., 0.), Vec3::new(0., h, 0.), Vec3::new(u, v, 1.))
+        }
+    }
+
+}
+
+impl NineSlice {
+    pub fn new(left: u32, top: u32, right: u32, bottom: u32) -> Self {
+        Self { left, top, right, bottom }
+    }
+
+    /// The same inset on every side
+    pub fn uniform(inset: u32) -> Self {
+        Self::new(inset, inset, inset, inset)
+    }
+}
+
+impl AtlasTextureIdentifier {
+    pub fn new(id: impl ToString) -> Self {
+        Self(id.to_string())
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl GridSheet {
+    pub fn new(cell_width: u32, cell_height: u32, names: Vec<String>) -> Self {
+        Self {
+            image: None,
+            cell_width,
+            cell_height,
+            margin: 0,
+            spacing: 0,
+            names,
+        }
+    }
+
+    pub fn with_margin(mut self, margin: u32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    pub fn with_spacing(mut self, spacing: u32) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    /// # Returns
+    /// the (x, y, width, height) of every named cell that fits on a sheet of `size`
+    fn cells(&self, size: (u32, u32)) -> Vec<(String, (u32, u32, u32, u32))> {
+        if self.cell_width == 0 || self.cell_height == 0 {
+            return Vec::new();
+        }
+        let step = (self.cell_width + self.spacing, self.cell_height + self.spacing);
+        // how many cells fit along a side of the sheet
+        let count = |len: u32, cell: u32, step: u32| {
+            let inner = len.saturating_sub(self.margin * 2);
+            if inner < cell { 0 } else { (inner - cell) / step + 1 }
+        };
+        let columns = count(size.0, self.cell_width, step.0);
+        let rows = count(size.1, self.cell_height, step.1);
+        if columns == 0 {
+            return Vec::new();
+        }
+        self.names.iter()
+            .take((columns * rows) as usize)
+            .enumerate()
+            .map(|(i, name)| {
+                let (column, row) = (i as u32 % columns, i as u32 / columns);
+                let x = self.margin + column * step.0;
+                let y = self.margin + row * step.1;
+                (name.clone(), (x, y, self.cell_width, self.cell_height))
+            })
+            .collect()
+    }
 }
 
 impl AtlasBuilder {
@@ -81,15 +477,80 @@ impl AtlasBuilder {
     pub fn new(size: (u32, u32), border_padding: u32, rectangle_padding: u32, min_filter: MinFilter, mag_filter: MagFilter) -> Self {
         Self {
             size,
-            texture: RgbaImage::new(size.0, size.1),
-texture_queue: Vec::new(),
+            texture_queue: Vec::new(),
+            animations: HashMap::new(),
+            frame_duration: DEFAULT_FRAME_DURATION,
+            nine_slices: HashMap::new(),
             border_padding,
             rectangle_padding,
             min_filter,
             mag_filter,
+            extrude: default_extrude(min_filter),
+            allow_rotation: false,
+            trim: false,
+            max_size: None,
         }
     }
 
+    /// Lets the built atlas grow up to `max_size` when try_insert runs out of space, see
+    /// Atlas::set_max_size. Off by default.
+    pub fn with_max_size(mut self, max_size: Option<(u32, u32)>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Cuts fully transparent borders off every added image before packing, saving atlas space.
+    /// The rects remember the trim offset and original size, which Sprite::from_atlas uses to
+    /// place the quad where the untrimmed image would be. Off by default.
+    pub fn with_trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Lets the packer rotate textures 90 degrees when that fits them better, see
+    /// AtlasRect::corner_uvs for sampling rotated textures. Off by default.
+    pub fn with_rotation(mut self, allow_rotation: bool) -> Self {
+        self.allow_rotation = allow_rotation;
+        self
+    }
+
+    /// Sets the per-frame duration of animations found by name, see Atlas::get_animation.
+    /// Defaults to 100ms.
+    pub fn with_frame_duration(mut self, frame_duration: Duration) -> Self {
+        self.frame_duration = frame_duration;
+        self
+    }
+
+    /// Adds an animation's frames as textures `{id}_0`, `{id}_1`, ... and records their order and
+    /// durations for Atlas::get_animation
+    pub fn add_animation(&mut self, id: AtlasTextureIdentifier, frames: Vec<(DynamicImage, Duration)>) -> Result<()> {
+        if self.animations.contains_key(&id) {
+            return Err(AtlasError::DuplicateId(id.0.to_string()).into());
+        }
+        let mut sequence = Vec::with_capacity(frames.len());
+        for (i, (img, duration)) in frames.into_iter().enumerate() {
+            let frame_id = AtlasTextureIdentifier(format!("{}_{i}", id.0));
+            self.add(frame_id.clone(), img)?;
+            sequence.push((frame_id, duration));
+        }
+        self.animations.insert(id, sequence);
+        Ok(())
+    }
+
+    /// Repeats each texture's edge pixels `extrude` pixels outwards so filtering near the edges
+    /// never samples a neighbour. Defaults to 1 for mipmapped min filters and 0 otherwise; deeper
+    /// mip levels need wider extrusion.
+    pub fn with_extrude(mut self, extrude: u32) -> Self {
+        self.extrude = extrude;
+        self
+    }
+
+    /// Attaches nine-slice insets to the texture `id`, see Atlas::get_nine_slice. Textures with
+    /// insets are never trimmed, so the insets keep matching the image.
+    pub fn set_nine_slice(&mut self, id: AtlasTextureIdentifier, insets: NineSlice) {
+        self.nine_slices.insert(id, insets);
+    }
+
     pub fn add(&mut self, id: AtlasTextureIdentifier, img: DynamicImage) -> Result<()> {
         for (id2, _) in &self.texture_queue {
             if id == *id2 {
@@ -100,6 +561,30 @@ texture_queue: Vec::new(),
         Ok(())
     }
 
+    /// Adds every image under `path`, including subdirectories, as `{id_prefix}{relative path}`
+    /// without the extension and with `/` separators, e.g. `ui/buttons/ok.png` loaded with the
+    /// prefix `"ui/"` becomes `ui/buttons/ok`. Files that aren't decodable images are skipped.
+    ///
+    /// # Returns
+    /// how many images were added
+    pub fn add_directory(&mut self, path: impl AsRef<Path>, id_prefix: &str) -> Result<usize> {
+        let root = path.as_ref();
+        let mut files = Vec::new();
+        collect_images(root, &mut files)?;
+        // directory listing order varies between platforms, sorting keeps the packing reproducible
+        files.sort();
+        for file in &files {
+            let relative = file.strip_prefix(root).unwrap_or(file).with_extension("");
+            let name = relative.components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let img = image::open(file)?;
+            self.add(AtlasTextureIdentifier(format!("{id_prefix}{name}")), img)?;
+        }
+        Ok(files.len())
+    }
+
     /// Turns the AtlasBuilder into an Atlas.
     /// if any images were not able to fit on the atlas, they are returned in the paired Vec.
     pub fn build_overflow(self) -> Result<(Atlas, Vec<(AtlasTextureIdentifier, DynamicImage)>)> {
@@ -140,7 +625,8 @@ texture_queue: Vec::new(),
             rectangle_padding: self.rectangle_padding as i32,
         };
 
-        let mut packer = Packer::new(config);
+        let mut packer = AtlasPacker::new(config);
+        packer.allow_rotation = self.allow_rotation;
         let mut img = RgbaImage::new(self.size.0, self.size.1);
         let mut rectangle_map = HashMap::new();
 
@@ -150,14 +636,18 @@ texture_queue: Vec::new(),
                 return Err(AtlasError::DuplicateId(id.0.to_string()).into());
             }
 
-            let (w, h) = tex.dimensions();
+            let source = tex.to_rgba8();
+            let (trimmed, trim_offset) = if self.trim && !self.nine_slices.contains_key(&id) {
+                trim_transparent(&source)
+            } else {
+                (source, (0, 0))
+            };
 
-            if packer.can_pack(w as i32, h as i32, false) {
-                let tex = tex.to_rgba8();
-                let rect = packer.pack(w as i32, h as i32, false).unwrap();
-                rectangle_map.insert(id, AtlasRect::new(self.size, (rect.x as u32, rect.y as u32, rect.width as u32, rect.height as u32)));
+            if let Some((rect, rotated)) = packer.pack_texture(trimmed.dimensions(), self.extrude) {
+                let prepared = prepare_texture(&trimmed, self.extrude, rotated);
+                rectangle_map.insert(id, AtlasRect::new(self.size, rect, rotated).with_trim(trim_offset, tex.dimensions()));
 
-                imageops::overlay(&mut img, &tex, rect.x as i64, rect.y as i64);
+                imageops::overlay(&mut img, &prepared, (rect.0 - self.extrude) as i64, (rect.1 - self.extrude) as i64);
 
             } else if error_on_overflow {
                 return Err(AtlasError::TextureOverflow.into());
@@ -186,8 +676,18 @@ texture_queue: Vec::new(),
 
         let atlas = Atlas {
             tex_id: glid,
+            nine_slices: self.nine_slices.into_iter().filter(|(id, _)| rectangle_map.contains_key(id)).collect(),
             position_data: rectangle_map,
             size: self.size,
+            packer,
+            min_filter: self.min_filter,
+            mag_filter: self.mag_filter,
+            extrude: self.extrude,
+            animations: self.animations,
+            frame_duration: self.frame_duration,
+            max_size: self.max_size,
+            generation: 0,
+            usage: AtlasUsage::default(),
         };
 
         Ok((atlas, overflow))
@@ -201,14 +701,41 @@ impl AtlasSetBuilder {
     pub fn new(size: (u32, u32), border_padding: u32, rectangle_padding: u32, min_filter: MinFilter, mag_filter: MagFilter) -> Self {
         Self {
             texture_queue: Vec::new(),
+            nine_slices: HashMap::new(),
             size,
             border_padding,
             rectangle_padding,
             min_filter,
-            mag_filter
+            mag_filter,
+            extrude: default_extrude(min_filter),
+            allow_rotation: false,
+            trim: false,
         }
     }
 
+    /// Trims transparent borders on every atlas in the set, see AtlasBuilder::with_trim
+    pub fn with_trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Sets the edge extrusion of every atlas in the set, see AtlasBuilder::with_extrude
+    pub fn with_extrude(mut self, extrude: u32) -> Self {
+        self.extrude = extrude;
+        self
+    }
+
+    /// Allows rotated packing on every atlas in the set, see AtlasBuilder::with_rotation
+    pub fn with_rotation(mut self, allow_rotation: bool) -> Self {
+        self.allow_rotation = allow_rotation;
+        self
+    }
+
+    /// Attaches nine-slice insets to the texture `id`, see AtlasBuilder::set_nine_slice
+    pub fn set_nine_slice(&mut self, id: AtlasTextureIdentifier, insets: NineSlice) {
+        self.nine_slices.insert(id, insets);
+    }
+
     pub fn add(&mut self, id: AtlasTextureIdentifier, texture: DynamicImage) -> Result<()> {
         for (id2, _) in &self.texture_queue {
             if id == *id2 {
@@ -219,29 +746,49 @@ impl AtlasSetBuilder {
         Ok(())
     }
 
-    pub fn build(mut self) -> AtlasSet {
+    /// Builds the set straight into a texture array, see AtlasSet::into_array
+    pub fn build_array(self, gl_state: &mut GlStateManager) -> Result<AtlasArray> {
+        self.build(gl_state)?.into_array(gl_state)
+    }
+
+    /// Packs the textures onto as many pages as they need.
+    /// If a texture doesn't fit on an empty page, an error is returned and the pages built so far
+    /// are deleted.
+    pub fn build(mut self, gl_state: &mut GlStateManager) -> Result<AtlasSet> {
 
         let mut finalized = Vec::new();
 
         let mut textures = mem::take(&mut self.texture_queue);
 
         loop {
-            let mut builder = AtlasBuilder::new(self.size, self.border_padding, self.rectangle_padding, self.min_filter, self.mag_filter);
+            let mut builder = AtlasBuilder::new(self.size, self.border_padding, self.rectangle_padding, self.min_filter, self.mag_filter)
+                .with_extrude(self.extrude)
+                .with_rotation(self.allow_rotation)
+                .with_trim(self.trim);
             let mut ts = Vec::new();
 
             mem::swap(&mut ts, &mut textures);
             for tex in ts {
-                builder.add(tex.0, tex.1).unwrap();
+                if let Some(insets) = self.nine_slices.get(&tex.0) {
+                    builder.set_nine_slice(tex.0.clone(), *insets);
+                }
+                builder.add(tex.0, tex.1)?;
             }
 
-            let (atlas, textures) = builder.build_overflow().unwrap();
+            let (atlas, overflow) = builder.build_overflow()?;
+            let packed_nothing = atlas.position_data.is_empty() && !overflow.is_empty();
 
             finalized.push(atlas);
+            textures = overflow;
 
-            if textures.is_empty() {
-                break AtlasSet {
-                    atlases: finalized
+            if packed_nothing {
+                for atlas in finalized {
+                    gl_state.defer_delete(GlObject::Texture(atlas.tex_id));
                 }
+                return Err(AtlasError::TextureOverflow.into());
+            }
+            if textures.is_empty() {
+                break Ok(AtlasSet::new(finalized))
             }
 
         }
@@ -256,40 +803,898 @@ impl Atlas {
         self.position_data.contains_key(id)
     }
 
+    /// Counts the lookup when usage tracking is on
     pub fn get_rect(&self, id: &AtlasTextureIdentifier) -> Option<AtlasRect> {
-        self.position_data.get(id).copied()
+        let rect = self.position_data.get(id).copied();
+        if rect.is_some() && self.usage.tracking.get() {
+            *self.usage.counts.borrow_mut().entry(id.clone()).or_insert(0) += 1;
+        }
+        rect
+    }
+
+    /// Starts or stops counting get_rect lookups per texture, for the usage heatmap.
+    /// Off by default.
+    pub fn set_usage_tracking(&self, enabled: bool) {
+        self.usage.tracking.set(enabled);
+    }
+
+    /// # Returns
+    /// how many times the texture's rect was fetched while tracking
+    pub fn get_access_count(&self, id: &AtlasTextureIdentifier) -> u64 {
+        self.usage.counts.borrow().get(id).copied().unwrap_or(0)
+    }
+
+    pub fn reset_access_counts(&self) {
+        self.usage.counts.borrow_mut().clear();
+    }
+
+    /// # Returns
+    /// the textures that were never fetched while tracking
+    pub fn get_unused_textures(&self) -> Vec<AtlasTextureIdentifier> {
+        let counts = self.usage.counts.borrow();
+        self.position_data.keys().filter(|id| !counts.contains_key(*id)).cloned().collect()
+    }
+
+    /// # Returns
+    /// the fraction of the atlas covered by textures, padding and extrusion count as free
+    pub fn get_occupancy(&self) -> f32 {
+        let used: u64 = self.position_data.values().map(|r| r.rect.2 as u64 * r.rect.3 as u64).sum();
+        used as f32 / (self.size.0 as u64 * self.size.1 as u64).max(1) as f32
+    }
+
+    /// Draws the packed rects over the free space, colored from blue (rarely fetched) to red
+    /// (fetched the most), with never fetched textures in magenta. Upload it with upload_image
+    /// or Texture::from_image to show it in a debug view.
+    pub fn usage_heatmap(&self) -> RgbaImage {
+        let mut img = RgbaImage::from_pixel(self.size.0, self.size.1, Rgba([24, 24, 24, 255]));
+        let counts = self.usage.counts.borrow();
+        let max = counts.values().copied().max().unwrap_or(0);
+
+        for (id, rect) in &self.position_data {
+            let count = counts.get(id).copied().unwrap_or(0);
+            let fill = if count == 0 {
+                Rgba([220, 0, 220, 255])
+            } else {
+                // log scale so a few hot sprites don't wash out the rest
+                let t = (count as f32).ln_1p() / (max as f32).ln_1p();
+                Rgba([(t * 255.) as u8, 40, ((1. - t) * 255.) as u8, 255])
+            };
+            let (x, y, w, h) = rect.rect;
+            for py in y..y + h {
+                for px in x..x + w {
+                    let edge = px == x || py == y || px + 1 == x + w || py + 1 == y + h;
+                    img.put_pixel(px, py, if edge { Rgba([255, 255, 255, 255]) } else { fill });
+                }
+            }
+        }
+        img
     }
 
     pub fn get_id(&self) -> GLuint {
         self.tex_id
     }
 
+    /// Looks up an animation added with AtlasBuilder::add_animation, or else one made of textures
+    /// named `{id}_0`, `{id}_1`, ... up to the first missing number, each shown for the builder's
+    /// frame duration.
+    ///
+    /// # Returns
+    /// None if there are no frames, or some frames didn't fit on this atlas
+    pub fn get_animation(&self, id: &AtlasTextureIdentifier) -> Option<AtlasAnimation> {
+        let frames = if let Some(sequence) = self.animations.get(id) {
+            sequence.iter()
+                .map(|(frame, duration)| Some((self.get_rect(frame)?, *duration)))
+                .collect::<Option<Vec<_>>>()?
+        } else {
+            (0..)
+                .map_while(|i| self.get_rect(&AtlasTextureIdentifier(format!("{}_{i}", id.0))))
+                .map(|rect| (rect, self.frame_duration))
+                .collect()
+        };
+        if frames.is_empty() {
+            return None;
+        }
+        Some(AtlasAnimation { frames })
+    }
+
     pub fn get_size(&self) -> (u32, u32) {
         self.size
     }
+
+    /// Forgets a texture. Its space isn't reused until the atlas is rebuilt, see AtlasSet::rebuild
+    ///
+    /// # Returns
+    /// whether the texture was on the atlas
+    pub fn remove(&mut self, id: &AtlasTextureIdentifier) -> bool {
+        self.nine_slices.remove(id);
+        self.usage.counts.borrow_mut().remove(id);
+        self.position_data.remove(id).is_some()
+    }
+
+    /// Lets try_insert grow the atlas up to `max_size` when it runs out of space, None disables
+    /// growth. Imported sprite sheets never grow.
+    pub fn set_max_size(&mut self, max_size: Option<(u32, u32)>) {
+        self.max_size = max_size;
+    }
+
+    pub fn get_max_size(&self) -> Option<(u32, u32)> {
+        self.max_size
+    }
+
+    /// Counts how many times the atlas has grown. Growing replaces the gl texture and changes the
+    /// uvs of every rect, so ids, rects and sprites fetched before a change must be fetched again.
+    pub fn get_generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Doubles the atlas in each direction, capped at the max size. The page is copied into a new
+    /// texture at the same pixel positions, and the old texture is deleted at the next frame_end
+    /// since queued draws may still use it.
+    ///
+    /// # Returns
+    /// whether the atlas grew
+    fn grow(&mut self, gl_state: &mut GlStateManager) -> bool {
+        let Some(max_size) = self.max_size else {
+            return false;
+        };
+        let (width, height) = self.size;
+        let new_size = (
+            (width * 2).min(max_size.0).max(width),
+            (height * 2).min(max_size.1).max(height),
+        );
+        if self.packer.sealed || new_size == self.size {
+            return false;
+        }
+
+        let blank = DynamicImage::ImageRgba8(RgbaImage::new(new_size.0, new_size.1));
+        let (tex_id, _) = upload_image(&blank, self.min_filter, self.mag_filter, TextureWrap::new(WrapMode::ClampToEdge, WrapMode::ClampToEdge));
+        unsafe {
+            gl::CopyImageSubData(
+                self.tex_id, gl::TEXTURE_2D, 0, 0, 0, 0,
+                tex_id, gl::TEXTURE_2D, 0, 0, 0, 0,
+                width as i32, height as i32, 1,
+            );
+        }
+        if !matches!(self.min_filter, MinFilter::Nearest | MinFilter::Linear) {
+            gl_state.bind_texture(0, tex_id);
+            unsafe {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
+        }
+        gl_state.defer_delete(GlObject::Texture(self.tex_id));
+        self.tex_id = tex_id;
+
+        let config = self.packer.packer.config();
+        if new_size.0 > width {
+            self.packer.regions.push(GrowthRegion::new((width, 0), (new_size.0 - width, new_size.1), config));
+        }
+        if new_size.1 > height {
+            self.packer.regions.push(GrowthRegion::new((0, height), (width, new_size.1 - height), config));
+        }
+        self.size = new_size;
+        for rect in self.position_data.values_mut() {
+            rect.size = (new_size.0 as f32, new_size.1 as f32);
+        }
+        self.generation += 1;
+        true
+    }
+
+    /// # Returns
+    /// the nine-slice insets attached to the texture when the atlas was built
+    pub fn get_nine_slice(&self, id: &AtlasTextureIdentifier) -> Option<NineSlice> {
+        self.nine_slices.get(id).copied()
+    }
+
+    /// Packs `img` into the atlas's remaining free space and uploads it with glTexSubImage2D, for
+    /// content that isn't known when the atlas is built. Space is never reclaimed. Atlases with a
+    /// max size grow until the texture fits, see set_max_size.
+    ///
+    /// # Returns
+    /// where the texture was placed, or AtlasError::AtlasFull if it doesn't fit
+    pub fn try_insert(&mut self, gl_state: &mut GlStateManager, id: AtlasTextureIdentifier, img: &DynamicImage) -> Result<AtlasRect> {
+        if self.position_data.contains_key(&id) {
+            return Err(AtlasError::DuplicateId(id.0.to_string()).into());
+        }
+        let (rect, rotated) = loop {
+            if let Some(packed) = self.packer.pack_texture(img.dimensions(), self.extrude) {
+                break packed;
+            }
+            if !self.grow(gl_state) {
+                return Err(AtlasError::AtlasFull(id.0.to_string()).into());
+            }
+        };
+        let prepared = DynamicImage::ImageRgba8(prepare_texture(&img.to_rgba8(), self.extrude, rotated));
+
+        let mut texture = Texture::from_raw(self.tex_id, TextureTarget::Texture2D);
+        texture.set_filters(gl_state, self.min_filter, self.mag_filter);
+        let uploaded = texture.update_region(gl_state, rect.0 - self.extrude, rect.1 - self.extrude, &prepared);
+        // the atlas still owns the gl texture
+        texture.into_raw();
+        uploaded?;
+
+        let rect = AtlasRect::new(self.size, rect, rotated);
+        self.position_data.insert(id, rect);
+        Ok(rect)
+    }
+
+    /// Writes the atlas image to `path` as a PNG, plus a metadata file with the rects, ids and
+    /// filter settings at `path` with a `.json` extension. Load both with Atlas::load to skip
+    /// packing at runtime.
+    pub fn save(&self, gl_state: &mut GlStateManager, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let texture = Texture::from_raw(self.tex_id, TextureTarget::Texture2D);
+        let image = texture.download(gl_state);
+        // the atlas still owns the gl texture
+        texture.into_raw();
+        image?.save_with_format(path, image::ImageFormat::Png)?;
+
+        let config = self.packer.packer.config();
+        let metadata = AtlasMetadata {
+            size: self.size,
+            border_padding: config.border_padding as u32,
+            rectangle_padding: config.rectangle_padding as u32,
+            min_filter: self.min_filter,
+            mag_filter: self.mag_filter,
+            extrude: self.extrude,
+            packed: self.packer.packed.clone(),
+            growth_regions: self.packer.regions.iter().map(|region| {
+                let config = region.packer.config();
+                GrowthRegionMetadata {
+                    offset: region.offset,
+                    size: (config.width as u32, config.height as u32),
+                    packed: region.packed.clone(),
+                }
+            }).collect(),
+            max_size: self.max_size,
+            sealed: self.packer.sealed,
+            allow_rotation: self.packer.allow_rotation,
+            rotated: self.position_data.iter().filter(|(_, rect)| rect.rotated).map(|(id, _)| id.clone()).collect(),
+            trims: self.position_data.iter()
+                .filter(|(_, rect)| rect.is_trimmed())
+                .map(|(id, rect)| (id.clone(), TrimMetadata { offset: rect.trim_offset, source_size: rect.source_size }))
+                .collect(),
+            animations: self.animations.clone(),
+            frame_duration: self.frame_duration,
+            nine_slices: self.nine_slices.clone(),
+            textures: self.position_data.iter().map(|(id, rect)| (id.clone(), rect.coords())).collect(),
+        };
+        fs::write(path.with_extension("json"), serde_json::to_string_pretty(&metadata)?)?;
+        Ok(())
+    }
+
+    /// Loads and uploads an atlas written by Atlas::save. The packer state is restored as well,
+    /// so try_insert keeps working.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path.with_extension("json"))?;
+        let metadata: AtlasMetadata = serde_json::from_str(&source).map_err(|e| AtlasError::InvalidMetadata(e.to_string()))?;
+
+        let img = image::open(path)?;
+        if img.dimensions() != metadata.size {
+            return Err(AtlasError::InvalidMetadata(format!(
+                "atlas is {:?}, the image is {:?}", metadata.size, img.dimensions()
+            )).into());
+        }
+
+        let mut packer = AtlasPacker::new(Config {
+            width: metadata.size.0 as i32,
+            height: metadata.size.1 as i32,
+            border_padding: metadata.border_padding as i32,
+            rectangle_padding: metadata.rectangle_padding as i32,
+        });
+        packer.allow_rotation = metadata.allow_rotation;
+        for (w, h) in metadata.packed {
+            if packer.pack(w, h).is_none() {
+                return Err(AtlasError::InvalidMetadata("packed rects don't fit on the atlas".to_string()).into());
+            }
+        }
+        for region in metadata.growth_regions {
+            let mut grown = GrowthRegion::new(region.offset, region.size, packer.packer.config());
+            for (w, h) in region.packed {
+                if grown.pack(w, h, packer.allow_rotation).is_none() {
+                    return Err(AtlasError::InvalidMetadata("packed rects don't fit in a growth region".to_string()).into());
+                }
+            }
+            packer.regions.push(grown);
+        }
+        packer.sealed = metadata.sealed;
+
+        let (tex_id, _) = upload_image(&img, metadata.min_filter, metadata.mag_filter, TextureWrap::new(WrapMode::ClampToEdge, WrapMode::ClampToEdge));
+        Ok(Self {
+            tex_id,
+            position_data: metadata.textures.into_iter().map(|(id, rect)| {
+                let rotated = metadata.rotated.contains(&id);
+                let mut rect = AtlasRect::new(metadata.size, rect, rotated);
+                if let Some(trim) = metadata.trims.get(&id) {
+                    rect = rect.with_trim(trim.offset, trim.source_size);
+                }
+                (id, rect)
+            }).collect(),
+            size: metadata.size,
+            packer,
+            min_filter: metadata.min_filter,
+            mag_filter: metadata.mag_filter,
+            extrude: metadata.extrude,
+            animations: metadata.animations,
+            frame_duration: metadata.frame_duration,
+            nine_slices: metadata.nine_slices,
+            max_size: metadata.max_size,
+            generation: 0,
+            usage: AtlasUsage::default(),
+        })
+    }
+
+    /// Builds an atlas from a pre-baked sprite sheet described in TexturePacker's JSON "hash"
+    /// format, with frame names as texture ids. Rotated frames aren't supported. The sheet's free
+    /// space is unknown, so try_insert always fails on the result.
+    pub fn from_texture_packer(img: &DynamicImage, json: &str, min_filter: MinFilter, mag_filter: MagFilter) -> Result<Self> {
+        let sheet: TexturePackerSheet = serde_json::from_str(json).map_err(|e| AtlasError::InvalidMetadata(e.to_string()))?;
+        let mut rects = Vec::with_capacity(sheet.frames.len());
+        for (name, frame) in sheet.frames {
+            if frame.rotated {
+                return Err(AtlasError::InvalidMetadata(format!("frame '{name}' is rotated, which isn't supported")).into());
+            }
+            rects.push((name, (frame.frame.x, frame.frame.y, frame.frame.w, frame.frame.h)));
+        }
+        Self::from_sheet(img, rects, min_filter, mag_filter)
+    }
+
+    /// Builds an atlas from a pre-baked sprite sheet of equally sized cells, see from_texture_packer
+    pub fn from_grid(img: &DynamicImage, sheet: &GridSheet, min_filter: MinFilter, mag_filter: MagFilter) -> Result<Self> {
+        Self::from_sheet(img, sheet.cells(img.dimensions()), min_filter, mag_filter)
+    }
+
+    /// Loads a sprite sheet description in TexturePacker's "hash" format or the GridSheet format,
+    /// and the image it names (`meta.image` or `image`, relative to the description). Without an
+    /// image name the description's path with a `.png` extension is used.
+    pub fn load_sprite_sheet(path: impl AsRef<Path>, min_filter: MinFilter, mag_filter: MagFilter) -> Result<Self> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        let value: serde_json::Value = serde_json::from_str(&source).map_err(|e| AtlasError::InvalidMetadata(e.to_string()))?;
+        let texture_packer = value.get("frames").is_some();
+        let image_name = if texture_packer {
+            value.pointer("/meta/image")
+        } else {
+            value.get("image")
+        };
+        let image_path = match image_name.and_then(|v| v.as_str()) {
+            Some(name) => path.with_file_name(name),
+            None => path.with_extension("png"),
+        };
+        let img = image::open(image_path)?;
+
+        if texture_packer {
+            Self::from_texture_packer(&img, &source, min_filter, mag_filter)
+        } else {
+            let sheet: GridSheet = serde_json::from_value(value).map_err(|e| AtlasError::InvalidMetadata(e.to_string()))?;
+            Self::from_grid(&img, &sheet, min_filter, mag_filter)
+        }
+    }
+
+    fn from_sheet(img: &DynamicImage, rects: Vec<(String, (u32, u32, u32, u32))>, min_filter: MinFilter, mag_filter: MagFilter) -> Result<Self> {
+        let size = img.dimensions();
+        let mut position_data = HashMap::with_capacity(rects.len());
+        for (name, (x, y, w, h)) in rects {
+            if x + w > size.0 || y + h > size.1 {
+                return Err(AtlasError::InvalidMetadata(format!("frame '{name}' is outside the {}x{} sheet", size.0, size.1)).into());
+            }
+            if position_data.insert(AtlasTextureIdentifier(name.clone()), AtlasRect::new(size, (x, y, w, h), false)).is_some() {
+                return Err(AtlasError::DuplicateId(name).into());
+            }
+        }
+
+        let mut packer = AtlasPacker::new(Config {
+            width: size.0 as i32,
+            height: size.1 as i32,
+            border_padding: 0,
+            rectangle_padding: 0,
+        });
+        packer.sealed = true;
+
+        let (tex_id, _) = upload_image(img, min_filter, mag_filter, TextureWrap::new(WrapMode::ClampToEdge, WrapMode::ClampToEdge));
+        Ok(Self {
+            tex_id,
+            position_data,
+            size,
+            packer,
+            min_filter,
+            mag_filter,
+            extrude: 0,
+            animations: HashMap::new(),
+            frame_duration: DEFAULT_FRAME_DURATION,
+            nine_slices: HashMap::new(),
+            max_size: None,
+            generation: 0,
+            usage: AtlasUsage::default(),
+        })
+    }
+
+    pub(crate) fn into_parts(self) -> (GLuint, HashMap<AtlasTextureIdentifier, AtlasRect>) {
+        (self.tex_id, self.position_data)
+    }
+}
+
+/// Gathers the paths of the files under `dir` that have a decodable image extension
+fn collect_images(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_images(&path, files)?;
+        } else if ImageFormat::from_path(&path).is_ok_and(|format| format.reading_enabled()) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+impl AtlasAnimation {
+    pub fn get_frames(&self) -> &[(AtlasRect, Duration)] {
+        &self.frames
+    }
+
+    pub fn get_frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// # Returns
+    /// the length of one playthrough
+    pub fn get_duration(&self) -> Duration {
+        self.frames.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    /// # Returns
+    /// the index of the frame shown `time` after the animation started. Looping animations wrap
+    /// around, others hold their last frame.
+    pub fn frame_index_at(&self, time: Duration, looping: bool) -> usize {
+        let total = self.get_duration();
+        if total.is_zero() {
+            return 0;
+        }
+        let mut time = if looping {
+            Duration::from_nanos((time.as_nanos() % total.as_nanos()) as u64)
+        } else {
+            time
+        };
+        for (i, (_, duration)) in self.frames.iter().enumerate() {
+            if time < *duration {
+                return i;
+            }
+            time -= *duration;
+        }
+        self.frames.len() - 1
+    }
+
+    pub fn frame_at(&self, time: Duration, looping: bool) -> AtlasRect {
+        self.frames[self.frame_index_at(time, looping)].0
+    }
 }
 
 impl AtlasSet {
-    pub fn has_texture(&self, id: &AtlasTextureIdentifier) -> bool {
-        for a in &self.atlases {
-            if a.has_texture(id) {
-                return true
+    fn new(atlases: Vec<Atlas>) -> Self {
+        let mut set = Self {
+            atlases,
+            lookup: HashMap::new(),
+        };
+        set.rebuild_lookup();
+        set
+    }
+
+    fn rebuild_lookup(&mut self) {
+        self.lookup = self.atlases.iter().enumerate()
+            .flat_map(|(i, atlas)| atlas.position_data.keys().map(move |id| (id.clone(), i)))
+            .collect();
+    }
+
+    pub(crate) fn into_atlases(self) -> Vec<Atlas> {
+        self.atlases
+    }
+
+    /// Copies every page into a layer of a texture array with the filters of the first page, and
+    /// deletes the pages' own textures. Every page must be the same size.
+    pub fn into_array(self, gl_state: &mut GlStateManager) -> Result<AtlasArray> {
+        let (size, min_filter, mag_filter) = self.atlases.first()
+            .map_or(((1, 1), MinFilter::Linear, MagFilter::Linear), |a| (a.size, a.min_filter, a.mag_filter));
+        let nine_slices = self.atlases.iter().flat_map(|a| a.nine_slices.clone()).collect();
+        let mut builder = TextureArrayBuilder::new(size, min_filter, mag_filter);
+        let rects = builder.add_atlas_set(self)?;
+        Ok(AtlasArray {
+            texture: builder.build(gl_state),
+            rects,
+            nine_slices,
+        })
+    }
+
+    /// Starts or stops usage tracking on every atlas, see Atlas::set_usage_tracking
+    pub fn set_usage_tracking(&self, enabled: bool) {
+        for atlas in &self.atlases {
+            atlas.set_usage_tracking(enabled);
+        }
+    }
+
+    /// # Returns
+    /// a usage heatmap per atlas, see Atlas::usage_heatmap
+    pub fn usage_heatmaps(&self) -> Vec<RgbaImage> {
+        self.atlases.iter().map(Atlas::usage_heatmap).collect()
+    }
+
+    /// Looks up an animation on whichever atlas holds its frames, see Atlas::get_animation.
+    /// All frames of an animation must be on the same atlas.
+    pub fn get_animation(&self, id: &AtlasTextureIdentifier) -> Option<(GLuint, AtlasAnimation)> {
+        self.atlases.iter().find_map(|a| Some((a.get_id(), a.get_animation(id)?)))
+    }
+
+    /// Removes a texture from whichever atlas holds it, see Atlas::remove
+    pub fn remove(&mut self, id: &AtlasTextureIdentifier) -> bool {
+        match self.lookup.remove(id) {
+            Some(i) => self.atlases[i].remove(id),
+            None => false,
+        }
+    }
+
+    /// Repacks every texture still in the set into as few pages as possible, e.g. after many
+    /// removals. Pages are as large as the largest current page and use the first page's
+    /// settings. The old pages are deleted at the next frame_end, since queued draws may still
+    /// use them.
+    ///
+    /// # Returns
+    /// where every texture moved, for patching cached vertex data, see AtlasRemap::remap_uv
+    pub fn rebuild(&mut self, gl_state: &mut GlStateManager) -> Result<HashMap<AtlasTextureIdentifier, AtlasRemap>> {
+        let Some(first) = self.atlases.first() else {
+            return Ok(HashMap::new());
+        };
+        let size = self.atlases.iter().fold(first.size, |size, a| (size.0.max(a.size.0), size.1.max(a.size.1)));
+        let config = first.packer.packer.config();
+        let (max_size, frame_duration) = (first.max_size, first.frame_duration);
+        let mut builder = AtlasSetBuilder::new(size, config.border_padding as u32, config.rectangle_padding as u32, first.min_filter, first.mag_filter)
+            .with_extrude(first.extrude)
+            .with_rotation(first.packer.allow_rotation);
+
+        let mut old_rects = HashMap::new();
+        let mut animations = HashMap::new();
+        for atlas in &self.atlases {
+            let texture = Texture::from_raw(atlas.tex_id, TextureTarget::Texture2D);
+            let page = texture.download(gl_state);
+            // the atlas still owns the gl texture
+            texture.into_raw();
+            let page = page?;
+
+            for (id, rect) in &atlas.position_data {
+                let (x, y, w, h) = rect.coords();
+                let pixels = imageops::crop_imm(&page, x, y, w, h).to_image();
+                let pixels = if rect.rotated { imageops::rotate270(&pixels) } else { pixels };
+                if let Some(insets) = atlas.nine_slices.get(id) {
+                    builder.set_nine_slice(id.clone(), *insets);
+                }
+                builder.add(id.clone(), DynamicImage::ImageRgba8(pixels))?;
+                old_rects.insert(id.clone(), (atlas.tex_id, *rect));
+            }
+            animations.extend(atlas.animations.clone());
+        }
+
+        let mut rebuilt = builder.build(gl_state)?;
+        let mut remap = HashMap::with_capacity(old_rects.len());
+        for atlas in &mut rebuilt.atlases {
+            atlas.animations = animations.clone();
+            atlas.frame_duration = frame_duration;
+            atlas.max_size = max_size;
+            for (id, rect) in &mut atlas.position_data {
+                let (old_texture, old_rect) = old_rects[id];
+                // the images were taken from the packed pixels, so they are trimmed already
+                *rect = rect.with_trim(old_rect.trim_offset, old_rect.source_size);
+                remap.insert(id.clone(), AtlasRemap {
+                    old_texture,
+                    old_rect,
+                    new_texture: atlas.tex_id,
+                    new_rect: *rect,
+                });
             }
         }
-        false
+
+        for atlas in mem::replace(&mut self.atlases, rebuilt.atlases) {
+            gl_state.defer_delete(GlObject::Texture(atlas.tex_id));
+        }
+        self.lookup = rebuilt.lookup;
+        Ok(remap)
+    }
+
+    pub fn get_atlas_count(&self) -> usize {
+        self.atlases.len()
+    }
+
+    /// # Returns
+    /// how full each page is, in page order, for tuning the page size
+    pub fn get_page_stats(&self) -> Vec<AtlasPageStats> {
+        self.atlases.iter()
+            .map(|atlas| AtlasPageStats {
+                atlas_id: atlas.tex_id,
+                size: atlas.size,
+                texture_count: atlas.position_data.len(),
+                used_area: atlas.position_data.values().map(|r| r.rect.2 as u64 * r.rect.3 as u64).sum(),
+                occupancy: atlas.get_occupancy(),
+            })
+            .collect()
+    }
+
+    /// # Returns
+    /// the fraction of all pages together covered by textures
+    pub fn get_occupancy(&self) -> f32 {
+        let stats = self.get_page_stats();
+        let used: u64 = stats.iter().map(|page| page.used_area).sum();
+        let total: u64 = stats.iter().map(|page| page.size.0 as u64 * page.size.1 as u64).sum();
+        used as f32 / total.max(1) as f32
+    }
+
+    /// Looks up nine-slice insets on whichever atlas holds the texture, see Atlas::get_nine_slice
+    pub fn get_nine_slice(&self, id: &AtlasTextureIdentifier) -> Option<NineSlice> {
+        self.atlases[*self.lookup.get(id)?].get_nine_slice(id)
+    }
+
+    pub fn has_texture(&self, id: &AtlasTextureIdentifier) -> bool {
+        self.lookup.contains_key(id)
     }
 
     pub fn get_id_and_rect(&self, id: &AtlasTextureIdentifier) -> Option<(GLuint, AtlasRect)> {
+        let atlas = &self.atlases[*self.lookup.get(id)?];
+        Some((atlas.get_id(), atlas.get_rect(id)?))
+    }
 
-        for a in &self.atlases {
-            if a.has_texture(id) {
-                return Some((a.get_id(), a.get_rect(id).unwrap()))
-            }
+}
+
+impl AtlasRemap {
+    /// Moves a uv inside the old rect to the same texel of the new rect, accounting for either
+    /// rect being rotated
+    pub fn remap_uv(&self, uv: (f32, f32)) -> (f32, f32) {
+        let (u, v, w, h) = self.old_rect.uvs();
+        let (a, b) = ((uv.0 - u) / w, (uv.1 - v) / h);
+        // undo the (s, t) -> (1 - t, s) of rotated rects, see AtlasRect::corner_uvs
+        let (s, t) = if self.old_rect.rotated { (b, 1. - a) } else { (a, b) };
+        let (s, t) = if self.new_rect.rotated { (1. - t, s) } else { (s, t) };
+        let (u, v, w, h) = self.new_rect.uvs();
+        (u + s * w, v + t * h)
+    }
+}
+
+impl AtlasArray {
+    pub fn get_id(&self) -> GLuint {
+        self.texture.get_id()
+    }
+
+    pub fn get_texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    pub fn get_layer_count(&self) -> u32 {
+        self.texture.get_depth()
+    }
+
+    pub fn has_texture(&self, id: &AtlasTextureIdentifier) -> bool {
+        self.rects.contains_key(id)
+    }
+
+    /// # Returns
+    /// the layer holding the texture and its rect within that layer
+    pub fn get_layer_and_rect(&self, id: &AtlasTextureIdentifier) -> Option<(u32, AtlasRect)> {
+        self.rects.get(id).copied()
+    }
+
+    pub fn get_nine_slice(&self, id: &AtlasTextureIdentifier) -> Option<NineSlice> {
+        self.nine_slices.get(id).copied()
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        self.texture.destroy(gl_state);
+    }
+}
+
+fn default_frame_duration() -> Duration {
+    DEFAULT_FRAME_DURATION
+}
+
+fn default_extrude(min_filter: MinFilter) -> u32 {
+    if matches!(min_filter, MinFilter::Nearest | MinFilter::Linear) { 0 } else { 1 }
+}
+
+/// # Returns
+/// `tex` extruded and, if packed rotated, turned 90 degrees clockwise to match its atlas rect
+fn prepare_texture(tex: &RgbaImage, extrude: u32, rotated: bool) -> RgbaImage {
+    let tex = extrude_edges(tex, extrude);
+    if rotated { imageops::rotate90(&tex) } else { tex }
+}
+
+/// # Returns
+/// the image cropped to its non-transparent pixels, and where the crop starts. Fully transparent
+/// images keep their top left pixel.
+fn trim_transparent(tex: &RgbaImage) -> (RgbaImage, (u32, u32)) {
+    let mut min = (u32::MAX, u32::MAX);
+    let mut max = (0, 0);
+    for (x, y, pixel) in tex.enumerate_pixels() {
+        if pixel.0[3] != 0 {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
         }
-        None
     }
+    if min.0 > max.0 {
+        return (imageops::crop_imm(tex, 0, 0, 1, 1).to_image(), (0, 0));
+    }
+    let cropped = imageops::crop_imm(tex, min.0, min.1, max.0 - min.0 + 1, max.1 - min.1 + 1).to_image();
+    (cropped, min)
+}
+
+/// # Returns
+/// `tex` with its edge pixels repeated `extrude` pixels outwards on every side
+fn extrude_edges(tex: &RgbaImage, extrude: u32) -> RgbaImage {
+    let (w, h) = tex.dimensions();
+    if extrude == 0 || w == 0 || h == 0 {
+        return tex.clone();
+    }
+    RgbaImage::from_fn(w + extrude * 2, h + extrude * 2, |x, y| {
+        let sx = x.saturating_sub(extrude).min(w - 1);
+        let sy = y.saturating_sub(extrude).min(h - 1);
+        *tex.get_pixel(sx, sy)
+    })
+}
+
+impl AtlasPacker {
+    pub(crate) fn new(config: Config) -> Self {
+        Self {
+            packer: Packer::new(config),
+            packed: Vec::new(),
+            sealed: false,
+            allow_rotation: false,
+            regions: Vec::new(),
+        }
+    }
+
+    fn pack(&mut self, width: u32, height: u32) -> Option<Rect> {
+        if self.sealed {
+            return None;
+        }
+        if let Some(rect) = self.packer.pack(width as i32, height as i32, self.allow_rotation) {
+            self.packed.push((width, height));
+            return Some(rect);
+        }
+        let allow_rotation = self.allow_rotation;
+        self.regions.iter_mut().find_map(|region| region.pack(width, height, allow_rotation))
+    }
+
+    /// Packs a texture of `size` with `extrude` pixels around it.
+    ///
+    /// # Returns
+    /// the texture's rect as laid out on the atlas, and whether it was rotated
+    pub(crate) fn pack_texture(&mut self, size: (u32, u32), extrude: u32) -> Option<((u32, u32, u32, u32), bool)> {
+        let (pw, ph) = (size.0 + extrude * 2, size.1 + extrude * 2);
+        let rect = self.pack(pw, ph)?;
+        let rotated = rect.width as u32 != pw;
+        let (w, h) = if rotated { (size.1, size.0) } else { size };
+        Some(((rect.x as u32 + extrude, rect.y as u32 + extrude, w, h), rotated))
+    }
+}
+
+impl GrowthRegion {
+    /// A region with the padding of the atlas's `config`
+    fn new(offset: (u32, u32), size: (u32, u32), config: Config) -> Self {
+        Self {
+            offset,
+            packer: Packer::new(Config {
+                width: size.0 as i32,
+                height: size.1 as i32,
+                ..config
+            }),
+            packed: Vec::new(),
+        }
+    }
+
+    /// # Returns
+    /// the packed rect in atlas coordinates
+    fn pack(&mut self, width: u32, height: u32, allow_rotation: bool) -> Option<Rect> {
+        let rect = self.packer.pack(width as i32, height as i32, allow_rotation)?;
+        self.packed.push((width, height));
+        Some(Rect::new(rect.x + self.offset.0 as i32, rect.y + self.offset.1 as i32, rect.width, rect.height))
+    }
+}
 
+impl fmt::Debug for AtlasPacker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AtlasPacker")
+            .field("config", &self.packer.config())
+            .field("packed", &self.packed.len())
+            .field("regions", &self.regions.len())
+            .finish()
+    }
+}
+
+/// What is under the cursor in an atlas debug view, see AtlasInspector
+#[derive(Debug, Clone)]
+pub struct TexelInfo {
+    /// index of the atlas in its set
+    pub atlas_index: usize,
+    /// gl texture id of the atlas
+    pub atlas_id: GLuint,
+    /// texture packed at the texel, None for padding and unused space
+    pub texture: Option<AtlasTextureIdentifier>,
+    /// texel position in the atlas, from the top left
+    pub pixel: (u32, u32),
+    /// texel position inside `texture` as it was added, from its top left, accounting for
+    /// rotation and trimming
+    pub local_pixel: Option<(u32, u32)>,
+    pub color: Rgba<u8>,
+}
+
+/// Debug tool to inspect atlases drawn on screen, reports which texture and color is under the
+/// cursor to track down packing and bleeding issues. The atlas pixels are read back once on
+/// creation, so create a new inspector after rebuilding the atlases.
+#[derive(Debug)]
+pub struct AtlasInspector {
+    atlases: Vec<(GLuint, HashMap<AtlasTextureIdentifier, AtlasRect>, RgbaImage)>,
 }
 
+impl AtlasInspector {
+    pub fn new(gl_state: &mut GlStateManager, set: &AtlasSet) -> Result<Self> {
+        let mut atlases = Vec::with_capacity(set.atlases.len());
+        for atlas in &set.atlases {
+            atlases.push(Self::read_atlas(gl_state, atlas)?);
+        }
+        Ok(Self { atlases })
+    }
+
+    pub fn from_atlas(gl_state: &mut GlStateManager, atlas: &Atlas) -> Result<Self> {
+        Ok(Self { atlases: vec![Self::read_atlas(gl_state, atlas)?] })
+    }
+
+    fn read_atlas(gl_state: &mut GlStateManager, atlas: &Atlas) -> Result<(GLuint, HashMap<AtlasTextureIdentifier, AtlasRect>, RgbaImage)> {
+        let texture = Texture::from_raw(atlas.tex_id, TextureTarget::Texture2D);
+        let image = texture.download(gl_state);
+        // the atlas still owns the gl texture
+        texture.into_raw();
+        Ok((atlas.tex_id, atlas.position_data.clone(), image?))
+    }
+
+    /// Inspects the texel under `cursor` when atlas `atlas_index` is drawn stretched over
+    /// `view` ([x, y, width, height], in the same pixel space as the cursor, with uv (0, 0) at the
+    /// top left of the view). Returns None if the cursor is outside the view.
+    pub fn inspect(&self, atlas_index: usize, view: [f32; 4], cursor: (f32, f32)) -> Option<TexelInfo> {
+        let (atlas_id, rects, image) = self.atlases.get(atlas_index)?;
+        let u = (cursor.0 - view[0]) / view[2];
+        let v = (cursor.1 - view[1]) / view[3];
+        if !(0. ..1.).contains(&u) || !(0. ..1.).contains(&v) {
+            return None;
+        }
+        let pixel = ((u * image.width() as f32) as u32, (v * image.height() as f32) as u32);
+        Some(self.inspect_pixel(atlas_index, *atlas_id, rects, image, pixel))
+    }
 
+    /// Inspects a texel given directly in atlas pixel coordinates
+    pub fn inspect_texel(&self, atlas_index: usize, pixel: (u32, u32)) -> Option<TexelInfo> {
+        let (atlas_id, rects, image) = self.atlases.get(atlas_index)?;
+        if pixel.0 >= image.width() || pixel.1 >= image.height() {
+            return None;
+        }
+        Some(self.inspect_pixel(atlas_index, *atlas_id, rects, image, pixel))
+    }
 
+    pub fn get_atlas_count(&self) -> usize {
+        self.atlases.len()
+    }
+
+    fn inspect_pixel(&self, atlas_index: usize, atlas_id: GLuint, rects: &HashMap<AtlasTextureIdentifier, AtlasRect>, image: &RgbaImage, pixel: (u32, u32)) -> TexelInfo {
+        let hit = rects.iter().find(|(_, rect)| {
+            let (x, y, w, h) = rect.coords();
+            pixel.0 >= x && pixel.0 < x + w && pixel.1 >= y && pixel.1 < y + h
+        });
+        TexelInfo {
+            atlas_index,
+            atlas_id,
+            texture: hit.map(|(id, _)| id.clone()),
+            pixel,
+            local_pixel: hit.map(|(_, rect)| {
+                let (x, y, w, _) = rect.coords();
+                let (lx, ly) = (pixel.0 - x, pixel.1 - y);
+                // rotated textures are stored turned clockwise, so turn the offset back
+                let (lx, ly) = if rect.is_rotated() { (ly, w - 1 - lx) } else { (lx, ly) };
+                let trim = rect.get_trim_offset();
+                (lx + trim.0, ly + trim.1)
+            }),
+            color: *image.get_pixel(pixel.0, pixel.1),
+        }
+    }
+}
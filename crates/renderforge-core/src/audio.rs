@@ -0,0 +1,164 @@
+use std::fmt::Debug;
+use std::time::Instant;
+
+use glam::Vec4;
+
+use crate::buffer::UniformBuffer;
+use crate::data::GlStateManager;
+use crate::texture::{Texture, TextureTarget};
+
+/// Maximum number of frequency bands exposed through the uniform block
+pub const MAX_AUDIO_BANDS: usize = 64;
+
+/// Uniform block matching `AudioBlock`, include AUDIO_BLOCK_GLSL in shaders that read it
+pub const AUDIO_BLOCK_GLSL: &str = r#"
+layout(std140) uniform AudioBlock {
+    vec4 u_audio_bands[16];
+    float u_audio_level;
+    float u_audio_beat_time;
+    float u_audio_beat_count;
+    float u_audio_band_count;
+};
+uniform sampler1D u_audio_spectrum;
+
+float audio_band(int i) {
+    return u_audio_bands[i / 4][i % 4];
+}
+"#;
+
+/// One analysis result from an AudioSource
+#[derive(Debug, Clone, Default)]
+pub struct AudioFrame {
+    /// FFT band magnitudes, low to high frequency, roughly 0 to 1. Only the first
+    /// MAX_AUDIO_BANDS bands go in the uniform block, the spectrum texture gets all of them.
+    pub bands: Vec<f32>,
+    /// overall loudness, roughly 0 to 1
+    pub level: f32,
+    /// whether a beat started since the last frame
+    pub beat: bool,
+}
+
+/// An external audio analyser (an FFT of the playing music, a microphone, ...).
+/// RenderForge doesn't play or analyse audio itself.
+pub trait AudioSource: Debug {
+    /// Returns the latest analysis, or None to keep the previous values
+    fn poll(&mut self) -> Option<AudioFrame>;
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct AudioBlock {
+    bands: [Vec4; MAX_AUDIO_BANDS / 4],
+    level: f32,
+    beat_time: f32,
+    beat_count: f32,
+    band_count: f32,
+}
+
+/// Exposes an AudioSource to shaders, as the `AudioBlock` uniform block and the `u_audio_spectrum`
+/// texture. Register it with the Registry as Resource::Audio (boxed) to have it updated by
+/// Registry::update_audio, or call update yourself once per frame.
+#[derive(Debug)]
+pub struct AudioUniforms {
+    source: Box<dyn AudioSource>,
+    block: UniformBuffer<AudioBlock>,
+    spectrum: Texture,
+    spectrum_size: usize,
+    last_beat: Option<Instant>,
+}
+
+impl AudioUniforms {
+    /// `binding` is the uniform buffer binding point, bind programs' `AudioBlock` to it with
+    /// shader::bind_uniform_block
+    pub fn new(source: Box<dyn AudioSource>, binding: u32) -> Self {
+        let block = AudioBlock {
+            bands: [Vec4::ZERO; MAX_AUDIO_BANDS / 4],
+            level: 0.,
+            beat_time: f32::MAX,
+            beat_count: 0.,
+            band_count: 0.,
+        };
+        Self {
+            source,
+            block: UniformBuffer::new(binding, block),
+            spectrum: Texture::from_raw(create_spectrum_texture(), TextureTarget::Texture1D),
+            spectrum_size: 1,
+            last_beat: None,
+        }
+    }
+
+    pub fn get_binding(&self) -> u32 {
+        self.block.get_binding()
+    }
+
+    pub fn get_spectrum(&self) -> &Texture {
+        &self.spectrum
+    }
+
+    pub fn get_level(&self) -> f32 {
+        self.block.get().level
+    }
+
+    pub fn get_beat_count(&self) -> u32 {
+        self.block.get().beat_count as u32
+    }
+
+    /// Polls the source and updates the uniform block and spectrum texture
+    pub fn update(&mut self, gl_state: &mut GlStateManager) {
+        let now = Instant::now();
+        if let Some(frame) = self.source.poll() {
+            let block = self.block.get_mut();
+            block.bands = [Vec4::ZERO; MAX_AUDIO_BANDS / 4];
+            for (i, band) in frame.bands.iter().take(MAX_AUDIO_BANDS).enumerate() {
+                block.bands[i / 4][i % 4] = *band;
+            }
+            block.band_count = frame.bands.len().min(MAX_AUDIO_BANDS) as f32;
+            block.level = frame.level;
+            if frame.beat {
+                block.beat_count += 1.;
+                self.last_beat = Some(now);
+            }
+
+            if !frame.bands.is_empty() {
+                self.spectrum.bind(gl_state, 0);
+                unsafe {
+                    if frame.bands.len() == self.spectrum_size {
+                        gl::TexSubImage1D(gl::TEXTURE_1D, 0, 0, frame.bands.len() as i32, gl::RED, gl::FLOAT, frame.bands.as_ptr() as *const _);
+                    } else {
+                        gl::TexImage1D(gl::TEXTURE_1D, 0, gl::R32F as i32, frame.bands.len() as i32, 0, gl::RED, gl::FLOAT, frame.bands.as_ptr() as *const _);
+                        self.spectrum_size = frame.bands.len();
+                    }
+                }
+            }
+        }
+        // seconds since the last beat, so shaders can animate pulses without their own clock
+        let beat_time = self.last_beat.map_or(f32::MAX, |beat| (now - beat).as_secs_f32());
+        self.block.get_mut().beat_time = beat_time;
+        self.block.bind(gl_state);
+    }
+
+    /// Binds the uniform block and the spectrum texture to `slot`
+    pub fn bind(&mut self, gl_state: &mut GlStateManager, slot: u32) {
+        self.block.bind(gl_state);
+        self.spectrum.bind(gl_state, slot);
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        self.block.destroy(gl_state);
+        self.spectrum.destroy(gl_state);
+    }
+}
+
+fn create_spectrum_texture() -> gl::types::GLuint {
+    unsafe {
+        let mut tex_id = 0;
+        gl::GenTextures(1, &mut tex_id);
+        gl::BindTexture(gl::TEXTURE_1D, tex_id);
+        gl::TexParameteri(gl::TEXTURE_1D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_1D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_1D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        let empty = 0f32;
+        gl::TexImage1D(gl::TEXTURE_1D, 0, gl::R32F as i32, 1, 0, gl::RED, gl::FLOAT, &empty as *const f32 as *const _);
+        tex_id
+    }
+}
@@ -0,0 +1,258 @@
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::thread::panicking;
+use std::{ptr, slice};
+
+use anyhow::Result;
+use gl::types::{GLbitfield, GLuint};
+
+use crate::data::GlStateManager;
+use crate::errors::BufferError;
+
+/// A gl uniform buffer holding a single `T`.
+///
+/// `T` must be `#[repr(C)]` and laid out to match the std140 block it's read as, std140 aligns
+/// vec3 and vec4 to 16 bytes, so pad vec3 fields and use `glam::Vec4`/`glam::Mat4` where possible.
+///
+/// Bind a program's block to the same binding point with `shader::bind_uniform_block`.
+#[derive(Debug)]
+pub struct UniformBuffer<T: Copy> {
+    data: T,
+    binding: u32,
+    buffer: GLuint,
+    dirty: bool,
+    freed: bool,
+}
+
+impl<T: Copy> UniformBuffer<T> {
+    pub fn new(binding: u32, data: T) -> Self {
+        debug_assert!(size_of::<T>().is_multiple_of(16), "std140 uniform blocks are padded to a multiple of 16 bytes");
+
+        unsafe {
+            let mut buffer = 0;
+            gl::GenBuffers(1, &mut buffer);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, buffer);
+            gl::BufferData(gl::UNIFORM_BUFFER, size_of::<T>() as isize, &data as *const T as *const c_void, gl::DYNAMIC_DRAW);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+
+            Self {
+                data,
+                binding,
+                buffer,
+                dirty: false,
+                freed: false,
+            }
+        }
+    }
+
+    pub fn get_id(&self) -> GLuint {
+        self.buffer
+    }
+
+    pub fn get_binding(&self) -> u32 {
+        self.binding
+    }
+
+    pub fn get(&self) -> &T {
+        &self.data
+    }
+
+    /// Gives mutable access to the data, it is uploaded on the next bind
+    pub fn get_mut(&mut self) -> &mut T {
+        self.dirty = true;
+        &mut self.data
+    }
+
+    /// Replaces the data, it is uploaded on the next bind
+    pub fn set(&mut self, data: T) {
+        self.data = data;
+        self.dirty = true;
+    }
+
+    /// Uploads the data if it changed and binds the buffer to its binding point
+    pub fn bind(&mut self, gl_state: &mut GlStateManager) {
+        if self.dirty {
+            self.dirty = false;
+            unsafe {
+                gl::BindBuffer(gl::UNIFORM_BUFFER, self.buffer);
+                gl::BufferSubData(gl::UNIFORM_BUFFER, 0, size_of::<T>() as isize, &self.data as *const T as *const c_void);
+                gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+            }
+        }
+        gl_state.bind_uniform_buffer(self.binding, self.buffer);
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        gl_state.destroy_uniform_buffer(self.buffer);
+        self.freed = true;
+    }
+}
+
+impl<T: Copy> Drop for UniformBuffer<T> {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            let mut state = GlStateManager::new();
+            self.destroy(&mut state);
+            panic!("Uniform buffer was not destroyed before dropping")
+        }
+    }
+}
+
+/// Access a mapped StorageBuffer is opened with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl MapAccess {
+    pub fn to_gl(&self) -> GLbitfield {
+        match self {
+            Self::Read => gl::MAP_READ_BIT,
+            // the previous contents are discarded since they can't be read anyway
+            Self::Write => gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_RANGE_BIT,
+            Self::ReadWrite => gl::MAP_READ_BIT | gl::MAP_WRITE_BIT,
+        }
+    }
+}
+
+/// A gl shader storage buffer holding an array of `T`.
+///
+/// `T` must be `#[repr(C)]` and laid out to match the std430 struct it's read as, std430 aligns
+/// vec3 and vec4 to 16 bytes.
+#[derive(Debug)]
+pub struct StorageBuffer<T: Copy> {
+    binding: u32,
+    buffer: GLuint,
+    len: usize,
+    capacity: usize,
+    freed: bool,
+    _implicit: PhantomData<T>,
+}
+
+impl<T: Copy> StorageBuffer<T> {
+    pub fn new(binding: u32, data: &[T]) -> Self {
+        unsafe {
+            let mut buffer = 0;
+            gl::GenBuffers(1, &mut buffer);
+
+            let mut storage = Self {
+                binding,
+                buffer,
+                len: 0,
+                capacity: 0,
+                freed: false,
+                _implicit: PhantomData,
+            };
+            storage.upload(data);
+            storage
+        }
+    }
+
+    /// Creates a zeroed buffer with room for `len` elements, for buffers written by shaders
+    pub fn with_len(binding: u32, len: usize) -> Self {
+        let mut storage = Self::new(binding, &[]);
+        unsafe {
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, storage.buffer);
+            gl::BufferData(gl::SHADER_STORAGE_BUFFER, (len * size_of::<T>()) as isize, ptr::null(), gl::DYNAMIC_DRAW);
+            gl::ClearBufferData(gl::SHADER_STORAGE_BUFFER, gl::R8, gl::RED, gl::UNSIGNED_BYTE, ptr::null());
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+        }
+        storage.len = len;
+        storage.capacity = len;
+        storage
+    }
+
+    pub fn get_id(&self) -> GLuint {
+        self.buffer
+    }
+
+    pub fn get_binding(&self) -> u32 {
+        self.binding
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Replaces the contents of the buffer, only reallocating when `data` doesn't fit
+    pub fn upload(&mut self, data: &[T]) {
+        unsafe {
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.buffer);
+            if data.len() > self.capacity || self.capacity == 0 {
+                gl::BufferData(gl::SHADER_STORAGE_BUFFER, size_of_val(data) as isize, data.as_ptr() as *const c_void, gl::DYNAMIC_DRAW);
+                self.capacity = data.len();
+            } else {
+                gl::BufferSubData(gl::SHADER_STORAGE_BUFFER, 0, size_of_val(data) as isize, data.as_ptr() as *const c_void);
+            }
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+        }
+        self.len = data.len();
+    }
+
+    /// Overwrites part of the buffer starting at element `offset`
+    pub fn update(&mut self, offset: usize, data: &[T]) -> Result<()> {
+        if offset + data.len() > self.len {
+            return Err(BufferError::OutOfRange { offset, count: data.len(), len: self.len }.into());
+        }
+        unsafe {
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.buffer);
+            gl::BufferSubData(gl::SHADER_STORAGE_BUFFER, (offset * size_of::<T>()) as isize, size_of_val(data) as isize, data.as_ptr() as *const c_void);
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+        }
+        Ok(())
+    }
+
+    /// Maps the buffer into memory and passes its contents to f.
+    /// Writes made by f are visible to the gpu once f returns.
+    pub fn map<R>(&mut self, access: MapAccess, f: impl FnOnce(&mut [T]) -> R) -> Result<R> {
+        if self.len == 0 {
+            return Ok(f(&mut []));
+        }
+        unsafe {
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.buffer);
+            let ptr = gl::MapBufferRange(gl::SHADER_STORAGE_BUFFER, 0, (self.len * size_of::<T>()) as isize, access.to_gl()) as *mut T;
+            if ptr.is_null() {
+                gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+                return Err(BufferError::MapFailed.into());
+            }
+
+            let res = f(slice::from_raw_parts_mut(ptr, self.len));
+
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.buffer);
+            gl::UnmapBuffer(gl::SHADER_STORAGE_BUFFER);
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+            Ok(res)
+        }
+    }
+
+    /// Reads the buffer back from the gpu
+    pub fn read(&mut self) -> Result<Vec<T>> {
+        self.map(MapAccess::Read, |data| data.to_vec())
+    }
+
+    /// Binds the buffer to its binding point
+    pub fn bind(&self, gl_state: &mut GlStateManager) {
+        gl_state.bind_storage_buffer(self.binding, self.buffer);
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        gl_state.destroy_storage_buffer(self.buffer);
+        self.freed = true;
+    }
+}
+
+impl<T: Copy> Drop for StorageBuffer<T> {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            let mut state = GlStateManager::new();
+            self.destroy(&mut state);
+            panic!("Storage buffer was not destroyed before dropping")
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::ffi::CStr;
+use std::sync::OnceLock;
+
+use gl::types::{GLint, GLuint};
+
+use crate::texture::CompressedFormat;
+
+/// Limits and features of the context that shader sources adapt to through the defines injected by
+/// compile_shader, see shader_capabilities
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderCapabilities {
+    /// e.g. 330 for GLSL 3.30
+    pub glsl_version: u32,
+    /// the context speaks GLSL ES (OpenGL ES and WebGL)
+    pub es: bool,
+    /// texture units available to a single shader stage
+    pub max_texture_units: i32,
+    /// bindless textures are loaded and supported, see load_bindless_support
+    pub bindless: bool,
+}
+
+static SHADER_CAPABILITIES: OnceLock<ShaderCapabilities> = OnceLock::new();
+
+/// Checks the extension list of the current context for an extension, e.g. "GL_ARB_gl_spirv"
+pub fn has_extension(name: &str) -> bool {
+    unsafe {
+        let mut count: GLint = 0;
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+        for i in 0..count {
+            let ext = gl::GetStringi(gl::EXTENSIONS, i as GLuint);
+            if !ext.is_null() && CStr::from_ptr(ext as *const _).to_bytes() == name.as_bytes() {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// # Returns
+/// "vendor / renderer / version" of the current context, identifying the driver
+pub fn driver_string() -> String {
+    [gl::VENDOR, gl::RENDERER, gl::VERSION]
+        .map(|name| unsafe {
+            let s = gl::GetString(name);
+            if s.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(s as *const _).to_string_lossy().into_owned()
+            }
+        })
+        .join(" / ")
+}
+
+/// # Returns
+/// the compressed texture formats the current context can upload, from the driver's format list
+/// and the S3TC extension (BC1 to BC3 aren't core gl)
+pub fn supported_compressed_formats() -> Vec<CompressedFormat> {
+    let mut formats: Vec<GLint> = Vec::new();
+    unsafe {
+        let mut count: GLint = 0;
+        gl::GetIntegerv(gl::NUM_COMPRESSED_TEXTURE_FORMATS, &mut count);
+        formats.resize(count.max(0) as usize, 0);
+        if count > 0 {
+            gl::GetIntegerv(gl::COMPRESSED_TEXTURE_FORMATS, formats.as_mut_ptr());
+        }
+    }
+    let s3tc = has_extension("GL_EXT_texture_compression_s3tc");
+
+    CompressedFormat::ALL.into_iter()
+        .filter(|format| {
+            let s3tc_format = matches!(format, CompressedFormat::Bc1Rgb | CompressedFormat::Bc1Rgba | CompressedFormat::Bc2 | CompressedFormat::Bc3);
+            (s3tc_format && s3tc) || formats.contains(&(format.to_gl() as GLint))
+        })
+        .collect()
+}
+
+/// Whether the current context can upload textures in `format`
+pub fn supports_compressed_format(format: CompressedFormat) -> bool {
+    supported_compressed_formats().contains(&format)
+}
+
+impl ShaderCapabilities {
+    /// Queries the current context. Without a context this describes plain GLSL 3.30 core.
+    pub fn query() -> Self {
+        let (glsl_version, es) = unsafe {
+            let s = gl::GetString(gl::SHADING_LANGUAGE_VERSION);
+            if s.is_null() {
+                (330, false)
+            } else {
+                parse_glsl_version(&CStr::from_ptr(s as *const _).to_string_lossy())
+            }
+        };
+        let mut max_texture_units: GLint = 16;
+        unsafe {
+            gl::GetIntegerv(gl::MAX_TEXTURE_IMAGE_UNITS, &mut max_texture_units);
+        }
+        #[cfg(feature = "bindless-textures")]
+        let bindless = crate::texture::bindless_supported();
+        #[cfg(not(feature = "bindless-textures"))]
+        let bindless = false;
+
+        Self {
+            glsl_version,
+            es,
+            max_texture_units,
+            bindless,
+        }
+    }
+
+    /// # Returns
+    /// the `#version` directive for the context's shading language, with a default float
+    /// precision for GLSL ES
+    pub fn version_header(&self) -> String {
+        match (self.es, self.glsl_version) {
+            (true, version) => format!("#version {version} es\nprecision highp float;\n"),
+            (false, version) if version >= 150 => format!("#version {version} core\n"),
+            (false, version) => format!("#version {version}\n"),
+        }
+    }
+
+    /// # Returns
+    /// `MAX_TEXTURE_UNITS <n>`, and `HAS_BINDLESS` when bindless textures are supported, for
+    /// inject_defines. GLSL already defines `__VERSION__` and, for GLSL ES, `GL_ES`.
+    pub fn defines(&self) -> Vec<String> {
+        let mut defines = vec![format!("MAX_TEXTURE_UNITS {}", self.max_texture_units)];
+        if self.bindless {
+            defines.push("HAS_BINDLESS".to_string());
+        }
+        defines
+    }
+}
+
+/// # Returns
+/// the capabilities of the context that was current at the first call, queried once since shaders
+/// are compiled often. Load bindless support before compiling the first shader.
+pub fn shader_capabilities() -> &'static ShaderCapabilities {
+    SHADER_CAPABILITIES.get_or_init(ShaderCapabilities::query)
+}
+
+/// Parses a GL_SHADING_LANGUAGE_VERSION string like "4.60 NVIDIA" or "OpenGL ES GLSL ES 3.00"
+///
+/// # Returns
+/// (version, whether it's GLSL ES)
+fn parse_glsl_version(version: &str) -> (u32, bool) {
+    let es = version.contains(" ES");
+    let number = version.split_whitespace()
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))
+        .and_then(|word| {
+            let (major, minor) = word.split_once('.')?;
+            let minor: String = minor.chars().take_while(char::is_ascii_digit).collect();
+            // "4.6" means 4.60
+            let minor = format!("{minor:0<2}");
+            Some(major.parse::<u32>().ok()? * 100 + minor[..2].parse::<u32>().ok()?)
+        });
+    (number.unwrap_or(if es { 300 } else { 330 }), es)
+}
@@ -0,0 +1,379 @@
+use glam::{Vec2, Vec3};
+
+/// Distance within which a point counts as lying on a splitting plane
+const EPSILON: f32 = 1e-5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CsgVertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub uv: Vec2,
+}
+
+impl CsgVertex {
+    fn lerp(&self, other: &CsgVertex, t: f32) -> CsgVertex {
+        CsgVertex {
+            position: self.position.lerp(other.position, t),
+            normal: self.normal.lerp(other.normal, t).normalize_or_zero(),
+            uv: self.uv.lerp(other.uv, t),
+        }
+    }
+
+    fn flip(&mut self) {
+        self.normal = -self.normal;
+    }
+}
+
+/// Indexed triangle mesh for boolean operations. Meshes should be closed (watertight) for
+/// union/subtract/intersect to give sensible results.
+#[derive(Debug, Clone, Default)]
+pub struct CsgMesh {
+    pub vertices: Vec<CsgVertex>,
+    /// three indices per triangle, counter clockwise when seen from outside
+    pub indices: Vec<u32>,
+}
+
+impl CsgMesh {
+    pub fn new(vertices: Vec<CsgVertex>, indices: Vec<u32>) -> Self {
+        Self { vertices, indices }
+    }
+
+    /// Axis aligned box with per-face normals and uvs
+    pub fn cuboid(center: Vec3, half_extents: Vec3) -> Self {
+        let mut mesh = CsgMesh::default();
+        for axis in 0..3 {
+            for sign in [-1f32, 1.] {
+                let mut normal = Vec3::ZERO;
+                normal[axis] = sign;
+                let u = Vec3::from_array(std::array::from_fn(|i| if i == (axis + 1) % 3 { 1. } else { 0. }));
+                let v = normal.cross(u);
+                let base = mesh.vertices.len() as u32;
+                for (du, dv) in [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)] {
+                    mesh.vertices.push(CsgVertex {
+                        position: center + (normal + u * du + v * dv) * half_extents,
+                        normal,
+                        uv: Vec2::new(du * 0.5 + 0.5, dv * 0.5 + 0.5),
+                    });
+                }
+                mesh.indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+            }
+        }
+        mesh
+    }
+
+    /// UV sphere with `segments` divisions around and `rings` divisions from pole to pole
+    pub fn sphere(center: Vec3, radius: f32, segments: u32, rings: u32) -> Self {
+        let (segments, rings) = (segments.max(3), rings.max(2));
+        let mut mesh = CsgMesh::default();
+        for ring in 0..=rings {
+            let v = ring as f32 / rings as f32;
+            let theta = v * std::f32::consts::PI;
+            for segment in 0..=segments {
+                let u = segment as f32 / segments as f32;
+                let phi = u * std::f32::consts::TAU;
+                let normal = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                mesh.vertices.push(CsgVertex { position: center + normal * radius, normal, uv: Vec2::new(u, v) });
+            }
+        }
+        let row = segments + 1;
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let a = ring * row + segment;
+                let b = a + row;
+                if ring != 0 {
+                    mesh.indices.extend([a, a + 1, b]);
+                }
+                if ring != rings - 1 {
+                    mesh.indices.extend([a + 1, b + 1, b]);
+                }
+            }
+        }
+        mesh
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Space inside either mesh
+    pub fn union(&self, other: &CsgMesh) -> CsgMesh {
+        let mut a = Node::new(self.to_polygons());
+        let mut b = Node::new(other.to_polygons());
+        a.clip_to(&b);
+        b.clip_to(&a);
+        b.invert();
+        b.clip_to(&a);
+        b.invert();
+        a.build(b.all_polygons());
+        CsgMesh::from_polygons(a.all_polygons())
+    }
+
+    /// Space inside this mesh but not `other`
+    pub fn subtract(&self, other: &CsgMesh) -> CsgMesh {
+        let mut a = Node::new(self.to_polygons());
+        let mut b = Node::new(other.to_polygons());
+        a.invert();
+        a.clip_to(&b);
+        b.clip_to(&a);
+        b.invert();
+        b.clip_to(&a);
+        b.invert();
+        a.build(b.all_polygons());
+        a.invert();
+        CsgMesh::from_polygons(a.all_polygons())
+    }
+
+    /// Space inside both meshes
+    pub fn intersect(&self, other: &CsgMesh) -> CsgMesh {
+        let mut a = Node::new(self.to_polygons());
+        let mut b = Node::new(other.to_polygons());
+        a.invert();
+        b.clip_to(&a);
+        b.invert();
+        a.clip_to(&b);
+        b.clip_to(&a);
+        a.build(b.all_polygons());
+        a.invert();
+        CsgMesh::from_polygons(a.all_polygons())
+    }
+
+    /// Writes the triangles unindexed in the standard mesh layout (position, normal, uv), as
+    /// InstancedMeshData::write_mesh expects. Returns the vertex count to create the mesh with.
+    pub fn write_standard_mesh(&self, buffer: &mut Vec<f32>) -> u32 {
+        for index in &self.indices {
+            let vertex = &self.vertices[*index as usize];
+            buffer.extend_from_slice(&vertex.position.to_array());
+            buffer.extend_from_slice(&vertex.normal.to_array());
+            buffer.extend_from_slice(&vertex.uv.to_array());
+        }
+        self.indices.len() as u32
+    }
+
+    fn to_polygons(&self) -> Vec<Polygon> {
+        self.indices
+            .chunks_exact(3)
+            .filter_map(|tri| Polygon::new(tri.iter().map(|i| self.vertices[*i as usize]).collect()))
+            .collect()
+    }
+
+    fn from_polygons(polygons: Vec<Polygon>) -> CsgMesh {
+        let mut mesh = CsgMesh::default();
+        for polygon in polygons {
+            let base = mesh.vertices.len() as u32;
+            let count = polygon.vertices.len() as u32;
+            mesh.vertices.extend(polygon.vertices);
+            for i in 1..count - 1 {
+                mesh.indices.extend([base, base + i, base + i + 1]);
+            }
+        }
+        mesh
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Plane {
+    normal: Vec3,
+    w: f32,
+}
+
+impl Plane {
+    fn from_points(a: Vec3, b: Vec3, c: Vec3) -> Option<Plane> {
+        let normal = (b - a).cross(c - a).try_normalize()?;
+        Some(Plane { normal, w: normal.dot(a) })
+    }
+
+    fn flip(&mut self) {
+        self.normal = -self.normal;
+        self.w = -self.w;
+    }
+
+    /// Sorts `polygon` into the lists by which side of the plane it is on, splitting it if it spans the plane
+    fn split_polygon(
+        &self,
+        polygon: &Polygon,
+        coplanar_front: &mut Vec<Polygon>,
+        coplanar_back: &mut Vec<Polygon>,
+        front: &mut Vec<Polygon>,
+        back: &mut Vec<Polygon>,
+    ) {
+        const COPLANAR: u8 = 0;
+        const FRONT: u8 = 1;
+        const BACK: u8 = 2;
+        const SPANNING: u8 = 3;
+
+        let mut polygon_type = COPLANAR;
+        let types: Vec<u8> = polygon
+            .vertices
+            .iter()
+            .map(|v| {
+                let t = self.normal.dot(v.position) - self.w;
+                let vertex_type = if t < -EPSILON { BACK } else if t > EPSILON { FRONT } else { COPLANAR };
+                polygon_type |= vertex_type;
+                vertex_type
+            })
+            .collect();
+
+        match polygon_type {
+            COPLANAR => {
+                if self.normal.dot(polygon.plane.normal) > 0. {
+                    coplanar_front.push(polygon.clone());
+                } else {
+                    coplanar_back.push(polygon.clone());
+                }
+            }
+            FRONT => front.push(polygon.clone()),
+            BACK => back.push(polygon.clone()),
+            _ => {
+                let mut f = Vec::new();
+                let mut b = Vec::new();
+                let count = polygon.vertices.len();
+                for i in 0..count {
+                    let j = (i + 1) % count;
+                    let (ti, tj) = (types[i], types[j]);
+                    let (vi, vj) = (&polygon.vertices[i], &polygon.vertices[j]);
+                    if ti != BACK {
+                        f.push(*vi);
+                    }
+                    if ti != FRONT {
+                        b.push(*vi);
+                    }
+                    if (ti | tj) == SPANNING {
+                        let t = (self.w - self.normal.dot(vi.position)) / self.normal.dot(vj.position - vi.position);
+                        let v = vi.lerp(vj, t);
+                        f.push(v);
+                        b.push(v);
+                    }
+                }
+                if f.len() >= 3 {
+                    front.push(Polygon { vertices: f, plane: polygon.plane });
+                }
+                if b.len() >= 3 {
+                    back.push(Polygon { vertices: b, plane: polygon.plane });
+                }
+            }
+        }
+    }
+}
+
+/// Convex, planar polygon
+#[derive(Debug, Clone)]
+struct Polygon {
+    vertices: Vec<CsgVertex>,
+    plane: Plane,
+}
+
+impl Polygon {
+    /// None for degenerate (zero area) polygons
+    fn new(vertices: Vec<CsgVertex>) -> Option<Polygon> {
+        let plane = Plane::from_points(vertices[0].position, vertices[1].position, vertices[2].position)?;
+        Some(Polygon { vertices, plane })
+    }
+
+    fn flip(&mut self) {
+        self.vertices.reverse();
+        self.vertices.iter_mut().for_each(CsgVertex::flip);
+        self.plane.flip();
+    }
+}
+
+/// BSP tree node, every polygon is stored in the node whose plane it lies on
+#[derive(Debug, Default)]
+struct Node {
+    plane: Option<Plane>,
+    front: Option<Box<Node>>,
+    back: Option<Box<Node>>,
+    polygons: Vec<Polygon>,
+}
+
+impl Node {
+    fn new(polygons: Vec<Polygon>) -> Node {
+        let mut node = Node::default();
+        node.build(polygons);
+        node
+    }
+
+    /// Turns solid space into empty space and back
+    fn invert(&mut self) {
+        self.polygons.iter_mut().for_each(Polygon::flip);
+        if let Some(plane) = &mut self.plane {
+            plane.flip();
+        }
+        if let Some(front) = &mut self.front {
+            front.invert();
+        }
+        if let Some(back) = &mut self.back {
+            back.invert();
+        }
+        std::mem::swap(&mut self.front, &mut self.back);
+    }
+
+    /// Removes the parts of `polygons` inside this tree
+    fn clip_polygons(&self, polygons: Vec<Polygon>) -> Vec<Polygon> {
+        let Some(plane) = self.plane else {
+            return polygons;
+        };
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        for polygon in &polygons {
+            let mut coplanar_front = Vec::new();
+            let mut coplanar_back = Vec::new();
+            plane.split_polygon(polygon, &mut coplanar_front, &mut coplanar_back, &mut front, &mut back);
+            front.append(&mut coplanar_front);
+            back.append(&mut coplanar_back);
+        }
+        let mut front = match &self.front {
+            Some(node) => node.clip_polygons(front),
+            None => front,
+        };
+        let back = match &self.back {
+            Some(node) => node.clip_polygons(back),
+            None => Vec::new(),
+        };
+        front.extend(back);
+        front
+    }
+
+    /// Removes the parts of this tree's polygons inside `other`
+    fn clip_to(&mut self, other: &Node) {
+        self.polygons = other.clip_polygons(std::mem::take(&mut self.polygons));
+        if let Some(front) = &mut self.front {
+            front.clip_to(other);
+        }
+        if let Some(back) = &mut self.back {
+            back.clip_to(other);
+        }
+    }
+
+    fn all_polygons(&self) -> Vec<Polygon> {
+        let mut polygons = self.polygons.clone();
+        if let Some(front) = &self.front {
+            polygons.extend(front.all_polygons());
+        }
+        if let Some(back) = &self.back {
+            polygons.extend(back.all_polygons());
+        }
+        polygons
+    }
+
+    fn build(&mut self, polygons: Vec<Polygon>) {
+        if polygons.is_empty() {
+            return;
+        }
+        let plane = *self.plane.get_or_insert(polygons[0].plane);
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        for polygon in &polygons {
+            let mut coplanar_front = Vec::new();
+            let mut coplanar_back = Vec::new();
+            plane.split_polygon(polygon, &mut coplanar_front, &mut coplanar_back, &mut front, &mut back);
+            self.polygons.append(&mut coplanar_front);
+            self.polygons.append(&mut coplanar_back);
+        }
+        if !front.is_empty() {
+            self.front.get_or_insert_with(Default::default).build(front);
+        }
+        if !back.is_empty() {
+            self.back.get_or_insert_with(Default::default).build(back);
+        }
+    }
+}
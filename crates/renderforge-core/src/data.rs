@@ -1,11 +1,19 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::ffi::CString;
+use std::mem;
 use std::ops::{AddAssign, MulAssign};
+use std::ptr;
 use std::rc::Rc;
+use std::sync::Mutex;
 use delegate::delegate;
-use gl::types::{GLboolean, GLenum, GLint, GLsizei, GLuint};
-use glam::{IVec2, IVec3, IVec4, Mat4, Quat, Vec2, Vec3, Vec4};
+use gl::types::{GLboolean, GLenum, GLint, GLsizei, GLsizeiptr, GLuint};
+use glam::{IVec2, IVec3, IVec4, Mat3, Mat4, Quat, UVec2, UVec3, UVec4, Vec2, Vec3, Vec4};
+use image::RgbaImage;
+use serde::Deserialize;
+use crate::frame::{FrameQueue, FrameStats, GlObject, PendingReadback};
+use crate::texture::{Sampler, SamplerDescriptor};
+use crate::window::ContextId;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct MatrixStack {
@@ -45,6 +53,65 @@ impl MatrixStack {
     pub fn get_transform(&self) -> &Mat4 {
         &self.current
     }
+
+    /// Creates a stack starting at `transform` instead of the identity
+    pub fn from_transform(transform: Mat4) -> Self {
+        Self {
+            stack: Vec::new(),
+            current: transform,
+        }
+    }
+
+    /// Starts a new stack at the current transform without the pushed history, so a subtree can
+    /// be traversed on another thread. Popping past the fork panics like popping an empty stack.
+    pub fn fork(&self) -> Self {
+        Self::from_transform(self.current)
+    }
+
+    /// Clears the stack and sets the current transform, keeping the allocation
+    pub fn reset(&mut self, transform: Mat4) {
+        self.stack.clear();
+        self.current = transform;
+    }
+
+    /// # Returns
+    /// how many pushes haven't been popped yet
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+}
+
+/// Recycles MatrixStacks between parallel scene traversal jobs. The pool is shared by reference,
+/// each job acquires a stack rooted at its subtree's transform and releases it when done, so
+/// jobs don't serialize on one stack or reallocate every frame.
+#[derive(Debug, Default)]
+pub struct MatrixStackPool {
+    stacks: Mutex<Vec<MatrixStack>>,
+}
+
+impl MatrixStackPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Returns
+    /// a pooled stack, or a new one if the pool is empty, starting at `transform`
+    pub fn acquire(&self, transform: Mat4) -> MatrixStack {
+        let mut stack = self.stacks.lock().unwrap().pop().unwrap_or_default();
+        stack.reset(transform);
+        stack
+    }
+
+    /// Returns a stack to the pool for later acquire calls
+    pub fn release(&self, stack: MatrixStack) {
+        self.stacks.lock().unwrap().push(stack);
+    }
+
+    /// # Returns
+    /// how many stacks are waiting to be reused
+    pub fn get_pooled_count(&self) -> usize {
+        self.stacks.lock().unwrap().len()
+    }
 }
 
 impl MulAssign<Mat4> for MatrixStack {
@@ -126,7 +193,8 @@ impl Color {
 }
 
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DepthFunc {
     Never,
     Less,
@@ -137,7 +205,8 @@ pub enum DepthFunc {
     NotEqual,
     Always,
 }
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CullFace {
     Back,
     Front,
@@ -195,6 +264,21 @@ pub enum AlphaEquation {
     Min,
     Max,
 }
+/// Common blend_func/blend_equation combinations, applied with GlState::blend_mode
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlendMode {
+    /// Standard alpha blending for non-premultiplied colors
+    Alpha,
+    /// Alpha blending for colors already multiplied by their alpha
+    Premultiplied,
+    Additive,
+    Multiply,
+    Screen,
+    /// Subtracts the source color from the destination
+    Subtract,
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum StencilFunc {
     Never,
@@ -314,6 +398,22 @@ impl AlphaEquation {
     }
 }
 
+impl BlendMode {
+    /// # Returns
+    /// (src_rgb, src_alpha, dst_rgb, dst_alpha, rgb_equation, alpha_equation)
+    pub fn factors(&self) -> (SrcRgb, SrcAlpha, DstRgb, DstAlpha, RgbEquation, AlphaEquation) {
+        use BlendFactor::*;
+        match self {
+            BlendMode::Alpha => (SrcRgb::Factor(SrcAlpha), One, OneMinusSrcAlpha, OneMinusSrcAlpha, RgbEquation::Add, AlphaEquation::Add),
+            BlendMode::Premultiplied => (SrcRgb::Factor(One), One, OneMinusSrcAlpha, OneMinusSrcAlpha, RgbEquation::Add, AlphaEquation::Add),
+            BlendMode::Additive => (SrcRgb::Factor(SrcAlpha), One, One, One, RgbEquation::Add, AlphaEquation::Add),
+            BlendMode::Multiply => (SrcRgb::Factor(DstColor), One, Zero, OneMinusSrcAlpha, RgbEquation::Add, AlphaEquation::Add),
+            BlendMode::Screen => (SrcRgb::Factor(One), One, OneMinusSrcColor, OneMinusSrcAlpha, RgbEquation::Add, AlphaEquation::Add),
+            BlendMode::Subtract => (SrcRgb::Factor(SrcAlpha), Zero, One, One, RgbEquation::ReverseSubtract, AlphaEquation::Add),
+        }
+    }
+}
+
 impl StencilFunc {
     pub fn to_gl(&self) -> GLenum {
         match self {
@@ -394,23 +494,45 @@ pub struct RasterState {
     pub scissor_test: bool,
     pub scissor_box: [i32; 4],
     pub viewport: [i32; 4],
+    /// (r, g, b, a) write mask
+    pub color_mask: [bool; 4],
+    /// whether writes to sRGB framebuffers are encoded from linear
+    pub framebuffer_srgb: bool,
+    /// whether vertex shaders set the size of points through gl_PointSize
+    pub program_point_size: bool,
 }
 #[derive(Debug, Clone)]
 pub struct SamplerState {
-
+    /// sampler object bound to each texture unit, units without an entry have no sampler bound
+    pub bound: HashMap<u32, GLuint>,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum GLUniform {
+    Mat3(Mat3),
     Mat4(Mat4),
     F32(f32),
     Vec2(Vec2),
     Vec3(Vec3),
     Vec4(Vec4),
+    /// uploaded as an int, for `bool` uniforms
+    Bool(bool),
     I32(i32),
     IVec2(IVec2),
     IVec3(IVec3),
     IVec4(IVec4),
+    U32(u32),
+    UVec2(UVec2),
+    UVec3(UVec3),
+    UVec4(UVec4),
+    /// A bindless texture handle, uploaded as a `uvec2` (low bits, high bits). Convert it in GLSL
+    /// with e.g. `sampler2D(u_handle)` (requires GL_ARB_bindless_texture).
+    TextureHandle(u64),
+    /// Uploaded starting at the uniform's location, set the array uniform by its base name
+    Mat4Array(Vec<Mat4>),
+    F32Array(Vec<f32>),
+    Vec4Array(Vec<Vec4>),
+    I32Array(Vec<i32>),
 }
 impl GLUniform {
     /// # Safety
@@ -418,6 +540,10 @@ impl GLUniform {
     pub unsafe fn upload(&self, loc: GLint) {
         unsafe {
             match self {
+                GLUniform::Mat3(m) => {
+                    let mat = m.to_cols_array();
+                    gl::UniformMatrix3fv(loc, 1, gl::FALSE, mat.as_ptr());
+                }
                 GLUniform::Mat4(m) => {
                     let mat = m.to_cols_array();
                     gl::UniformMatrix4fv(loc, 1, gl::FALSE, mat.as_ptr());
@@ -434,6 +560,9 @@ impl GLUniform {
                 GLUniform::Vec4(v) => {
                     gl::Uniform4f(loc, v.x, v.y, v.z, v.w);
                 }
+                GLUniform::Bool(b) => {
+                    gl::Uniform1i(loc, *b as i32);
+                }
                 GLUniform::I32(i) => {
                     gl::Uniform1i(loc, *i);
                 }
@@ -446,11 +575,54 @@ impl GLUniform {
                 GLUniform::IVec4(v) => {
                     gl::Uniform4i(loc, v.x, v.y, v.z, v.w);
                 }
+                GLUniform::U32(u) => {
+                    gl::Uniform1ui(loc, *u);
+                }
+                GLUniform::UVec2(v) => {
+                    gl::Uniform2ui(loc, v.x, v.y);
+                }
+                GLUniform::UVec3(v) => {
+                    gl::Uniform3ui(loc, v.x, v.y, v.z);
+                }
+                GLUniform::UVec4(v) => {
+                    gl::Uniform4ui(loc, v.x, v.y, v.z, v.w);
+                }
+                GLUniform::TextureHandle(handle) => {
+                    gl::Uniform2ui(loc, *handle as u32, (*handle >> 32) as u32);
+                }
+                GLUniform::Mat4Array(mats) => {
+                    // Mat4 is 16 contiguous column major f32s
+                    gl::UniformMatrix4fv(loc, mats.len() as GLsizei, gl::FALSE, mats.as_ptr() as *const f32);
+                }
+                GLUniform::F32Array(values) => {
+                    gl::Uniform1fv(loc, values.len() as GLsizei, values.as_ptr());
+                }
+                GLUniform::Vec4Array(values) => {
+                    gl::Uniform4fv(loc, values.len() as GLsizei, values.as_ptr() as *const f32);
+                }
+                GLUniform::I32Array(values) => {
+                    gl::Uniform1iv(loc, values.len() as GLsizei, values.as_ptr());
+                }
             }
         }
     }
 }
 
+/// A cached uniform location of a program, see GlState::get_uniform_handle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UniformHandle {
+    program: GLuint,
+    location: GLint,
+}
+
+/// Uniform locations and last uploaded values of a program
+#[derive(Debug, Clone, Default)]
+struct ProgramUniforms {
+    locations: HashMap<String, GLint>,
+    /// indexed by location
+    values: Vec<Option<GLUniform>>,
+}
+
 /// Not meant to be hand-constructed, relies on GlStateManager for manipulation and for GlStateSnapshot to work
 #[derive(Debug, Clone)]
 pub struct GlState {
@@ -464,9 +636,21 @@ pub struct GlState {
     vao: GLuint,
     fbo: GLuint,
     program: GLuint,
-    framebuffer: GLuint,
 
-    uniforms: HashMap<GLuint, HashMap<String, GLUniform>>,
+    /// programs are shared between contexts, and so are their uniform values, so every context's
+    /// state shares this cache
+    uniforms: Rc<RefCell<HashMap<GLuint, ProgramUniforms>>>,
+    /// buffer bound to each uniform buffer binding point
+    uniform_buffers: HashMap<u32, GLuint>,
+    /// buffer bound to each shader storage buffer binding point
+    storage_buffers: HashMap<u32, GLuint>,
+
+    /// previous (scissor_test, scissor_box) for every push_scissor
+    scissor_stack: Vec<(bool, [i32; 4])>,
+    /// previous viewport for every push_viewport
+    viewport_stack: Vec<[i32; 4]>,
+
+    stats: FrameStats,
 
 }
 
@@ -477,7 +661,16 @@ pub struct GlStateRef {
 
 #[derive(Debug)]
 pub struct GlStateManager {
-    state: Rc<RefCell<GlState>>
+    /// state of the current context
+    state: Rc<RefCell<GlState>>,
+    /// every context's state, bindings and capabilities are per-context so each gets its own cache
+    contexts: HashMap<ContextId, Rc<RefCell<GlState>>>,
+    context: ContextId,
+    frame: FrameQueue,
+    /// samplers made by get_sampler, shared by every context
+    samplers: HashMap<SamplerDescriptor, Sampler>,
+    /// VAOs of other contexts waiting for their context to be current, see destroy_vao_in
+    context_deletions: HashMap<ContextId, Vec<GLuint>>,
 }
 
 /// Dropping this will reset the GL state to match when it was created
@@ -487,6 +680,22 @@ pub struct GlStateSnapshot {
     true_state: Rc<RefCell<GlState>>
 }
 
+impl UniformHandle {
+    pub fn get_program(&self) -> GLuint {
+        self.program
+    }
+
+    /// # Returns
+    /// the uniform location, -1 if the uniform isn't active in the program
+    pub fn get_location(&self) -> GLint {
+        self.location
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.location >= 0
+    }
+}
+
 impl GlState {
 
     pub fn depth_test(&mut self, enabled: bool) {
@@ -502,6 +711,15 @@ impl GlState {
         }
     }
 
+    pub fn depth_func(&mut self, func: DepthFunc) {
+        if self.depth.func != func {
+            self.depth.func = func;
+            unsafe {
+                gl::DepthFunc(func.to_gl());
+            }
+        }
+    }
+
     pub fn depth_mask(&mut self, enabled: bool) {
         if self.depth.mask != enabled {
             self.depth.mask = enabled;
@@ -565,6 +783,13 @@ impl GlState {
         self.blend_equation(rgb_equation, alpha_equation);
     }
 
+    /// Enables blending and sets the blend function and equation of a preset
+    pub fn blend_mode(&mut self, mode: BlendMode) {
+        let (src_rgb, src_alpha, dst_rgb, dst_alpha, rgb_equation, alpha_equation) = mode.factors();
+        self.blending(true);
+        self.blend_func(src_rgb, src_alpha, dst_rgb, dst_alpha, rgb_equation, alpha_equation);
+    }
+
     pub fn blend_func_separate(&mut self, src_rgb: SrcRgb, src_alpha: SrcAlpha, dst_rgb: DstRgb, dst_alpha: DstAlpha) {
         if self.blend.src_rgb != src_rgb
             || self.blend.src_alpha != src_alpha
@@ -576,7 +801,7 @@ impl GlState {
             self.blend.dst_rgb = dst_rgb;
             self.blend.dst_alpha = dst_alpha;
             unsafe {
-                gl::BlendFuncSeparate(src_rgb.to_gl(), src_alpha.to_gl(), dst_rgb.to_gl(), dst_alpha.to_gl());
+                gl::BlendFuncSeparate(src_rgb.to_gl(), dst_rgb.to_gl(), src_alpha.to_gl(), dst_alpha.to_gl());
             }
         }
     }
@@ -601,9 +826,132 @@ impl GlState {
         }
     }
 
+    pub fn color_mask(&mut self, mask: [bool; 4]) {
+        if self.raster.color_mask != mask {
+            self.raster.color_mask = mask;
+            unsafe {
+                gl::ColorMask(mask[0] as GLboolean, mask[1] as GLboolean, mask[2] as GLboolean, mask[3] as GLboolean);
+            }
+        }
+    }
+
+    /// Enables linear to sRGB encoding when writing to sRGB framebuffers (including an sRGB capable
+    /// default framebuffer), the counterpart of sampling sRGB textures
+    pub fn framebuffer_srgb(&mut self, enabled: bool) {
+        if self.raster.framebuffer_srgb != enabled {
+            self.raster.framebuffer_srgb = enabled;
+            unsafe {
+                if enabled {
+                    gl::Enable(gl::FRAMEBUFFER_SRGB);
+                } else {
+                    gl::Disable(gl::FRAMEBUFFER_SRGB);
+                }
+            }
+        }
+    }
+
+    pub fn program_point_size(&mut self, enabled: bool) {
+        if self.raster.program_point_size != enabled {
+            self.raster.program_point_size = enabled;
+            unsafe {
+                if enabled {
+                    gl::Enable(gl::PROGRAM_POINT_SIZE);
+                } else {
+                    gl::Disable(gl::PROGRAM_POINT_SIZE);
+                }
+            }
+        }
+    }
+
+    pub fn scissor_test(&mut self, enabled: bool) {
+        if self.raster.scissor_test != enabled {
+            self.raster.scissor_test = enabled;
+            unsafe {
+                if enabled {
+                    gl::Enable(gl::SCISSOR_TEST);
+                } else {
+                    gl::Disable(gl::SCISSOR_TEST);
+                }
+            }
+        }
+    }
+
+    /// rect is (x, y, width, height) in window coordinates, with the origin at the bottom left
+    pub fn scissor_box(&mut self, rect: [i32; 4]) {
+        if self.raster.scissor_box != rect {
+            self.raster.scissor_box = rect;
+            unsafe {
+                gl::Scissor(rect[0], rect[1], rect[2], rect[3]);
+            }
+        }
+    }
+
+    /// rect is (x, y, width, height) in window coordinates, with the origin at the bottom left
+    pub fn viewport(&mut self, rect: [i32; 4]) {
+        if self.raster.viewport != rect {
+            self.raster.viewport = rect;
+            unsafe {
+                gl::Viewport(rect[0], rect[1], rect[2], rect[3]);
+            }
+        }
+    }
+
+    /// Enables scissor testing with the given rect, intersected with the current scissor box if
+    /// scissor testing is already enabled. Every push_scissor() must be matched with a pop_scissor()
+    pub fn push_scissor(&mut self, rect: [i32; 4]) {
+        self.scissor_stack.push((self.raster.scissor_test, self.raster.scissor_box));
+
+        let rect = if self.raster.scissor_test {
+            intersect_rects(self.raster.scissor_box, rect)
+        } else {
+            rect
+        };
+
+        self.scissor_box(rect);
+        self.scissor_test(true);
+    }
+
+    /// Restores the scissor state from before the matching push_scissor().
+    /// panics if the stack is empty
+    pub fn pop_scissor(&mut self) {
+        let (enabled, rect) = self.scissor_stack.pop().expect("Scissor stack underflow");
+        self.scissor_box(rect);
+        self.scissor_test(enabled);
+    }
+
+    /// Sets the viewport, saving the current one. Every push_viewport() must be matched with a pop_viewport()
+    pub fn push_viewport(&mut self, rect: [i32; 4]) {
+        self.viewport_stack.push(self.raster.viewport);
+        self.viewport(rect);
+    }
+
+    /// Restores the viewport from before the matching push_viewport().
+    /// panics if the stack is empty
+    pub fn pop_viewport(&mut self) {
+        let rect = self.viewport_stack.pop().expect("Viewport stack underflow");
+        self.viewport(rect);
+    }
+
+    pub fn get_viewport(&self) -> [i32; 4] {
+        self.raster.viewport
+    }
+
+    pub fn get_program(&self) -> GLuint {
+        self.program
+    }
+
+    pub fn get_vao(&self) -> GLuint {
+        self.vao
+    }
+
+    pub fn get_fbo(&self) -> GLuint {
+        self.fbo
+    }
+
     pub fn use_program(&mut self, program: GLuint) {
         if self.program != program {
             self.program = program;
+            self.stats.program_binds += 1;
             unsafe {
                 gl::UseProgram(program);
             }
@@ -613,6 +961,7 @@ impl GlState {
     pub fn bind_vao(&mut self, vao: GLuint) {
         if self.vao != vao {
             self.vao = vao;
+            self.stats.vao_binds += 1;
             unsafe {
                 gl::BindVertexArray(vao);
             }
@@ -621,6 +970,8 @@ impl GlState {
 
     pub fn bind_fbo(&mut self, fbo: GLuint) {
         if self.fbo != fbo {
+            self.fbo = fbo;
+            self.stats.fbo_binds += 1;
             unsafe {
                 gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
             }
@@ -628,42 +979,148 @@ impl GlState {
     }
 
     pub fn set_uniform(&mut self, name: impl ToString, value: GLUniform) {
+        let handle = self.get_uniform_handle(name);
+        self.set_uniform_handle(handle, value);
+    }
+
+    /// Looks up (and caches) the location of a uniform of the current program.
+    /// Hot paths can keep the handle and use set_uniform_handle to skip the name lookup.
+    /// The handle stays valid until the program is relinked or destroyed.
+    pub fn get_uniform_handle(&mut self, name: impl ToString) -> UniformHandle {
         let name = name.to_string();
+        let program = self.program;
+        let mut uniforms = self.uniforms.borrow_mut();
+        let uniforms = uniforms.entry(program).or_default();
+
+        let location = match uniforms.locations.get(&name) {
+            Some(location) => *location,
+            None => {
+                let cstr = CString::new(name.clone()).unwrap();
+                let location = unsafe { gl::GetUniformLocation(program, cstr.as_ptr()) };
+                uniforms.locations.insert(name, location);
+                location
+            }
+        };
 
-        if !self.uniforms.contains_key(&self.program) {
-            self.uniforms.insert(self.program, HashMap::new());
+        UniformHandle {
+            program,
+            location,
         }
+    }
 
-        let uniforms = self.uniforms.get_mut(&self.program).unwrap();
+    /// Sets a uniform by handle, skipping the upload if the cached value is unchanged.
+    /// The handle's program must be the current program.
+    pub fn set_uniform_handle(&mut self, handle: UniformHandle, value: GLUniform) {
+        debug_assert_eq!(handle.program, self.program, "uniform handle belongs to a program that isn't in use");
+        // inactive uniforms have no location, setting them is a no-op
+        if handle.location < 0 {
+            return;
+        }
 
-        unsafe {
-            if let Some(v) = uniforms.get(&name) {
-                if *v != value {
-                    let cstr = CString::new(name.clone()).unwrap();
-                    let loc = gl::GetUniformLocation(self.program, cstr.as_ptr());
-                    value.upload(loc);
-                    uniforms.insert(name, value);
-                }
-            } else {
-                uniforms.insert(name.clone(), value);
-                let cstr = CString::new(name).unwrap();
-                let loc = gl::GetUniformLocation(self.program, cstr.as_ptr());
-                value.upload(loc);
+        let mut uniforms = self.uniforms.borrow_mut();
+        let values = &mut uniforms.entry(handle.program).or_default().values;
+        let index = handle.location as usize;
+        if values.len() <= index {
+            values.resize(index + 1, None);
+        }
+        if values[index].as_ref() != Some(&value) {
+            unsafe {
+                value.upload(handle.location);
             }
+            values[index] = Some(value);
+            self.stats.uniform_uploads += 1;
         }
+    }
 
+    /// Forgets every cached uniform value and location of a program, so the next set_uniform calls
+    /// upload again. Must be called after a program is relinked, since its uniform values and
+    /// locations are reset. UniformHandles of the program must be fetched again.
+    pub fn invalidate_uniforms(&mut self, program: GLuint) {
+        self.uniforms.borrow_mut().remove(&program);
+    }
+
+    pub fn bind_uniform_buffer(&mut self, binding: u32, buffer: GLuint) {
+        if self.uniform_buffers.get(&binding) != Some(&buffer) {
+            self.uniform_buffers.insert(binding, buffer);
+            unsafe {
+                gl::BindBufferBase(gl::UNIFORM_BUFFER, binding, buffer);
+            }
+        }
+    }
+
+    pub fn bind_storage_buffer(&mut self, binding: u32, buffer: GLuint) {
+        if self.storage_buffers.get(&binding) != Some(&buffer) {
+            self.storage_buffers.insert(binding, buffer);
+            unsafe {
+                gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, binding, buffer);
+            }
+        }
     }
 
     pub fn bind_texture(&mut self, slot: u32, tex: GLuint) {
+        self.stats.texture_binds += 1;
         unsafe {
             gl::ActiveTexture(gl::TEXTURE0 + slot);
             gl::BindTexture(gl::TEXTURE_2D, tex);
         }
     }
 
+    pub fn bind_texture_1d(&mut self, slot: u32, tex: GLuint) {
+        self.stats.texture_binds += 1;
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + slot);
+            gl::BindTexture(gl::TEXTURE_1D, tex);
+        }
+    }
+
+    pub fn bind_texture_3d(&mut self, slot: u32, tex: GLuint) {
+        self.stats.texture_binds += 1;
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + slot);
+            gl::BindTexture(gl::TEXTURE_3D, tex);
+        }
+    }
+
+    pub fn bind_texture_cube(&mut self, slot: u32, tex: GLuint) {
+        self.stats.texture_binds += 1;
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + slot);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, tex);
+        }
+    }
+
+    pub fn bind_texture_array(&mut self, slot: u32, tex: GLuint) {
+        self.stats.texture_binds += 1;
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + slot);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, tex);
+        }
+    }
+
+    /// Binds a sampler object to a texture unit, 0 unbinds it so the texture's own parameters apply
     pub fn bind_sampler(&mut self, slot: u32, sampler: GLuint) {
+        let current = self.sampler.bound.get(&slot).copied().unwrap_or(0);
+        if current != sampler {
+            if sampler == 0 {
+                self.sampler.bound.remove(&slot);
+            } else {
+                self.sampler.bound.insert(slot, sampler);
+            }
+            unsafe {
+                gl::BindSampler(slot, sampler);
+            }
+        }
+    }
+
+    pub fn get_bound_sampler(&self, slot: u32) -> GLuint {
+        self.sampler.bound.get(&slot).copied().unwrap_or(0)
+    }
+
+    pub fn destroy_sampler(&mut self, sampler: GLuint) {
+        // deleting a sampler unbinds it from every unit
+        self.sampler.bound.retain(|_, s| *s != sampler);
         unsafe {
-            gl::BindSampler(slot, sampler);
+            gl::DeleteSamplers(1, &sampler);
         }
     }
 
@@ -671,7 +1128,7 @@ impl GlState {
         if self.program == program {
             self.use_program(0);
         }
-        self.uniforms.remove(&program);
+        self.uniforms.borrow_mut().remove(&program);
         unsafe {
             gl::DeleteProgram(program);
         }
@@ -682,6 +1139,7 @@ impl GlState {
             gl::DeleteBuffers(vbos.len() as GLsizei, vbos.as_ptr())
         }
     }
+    #[allow(clippy::boxed_local)]
     pub fn destroy_vbo_box_array(&mut self, vbos: Box<[GLuint]>) {
         unsafe {
             gl::DeleteBuffers(vbos.len() as GLsizei, vbos.as_ptr())
@@ -689,11 +1147,65 @@ impl GlState {
     }
 
     pub fn destroy_vao(&mut self, vao: GLuint) {
+        // deleting a bound object reverts the binding to 0
+        if self.vao == vao {
+            self.vao = 0;
+        }
         unsafe {
             gl::DeleteVertexArrays(1, &vao);
         }
     }
 
+    pub fn destroy_fbo(&mut self, fbo: GLuint) {
+        if self.fbo == fbo {
+            self.fbo = 0;
+        }
+        unsafe {
+            gl::DeleteFramebuffers(1, &fbo);
+        }
+    }
+
+    pub fn destroy_uniform_buffer(&mut self, buffer: GLuint) {
+        // deleting a buffer unbinds it from every binding point
+        self.uniform_buffers.retain(|_, b| *b != buffer);
+        unsafe {
+            gl::DeleteBuffers(1, &buffer);
+        }
+    }
+
+    pub fn destroy_storage_buffer(&mut self, buffer: GLuint) {
+        self.storage_buffers.retain(|_, b| *b != buffer);
+        unsafe {
+            gl::DeleteBuffers(1, &buffer);
+        }
+    }
+
+    pub fn destroy_texture(&mut self, tex: GLuint) {
+        unsafe {
+            gl::DeleteTextures(1, &tex);
+        }
+    }
+
+    /// A default state for another context, sharing the uniform cache with this one
+    fn new_context(&self) -> Self {
+        Self {
+            uniforms: Rc::clone(&self.uniforms),
+            ..Self::new()
+        }
+    }
+
+    pub fn get_stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    /// Starts a new frame of statistics
+    pub fn reset_stats(&mut self) {
+        self.stats = FrameStats {
+            frame: self.stats.frame + 1,
+            ..FrameStats::default()
+        };
+    }
+
     pub fn set_state(&mut self, state: &GlState) {
         self.use_program(state.program);
         self.bind_fbo(state.fbo);
@@ -702,8 +1214,29 @@ impl GlState {
         self.blending(state.blend.enabled);
         self.depth_test(state.depth.enabled);
         self.depth_mask(state.depth.mask);
+        self.depth_func(state.depth.func);
         self.culling(state.cull.enabled);
         self.cull_face(state.cull.face);
+        self.front_face(state.cull.front_face);
+        self.scissor_box(state.raster.scissor_box);
+        self.scissor_test(state.raster.scissor_test);
+        self.viewport(state.raster.viewport);
+        self.color_mask(state.raster.color_mask);
+        self.framebuffer_srgb(state.raster.framebuffer_srgb);
+        self.program_point_size(state.raster.program_point_size);
+        for (binding, buffer) in &state.uniform_buffers {
+            self.bind_uniform_buffer(*binding, *buffer);
+        }
+        for (binding, buffer) in &state.storage_buffers {
+            self.bind_storage_buffer(*binding, *buffer);
+        }
+        let unbound: Vec<u32> = self.sampler.bound.keys().filter(|slot| !state.sampler.bound.contains_key(slot)).copied().collect();
+        for slot in unbound {
+            self.bind_sampler(slot, 0);
+        }
+        for (slot, sampler) in &state.sampler.bound {
+            self.bind_sampler(*slot, *sampler);
+        }
         // TODO: set the rest of the states
     }
 
@@ -742,20 +1275,42 @@ impl GlState {
                 scissor_test: false,
                 scissor_box: [0, 0, 8096, 8096],
                 viewport: [0, 0, 8096, 8096],
+                color_mask: [true; 4],
+                framebuffer_srgb: false,
+                program_point_size: false,
             },
             sampler: SamplerState {
-
+                bound: HashMap::new(),
             },
             vao: 0,
             fbo: 0,
             program: 0,
-            framebuffer: 0,
-            uniforms: HashMap::new(),
+            uniforms: Rc::new(RefCell::new(HashMap::new())),
+            uniform_buffers: HashMap::new(),
+            storage_buffers: HashMap::new(),
+            scissor_stack: Vec::new(),
+            viewport_stack: Vec::new(),
+            stats: FrameStats::default(),
         }
     }
 
 }
 
+/// intersects two (x, y, width, height) rects, returning an empty rect if they don't overlap
+fn intersect_rects(a: [i32; 4], b: [i32; 4]) -> [i32; 4] {
+    let x0 = a[0].max(b[0]);
+    let y0 = a[1].max(b[1]);
+    let x1 = (a[0] + a[2]).min(b[0] + b[2]);
+    let y1 = (a[1] + a[3]).min(b[1] + b[3]);
+    [x0, y0, (x1 - x0).max(0), (y1 - y0).max(0)]
+}
+
+impl GlStateRef {
+    pub fn copy_state(&self) -> GlState {
+        self.state.borrow().clone()
+    }
+}
+
 impl Drop for GlStateSnapshot {
     fn drop(&mut self) {
         self.true_state.borrow_mut().set_state(&self.save_state)
@@ -766,8 +1321,55 @@ impl Drop for GlStateSnapshot {
 impl GlStateManager {
 
     pub fn new() -> Self {
+        let state = Rc::new(RefCell::new(GlState::new()));
+        let mut contexts = HashMap::new();
+        contexts.insert(ContextId::default(), Rc::clone(&state));
         Self {
-            state: Rc::new(RefCell::new(GlState::new()))
+            state,
+            contexts,
+            context: ContextId::default(),
+            frame: FrameQueue::new(),
+            samplers: HashMap::new(),
+            context_deletions: HashMap::new(),
+        }
+    }
+
+    pub fn get_current_context(&self) -> ContextId {
+        self.context
+    }
+
+    /// Switches to the state cache of another context, must be called after making a different
+    /// context current. A fresh cache is made the first time a context is used.
+    /// Snapshots and GlStateRefs stay tied to the context they were made in.
+    pub fn set_current_context(&mut self, context: ContextId) {
+        if self.context == context {
+            return;
+        }
+        let state = self.contexts.entry(context)
+            .or_insert_with(|| Rc::new(RefCell::new(self.state.borrow().new_context())));
+        self.state = Rc::clone(state);
+        self.context = context;
+        for vao in self.context_deletions.remove(&context).unwrap_or_default() {
+            self.destroy_vao(vao);
+        }
+    }
+
+    /// Forgets the state cache of a context that was destroyed. The current context can't be removed.
+    pub fn remove_context(&mut self, context: ContextId) {
+        if context != self.context {
+            self.contexts.remove(&context);
+            // the context's objects went with it
+            self.context_deletions.remove(&context);
+        }
+    }
+
+    /// Deletes a VAO of `context`. VAOs aren't shared between contexts, so a VAO of another
+    /// context is deleted once set_current_context switches to it.
+    pub fn destroy_vao_in(&mut self, context: ContextId, vao: GLuint) {
+        if context == self.context {
+            self.destroy_vao(vao);
+        } else {
+            self.context_deletions.entry(context).or_default().push(vao);
         }
     }
 
@@ -783,9 +1385,168 @@ impl GlStateManager {
         self.state.borrow().clone()
     }
 
+    /// Runs f, then restores the state from before f was called.
+    /// The state is restored however f exits, including early returns and panics.
+    pub fn scoped<R>(&mut self, f: impl FnOnce(&mut GlStateManager) -> R) -> R {
+        let _snapshot = self.snapshot();
+        f(self)
+    }
+
+    /// Runs f with the given blend mode, restoring the previous blend state afterwards
+    pub fn with_blend<R>(&mut self, mode: BlendMode, f: impl FnOnce(&mut GlStateManager) -> R) -> R {
+        self.scoped(|state| {
+            state.blend_mode(mode);
+            f(state)
+        })
+    }
+
+    /// Runs f with depth testing disabled, restoring the previous depth state afterwards
+    pub fn with_depth_disabled<R>(&mut self, f: impl FnOnce(&mut GlStateManager) -> R) -> R {
+        self.scoped(|state| {
+            state.depth_test(false);
+            f(state)
+        })
+    }
+
+    /// Per-frame housekeeping to call before rendering a frame.
+    /// Resets the frame statistics, collects finished gpu timer results and readbacks, and if
+    /// set_max_frames_in_flight is used, waits for the gpu to catch up. The gpu time from here to
+    /// frame_end shows up in get_gpu_timings as frame::FRAME_TIMING.
+    pub fn frame_begin(&mut self) {
+        self.frame.wait_fences();
+        self.frame.resolve_timers();
+        self.frame.resolve_readbacks();
+        self.state.borrow_mut().reset_stats();
+        self.frame.begin_frame_timing();
+    }
+
+    /// Per-frame housekeeping to call after rendering a frame, before swapping buffers.
+    /// Deletes the objects queued with defer_delete and inserts a frame fence if enabled.
+    pub fn frame_end(&mut self) {
+        self.end_gpu_timer();
+        self.frame.end_frame_timing();
+
+        for object in mem::take(&mut self.frame.deletions) {
+            match object {
+                GlObject::Buffer(buffer) => self.destroy_vbo_vec(vec![buffer]),
+                GlObject::VertexArray(vao) => self.destroy_vao(vao),
+                GlObject::Framebuffer(fbo) => self.destroy_fbo(fbo),
+                GlObject::Renderbuffer(rbo) => unsafe {
+                    gl::DeleteRenderbuffers(1, &rbo);
+                },
+                GlObject::Texture(tex) => self.destroy_texture(tex),
+                GlObject::Program(program) => self.destroy_program(program),
+            }
+        }
+
+        self.frame.insert_fence();
+    }
+
+    /// Queues a gl object to be deleted at the next frame_end, for objects that may still be used
+    /// by draws recorded earlier in the frame
+    pub fn defer_delete(&mut self, object: GlObject) {
+        self.frame.deletions.push(object);
+    }
+
+    /// Copies `rect` ([x, y, width, height], from the bottom left) of the bound framebuffer into a
+    /// pixel buffer without waiting for the gpu. Once the copy has finished, a later frame_begin
+    /// calls `callback` with the pixels as an rgba image, top row first.
+    pub fn read_pixels_async(
+        &mut self,
+        rect: [i32; 4],
+        callback: impl FnOnce(RgbaImage) + 'static,
+    ) {
+        let [x, y, width, height] = rect;
+        let (width, height) = (width.max(0), height.max(0));
+        unsafe {
+            let mut pbo = 0;
+            gl::GenBuffers(1, &mut pbo);
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, pbo);
+            gl::BufferData(
+                gl::PIXEL_PACK_BUFFER,
+                (width * height * 4) as GLsizeiptr,
+                ptr::null(),
+                gl::STREAM_READ,
+            );
+            gl::ReadPixels(x, y, width, height, gl::RGBA, gl::UNSIGNED_BYTE, ptr::null_mut());
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+
+            self.frame.readbacks.push(PendingReadback {
+                pbo,
+                fence: gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0),
+                size: (width as u32, height as u32),
+                callback: Box::new(callback),
+            });
+        }
+    }
+
+    /// Starts timing gpu work under `name`, until end_gpu_timer or frame_end.
+    /// Results show up in get_gpu_timings a frame or more later, once the gpu has finished.
+    /// Only one timer can run at a time, starting a timer ends the running one.
+    pub fn begin_gpu_timer(&mut self, name: impl ToString) {
+        self.end_gpu_timer();
+        unsafe {
+            let mut query = 0;
+            gl::GenQueries(1, &mut query);
+            gl::BeginQuery(gl::TIME_ELAPSED, query);
+            self.frame.active_timer = Some((name.to_string(), query));
+        }
+    }
+
+    pub fn end_gpu_timer(&mut self) {
+        if let Some(timer) = self.frame.active_timer.take() {
+            unsafe {
+                gl::EndQuery(gl::TIME_ELAPSED);
+            }
+            self.frame.pending_timers.push(timer);
+        }
+    }
+
+    /// # Returns
+    /// the most recent gpu time of every timer, in milliseconds
+    pub fn get_gpu_timings(&self) -> &HashMap<String, f64> {
+        &self.frame.timings
+    }
+
+    /// Limits how many frames the cpu can queue ahead of the gpu, by fencing each frame in frame_end
+    /// and waiting on old fences in frame_begin. None (the default) disables frame fences.
+    pub fn set_max_frames_in_flight(&mut self, frames: Option<usize>) {
+        self.frame.max_frames_in_flight = frames;
+        if frames.is_none() {
+            for fence in self.frame.fences.drain(..) {
+                unsafe {
+                    gl::DeleteSync(fence);
+                }
+            }
+        }
+    }
+
+    /// # Returns
+    /// the id of a sampler matching `descriptor`, created the first time it is asked for and
+    /// shared by every later call with an equal descriptor
+    pub fn get_sampler(&mut self, descriptor: &SamplerDescriptor) -> GLuint {
+        self.samplers.entry(descriptor.clone()).or_insert_with(|| Sampler::new(descriptor)).get_id()
+    }
+
+    /// Deletes every sampler made by get_sampler
+    pub fn destroy_cached_samplers(&mut self) {
+        let samplers: Vec<Sampler> = self.samplers.drain().map(|(_, sampler)| sampler).collect();
+        for mut sampler in samplers {
+            sampler.destroy(self);
+        }
+    }
+
+    /// Creates a shared handle to the managed state, useful for reading the state without access to the manager
+    pub fn get_ref(&self) -> GlStateRef {
+        GlStateRef {
+            state: Rc::clone(&self.state)
+        }
+    }
+
     delegate! {
         to self.state.borrow_mut() {
             pub fn depth_test(&mut self, enabled: bool);
+            pub fn depth_func(&mut self, func: DepthFunc);
             pub fn depth_mask(&mut self, enabled: bool);
             pub fn culling(&mut self, enabled: bool);
             pub fn cull_face(&mut self, face: CullFace);
@@ -793,19 +1554,50 @@ impl GlStateManager {
             pub fn blending(&mut self, enabled: bool);
             pub fn blend_func_both(&mut self, src: BlendFactor, dst: BlendFactor);
             pub fn blend_func(&mut self, src_rgb: SrcRgb, src_alpha: SrcAlpha, dst_rgb: DstRgb, dst_alpha: DstAlpha, rgb_equation: RgbEquation, alpha_equation: AlphaEquation);
+            pub fn blend_mode(&mut self, mode: BlendMode);
             pub fn blend_func_separate(&mut self, src_rgb: SrcRgb, src_alpha: SrcAlpha, dst_rgb: DstRgb, dst_alpha: DstAlpha);
             pub fn blend_func_rgb(&mut self, src_rgb: SrcRgb, dst_rgb: DstRgb);
             pub fn blend_equation(&mut self, rgb_equation: RgbEquation, alpha_equation: AlphaEquation);
+            pub fn color_mask(&mut self, mask: [bool; 4]);
+            pub fn framebuffer_srgb(&mut self, enabled: bool);
+            pub fn program_point_size(&mut self, enabled: bool);
+            pub fn scissor_test(&mut self, enabled: bool);
+            pub fn scissor_box(&mut self, rect: [i32; 4]);
+            pub fn viewport(&mut self, rect: [i32; 4]);
+            pub fn push_scissor(&mut self, rect: [i32; 4]);
+            pub fn pop_scissor(&mut self);
+            pub fn push_viewport(&mut self, rect: [i32; 4]);
+            pub fn pop_viewport(&mut self);
+            pub fn get_viewport(&self) -> [i32; 4];
+            pub fn get_program(&self) -> GLuint;
+            pub fn get_vao(&self) -> GLuint;
+            pub fn get_fbo(&self) -> GLuint;
             pub fn use_program(&mut self, program: GLuint);
             pub fn bind_vao(&mut self, vao: GLuint);
             pub fn bind_fbo(&mut self, fbo: GLuint);
             pub fn set_uniform(&mut self, name: impl ToString, value: GLUniform);
+            pub fn get_uniform_handle(&mut self, name: impl ToString) -> UniformHandle;
+            pub fn set_uniform_handle(&mut self, handle: UniformHandle, value: GLUniform);
+            pub fn invalidate_uniforms(&mut self, program: GLuint);
+            pub fn bind_uniform_buffer(&mut self, binding: u32, buffer: GLuint);
+            pub fn bind_storage_buffer(&mut self, binding: u32, buffer: GLuint);
             pub fn bind_texture(&mut self, slot: u32, tex: GLuint);
+            pub fn bind_texture_1d(&mut self, slot: u32, tex: GLuint);
+            pub fn bind_texture_3d(&mut self, slot: u32, tex: GLuint);
+            pub fn bind_texture_cube(&mut self, slot: u32, tex: GLuint);
+            pub fn bind_texture_array(&mut self, slot: u32, tex: GLuint);
             pub fn bind_sampler(&mut self, slot: u32, sampler: GLuint);
+            pub fn get_bound_sampler(&self, slot: u32) -> GLuint;
+            pub fn destroy_sampler(&mut self, sampler: GLuint);
             pub fn destroy_program(&mut self, program: GLuint);
             pub fn destroy_vbo_vec(&mut self, vbos: Vec<GLuint>);
             pub fn destroy_vbo_box_array(&mut self, vbos: Box<[GLuint]>);
             pub fn destroy_vao(&mut self, vao: GLuint);
+            pub fn destroy_fbo(&mut self, fbo: GLuint);
+            pub fn destroy_uniform_buffer(&mut self, buffer: GLuint);
+            pub fn destroy_storage_buffer(&mut self, buffer: GLuint);
+            pub fn destroy_texture(&mut self, tex: GLuint);
+            pub fn get_stats(&self) -> FrameStats;
             pub fn set_state(&mut self, state: &GlState);
         }
     }
@@ -872,6 +1664,140 @@ impl Default for GlStateManager {
 }
 
 
-
-
-
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gl_trace::{self, GlCall};
+
+    /// A state manager on the trace backend with nothing recorded yet
+    fn traced() -> GlStateManager {
+        gl_trace::load();
+        gl_trace::clear();
+        GlStateManager::new()
+    }
+
+    #[test]
+    fn redundant_calls_are_skipped() {
+        let mut state = traced();
+        for _ in 0..3 {
+            state.use_program(3);
+            state.bind_vao(4);
+            state.depth_test(true);
+            state.blend_mode(BlendMode::Alpha);
+            state.viewport([0, 0, 640, 480]);
+            state.bind_sampler(1, 9);
+        }
+        assert_eq!(gl_trace::take_calls(), vec![
+            GlCall::UseProgram(3),
+            GlCall::BindVertexArray(4),
+            GlCall::Enable(gl::DEPTH_TEST),
+            GlCall::Enable(gl::BLEND),
+            GlCall::BlendFuncSeparate(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA, gl::ONE, gl::ONE_MINUS_SRC_ALPHA),
+            GlCall::Viewport([0, 0, 640, 480]),
+            GlCall::BindSampler(1, 9),
+        ]);
+        assert_eq!(state.get_stats().program_binds, 1);
+        assert_eq!(state.get_stats().vao_binds, 1);
+    }
+
+    #[test]
+    fn setting_the_default_state_emits_nothing() {
+        let mut state = traced();
+        state.set_state(&GlState::new());
+        assert_eq!(gl_trace::take_calls(), vec![]);
+    }
+
+    #[test]
+    fn snapshot_restores_only_what_changed() {
+        let mut state = traced();
+        state.use_program(2);
+        state.viewport([0, 0, 800, 600]);
+        gl_trace::clear();
+
+        state.scoped(|state| {
+            state.use_program(5);
+            state.depth_test(true);
+            state.blend_mode(BlendMode::Additive);
+            state.cull_face(CullFace::Front);
+            state.push_scissor([10, 10, 100, 100]);
+            state.bind_sampler(2, 7);
+            gl_trace::clear();
+        });
+
+        // restored in set_state's order
+        assert_eq!(gl_trace::take_calls(), vec![
+            GlCall::UseProgram(2),
+            GlCall::BlendFuncSeparate(gl::ONE, gl::ZERO, gl::ONE, gl::ZERO),
+            GlCall::Disable(gl::BLEND),
+            GlCall::Disable(gl::DEPTH_TEST),
+            GlCall::CullFace(gl::BACK),
+            GlCall::Scissor([0, 0, 8096, 8096]),
+            GlCall::Disable(gl::SCISSOR_TEST),
+            GlCall::BindSampler(2, 0),
+        ]);
+        assert_eq!(state.get_program(), 2);
+        assert_eq!(state.get_bound_sampler(2), 0);
+    }
+
+    #[test]
+    fn unchanged_scope_restores_nothing() {
+        let mut state = traced();
+        state.depth_test(true);
+        gl_trace::clear();
+        state.scoped(|state| {
+            state.depth_test(true);
+            state.viewport([0, 0, 8096, 8096]);
+        });
+        assert_eq!(gl_trace::take_calls(), vec![]);
+    }
+
+    #[test]
+    fn nested_scissors_intersect_and_pop() {
+        let mut state = traced();
+        state.push_scissor([0, 0, 100, 100]);
+        state.push_scissor([50, 50, 100, 100]);
+        state.pop_scissor();
+        state.pop_scissor();
+        assert_eq!(gl_trace::take_calls(), vec![
+            GlCall::Scissor([0, 0, 100, 100]),
+            GlCall::Enable(gl::SCISSOR_TEST),
+            GlCall::Scissor([50, 50, 50, 50]),
+            GlCall::Scissor([0, 0, 100, 100]),
+            GlCall::Scissor([0, 0, 8096, 8096]),
+            GlCall::Disable(gl::SCISSOR_TEST),
+        ]);
+    }
+
+    #[test]
+    fn uniform_values_and_locations_are_cached() {
+        let mut state = traced();
+        state.use_program(11);
+        state.set_uniform("u_time", GLUniform::F32(1.));
+        state.set_uniform("u_time", GLUniform::F32(1.));
+        state.set_uniform("u_time", GLUniform::F32(2.));
+        state.invalidate_uniforms(11);
+        state.set_uniform("u_time", GLUniform::F32(2.));
+        assert_eq!(gl_trace::take_calls(), vec![
+            GlCall::UseProgram(11),
+            GlCall::GetUniformLocation(11),
+            GlCall::Uniform1f(0, 1.),
+            GlCall::Uniform1f(0, 2.),
+            GlCall::GetUniformLocation(11),
+            GlCall::Uniform1f(0, 2.),
+        ]);
+    }
+
+    #[test]
+    fn contexts_have_separate_caches() {
+        let mut state = traced();
+        state.use_program(3);
+        state.set_current_context(ContextId(1));
+        state.use_program(3);
+        state.set_current_context(ContextId::default());
+        state.use_program(3);
+        assert_eq!(gl_trace::take_calls(), vec![
+            GlCall::UseProgram(3),
+            GlCall::UseProgram(3),
+        ]);
+    }
+}
@@ -0,0 +1,217 @@
+//! Diagnostics toggled at runtime through environment variables and command line flags, so they
+//! can be turned on in shipped builds without recompiling.
+//!
+//! | flag            | environment variable       |                                               |
+//! |-----------------|----------------------------|-----------------------------------------------|
+//! | `--gl-debug`    | `RENDERFORGE_GL_DEBUG`     | print KHR_debug messages                      |
+//! | `--validation`  | `RENDERFORGE_VALIDATION`   | synchronous debug output, glGetError checks   |
+//! | `--profiler`    | `RENDERFORGE_PROFILER`     | show the profiler overlay                     |
+//! | `--capture`     | `RENDERFORGE_CAPTURE`      | record frames from startup                    |
+//! | `--software-gl` | `RENDERFORGE_SOFTWARE_GL`  | ask the driver for its software rasterizer    |
+//!
+//! Environment variables are enabled by `1`, `true`, `on` or `yes`. Flags can be negated with
+//! `--no-`, e.g. `--no-gl-debug`, and take precedence over the environment.
+
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+
+use gl::types::{GLenum, GLsizei, GLuint};
+
+use crate::data::GlStateManager;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DebugConfig {
+    /// print gl debug messages to stderr
+    pub gl_debug_output: bool,
+    /// report debug messages at the call that caused them and check glGetError every frame,
+    /// slow but pinpoints errors
+    pub validation: bool,
+    /// show frame stats and gpu timings, see profiler_summary
+    pub profiler_overlay: bool,
+    /// start recording frames as soon as the engine runs
+    pub capture_on_start: bool,
+    /// use the driver's software rasterizer, for machines with broken gpu drivers
+    pub software_renderer: bool,
+}
+
+/// (flag, environment variable) of each setting
+const SETTINGS: [(&str, &str); 5] = [
+    ("gl-debug", "RENDERFORGE_GL_DEBUG"),
+    ("validation", "RENDERFORGE_VALIDATION"),
+    ("profiler", "RENDERFORGE_PROFILER"),
+    ("capture", "RENDERFORGE_CAPTURE"),
+    ("software-gl", "RENDERFORGE_SOFTWARE_GL"),
+];
+
+impl DebugConfig {
+    /// Everything disabled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the environment, then the process's command line flags
+    pub fn load() -> Self {
+        Self::from_env().with_args(std::env::args().skip(1))
+    }
+
+    pub fn from_env() -> Self {
+        let mut config = Self::new();
+        for (flag, variable) in SETTINGS {
+            if let Ok(value) = std::env::var(variable) {
+                let enabled = matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true" | "on" | "yes");
+                config.set(flag, enabled);
+            }
+        }
+        config
+    }
+
+    /// Applies `--flag` and `--no-flag` arguments, ignoring everything else
+    pub fn with_args(mut self, args: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        for arg in args {
+            let Some(flag) = arg.as_ref().strip_prefix("--") else {
+                continue;
+            };
+            match flag.strip_prefix("no-") {
+                Some(flag) => self.set(flag, false),
+                None => self.set(flag, true),
+            }
+        }
+        self
+    }
+
+    pub fn with_gl_debug_output(mut self, enabled: bool) -> Self {
+        self.gl_debug_output = enabled;
+        self
+    }
+
+    pub fn with_validation(mut self, enabled: bool) -> Self {
+        self.validation = enabled;
+        self
+    }
+
+    pub fn with_profiler_overlay(mut self, enabled: bool) -> Self {
+        self.profiler_overlay = enabled;
+        self
+    }
+
+    pub fn with_capture_on_start(mut self, enabled: bool) -> Self {
+        self.capture_on_start = enabled;
+        self
+    }
+
+    pub fn with_software_renderer(mut self, enabled: bool) -> Self {
+        self.software_renderer = enabled;
+        self
+    }
+
+    fn set(&mut self, flag: &str, enabled: bool) {
+        match flag {
+            "gl-debug" => self.gl_debug_output = enabled,
+            "validation" => self.validation = enabled,
+            "profiler" => self.profiler_overlay = enabled,
+            "capture" => self.capture_on_start = enabled,
+            "software-gl" => self.software_renderer = enabled,
+            _ => {}
+        }
+    }
+
+    /// Applies the settings that must be in place before the gl context is created, currently
+    /// the software renderer request (`LIBGL_ALWAYS_SOFTWARE` for Mesa drivers).
+    ///
+    /// # Safety
+    /// Sets environment variables, so no other threads may be reading or writing the environment,
+    /// call this at the start of main.
+    pub unsafe fn apply_before_context(&self) {
+        if self.software_renderer {
+            unsafe {
+                std::env::set_var("LIBGL_ALWAYS_SOFTWARE", "1");
+            }
+        }
+    }
+
+    /// Turns on gl debug output when enabled, needs a current context with debug output support
+    /// (GL 4.3 or KHR_debug).
+    ///
+    /// # Returns
+    /// false if debug output was requested but isn't available
+    pub fn apply_to_context(&self) -> bool {
+        if !self.gl_debug_output && !self.validation {
+            return true;
+        }
+        enable_gl_debug_output(self.validation)
+    }
+}
+
+/// Prints gl debug messages to stderr. With `synchronous` messages are reported from inside the gl
+/// call that caused them, so a breakpoint in the callback shows the culprit.
+///
+/// # Returns
+/// false if the context doesn't support debug output
+pub fn enable_gl_debug_output(synchronous: bool) -> bool {
+    if !gl::DebugMessageCallback::is_loaded() {
+        return false;
+    }
+    unsafe {
+        gl::Enable(gl::DEBUG_OUTPUT);
+        if synchronous {
+            gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+        } else {
+            gl::Disable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+        }
+        gl::DebugMessageCallback(Some(debug_callback), std::ptr::null());
+    }
+    true
+}
+
+extern "system" fn debug_callback(source: GLenum, kind: GLenum, id: GLuint, severity: GLenum, _length: GLsizei, message: *const c_char, _user: *mut c_void) {
+    if severity == gl::DEBUG_SEVERITY_NOTIFICATION {
+        return;
+    }
+    let message = unsafe { CStr::from_ptr(message) }.to_string_lossy();
+    let severity = match severity {
+        gl::DEBUG_SEVERITY_HIGH => "high",
+        gl::DEBUG_SEVERITY_MEDIUM => "medium",
+        _ => "low",
+    };
+    let kind = match kind {
+        gl::DEBUG_TYPE_ERROR => "error",
+        gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated",
+        gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "undefined behavior",
+        gl::DEBUG_TYPE_PERFORMANCE => "performance",
+        gl::DEBUG_TYPE_PORTABILITY => "portability",
+        _ => "other",
+    };
+    eprintln!("[RenderForge] gl {kind} ({severity}, source {source:#x}, id {id}): {message}");
+}
+
+/// Pops every pending glGetError code.
+///
+/// # Returns
+/// the error codes in the order gl raised them
+pub fn drain_gl_errors() -> Vec<GLenum> {
+    let mut errors = Vec::new();
+    loop {
+        let error = unsafe { gl::GetError() };
+        // a lost context can report errors forever
+        if error == gl::NO_ERROR || errors.len() >= 32 {
+            break errors;
+        }
+        errors.push(error);
+    }
+}
+
+/// # Returns
+/// the frame's state changes and the latest gpu timings as lines of text, for a profiler overlay
+pub fn profiler_summary(gl_state: &GlStateManager) -> String {
+    let stats = gl_state.get_stats();
+    let mut summary = format!(
+        "frame {}\nprograms {}  vaos {}  fbos {}  textures {}  uniforms {}",
+        stats.frame, stats.program_binds, stats.vao_binds, stats.fbo_binds, stats.texture_binds, stats.uniform_uploads,
+    );
+    let mut timings: Vec<_> = gl_state.get_gpu_timings().iter().collect();
+    timings.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, ms) in timings {
+        summary.push_str(&format!("\n{name}: {ms:.2} ms"));
+    }
+    summary
+}
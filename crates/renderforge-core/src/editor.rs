@@ -0,0 +1,644 @@
+use std::mem;
+use std::thread::panicking;
+
+use anyhow::Result;
+use gl::types::GLuint;
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+
+use crate::data::{BlendMode, Color, GLUniform, GlStateManager};
+use crate::mesh::{BufferBuilder, SimpleBufferFormat};
+use crate::semantics::{U_COLOR, U_INVERSE_VIEW_PROJ, U_VIEW_PROJ};
+use crate::shader::compile_program;
+
+const GRID_VERTEX_SHADER: &str = r#"#version 330 core
+uniform mat4 u_inverse_view_proj;
+
+out vec3 v_near;
+out vec3 v_far;
+
+vec3 unproject(vec2 p, float z) {
+    vec4 v = u_inverse_view_proj * vec4(p, z, 1.0);
+    return v.xyz / v.w;
+}
+
+void main() {
+    // fullscreen triangle
+    vec2 p = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2) * 2.0 - 1.0;
+    v_near = unproject(p, -1.0);
+    v_far = unproject(p, 1.0);
+    gl_Position = vec4(p, 0.0, 1.0);
+}
+"#;
+
+const GRID_FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec3 v_near;
+in vec3 v_far;
+
+uniform mat4 u_view_proj;
+uniform float u_spacing;
+uniform vec4 u_color;
+
+out vec4 frag_color;
+
+void main() {
+    float t = -v_near.y / (v_far.y - v_near.y);
+    if (t <= 0.0) {
+        discard;
+    }
+    vec3 p = v_near + t * (v_far - v_near);
+
+    vec2 coord = p.xz / u_spacing;
+    vec2 width = fwidth(coord);
+    vec2 grid = abs(fract(coord - 0.5) - 0.5) / width;
+    float line = 1.0 - min(min(grid.x, grid.y), 1.0);
+
+    vec4 color = u_color;
+    if (abs(coord.y) < width.y) {
+        color = vec4(0.9, 0.2, 0.2, 1.0);
+    } else if (abs(coord.x) < width.x) {
+        color = vec4(0.2, 0.3, 0.9, 1.0);
+    }
+
+    // lines closer than a pixel apart turn into noise near the horizon
+    float fade = 1.0 - smoothstep(0.3, 1.0, max(width.x, width.y));
+    color.a *= line * fade;
+    if (color.a <= 0.0) {
+        discard;
+    }
+
+    vec4 clip = u_view_proj * vec4(p, 1.0);
+    gl_FragDepth = (clip.z / clip.w) * 0.5 + 0.5;
+    frag_color = color;
+}
+"#;
+
+const GIZMO_VERTEX_SHADER: &str = r#"#version 330 core
+layout(location = 0) in vec3 a_position;
+layout(location = 1) in vec4 a_color;
+
+uniform mat4 u_view_proj;
+
+out vec4 v_color;
+
+void main() {
+    v_color = a_color;
+    gl_Position = u_view_proj * vec4(a_position, 1.0);
+}
+"#;
+
+const GIZMO_FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec4 v_color;
+
+out vec4 frag_color;
+
+void main() {
+    frag_color = v_color;
+}
+"#;
+
+/// handle thickness and pick tolerance, relative to Gizmo::size
+const HANDLE_THICKNESS: f32 = 0.03;
+const PICK_TOLERANCE: f32 = 0.08;
+const RING_SEGMENTS: usize = 48;
+/// smallest factor a scale drag can apply, so dragging past the pivot can't flip or collapse an axis
+const MIN_SCALE_FACTOR: f32 = 0.01;
+
+/// A world space ray, usually built from the cursor position for picking
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// normalized
+    pub direction: Vec3,
+}
+
+/// An infinite plane through `point`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub point: Vec3,
+    /// normalized
+    pub normal: Vec3,
+}
+
+/// Step sizes for gizmo manipulation, None disables snapping for that mode
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Snapping {
+    pub translation: Option<f32>,
+    /// in radians
+    pub rotation: Option<f32>,
+    pub scale: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GizmoAxis {
+    X,
+    Y,
+    Z,
+}
+
+/// A manipulation step reported by Gizmo::take_events. Every event carries the transform from
+/// before the drag started, so undo entries can be built without tracking deltas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GizmoEvent {
+    Start {
+        mode: GizmoMode,
+        axis: GizmoAxis,
+        before: Mat4,
+    },
+    Drag {
+        mode: GizmoMode,
+        axis: GizmoAxis,
+        before: Mat4,
+        after: Mat4,
+    },
+    /// the drag finished at `after`, push this onto the undo stack
+    Commit {
+        mode: GizmoMode,
+        axis: GizmoAxis,
+        before: Mat4,
+        after: Mat4,
+    },
+    /// the drag was abandoned, the transform should go back to `before`
+    Cancel {
+        mode: GizmoMode,
+        axis: GizmoAxis,
+        before: Mat4,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GizmoDrag {
+    axis: GizmoAxis,
+    start_transform: Mat4,
+    /// last transform returned by update_drag
+    current_transform: Mat4,
+    /// distance along the axis for translate/scale, angle around it for rotate
+    start_value: f32,
+}
+
+/// Translate/rotate/scale handles around a transform, aligned to the world axes.
+/// Feed it picking rays from mouse input with hover/begin_drag/update_drag/end_drag.
+#[derive(Debug, Clone)]
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    pub snapping: Snapping,
+    /// world space length of the handles
+    pub size: f32,
+    hovered: Option<GizmoAxis>,
+    drag: Option<GizmoDrag>,
+    events: Vec<GizmoEvent>,
+}
+
+/// Draws an infinite grid on the XZ plane, with the X and Z axes highlighted
+#[derive(Debug)]
+pub struct GridRenderer {
+    program: GLuint,
+    vao: GLuint,
+    pub spacing: f32,
+    pub color: Color,
+    freed: bool,
+}
+
+#[derive(Debug)]
+pub struct GizmoRenderer {
+    builder: BufferBuilder<SimpleBufferFormat>,
+    program: GLuint,
+    freed: bool,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3) -> Self {
+        Self {
+            point,
+            normal: normal.normalize(),
+        }
+    }
+
+    /// The ground plane, y = 0
+    pub const XZ: Self = Self { point: Vec3::ZERO, normal: Vec3::Y };
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    /// Builds the ray under a cursor position given in window pixels, with the origin at the top left
+    pub fn from_cursor(cursor: Vec2, viewport_size: Vec2, view_proj: Mat4) -> Self {
+        let ndc = Vec2::new(
+            cursor.x / viewport_size.x * 2. - 1.,
+            1. - cursor.y / viewport_size.y * 2.,
+        );
+        let inverse = view_proj.inverse();
+        let near = inverse.project_point3(ndc.extend(-1.));
+        let far = inverse.project_point3(ndc.extend(1.));
+        Self::new(near, far - near)
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    /// distance along the ray to the plane, None if the plane is parallel or behind the ray
+    pub fn intersect_plane(&self, point: Vec3, normal: Vec3) -> Option<f32> {
+        let denom = self.direction.dot(normal);
+        if denom.abs() < 1e-6 {
+            return None;
+        }
+        let t = (point - self.origin).dot(normal) / denom;
+        if t >= 0. { Some(t) } else { None }
+    }
+
+    /// Finds the closest points between the ray and an infinite line.
+    ///
+    /// # Returns
+    /// (distance along the ray, distance along the line, distance between the two points)
+    pub fn closest_to_line(&self, point: Vec3, direction: Vec3) -> (f32, f32, f32) {
+        let direction = direction.normalize();
+        let w = self.origin - point;
+        let b = self.direction.dot(direction);
+        let d = self.direction.dot(w);
+        let e = direction.dot(w);
+        let denom = 1. - b * b;
+        let (t, s) = if denom.abs() < 1e-6 {
+            (0., e)
+        } else {
+            ((b * e - d) / denom, (e - b * d) / denom)
+        };
+        let t = t.max(0.);
+        (t, s, self.at(t).distance(point + direction * s))
+    }
+}
+
+/// rounds value to the nearest multiple of step
+pub fn snap(value: f32, step: Option<f32>) -> f32 {
+    match step {
+        Some(step) if step > 0. => (value / step).round() * step,
+        _ => value,
+    }
+}
+
+/// rounds every component of a position to the nearest grid line
+pub fn snap_to_grid(position: Vec3, spacing: f32) -> Vec3 {
+    (position / spacing).round() * spacing
+}
+
+impl GizmoAxis {
+    pub const ALL: [GizmoAxis; 3] = [GizmoAxis::X, GizmoAxis::Y, GizmoAxis::Z];
+
+    pub fn vector(&self) -> Vec3 {
+        match self {
+            Self::X => Vec3::X,
+            Self::Y => Vec3::Y,
+            Self::Z => Vec3::Z,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Self::X => Color::rgb(0.9, 0.2, 0.2),
+            Self::Y => Color::rgb(0.2, 0.8, 0.2),
+            Self::Z => Color::rgb(0.2, 0.3, 0.9),
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Self::X => 0,
+            Self::Y => 1,
+            Self::Z => 2,
+        }
+    }
+
+    /// two vectors perpendicular to the axis and each other
+    fn basis(&self) -> (Vec3, Vec3) {
+        match self {
+            Self::X => (Vec3::Y, Vec3::Z),
+            Self::Y => (Vec3::Z, Vec3::X),
+            Self::Z => (Vec3::X, Vec3::Y),
+        }
+    }
+}
+
+impl Gizmo {
+    pub fn new(mode: GizmoMode, size: f32) -> Self {
+        Self {
+            mode,
+            snapping: Snapping::default(),
+            size,
+            hovered: None,
+            drag: None,
+            events: Vec::new(),
+        }
+    }
+
+    /// # Returns
+    /// the manipulation events since the last call, oldest first
+    pub fn take_events(&mut self) -> Vec<GizmoEvent> {
+        mem::take(&mut self.events)
+    }
+
+    pub fn get_hovered(&self) -> Option<GizmoAxis> {
+        self.hovered
+    }
+
+    /// the axis being dragged, if any
+    pub fn get_active(&self) -> Option<GizmoAxis> {
+        self.drag.map(|d| d.axis)
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    /// Finds the handle under the ray, preferring the one closest to the ray origin
+    pub fn hit_test(&self, ray: &Ray, transform: &Mat4) -> Option<GizmoAxis> {
+        let center = transform.w_axis.truncate();
+        let tolerance = self.size * PICK_TOLERANCE;
+        let mut best: Option<(f32, GizmoAxis)> = None;
+
+        for axis in GizmoAxis::ALL {
+            let hit = match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    let (t, s, dist) = ray.closest_to_line(center, axis.vector());
+                    (dist < tolerance && (0. ..=self.size).contains(&s)).then_some(t)
+                }
+                GizmoMode::Rotate => {
+                    ray.intersect_plane(center, axis.vector()).filter(|t| {
+                        (ray.at(*t).distance(center) - self.size).abs() < tolerance
+                    })
+                }
+            };
+
+            if let Some(t) = hit
+                && best.is_none_or(|(bt, _)| t < bt)
+            {
+                best = Some((t, axis));
+            }
+        }
+
+        best.map(|(_, axis)| axis)
+    }
+
+    /// Updates the hovered handle, call this when the cursor moves while not dragging
+    pub fn hover(&mut self, ray: &Ray, transform: &Mat4) -> Option<GizmoAxis> {
+        if self.drag.is_none() {
+            self.hovered = self.hit_test(ray, transform);
+        }
+        self.hovered
+    }
+
+    /// Starts dragging the handle under the ray.
+    /// returns false if no handle was hit
+    pub fn begin_drag(&mut self, ray: &Ray, transform: &Mat4) -> bool {
+        let Some(axis) = self.hit_test(ray, transform) else {
+            return false;
+        };
+        let Some(start_value) = self.drag_value(ray, axis, transform.w_axis.truncate()) else {
+            return false;
+        };
+
+        self.hovered = Some(axis);
+        self.drag = Some(GizmoDrag {
+            axis,
+            start_transform: *transform,
+            current_transform: *transform,
+            start_value,
+        });
+        self.events.push(GizmoEvent::Start {
+            mode: self.mode,
+            axis,
+            before: *transform,
+        });
+        true
+    }
+
+    /// Computes the dragged transform for the current ray.
+    /// returns None if not dragging, or if the ray can't be projected onto the handle
+    pub fn update_drag(&mut self, ray: &Ray) -> Option<Mat4> {
+        let drag = self.drag?;
+        let (scale, rotation, translation) = drag.start_transform.to_scale_rotation_translation();
+        let value = self.drag_value(ray, drag.axis, translation)?;
+        let delta = value - drag.start_value;
+
+        let (scale, rotation, translation) = match self.mode {
+            GizmoMode::Translate => {
+                let delta = snap(delta, self.snapping.translation);
+                (scale, rotation, translation + drag.axis.vector() * delta)
+            }
+            GizmoMode::Rotate => {
+                let delta = snap(delta, self.snapping.rotation);
+                (scale, Quat::from_axis_angle(drag.axis.vector(), delta) * rotation, translation)
+            }
+            GizmoMode::Scale => {
+                let factor = snap(1. + delta / self.size, self.snapping.scale).max(MIN_SCALE_FACTOR);
+                let mut scale = scale;
+                scale[drag.axis.index()] *= factor;
+                (scale, rotation, translation)
+            }
+        };
+
+        let transform = Mat4::from_scale_rotation_translation(scale, rotation, translation);
+        if let Some(drag) = &mut self.drag
+            && drag.current_transform != transform
+        {
+            drag.current_transform = transform;
+            self.events.push(GizmoEvent::Drag {
+                mode: self.mode,
+                axis: drag.axis,
+                before: drag.start_transform,
+                after: transform,
+            });
+        }
+        Some(transform)
+    }
+
+    /// Stops dragging, returning the committed transform.
+    /// Queues a Commit event with the drag's start and committed transforms.
+    pub fn end_drag(&mut self) -> Option<Mat4> {
+        let drag = self.drag.take()?;
+        self.events.push(GizmoEvent::Commit {
+            mode: self.mode,
+            axis: drag.axis,
+            before: drag.start_transform,
+            after: drag.current_transform,
+        });
+        Some(drag.current_transform)
+    }
+
+    /// Abandons the drag, e.g. on escape, returning the transform to restore.
+    /// Queues a Cancel event instead of a Commit.
+    pub fn cancel_drag(&mut self) -> Option<Mat4> {
+        let drag = self.drag.take()?;
+        self.events.push(GizmoEvent::Cancel {
+            mode: self.mode,
+            axis: drag.axis,
+            before: drag.start_transform,
+        });
+        Some(drag.start_transform)
+    }
+
+    fn drag_value(&self, ray: &Ray, axis: GizmoAxis, center: Vec3) -> Option<f32> {
+        match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                let (_, s, _) = ray.closest_to_line(center, axis.vector());
+                Some(s)
+            }
+            GizmoMode::Rotate => {
+                let t = ray.intersect_plane(center, axis.vector())?;
+                let local = ray.at(t) - center;
+                let (u, v) = axis.basis();
+                Some(local.dot(v).atan2(local.dot(u)))
+            }
+        }
+    }
+
+    /// Writes the handle geometry as colored triangles, highlighting the hovered or dragged handle
+    pub fn write_geometry(&self, transform: &Mat4, builder: &mut BufferBuilder<SimpleBufferFormat>) {
+        let center = transform.w_axis.truncate();
+        let thickness = self.size * HANDLE_THICKNESS;
+        let highlight = self.get_active().or(self.hovered);
+
+        for axis in GizmoAxis::ALL {
+            let color = if highlight == Some(axis) {
+                Color::rgb(1., 0.85, 0.1)
+            } else {
+                axis.color()
+            };
+            let dir = axis.vector();
+
+            match self.mode {
+                GizmoMode::Translate => {
+                    write_box(builder, center, center + dir * self.size, thickness, color);
+                    write_box(builder, center + dir * self.size * 0.85, center + dir * self.size, thickness * 3., color);
+                }
+                GizmoMode::Scale => {
+                    write_box(builder, center, center + dir * self.size, thickness, color);
+                    write_box(builder, center + dir * (self.size - thickness * 3.), center + dir * (self.size + thickness * 3.), thickness * 3., color);
+                }
+                GizmoMode::Rotate => {
+                    let (u, v) = axis.basis();
+                    for i in 0..RING_SEGMENTS {
+                        let a0 = i as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                        let a1 = (i + 1) as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                        let p0 = center + (u * a0.cos() + v * a0.sin()) * self.size;
+                        let p1 = center + (u * a1.cos() + v * a1.sin()) * self.size;
+                        write_box(builder, p0, p1, thickness, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// writes a box of the given thickness from a to b
+fn write_box(builder: &mut BufferBuilder<SimpleBufferFormat>, a: Vec3, b: Vec3, thickness: f32, color: Color) {
+    let dir = (b - a).normalize_or_zero();
+    let side = if dir.abs().dot(Vec3::Y) > 0.9 { Vec3::X } else { Vec3::Y };
+    let u = dir.cross(side).normalize() * thickness * 0.5;
+    let v = dir.cross(u).normalize() * thickness * 0.5;
+
+    let corners = [
+        a - u - v, a + u - v, a + u + v, a - u + v,
+        b - u - v, b + u - v, b + u + v, b - u + v,
+    ];
+    let faces = [
+        [0, 1, 2, 3], [4, 7, 6, 5], [0, 4, 5, 1],
+        [1, 5, 6, 2], [2, 6, 7, 3], [3, 7, 4, 0],
+    ];
+
+    for [i0, i1, i2, i3] in faces {
+        for i in [i0, i1, i2, i0, i2, i3] {
+            builder.add_vertex(corners[i]).set_color(color);
+        }
+    }
+}
+
+impl GridRenderer {
+    pub fn new(spacing: f32) -> Result<Self> {
+        let program = compile_program(GRID_VERTEX_SHADER, GRID_FRAGMENT_SHADER)?;
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+        }
+        Ok(Self {
+            program,
+            vao,
+            spacing,
+            color: Color::rgba(0.5, 0.5, 0.5, 0.6),
+            freed: false,
+        })
+    }
+
+    pub fn render(&mut self, gl_state: &mut GlStateManager, view_proj: Mat4) {
+        gl_state.with_blend(BlendMode::Alpha, |gl_state| {
+            gl_state.use_program(self.program);
+            gl_state.bind_vao(self.vao);
+            gl_state.set_uniform(U_INVERSE_VIEW_PROJ, GLUniform::Mat4(view_proj.inverse()));
+            gl_state.set_uniform(U_VIEW_PROJ, GLUniform::Mat4(view_proj));
+            gl_state.set_uniform("u_spacing", GLUniform::F32(self.spacing));
+            gl_state.set_uniform(U_COLOR, GLUniform::Vec4(Vec4::from(self.color.to_array())));
+            unsafe {
+                gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            }
+        });
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        gl_state.destroy_program(self.program);
+        gl_state.destroy_vao(self.vao);
+        self.freed = true;
+    }
+}
+
+impl Drop for GridRenderer {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            let mut state = GlStateManager::new();
+            self.destroy(&mut state);
+            panic!("Grid renderer was not destroyed before dropping")
+        }
+    }
+}
+
+impl GizmoRenderer {
+    pub fn new() -> Result<Self> {
+        let program = compile_program(GIZMO_VERTEX_SHADER, GIZMO_FRAGMENT_SHADER)?;
+        Ok(Self {
+            builder: BufferBuilder::<SimpleBufferFormat>::new(program, true, false, false),
+            program,
+            freed: false,
+        })
+    }
+
+    /// Draws the gizmo on top of the scene
+    pub fn render(&mut self, gl_state: &mut GlStateManager, gizmo: &Gizmo, transform: &Mat4, view_proj: Mat4) -> Result<()> {
+        gizmo.write_geometry(transform, &mut self.builder);
+        self.builder.set_uniform(U_VIEW_PROJ, GLUniform::Mat4(view_proj));
+        gl_state.with_depth_disabled(|gl_state| self.builder.render(gl_state))
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        gl_state.destroy_program(self.program);
+        self.freed = true;
+    }
+}
+
+impl Drop for GizmoRenderer {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            let mut state = GlStateManager::new();
+            self.destroy(&mut state);
+            panic!("Gizmo renderer was not destroyed before dropping")
+        }
+    }
+}
@@ -1,13 +1,60 @@
+use std::time::Instant;
+
+use glam::{Mat4, Vec2, Vec3, Vec4};
 use hecs::World;
 
+use crate::animation::animate_materials;
 use crate::data::GlStateManager;
+use crate::debug_config::{drain_gl_errors, DebugConfig};
+use crate::editor::{Plane, Ray};
+use crate::recording::FrameRecorder;
 use crate::registry::Registry;
+use crate::replay::{InputEvent, Replay};
+use crate::resolution::DynamicResolution;
+use crate::streaming::TextureStreamer;
+use crate::texture::set_assume_srgb_sources;
+use crate::transform::{interpolate_transforms, record_transforms, FixedTimestep};
+use crate::window::ContextId;
+#[cfg(feature = "renderforge-xr")]
+use crate::xr::XrPoses;
+
+type FixedUpdateFn = Box<dyn FnMut(&mut World, f32)>;
 
 pub struct Engine {
     pub gl_state: GlStateManager,
     pub registry: Registry,
     pub ecs: World,
     pub running: bool,
+    /// where each frame's delta time and input come from, see Replay
+    pub replay: Replay,
+    /// seconds since the previous frame, from real time or the replay
+    pub delta_time: f32,
+    /// input of the current frame
+    pub events: Vec<InputEvent>,
+    /// input queued for the next frame
+    pending_events: Vec<InputEvent>,
+    /// background texture loading, updated every frame once enabled
+    pub texture_streamer: Option<TextureStreamer>,
+    /// scene render target scaled from gpu frame times, updated every frame once enabled
+    pub dynamic_resolution: Option<DynamicResolution>,
+    /// size of the window being rendered in logical pixels, see set_screen
+    pub screen_size: Vec2,
+    /// physical pixels per logical pixel, the window's DPI scale
+    pub scale_factor: f32,
+    /// render target pixels per physical pixel, e.g. DynamicResolution::get_scale while rendering
+    /// into its target
+    pub canvas_scale: f32,
+    /// diagnostics in use, see apply_debug_config
+    pub debug: DebugConfig,
+    /// records the window every frame while set, started by DebugConfig::capture_on_start
+    pub recorder: Option<FrameRecorder>,
+    /// rate at which the fixed update runs and GlobalTransforms are recorded, see transform
+    pub fixed_timestep: FixedTimestep,
+    /// game code run at every fixed step, see set_fixed_update
+    fixed_update: Option<FixedUpdateFn>,
+    /// head and controller poses of the current XR frame, see XrSession::render_frame
+    #[cfg(feature = "renderforge-xr")]
+    pub xr_poses: XrPoses,
 }
 
 
@@ -21,16 +68,202 @@ impl Engine {
             registry: Registry::new(),
             ecs: World::new(),
             running: true,
+            replay: Replay::Live,
+            delta_time: 0.,
+            events: Vec::new(),
+            pending_events: Vec::new(),
+            texture_streamer: None,
+            dynamic_resolution: None,
+            screen_size: Vec2::ONE,
+            scale_factor: 1.,
+            canvas_scale: 1.,
+            debug: DebugConfig::default(),
+            recorder: None,
+            fixed_timestep: FixedTimestep::default(),
+            fixed_update: None,
+            #[cfg(feature = "renderforge-xr")]
+            xr_poses: XrPoses::default(),
         }
     }
 
-    pub fn run(self) {
+    /// Turns on the diagnostics of `config` for the current context, e.g. with DebugConfig::load
+    /// to pick them up from the environment and command line. Recording started by
+    /// capture_on_start runs at 30 fps and captures the window at the size given to set_screen.
+    pub fn apply_debug_config(&mut self, config: DebugConfig) {
+        if !config.apply_to_context() {
+            eprintln!("[RenderForge] gl debug output was requested but isn't supported by the context");
+        }
+        if config.capture_on_start && self.recorder.is_none() {
+            let mut recorder = FrameRecorder::new(30.);
+            recorder.start();
+            self.recorder = Some(recorder);
+        }
+        self.debug = config;
+    }
 
-        'mainloop: loop {
+    /// Sets the window size and DPI scale used by the coordinate helpers, call this on resize and
+    /// scale factor changes
+    pub fn set_screen(&mut self, physical_size: (u32, u32), scale_factor: f32) {
+        self.scale_factor = scale_factor;
+        self.screen_size = Vec2::new(physical_size.0 as f32, physical_size.1 as f32) / scale_factor;
+    }
+
+    /// # Returns
+    /// the current gl viewport in screen coordinates: logical pixels from the top left of the
+    /// window, as [x, y, width, height]
+    pub fn screen_viewport(&self) -> Vec4 {
+        let [x, y, w, h] = self.gl_state.get_viewport().map(|v| v as f32 / (self.canvas_scale * self.scale_factor));
+        // gl viewports start at the bottom left
+        Vec4::new(x, self.screen_size.y - y - h, w.max(1e-6), h.max(1e-6))
+    }
+
+    /// Converts a point in screen coordinates (logical pixels from the top left, like cursor
+    /// positions) to normalized device coordinates of the current viewport
+    pub fn screen_to_ndc(&self, screen: Vec2) -> Vec2 {
+        let viewport = self.screen_viewport();
+        Vec2::new(
+            (screen.x - viewport.x) / viewport.z * 2. - 1.,
+            1. - (screen.y - viewport.y) / viewport.w * 2.,
+        )
+    }
 
+    pub fn ndc_to_screen(&self, ndc: Vec2) -> Vec2 {
+        let viewport = self.screen_viewport();
+        Vec2::new(
+            viewport.x + (ndc.x + 1.) * 0.5 * viewport.z,
+            viewport.y + (1. - ndc.y) * 0.5 * viewport.w,
+        )
+    }
+
+    /// Projects a world position with a camera's view projection matrix.
+    ///
+    /// # Returns
+    /// the screen position, None if the point is behind the camera
+    pub fn world_to_screen(&self, view_proj: &Mat4, world: Vec3) -> Option<Vec2> {
+        let clip = *view_proj * world.extend(1.);
+        if clip.w <= 0. {
+            return None;
         }
+        Some(self.ndc_to_screen(clip.truncate().truncate() / clip.w))
+    }
+
+    /// # Returns
+    /// the world space ray through a screen position, for picking
+    pub fn screen_to_ray(&self, view_proj: &Mat4, screen: Vec2) -> Ray {
+        let ndc = self.screen_to_ndc(screen);
+        let inverse = view_proj.inverse();
+        let near = inverse.project_point3(ndc.extend(-1.));
+        let far = inverse.project_point3(ndc.extend(1.));
+        Ray::new(near, far - near)
+    }
+
+    /// # Returns
+    /// where the ray through a screen position hits `plane`, None if it misses
+    pub fn screen_to_world_on_plane(&self, view_proj: &Mat4, screen: Vec2, plane: &Plane) -> Option<Vec3> {
+        let ray = self.screen_to_ray(view_proj, screen);
+        ray.intersect_plane(plane.point, plane.normal).map(|t| ray.at(t))
+    }
+
+    /// Switches the gl state cache and the registry to another context, call this after making the
+    /// context current (e.g. before rendering each window)
+    pub fn make_current(&mut self, context: ContextId) {
+        self.gl_state.set_current_context(context);
+        self.registry.set_current_context(context);
+    }
+
+    /// Treats images uploaded without an explicit format as sRGB and encodes rendering output back
+    /// to sRGB, so shaders work with linear colors. See texture::set_assume_srgb_sources.
+    pub fn set_assume_srgb_sources(&mut self, enabled: bool) {
+        set_assume_srgb_sources(enabled);
+        self.gl_state.framebuffer_srgb(enabled);
     }
 
+    /// Sets the simulation (e.g. physics) the main loop runs at every step of fixed_timestep, with
+    /// the world and the step in seconds. After each step the GlobalTransforms of interpolated
+    /// entities are recorded, and once per frame they are interpolated between the last two
+    /// steps. Without a fixed update the main loop does neither, leaving both to the caller.
+    pub fn set_fixed_update(&mut self, update: impl FnMut(&mut World, f32) + 'static) {
+        self.fixed_update = Some(Box::new(update));
+    }
+
+    /// Queues input for the next frame
+    pub fn push_event(&mut self, event: InputEvent) {
+        self.pending_events.push(event);
+    }
+
+    /// Moves to the next frame's delta time and input, taken from real time and the queued events
+    /// or from the replay. Stops the engine when a replay finishes.
+    pub fn advance_frame(&mut self, real_delta: f32) {
+        let frame = self.replay.next_frame(real_delta, &mut self.pending_events);
+        self.delta_time = frame.delta;
+        self.events = frame.events;
+        if self.replay.is_finished() {
+            self.running = false;
+        }
+    }
+
+    /// Starts background texture streaming with `workers` decoding threads, uploading at most
+    /// `budget` bytes per frame. Needs a current gl context for the placeholder texture.
+    pub fn enable_texture_streaming(&mut self, workers: usize, budget: usize) -> &mut TextureStreamer {
+        self.texture_streamer.get_or_insert_with(|| TextureStreamer::new(workers, budget))
+    }
+
+    /// Renders the scene at a resolution scaled to hold `target_frame_ms` on the gpu. The main
+    /// loop updates the scale every frame from the gpu time of the last finished frame; render the
+    /// scene into DynamicResolution::target, with canvas_scale set to its scale, and upscale it.
+    pub fn enable_dynamic_resolution(&mut self, target_frame_ms: f64) -> &mut DynamicResolution {
+        self.dynamic_resolution.get_or_insert_with(|| DynamicResolution::new(target_frame_ms))
+    }
+
+    pub fn run(mut self) {
+
+        let mut last_frame = Instant::now();
+        'mainloop: loop {
+            if !self.running {
+                break 'mainloop;
+            }
+            let now = Instant::now();
+            self.advance_frame((now - last_frame).as_secs_f32());
+            last_frame = now;
+            self.gl_state.frame_begin();
+            if let Some(resolution) = &mut self.dynamic_resolution {
+                resolution.update_frame(&self.gl_state);
+            }
+            self.registry.update_audio(&mut self.gl_state);
+            animate_materials(&mut self.ecs, self.delta_time);
+            if let Some(update) = &mut self.fixed_update {
+                for _ in 0..self.fixed_timestep.advance(self.delta_time) {
+                    update(&mut self.ecs, self.fixed_timestep.get_step());
+                    record_transforms(&mut self.ecs);
+                }
+                interpolate_transforms(&mut self.ecs, self.fixed_timestep.get_alpha());
+            }
+            if let Some(streamer) = &mut self.texture_streamer {
+                streamer.update(&mut self.gl_state, &mut self.registry);
+            }
+            if let Some(recorder) = &mut self.recorder {
+                // the viewport isn't set until something renders, the window size is known
+                let size = (self.screen_size * self.scale_factor).round();
+                recorder.capture(&mut self.gl_state, [0, 0, size.x as i32, size.y as i32]);
+            }
+            self.gl_state.frame_end();
+            if self.debug.validation {
+                for error in drain_gl_errors() {
+                    eprintln!("[RenderForge] validation: gl error {error:#x} during frame {}", self.gl_state.get_stats().frame);
+                }
+            }
+        }
+        if let Some(resolution) = &mut self.dynamic_resolution {
+            resolution.destroy(&mut self.gl_state);
+        }
+    }
+
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 
@@ -1,12 +1,27 @@
 
 use thiserror::Error;
 
+use crate::mesh::PrimitiveTopology;
+use crate::shader::PrimitiveType;
+
 #[derive(Error, Debug)]
 pub enum BufferRenderError {
     #[error("Data is missing vertices as defined by the buffer layout")]
     IncompleteTriangleData,
+    #[error("Data is not a whole number of {0} vertex patches")]
+    IncompletePatchData(u32),
+    #[error("{count} vertices don't form whole {topology:?} primitives")]
+    IncompletePrimitiveData {
+        topology: PrimitiveTopology,
+        count: u32,
+    },
     #[error("Data does not align with vertex format")]
     MalformedData,
+    #[error("Index {index} is out of range for {vertex_count} vertices")]
+    IndexOutOfRange {
+        index: u32,
+        vertex_count: u32,
+    },
 }
 
 #[derive(Error, Debug)]
@@ -27,5 +42,228 @@ pub enum AtlasError {
 
     #[error("Cannot add texture with same id twice: '{0}'")]
     DuplicateId(String),
+
+    #[error("No free space left on atlas for texture: '{0}'")]
+    AtlasFull(String),
+
+    #[error("Invalid atlas metadata: {0}")]
+    InvalidMetadata(String),
+}
+
+
+#[derive(Error, Debug)]
+pub enum ShaderError {
+    #[error("Shader source contains an interior nul byte")]
+    InvalidSource,
+    #[error("Failed to compile {stage} shader:\n{log}")]
+    Compile {
+        stage: &'static str,
+        log: String,
+    },
+    #[error("Failed to link shader program:\n{0}")]
+    Link(String),
+    #[error("Malformed #include directive: '{0}'")]
+    MalformedInclude(String),
+    #[error("Included shader file does not exist: '{0}'")]
+    MissingInclude(String),
+    #[error("Shader file includes itself: '{0}'")]
+    RecursiveInclude(String),
+    #[error("SPIR-V binary is malformed")]
+    InvalidSpirv,
+    #[error("SPIR-V shaders are not supported by this context and no GLSL fallback was given")]
+    SpirvUnsupported,
+    #[error("Attribute at location {location} expects {expected} components, layout provides {found}")]
+    AttributeSizeMismatch {
+        location: u32,
+        expected: u32,
+        found: u32,
+    },
+    #[error("Shader attribute '{name}' at location {location} is not provided by the layout")]
+    UnboundAttribute {
+        name: String,
+        location: u32,
+    },
+    #[error("Shader attribute '{name}' is at location {found}, expected at location {expected}")]
+    SemanticMismatch {
+        name: String,
+        expected: u32,
+        found: u32,
+    },
+    #[error("Shader attribute '{name}' is at location {location}, where the vertex format puts '{expected}'")]
+    SemanticConflict {
+        name: String,
+        location: u32,
+        expected: String,
+    },
+    #[error("{count} defines give too many shader permutations, at most {max} are allowed")]
+    TooManyPermutations {
+        count: usize,
+        max: usize,
+    },
+    #[error("Program has no active uniform block named '{0}'")]
+    MissingUniformBlock(String),
+    #[error("Geometry shader expects {expected:?} input, but is drawn with {found:?}")]
+    PrimitiveMismatch {
+        expected: PrimitiveType,
+        found: PrimitiveType,
+    },
 }
 
+#[derive(Error, Debug)]
+pub enum TextureError {
+    #[error("LUT strip must be size*size pixels wide and size pixels tall, got {width}x{height}")]
+    InvalidLutDimensions {
+        width: u32,
+        height: u32,
+    },
+    #[error("Mip level {level} should be {expected:?}, got {found:?}")]
+    MipSizeMismatch {
+        level: u32,
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    #[error("Channel packing needs at least one source, and all sources must be the same size")]
+    MismatchedChannelSources,
+    #[error("Invalid texture container: {0}")]
+    InvalidContainer(String),
+    #[error("Unsupported texture container contents: {0}")]
+    UnsupportedContainerFormat(String),
+    #[error("Compressed mip level {level} should be {expected} bytes, got {found}")]
+    CompressedSizeMismatch {
+        level: u32,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Array texture layers must all be the size of the array, got a {width}x{height} layer")]
+    LayerSizeMismatch {
+        width: u32,
+        height: u32,
+    },
+    #[error("3D texture needs at least one slice, and all slices must be the same size")]
+    MismatchedSlices,
+    #[error("Cubemap faces must all be square and the same size")]
+    MismatchedCubemapFaces,
+    #[error("Cubemap cross must be a 4x3 or 3x4 grid of square faces, got {width}x{height}")]
+    InvalidCubemapCross {
+        width: u32,
+        height: u32,
+    },
+    #[error("Region {width}x{height} at ({x}, {y}) does not fit in a {texture_width}x{texture_height} texture")]
+    RegionOutOfBounds {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        texture_width: u32,
+        texture_height: u32,
+    },
+    #[error("Region updates are only supported on 2D and 2D array textures")]
+    UnsupportedRegionTarget,
+    #[error("Layer {layer} is out of range for a texture with {layers} layers")]
+    LayerOutOfRange {
+        layer: u32,
+        layers: u32,
+    },
+    #[error("Texture cannot be attached to a framebuffer for reading (status {0:#x})")]
+    UnreadableTexture(u32),
+}
+
+#[derive(Error, Debug)]
+pub enum BufferError {
+    #[error("Cannot write {count} elements at offset {offset} of a buffer with {len} elements")]
+    OutOfRange {
+        offset: usize,
+        count: usize,
+        len: usize,
+    },
+    #[error("Failed to map buffer")]
+    MapFailed,
+}
+
+#[derive(Error, Debug)]
+pub enum FramebufferError {
+    #[error("Framebuffer is incomplete (status 0x{0:x})")]
+    Incomplete(u32),
+    #[error("{requested} samples requested, the driver supports at most {max}")]
+    TooManySamples {
+        requested: u32,
+        max: u32,
+    },
+    #[error("Multisampled attachments can't have mipmaps")]
+    MultisampledMipmaps,
+    #[error("Multisampled framebuffers can't have a depth texture")]
+    MultisampledDepthTexture,
+    #[error("Framebuffer has no color attachment")]
+    MissingColorAttachment,
+}
+
+#[derive(Error, Debug)]
+pub enum RenderGraphError {
+    #[error("Render pass '{pass}' targets missing framebuffer '{target}'")]
+    MissingTarget {
+        pass: String,
+        target: String,
+    },
+    #[error("Render pass '{pass}' samples the depth of '{target}', which has no depth texture")]
+    MissingDepthTexture {
+        pass: String,
+        target: String,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum RecordingError {
+    #[error("No frames have been recorded")]
+    NoFrames,
+}
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error("Invalid replay line {line}: '{content}'")]
+    InvalidLine {
+        line: usize,
+        content: String,
+    },
+    #[error("Replay event on line {line} comes before the first frame")]
+    EventBeforeFrame {
+        line: usize,
+    },
+    #[error("Replay is not recording")]
+    NotRecording,
+}
+
+#[derive(Error, Debug)]
+pub enum MaterialDefinitionError {
+    #[error("Invalid material definition: {0}")]
+    Parse(String),
+    #[error("Material '{material}' declares '{name}' more than once")]
+    DuplicateName {
+        material: String,
+        name: String,
+    },
+    #[error("Material '{material}' binds '{first}' and '{second}' to texture slot {slot}")]
+    SlotConflict {
+        material: String,
+        first: String,
+        second: String,
+        slot: u32,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum TimestepError {
+    #[error("Fixed timestep must be a positive number of seconds, got {0}")]
+    InvalidStep(f32),
+}
+
+#[cfg(feature = "renderforge-xr")]
+#[derive(Error, Debug)]
+pub enum XrError {
+    #[error("XR frame has {found} views, the session has {expected} eyes")]
+    ViewCountMismatch {
+        expected: usize,
+        found: usize,
+    },
+    #[error("XR eye target '{0}' was removed from the render graph")]
+    MissingTarget(String),
+}
@@ -0,0 +1,203 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ptr;
+
+use gl::types::{GLsizeiptr, GLsync, GLuint};
+use image::{imageops, RgbaImage};
+
+/// Name of the gpu time of a whole frame in GlStateManager::get_gpu_timings, measured from
+/// frame_begin to frame_end with timestamps, so it doesn't interfere with other timers
+pub const FRAME_TIMING: &str = "frame";
+
+/// GL state changes made since the last GlStateManager::frame_begin, only counting changes that
+/// actually reached GL
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// frames started so far
+    pub frame: u64,
+    pub program_binds: u32,
+    pub vao_binds: u32,
+    pub fbo_binds: u32,
+    pub texture_binds: u32,
+    pub uniform_uploads: u32,
+}
+
+/// A gl object queued for deletion with GlStateManager::defer_delete
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlObject {
+    Buffer(GLuint),
+    VertexArray(GLuint),
+    Framebuffer(GLuint),
+    Renderbuffer(GLuint),
+    Texture(GLuint),
+    Program(GLuint),
+}
+
+/// A framebuffer read started with GlStateManager::read_pixels_async, waiting on its fence
+pub(crate) struct PendingReadback {
+    pub(crate) pbo: GLuint,
+    pub(crate) fence: GLsync,
+    pub(crate) size: (u32, u32),
+    pub(crate) callback: Box<dyn FnOnce(RgbaImage)>,
+}
+
+impl fmt::Debug for PendingReadback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PendingReadback")
+            .field("pbo", &self.pbo)
+            .field("fence", &self.fence)
+            .field("size", &self.size)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Per-frame housekeeping owned by GlStateManager
+#[derive(Debug)]
+pub(crate) struct FrameQueue {
+    pub(crate) deletions: Vec<GlObject>,
+    pub(crate) readbacks: Vec<PendingReadback>,
+    /// (timer name, query) waiting for their results
+    pub(crate) pending_timers: Vec<(String, GLuint)>,
+    pub(crate) active_timer: Option<(String, GLuint)>,
+    /// latest resolved gpu time of each timer, in milliseconds
+    pub(crate) timings: HashMap<String, f64>,
+    /// timestamp query issued by frame_begin, paired with one from frame_end
+    pub(crate) frame_start: Option<GLuint>,
+    /// (start, end) timestamp queries of finished frames waiting for their results
+    pub(crate) pending_frames: Vec<(GLuint, GLuint)>,
+    pub(crate) fences: VecDeque<GLsync>,
+    pub(crate) max_frames_in_flight: Option<usize>,
+}
+
+impl FrameQueue {
+    pub(crate) fn new() -> Self {
+        Self {
+            deletions: Vec::new(),
+            readbacks: Vec::new(),
+            pending_timers: Vec::new(),
+            active_timer: None,
+            timings: HashMap::new(),
+            frame_start: None,
+            pending_frames: Vec::new(),
+            fences: VecDeque::new(),
+            max_frames_in_flight: None,
+        }
+    }
+
+    /// Reads back every timer query whose result is ready, without stalling on the rest
+    pub(crate) fn resolve_timers(&mut self) {
+        let timings = &mut self.timings;
+        self.pending_timers.retain(|(name, query)| unsafe {
+            let mut available = 0;
+            gl::GetQueryObjectiv(*query, gl::QUERY_RESULT_AVAILABLE, &mut available);
+            if available == 0 {
+                return true;
+            }
+            let mut nanos = 0u64;
+            gl::GetQueryObjectui64v(*query, gl::QUERY_RESULT, &mut nanos);
+            gl::DeleteQueries(1, query);
+            timings.insert(name.clone(), nanos as f64 / 1_000_000.);
+            false
+        });
+        // the end timestamp is written last, so the start is ready once it is
+        self.pending_frames.retain(|(start, end)| unsafe {
+            let mut available = 0;
+            gl::GetQueryObjectiv(*end, gl::QUERY_RESULT_AVAILABLE, &mut available);
+            if available == 0 {
+                return true;
+            }
+            let (mut start_nanos, mut end_nanos) = (0u64, 0u64);
+            gl::GetQueryObjectui64v(*start, gl::QUERY_RESULT, &mut start_nanos);
+            gl::GetQueryObjectui64v(*end, gl::QUERY_RESULT, &mut end_nanos);
+            gl::DeleteQueries(2, [*start, *end].as_ptr());
+            timings.insert(FRAME_TIMING.to_string(), end_nanos.saturating_sub(start_nanos) as f64 / 1_000_000.);
+            false
+        });
+    }
+
+    /// Records the gpu timestamp a frame starts at
+    pub(crate) fn begin_frame_timing(&mut self) {
+        if self.frame_start.is_none() {
+            self.frame_start = Some(timestamp());
+        }
+    }
+
+    /// Records the gpu timestamp the frame ends at, pairing it with begin_frame_timing's
+    pub(crate) fn end_frame_timing(&mut self) {
+        if let Some(start) = self.frame_start.take() {
+            self.pending_frames.push((start, timestamp()));
+        }
+    }
+
+    /// Blocks until at most max_frames_in_flight fenced frames are still running on the gpu
+    pub(crate) fn wait_fences(&mut self) {
+        let Some(max) = self.max_frames_in_flight else {
+            return;
+        };
+        while self.fences.len() > max {
+            let fence = self.fences.pop_front().unwrap();
+            unsafe {
+                gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, u64::MAX);
+                gl::DeleteSync(fence);
+            }
+        }
+    }
+
+    pub(crate) fn insert_fence(&mut self) {
+        if self.max_frames_in_flight.is_some() {
+            unsafe {
+                self.fences.push_back(gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0));
+            }
+        }
+    }
+
+    /// Hands every readback whose fence has signaled to its callback, without stalling on the rest
+    pub(crate) fn resolve_readbacks(&mut self) {
+        let mut ready = Vec::new();
+        let mut i = 0;
+        while i < self.readbacks.len() {
+            let status = unsafe { gl::ClientWaitSync(self.readbacks[i].fence, 0, 0) };
+            if status == gl::ALREADY_SIGNALED || status == gl::CONDITION_SATISFIED {
+                ready.push(self.readbacks.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+
+        for readback in ready {
+            let (width, height) = readback.size;
+            let len = width as usize * height as usize * 4;
+            let mut pixels = vec![0u8; len];
+            unsafe {
+                gl::DeleteSync(readback.fence);
+                gl::BindBuffer(gl::PIXEL_PACK_BUFFER, readback.pbo);
+                let mapped = gl::MapBufferRange(
+                    gl::PIXEL_PACK_BUFFER,
+                    0,
+                    len as GLsizeiptr,
+                    gl::MAP_READ_BIT,
+                );
+                if !mapped.is_null() {
+                    ptr::copy_nonoverlapping(mapped as *const u8, pixels.as_mut_ptr(), len);
+                    gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+                }
+                gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+                gl::DeleteBuffers(1, &readback.pbo);
+            }
+            let image = RgbaImage::from_raw(width, height, pixels).unwrap();
+            // gl rows start at the bottom
+            (readback.callback)(imageops::flip_vertical(&image));
+        }
+    }
+}
+
+/// # Returns
+/// a query that receives the gpu time once the commands issued so far have finished
+fn timestamp() -> GLuint {
+    let mut query = 0;
+    unsafe {
+        gl::GenQueries(1, &mut query);
+        gl::QueryCounter(query, gl::TIMESTAMP);
+    }
+    query
+}
@@ -0,0 +1,387 @@
+use std::ptr;
+use std::thread::panicking;
+
+use anyhow::Result;
+use gl::types::{GLenum, GLuint};
+
+use crate::data::GlStateManager;
+use crate::errors::FramebufferError;
+
+/// Pixel format of a framebuffer color attachment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentFormat {
+    R8,
+    Rgba8,
+    Rg16F,
+    Rgba16F,
+    Rgba32F,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ColorAttachment {
+    pub texture: GLuint,
+    pub format: AttachmentFormat,
+    /// TEXTURE_2D_MULTISAMPLE when the framebuffer has more than one sample, TEXTURE_2D otherwise
+    pub target: GLenum,
+    /// Regenerate the texture's mipmaps after every pass that renders into it
+    pub generate_mipmaps: bool,
+}
+
+/// Builds a Framebuffer, see Framebuffer::builder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FramebufferBuilder {
+    width: u32,
+    height: u32,
+    color: Vec<(AttachmentFormat, bool)>,
+    depth: bool,
+    depth_texture: bool,
+    samples: u32,
+}
+
+/// An offscreen render target with owned color textures and an optional depth renderbuffer or texture
+#[derive(Debug)]
+pub struct Framebuffer {
+    fbo: GLuint,
+    width: u32,
+    height: u32,
+    color_attachments: Vec<ColorAttachment>,
+    depth: Option<GLuint>,
+    /// whether `depth` is a texture instead of a renderbuffer
+    depth_texture: bool,
+    samples: u32,
+    /// whether color attachment 0 renders into a texture owned elsewhere, see set_external_color
+    external_color: bool,
+    freed: bool,
+}
+
+impl AttachmentFormat {
+    /// # Returns
+    /// (internal format, format, type)
+    pub fn to_gl(&self) -> (GLenum, GLenum, GLenum) {
+        match self {
+            Self::R8 => (gl::R8, gl::RED, gl::UNSIGNED_BYTE),
+            Self::Rgba8 => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE),
+            Self::Rg16F => (gl::RG16F, gl::RG, gl::FLOAT),
+            Self::Rgba16F => (gl::RGBA16F, gl::RGBA, gl::FLOAT),
+            Self::Rgba32F => (gl::RGBA32F, gl::RGBA, gl::FLOAT),
+        }
+    }
+}
+
+impl FramebufferBuilder {
+    pub fn with_color_attachment(mut self, format: AttachmentFormat) -> Self {
+        self.color.push((format, false));
+        self
+    }
+
+    /// Adds a color attachment with a full mip chain that is regenerated after each pass rendering into it,
+    /// for bloom chains and blurred backgrounds
+    pub fn with_mipmapped_color_attachment(mut self, format: AttachmentFormat) -> Self {
+        self.color.push((format, true));
+        self
+    }
+
+    pub fn with_depth(mut self) -> Self {
+        self.depth = true;
+        self
+    }
+
+    /// Adds a depth buffer stored in a texture, so later passes can sample the scene depth, for soft
+    /// particles and screen space effects. Not available for multisampled framebuffers.
+    pub fn with_depth_texture(mut self) -> Self {
+        self.depth = true;
+        self.depth_texture = true;
+        self
+    }
+
+    /// Renders with `samples` samples per pixel for MSAA. Color attachments become
+    /// TEXTURE_2D_MULTISAMPLE textures and the depth buffer a multisampled renderbuffer, which can't
+    /// be sampled like regular textures, see Framebuffer::resolve. 0 and 1 disable multisampling.
+    pub fn with_samples(mut self, samples: u32) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn build(self, gl_state: &mut GlStateManager) -> Result<Framebuffer> {
+        let multisampled = self.samples > 1;
+        if multisampled {
+            if self.color.iter().any(|(_, mipmaps)| *mipmaps) {
+                return Err(FramebufferError::MultisampledMipmaps.into());
+            }
+            if self.depth_texture {
+                return Err(FramebufferError::MultisampledDepthTexture.into());
+            }
+            let max = get_max_samples();
+            if self.samples > max {
+                return Err(FramebufferError::TooManySamples { requested: self.samples, max }.into());
+            }
+        }
+        let target = if multisampled { gl::TEXTURE_2D_MULTISAMPLE } else { gl::TEXTURE_2D };
+
+        unsafe {
+            let mut fbo = 0;
+            gl::GenFramebuffers(1, &mut fbo);
+            let previous_fbo = gl_state.get_fbo();
+            gl_state.bind_fbo(fbo);
+
+            let mut color_attachments = Vec::with_capacity(self.color.len());
+            let mut draw_buffers = Vec::with_capacity(self.color.len());
+            for (i, (format, generate_mipmaps)) in self.color.into_iter().enumerate() {
+                let (internal, pixel_format, ty) = format.to_gl();
+
+                let mut texture = 0;
+                gl::GenTextures(1, &mut texture);
+                gl::BindTexture(target, texture);
+                if multisampled {
+                    gl::TexImage2DMultisample(target, self.samples as i32, internal, self.width as i32, self.height as i32, gl::TRUE);
+                } else {
+                    gl::TexImage2D(gl::TEXTURE_2D, 0, internal as i32, self.width as i32, self.height as i32, 0, pixel_format, ty, ptr::null());
+                    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+                    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+                    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+                    if generate_mipmaps {
+                        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
+                        gl::GenerateMipmap(gl::TEXTURE_2D);
+                    } else {
+                        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+                    }
+                }
+
+                let attachment = gl::COLOR_ATTACHMENT0 + i as GLenum;
+                gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment, target, texture, 0);
+                draw_buffers.push(attachment);
+                color_attachments.push(ColorAttachment {
+                    texture,
+                    format,
+                    target,
+                    generate_mipmaps,
+                });
+            }
+            gl::DrawBuffers(draw_buffers.len() as i32, draw_buffers.as_ptr());
+
+            let depth = if self.depth_texture {
+                let mut texture = 0;
+                gl::GenTextures(1, &mut texture);
+                gl::BindTexture(gl::TEXTURE_2D, texture);
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    gl::DEPTH24_STENCIL8 as i32,
+                    self.width as i32,
+                    self.height as i32,
+                    0,
+                    gl::DEPTH_STENCIL,
+                    gl::UNSIGNED_INT_24_8,
+                    ptr::null(),
+                );
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+                gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::TEXTURE_2D, texture, 0);
+                Some(texture)
+            } else if self.depth {
+                let mut rbo = 0;
+                gl::GenRenderbuffers(1, &mut rbo);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, rbo);
+                if multisampled {
+                    gl::RenderbufferStorageMultisample(gl::RENDERBUFFER, self.samples as i32, gl::DEPTH24_STENCIL8, self.width as i32, self.height as i32);
+                } else {
+                    gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, self.width as i32, self.height as i32);
+                }
+                gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, rbo);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+                Some(rbo)
+            } else {
+                None
+            };
+
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl_state.bind_fbo(previous_fbo);
+
+            let mut framebuffer = Framebuffer {
+                fbo,
+                width: self.width,
+                height: self.height,
+                color_attachments,
+                depth,
+                depth_texture: self.depth_texture,
+                samples: self.samples.max(1),
+                external_color: false,
+                freed: false,
+            };
+
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                framebuffer.destroy(gl_state);
+                return Err(FramebufferError::Incomplete(status).into());
+            }
+
+            Ok(framebuffer)
+        }
+    }
+}
+
+impl Framebuffer {
+    pub fn builder(width: u32, height: u32) -> FramebufferBuilder {
+        FramebufferBuilder {
+            width,
+            height,
+            color: Vec::new(),
+            depth: false,
+            depth_texture: false,
+            samples: 1,
+        }
+    }
+
+    pub fn get_id(&self) -> GLuint {
+        self.fbo
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// # Returns
+    /// the samples per pixel, 1 if the framebuffer isn't multisampled
+    pub fn get_samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn get_color_attachments(&self) -> &[ColorAttachment] {
+        &self.color_attachments
+    }
+
+    /// # Returns
+    /// the texture of color attachment `index`
+    pub fn get_texture(&self, index: usize) -> Option<GLuint> {
+        self.color_attachments.get(index).map(|a| a.texture)
+    }
+
+    /// # Returns
+    /// the depth texture of a framebuffer built with_depth_texture
+    pub fn get_depth_texture(&self) -> Option<GLuint> {
+        self.depth.filter(|_| self.depth_texture)
+    }
+
+    /// A handle to the same gl objects that doesn't own them, so destroying or dropping it is up to
+    /// the original
+    pub(crate) fn alias(&self) -> Framebuffer {
+        Framebuffer {
+            color_attachments: self.color_attachments.clone(),
+            freed: true,
+            ..*self
+        }
+    }
+
+    /// Renders color attachment 0 into `texture` instead, a 2D texture of the framebuffer's size
+    /// owned elsewhere, e.g. a swapchain image of an XR runtime. The attachment's own texture is
+    /// deleted the first time, and destroy leaves the external texture alone.
+    pub fn set_external_color(&mut self, gl_state: &mut GlStateManager, texture: GLuint) -> Result<()> {
+        let Some(attachment) = self.color_attachments.first_mut() else {
+            return Err(FramebufferError::MissingColorAttachment.into());
+        };
+        if !self.external_color {
+            gl_state.destroy_texture(attachment.texture);
+            self.external_color = true;
+        }
+        attachment.texture = texture;
+        attachment.target = gl::TEXTURE_2D;
+        let previous_fbo = gl_state.get_fbo();
+        gl_state.bind_fbo(self.fbo);
+        let status = unsafe {
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, texture, 0);
+            gl::CheckFramebufferStatus(gl::FRAMEBUFFER)
+        };
+        gl_state.bind_fbo(previous_fbo);
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(FramebufferError::Incomplete(status).into());
+        }
+        Ok(())
+    }
+
+    /// Binds the framebuffer and sets the viewport to cover it
+    pub fn bind(&self, gl_state: &mut GlStateManager) {
+        gl_state.bind_fbo(self.fbo);
+        gl_state.viewport([0, 0, self.width as i32, self.height as i32]);
+    }
+
+    /// Regenerates the mipmaps of every attachment created with generate_mipmaps.
+    /// The render graph calls this after each pass that targets the framebuffer.
+    pub fn finish_pass(&self) {
+        for attachment in &self.color_attachments {
+            if attachment.generate_mipmaps {
+                unsafe {
+                    gl::BindTexture(attachment.target, attachment.texture);
+                    gl::GenerateMipmap(gl::TEXTURE_2D);
+                }
+            }
+        }
+    }
+
+    /// Resolves the multisampled color attachment `index` into the draw buffers of `target` (0 for
+    /// the default framebuffer), and the depth buffer when `depth` is set. Both framebuffers must
+    /// have the same size. Also works as a plain copy between framebuffers that aren't multisampled.
+    pub fn resolve(&self, gl_state: &GlStateManager, index: usize, target: GLuint, depth: bool) {
+        let (width, height) = (self.width as i32, self.height as i32);
+        let mask = if depth && self.depth.is_some() { gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT } else { gl::COLOR_BUFFER_BIT };
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.fbo);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target);
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0 + index as GLenum);
+            gl::BlitFramebuffer(0, 0, width, height, 0, 0, width, height, mask, gl::NEAREST);
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+            // back to the framebuffer the state manager thinks is bound
+            gl::BindFramebuffer(gl::FRAMEBUFFER, gl_state.get_fbo());
+        }
+    }
+
+    /// Copies color attachment `index` into `rect` of the framebuffer bound on `gl_state`,
+    /// stretching it with linear filtering if the sizes differ
+    pub fn blit_to_bound(&self, gl_state: &GlStateManager, index: usize, rect: [i32; 4]) {
+        let [x, y, width, height] = rect;
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.fbo);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, gl_state.get_fbo());
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0 + index as GLenum);
+            gl::BlitFramebuffer(0, 0, self.width as i32, self.height as i32, x, y, x + width, y + height, gl::COLOR_BUFFER_BIT, gl::LINEAR);
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, gl_state.get_fbo());
+        }
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        gl_state.destroy_fbo(self.fbo);
+        // an external texture is only ever attachment 0
+        for attachment in &self.color_attachments[usize::from(self.external_color)..] {
+            gl_state.destroy_texture(attachment.texture);
+        }
+        match self.depth {
+            Some(texture) if self.depth_texture => gl_state.destroy_texture(texture),
+            Some(rbo) => unsafe {
+                gl::DeleteRenderbuffers(1, &rbo);
+            },
+            None => {}
+        }
+        self.freed = true;
+    }
+}
+
+/// # Returns
+/// the most samples per pixel the driver supports for multisampled render targets
+pub fn get_max_samples() -> u32 {
+    let mut max = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAX_SAMPLES, &mut max);
+    }
+    max.max(1) as u32
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            let mut state = GlStateManager::new();
+            self.destroy(&mut state);
+            panic!("Framebuffer was not destroyed before dropping")
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! A fake gl backend that records calls instead of executing them, so state handling can be
+//! tested without a context. `load` points the gl function pointers at recording stubs; calls are
+//! collected per thread, so tests running in parallel don't see each other's calls.
+//!
+//! Only the calls made by GlState's state setters are recorded. Buffer and vertex array calls
+//! are accepted without being recorded so meshes can be created, with gen calls handing out ids
+//! counting up from 1. Anything else panics as an unloaded gl function. Queries return fixed
+//! values: uniform locations are 0.
+
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::Once;
+
+use gl::types::{GLboolean, GLchar, GLenum, GLfloat, GLint, GLintptr, GLsizei, GLsizeiptr, GLuint};
+
+/// A recorded gl call with its arguments
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlCall {
+    Enable(GLenum),
+    Disable(GLenum),
+    UseProgram(GLuint),
+    BindVertexArray(GLuint),
+    BindFramebuffer(GLenum, GLuint),
+    BlendFunc(GLenum, GLenum),
+    BlendFuncSeparate(GLenum, GLenum, GLenum, GLenum),
+    BlendEquationSeparate(GLenum, GLenum),
+    DepthFunc(GLenum),
+    DepthMask(bool),
+    CullFace(GLenum),
+    FrontFace(GLenum),
+    ColorMask([bool; 4]),
+    Scissor([i32; 4]),
+    Viewport([i32; 4]),
+    BindBufferBase(GLenum, GLuint, GLuint),
+    ActiveTexture(GLenum),
+    BindTexture(GLenum, GLuint),
+    BindSampler(GLuint, GLuint),
+    GetUniformLocation(GLuint),
+    Uniform1f(GLint, f32),
+    Uniform1i(GLint, i32),
+}
+
+thread_local! {
+    static CALLS: RefCell<Vec<GlCall>> = const { RefCell::new(Vec::new()) };
+    static NEXT_ID: Cell<GLuint> = const { Cell::new(1) };
+}
+
+static LOAD: Once = Once::new();
+
+/// Points the gl function pointers at the recording stubs. Safe to call from every test, the
+/// pointers are only written once.
+pub fn load() {
+    LOAD.call_once(|| gl::load_with(stub));
+}
+
+/// # Returns
+/// the calls recorded on this thread since the last take_calls or clear, oldest first
+pub fn take_calls() -> Vec<GlCall> {
+    CALLS.with(|calls| calls.take())
+}
+
+/// Forgets the calls recorded on this thread
+pub fn clear() {
+    CALLS.with(|calls| calls.borrow_mut().clear());
+}
+
+fn record(call: GlCall) {
+    CALLS.with(|calls| calls.borrow_mut().push(call));
+}
+
+fn stub(name: &str) -> *const c_void {
+    match name {
+        "glEnable" => enable as *const c_void,
+        "glDisable" => disable as *const c_void,
+        "glUseProgram" => use_program as *const c_void,
+        "glBindVertexArray" => bind_vertex_array as *const c_void,
+        "glBindFramebuffer" => bind_framebuffer as *const c_void,
+        "glBlendFunc" => blend_func as *const c_void,
+        "glBlendFuncSeparate" => blend_func_separate as *const c_void,
+        "glBlendEquationSeparate" => blend_equation_separate as *const c_void,
+        "glDepthFunc" => depth_func as *const c_void,
+        "glDepthMask" => depth_mask as *const c_void,
+        "glCullFace" => cull_face as *const c_void,
+        "glFrontFace" => front_face as *const c_void,
+        "glColorMask" => color_mask as *const c_void,
+        "glScissor" => scissor as *const c_void,
+        "glViewport" => viewport as *const c_void,
+        "glBindBufferBase" => bind_buffer_base as *const c_void,
+        "glActiveTexture" => active_texture as *const c_void,
+        "glBindTexture" => bind_texture as *const c_void,
+        "glBindSampler" => bind_sampler as *const c_void,
+        "glGetUniformLocation" => get_uniform_location as *const c_void,
+        "glUniform1f" => uniform_1f as *const c_void,
+        "glUniform1i" => uniform_1i as *const c_void,
+        "glGenVertexArrays" | "glGenBuffers" => gen_objects as *const c_void,
+        "glDeleteVertexArrays" | "glDeleteBuffers" => delete_objects as *const c_void,
+        "glBindBuffer" => bind_buffer as *const c_void,
+        "glBufferData" => buffer_data as *const c_void,
+        "glBufferSubData" => buffer_sub_data as *const c_void,
+        "glVertexAttribPointer" => vertex_attrib_pointer as *const c_void,
+        "glEnableVertexAttribArray" => enable_vertex_attrib_array as *const c_void,
+        "glVertexAttribDivisor" => vertex_attrib_divisor as *const c_void,
+        _ => ptr::null(),
+    }
+}
+
+extern "system" fn enable(cap: GLenum) {
+    record(GlCall::Enable(cap));
+}
+
+extern "system" fn disable(cap: GLenum) {
+    record(GlCall::Disable(cap));
+}
+
+extern "system" fn use_program(program: GLuint) {
+    record(GlCall::UseProgram(program));
+}
+
+extern "system" fn bind_vertex_array(vao: GLuint) {
+    record(GlCall::BindVertexArray(vao));
+}
+
+extern "system" fn bind_framebuffer(target: GLenum, fbo: GLuint) {
+    record(GlCall::BindFramebuffer(target, fbo));
+}
+
+extern "system" fn blend_func(src: GLenum, dst: GLenum) {
+    record(GlCall::BlendFunc(src, dst));
+}
+
+extern "system" fn blend_func_separate(src_rgb: GLenum, dst_rgb: GLenum, src_alpha: GLenum, dst_alpha: GLenum) {
+    record(GlCall::BlendFuncSeparate(src_rgb, dst_rgb, src_alpha, dst_alpha));
+}
+
+extern "system" fn blend_equation_separate(rgb: GLenum, alpha: GLenum) {
+    record(GlCall::BlendEquationSeparate(rgb, alpha));
+}
+
+extern "system" fn depth_func(func: GLenum) {
+    record(GlCall::DepthFunc(func));
+}
+
+extern "system" fn depth_mask(enabled: GLboolean) {
+    record(GlCall::DepthMask(enabled != gl::FALSE));
+}
+
+extern "system" fn cull_face(face: GLenum) {
+    record(GlCall::CullFace(face));
+}
+
+extern "system" fn front_face(winding: GLenum) {
+    record(GlCall::FrontFace(winding));
+}
+
+extern "system" fn color_mask(r: GLboolean, g: GLboolean, b: GLboolean, a: GLboolean) {
+    record(GlCall::ColorMask([r, g, b, a].map(|c| c != gl::FALSE)));
+}
+
+extern "system" fn scissor(x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
+    record(GlCall::Scissor([x, y, width, height]));
+}
+
+extern "system" fn viewport(x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
+    record(GlCall::Viewport([x, y, width, height]));
+}
+
+extern "system" fn bind_buffer_base(target: GLenum, binding: GLuint, buffer: GLuint) {
+    record(GlCall::BindBufferBase(target, binding, buffer));
+}
+
+extern "system" fn active_texture(unit: GLenum) {
+    record(GlCall::ActiveTexture(unit));
+}
+
+extern "system" fn bind_texture(target: GLenum, tex: GLuint) {
+    record(GlCall::BindTexture(target, tex));
+}
+
+extern "system" fn bind_sampler(unit: GLuint, sampler: GLuint) {
+    record(GlCall::BindSampler(unit, sampler));
+}
+
+extern "system" fn get_uniform_location(program: GLuint, _name: *const GLchar) -> GLint {
+    record(GlCall::GetUniformLocation(program));
+    0
+}
+
+extern "system" fn uniform_1f(location: GLint, value: GLfloat) {
+    record(GlCall::Uniform1f(location, value));
+}
+
+extern "system" fn uniform_1i(location: GLint, value: GLint) {
+    record(GlCall::Uniform1i(location, value));
+}
+
+extern "system" fn gen_objects(n: GLsizei, ids: *mut GLuint) {
+    for i in 0..n as usize {
+        let id = NEXT_ID.with(|next| next.replace(next.get() + 1));
+        unsafe { *ids.add(i) = id };
+    }
+}
+
+extern "system" fn delete_objects(_n: GLsizei, _ids: *const GLuint) {}
+
+extern "system" fn bind_buffer(_target: GLenum, _buffer: GLuint) {}
+
+extern "system" fn buffer_data(_target: GLenum, _size: GLsizeiptr, _data: *const c_void, _usage: GLenum) {}
+
+extern "system" fn buffer_sub_data(_target: GLenum, _offset: GLintptr, _size: GLsizeiptr, _data: *const c_void) {}
+
+extern "system" fn vertex_attrib_pointer(_index: GLuint, _size: GLint, _ty: GLenum, _normalized: GLboolean, _stride: GLsizei, _pointer: *const c_void) {}
+
+extern "system" fn enable_vertex_attrib_array(_index: GLuint) {}
+
+extern "system" fn vertex_attrib_divisor(_index: GLuint, _divisor: GLuint) {}
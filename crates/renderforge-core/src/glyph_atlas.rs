@@ -0,0 +1,324 @@
+//! Glyph cache for text rendering, packed with the atlas module into the layers of a 2D array
+//! texture. Glyphs are rasterized the first time they are requested, by a GlyphRasterizer wrapping
+//! whatever font library the application uses, and go onto the first page (layer) with room.
+//! Fonts can fall back to other fonts for characters they don't have (e.g. latin, then CJK, then
+//! emoji); the glyphs of every font share the array, so mixed text still draws from one texture.
+//! When every page is full, the least recently used glyphs are evicted and the rest are repacked.
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::iter;
+
+use anyhow::Result;
+use gl::types::GLuint;
+use glam::Vec2;
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use rect_packer::Config;
+
+use crate::atlas::{AtlasPacker, AtlasRect};
+use crate::data::GlStateManager;
+use crate::errors::AtlasError;
+use crate::frame::GlObject;
+use crate::texture::{MagFilter, MinFilter, Texture, TextureArrayBuilder};
+
+/// A glyph of a font at a pixel size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlyphKey {
+    /// the application's id for the font
+    pub font: u32,
+    pub character: char,
+    /// pixel height the glyph is rasterized at
+    pub size: u32,
+}
+
+/// Layout of a glyph, in pixels with y pointing down
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GlyphMetrics {
+    /// how far the pen moves after the glyph
+    pub advance: f32,
+    /// offset from the pen position on the baseline to the top left of the bitmap
+    pub bearing: Vec2,
+    /// size of the bitmap
+    pub size: (u32, u32),
+}
+
+/// A cached glyph, the rect is None for glyphs without pixels like spaces
+#[derive(Debug, Clone, Copy)]
+pub struct Glyph {
+    pub metrics: GlyphMetrics,
+    pub rect: Option<AtlasRect>,
+    /// the array layer holding the glyph's pixels
+    pub layer: u32,
+}
+
+/// Turns glyphs into bitmaps, implemented over a font library such as ab_glyph or fontdue
+pub trait GlyphRasterizer {
+    /// # Returns
+    /// the glyph's metrics and its coverage as white pixels with alpha, or None if the font
+    /// doesn't have the glyph. The bitmap is ignored for glyphs with an empty size.
+    fn rasterize(&mut self, key: &GlyphKey) -> Option<(GlyphMetrics, DynamicImage)>;
+}
+
+#[derive(Debug)]
+struct CachedGlyph {
+    glyph: Glyph,
+    /// kept for repacking after evictions, None for glyphs without pixels
+    bitmap: Option<DynamicImage>,
+    last_used: u64,
+}
+
+pub struct GlyphAtlas {
+    texture: Texture,
+    /// one packer per layer of the texture
+    pages: Vec<AtlasPacker>,
+    rasterizer: Box<dyn GlyphRasterizer>,
+    glyphs: HashMap<GlyphKey, CachedGlyph>,
+    /// glyphs the rasterizer doesn't have, so fallbacks don't rasterize them again
+    missing: HashSet<GlyphKey>,
+    /// fonts tried in order for characters a font doesn't have, see set_fallbacks
+    fallbacks: HashMap<u32, Vec<u32>>,
+    page_size: (u32, u32),
+    /// counts frames for least recently used eviction, see next_frame
+    frame: u64,
+    /// counts repacks, see get_generation
+    generation: u32,
+}
+
+impl GlyphAtlas {
+    /// An empty glyph atlas of `page_count` pages of `page_size`, evicting glyphs once every
+    /// page is full
+    pub fn new(gl_state: &mut GlStateManager, rasterizer: impl GlyphRasterizer + 'static, page_size: (u32, u32), page_count: u32) -> Result<Self> {
+        let mut builder = TextureArrayBuilder::new(page_size, MinFilter::Linear, MagFilter::Linear);
+        for _ in 0..page_count.max(1) {
+            builder.add_layer(DynamicImage::ImageRgba8(RgbaImage::new(page_size.0, page_size.1)))?;
+        }
+        Ok(Self {
+            texture: builder.build(gl_state),
+            pages: (0..page_count.max(1)).map(|_| Self::packer(page_size)).collect(),
+            rasterizer: Box::new(rasterizer),
+            glyphs: HashMap::new(),
+            missing: HashSet::new(),
+            fallbacks: HashMap::new(),
+            page_size,
+            frame: 0,
+            generation: 0,
+        })
+    }
+
+    fn packer(page_size: (u32, u32)) -> AtlasPacker {
+        // one pixel apart so linear filtering doesn't pick up the neighbouring glyph
+        AtlasPacker::new(Config {
+            width: page_size.0 as i32,
+            height: page_size.1 as i32,
+            border_padding: 1,
+            rectangle_padding: 1,
+        })
+    }
+
+    /// Marks the start of a frame. Glyphs fetched during the current frame are never evicted, so
+    /// the glyphs of the text being drawn stay valid until the next call.
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Fetches a glyph, rasterizing and packing it on first use. Packing can repack the pages,
+    /// which changes the layers and uvs of every glyph, see get_generation.
+    ///
+    /// # Returns
+    /// None if the font doesn't have the glyph, AtlasError::AtlasFull if it doesn't fit even
+    /// after evicting every glyph not used this frame
+    pub fn get(&mut self, gl_state: &mut GlStateManager, key: GlyphKey) -> Result<Option<Glyph>> {
+        if let Some(cached) = self.glyphs.get_mut(&key) {
+            cached.last_used = self.frame;
+            return Ok(Some(cached.glyph));
+        }
+        if self.missing.contains(&key) {
+            return Ok(None);
+        }
+        let Some((metrics, bitmap)) = self.rasterizer.rasterize(&key) else {
+            self.missing.insert(key);
+            return Ok(None);
+        };
+        let bitmap = (metrics.size.0 > 0 && metrics.size.1 > 0).then_some(bitmap);
+        let (rect, layer) = match &bitmap {
+            Some(bitmap) => {
+                let (rect, layer) = self.insert(gl_state, bitmap)?;
+                (Some(rect), layer)
+            }
+            None => (None, 0),
+        };
+        let glyph = Glyph { metrics, rect, layer };
+        self.glyphs.insert(key, CachedGlyph {
+            glyph,
+            bitmap,
+            last_used: self.frame,
+        });
+        Ok(Some(glyph))
+    }
+
+    /// Sets the fonts tried in order for characters `font` doesn't have, e.g. a CJK font and then an
+    /// emoji font after a latin font. The fallbacks' own fallbacks aren't followed. An empty list
+    /// removes the chain.
+    pub fn set_fallbacks(&mut self, font: u32, fallbacks: Vec<u32>) {
+        if fallbacks.is_empty() {
+            self.fallbacks.remove(&font);
+        } else {
+            self.fallbacks.insert(font, fallbacks);
+        }
+    }
+
+    pub fn get_fallbacks(&self, font: u32) -> &[u32] {
+        self.fallbacks.get(&font).map_or(&[], |fallbacks| fallbacks.as_slice())
+    }
+
+    /// Fetches a glyph from `key.font`, or from the first of its fallbacks that has the character,
+    /// see get and set_fallbacks.
+    ///
+    /// # Returns
+    /// the key of the font the glyph came from along with the glyph, None if no font of the chain
+    /// has the character
+    pub fn resolve(&mut self, gl_state: &mut GlStateManager, key: GlyphKey) -> Result<Option<(GlyphKey, Glyph)>> {
+        let chain: Vec<u32> = iter::once(key.font).chain(self.get_fallbacks(key.font).iter().copied()).collect();
+        for font in chain {
+            let key = GlyphKey { font, ..key };
+            if let Some(glyph) = self.get(gl_state, key)? {
+                return Ok(Some((key, glyph)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Packs and uploads a bitmap onto the first page with room, evicting glyphs if none has
+    fn insert(&mut self, gl_state: &mut GlStateManager, bitmap: &DynamicImage) -> Result<(AtlasRect, u32)> {
+        if let Some(placed) = self.place(gl_state, bitmap)? {
+            return Ok(placed);
+        }
+        self.evict(gl_state, bitmap.dimensions())?;
+        self.place(gl_state, bitmap)?
+            .ok_or_else(|| AtlasError::AtlasFull(self.describe()).into())
+    }
+
+    fn place(&mut self, gl_state: &mut GlStateManager, bitmap: &DynamicImage) -> Result<Option<(AtlasRect, u32)>> {
+        let page = self.pages.iter_mut()
+            .enumerate()
+            .find_map(|(layer, packer)| packer.pack_texture(bitmap.dimensions(), 0).map(|(rect, _)| (rect, layer as u32)));
+        let Some((rect, layer)) = page else {
+            return Ok(None);
+        };
+        self.texture.update_layer_region(gl_state, layer, rect.0, rect.1, bitmap)?;
+        Ok(Some((AtlasRect::new(self.page_size, rect, false), layer)))
+    }
+
+    /// Drops the least recently used glyphs until the survivors fill at most half of the pages,
+    /// keeping room for `needed`, then repacks the survivors from the first page on
+    fn evict(&mut self, gl_state: &mut GlStateManager, needed: (u32, u32)) -> Result<()> {
+        let capacity = self.page_size.0 as u64 * self.page_size.1 as u64 * self.pages.len() as u64;
+        let budget = (capacity / 2).saturating_sub(needed.0 as u64 * needed.1 as u64);
+
+        let mut by_recency: Vec<_> = self.glyphs.iter()
+            .filter(|(_, cached)| cached.bitmap.is_some())
+            .map(|(key, cached)| (*key, cached.last_used, cached.glyph.metrics.size))
+            .collect();
+        by_recency.sort_by_key(|(_, last_used, _)| Reverse(*last_used));
+        let mut kept_area = 0;
+        let mut evicted = Vec::new();
+        for (key, last_used, (w, h)) in by_recency {
+            let area = w as u64 * h as u64;
+            if last_used == self.frame || kept_area + area <= budget {
+                kept_area += area;
+            } else {
+                evicted.push(key);
+            }
+        }
+        if evicted.is_empty() {
+            return Err(AtlasError::AtlasFull(self.describe()).into());
+        }
+        for key in &evicted {
+            self.glyphs.remove(key);
+        }
+
+        self.reset_pages(gl_state)?;
+        let mut survivors: Vec<_> = self.glyphs.iter()
+            .filter_map(|(key, cached)| cached.bitmap.clone().map(|bitmap| (*key, bitmap)))
+            .collect();
+        // largest first packs tightest, like AtlasBuilder
+        survivors.sort_by_key(|(_, bitmap)| Reverse(bitmap.width() * bitmap.height()));
+        for (key, bitmap) in survivors {
+            let (rect, layer) = self.place(gl_state, &bitmap)?
+                .ok_or_else(|| AtlasError::AtlasFull(self.describe()))?;
+            if let Some(cached) = self.glyphs.get_mut(&key) {
+                cached.glyph.rect = Some(rect);
+                cached.glyph.layer = layer;
+            }
+        }
+        Ok(())
+    }
+
+    /// Empties every page, clearing the pixels so filtering doesn't pick up old glyphs
+    fn reset_pages(&mut self, gl_state: &mut GlStateManager) -> Result<()> {
+        let blank = DynamicImage::ImageRgba8(RgbaImage::new(self.page_size.0, self.page_size.1));
+        for (layer, packer) in self.pages.iter_mut().enumerate() {
+            *packer = Self::packer(self.page_size);
+            self.texture.update_layer_region(gl_state, layer as u32, 0, 0, &blank)?;
+        }
+        self.generation += 1;
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!("glyph atlas of {} {}x{} pages", self.pages.len(), self.page_size.0, self.page_size.1)
+    }
+
+    /// # Returns
+    /// the gl 2D array texture holding the glyphs
+    pub fn get_id(&self) -> GLuint {
+        self.texture.get_id()
+    }
+
+    pub fn get_texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    pub fn get_page_size(&self) -> (u32, u32) {
+        self.page_size
+    }
+
+    pub fn get_page_count(&self) -> u32 {
+        self.pages.len() as u32
+    }
+
+    /// Counts the repacks that moved glyphs to other layers and uvs. Glyphs fetched before a
+    /// change must be fetched again.
+    pub fn get_generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn get_glyph_count(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    /// Forgets every glyph, keeping the texture and the fallbacks
+    pub fn clear(&mut self, gl_state: &mut GlStateManager) -> Result<()> {
+        self.reset_pages(gl_state)?;
+        self.glyphs.clear();
+        self.missing.clear();
+        Ok(())
+    }
+
+    /// Deletes the texture at the next frame_end
+    pub fn destroy(self, gl_state: &mut GlStateManager) {
+        gl_state.defer_delete(GlObject::Texture(self.texture.into_raw()));
+    }
+}
+
+impl fmt::Debug for GlyphAtlas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GlyphAtlas")
+            .field("texture", &self.texture)
+            .field("pages", &self.pages.len())
+            .field("glyphs", &self.glyphs.len())
+            .field("frame", &self.frame)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,517 @@
+use std::os::raw::c_void;
+use std::path::Path;
+use std::ptr;
+
+use anyhow::Result;
+use gl::types::{GLenum, GLint, GLsizei, GLuint};
+use glam::{Mat4, Vec3};
+
+use crate::data::{GLUniform, GlStateManager};
+use crate::registry::{Registry, Resource};
+use crate::semantics::U_VIEW_PROJ;
+use crate::shader::{compile_program, compile_program_with_includes, ShaderIncludes};
+use crate::texture::{upload_hdr_image, Texture, TextureTarget};
+
+const CUBE_VERTEX_SHADER: &str = r#"#version 330 core
+layout(location = 0) in vec3 a_position;
+
+uniform mat4 u_view_proj;
+
+out vec3 v_direction;
+
+void main() {
+    v_direction = a_position;
+    gl_Position = u_view_proj * vec4(a_position, 1.0);
+}
+"#;
+
+const IRRADIANCE_FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec3 v_direction;
+
+uniform samplerCube u_environment;
+
+out vec4 frag_color;
+
+const float PI = 3.14159265359;
+
+void main() {
+    vec3 normal = normalize(v_direction);
+    vec3 up = abs(normal.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+    vec3 right = normalize(cross(up, normal));
+    up = cross(normal, right);
+
+    vec3 irradiance = vec3(0.0);
+    float samples = 0.0;
+    float delta = 0.025;
+    for (float phi = 0.0; phi < 2.0 * PI; phi += delta) {
+        for (float theta = 0.0; theta < 0.5 * PI; theta += delta) {
+            vec3 tangent = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            vec3 dir = tangent.x * right + tangent.y * up + tangent.z * normal;
+            irradiance += texture(u_environment, dir).rgb * cos(theta) * sin(theta);
+            samples += 1.0;
+        }
+    }
+
+    frag_color = vec4(PI * irradiance / samples, 1.0);
+}
+"#;
+
+const EQUIRECTANGULAR_FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec3 v_direction;
+
+uniform sampler2D u_equirectangular;
+
+out vec4 frag_color;
+
+const vec2 INV_ATAN = vec2(0.1591, 0.3183);
+
+void main() {
+    vec3 dir = normalize(v_direction);
+    vec2 uv = vec2(atan(dir.z, dir.x), asin(dir.y)) * INV_ATAN + 0.5;
+    // image rows are uploaded top first, so the top of the sky is at v = 0
+    uv.y = 1.0 - uv.y;
+    frag_color = vec4(texture(u_equirectangular, uv).rgb, 1.0);
+}
+"#;
+
+const IMPORTANCE_SAMPLING: &str = r#"
+const float PI = 3.14159265359;
+
+float radical_inverse(uint bits) {
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return float(bits) * 2.3283064365386963e-10;
+}
+
+vec2 hammersley(uint i, uint n) {
+    return vec2(float(i) / float(n), radical_inverse(i));
+}
+
+vec3 importance_sample_ggx(vec2 xi, vec3 n, float roughness) {
+    float a = roughness * roughness;
+    float phi = 2.0 * PI * xi.x;
+    float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    vec3 h = vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+    vec3 up = abs(n.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(up, n));
+    vec3 bitangent = cross(n, tangent);
+    return normalize(tangent * h.x + bitangent * h.y + n * h.z);
+}
+"#;
+
+const PREFILTER_FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec3 v_direction;
+
+uniform samplerCube u_environment;
+uniform float u_roughness;
+uniform float u_resolution;
+
+out vec4 frag_color;
+
+#include "importance_sampling"
+
+const uint SAMPLE_COUNT = 1024u;
+
+float distribution_ggx(float n_dot_h, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+void main() {
+    vec3 n = normalize(v_direction);
+    vec3 v = n;
+
+    vec3 color = vec3(0.0);
+    float weight = 0.0;
+    for (uint i = 0u; i < SAMPLE_COUNT; ++i) {
+        vec2 xi = hammersley(i, SAMPLE_COUNT);
+        vec3 h = importance_sample_ggx(xi, n, u_roughness);
+        vec3 l = normalize(2.0 * dot(v, h) * h - v);
+
+        float n_dot_l = max(dot(n, l), 0.0);
+        if (n_dot_l > 0.0) {
+            // low probability samples read from a blurrier mip to avoid bright speckles
+            float n_dot_h = max(dot(n, h), 0.0);
+            float h_dot_v = max(dot(h, v), 0.0);
+            float pdf = distribution_ggx(n_dot_h, u_roughness) * n_dot_h / (4.0 * h_dot_v) + 0.0001;
+            float sa_texel = 4.0 * PI / (6.0 * u_resolution * u_resolution);
+            float sa_sample = 1.0 / (float(SAMPLE_COUNT) * pdf + 0.0001);
+            float mip = u_roughness == 0.0 ? 0.0 : 0.5 * log2(sa_sample / sa_texel);
+
+            color += textureLod(u_environment, l, mip).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+
+    frag_color = vec4(color / weight, 1.0);
+}
+"#;
+
+const BRDF_VERTEX_SHADER: &str = r#"#version 330 core
+out vec2 v_uv;
+
+void main() {
+    // fullscreen triangle
+    vec2 p = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    v_uv = p;
+    gl_Position = vec4(p * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+const BRDF_FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec2 v_uv;
+
+out vec2 frag_color;
+
+#include "importance_sampling"
+
+const uint SAMPLE_COUNT = 1024u;
+
+float geometry_schlick_ggx(float n_dot_v, float roughness) {
+    float k = (roughness * roughness) / 2.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+void main() {
+    float n_dot_v = max(v_uv.x, 0.0001);
+    float roughness = v_uv.y;
+
+    vec3 v = vec3(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    vec3 n = vec3(0.0, 0.0, 1.0);
+
+    float a = 0.0;
+    float b = 0.0;
+    for (uint i = 0u; i < SAMPLE_COUNT; ++i) {
+        vec2 xi = hammersley(i, SAMPLE_COUNT);
+        vec3 h = importance_sample_ggx(xi, n, roughness);
+        vec3 l = normalize(2.0 * dot(v, h) * h - v);
+
+        float n_dot_l = max(l.z, 0.0);
+        float n_dot_h = max(h.z, 0.0);
+        float v_dot_h = max(dot(v, h), 0.0);
+
+        if (n_dot_l > 0.0) {
+            float g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+            float g_vis = (g * v_dot_h) / (n_dot_h * n_dot_v);
+            float fc = pow(1.0 - v_dot_h, 5.0);
+            a += (1.0 - fc) * g_vis;
+            b += fc * g_vis;
+        }
+    }
+
+    frag_color = vec2(a, b) / float(SAMPLE_COUNT);
+}
+"#;
+
+#[rustfmt::skip]
+const CUBE_VERTICES: [f32; 108] = [
+    -1., -1., -1.,  1.,  1., -1.,  1., -1., -1.,
+     1.,  1., -1., -1., -1., -1., -1.,  1., -1.,
+    -1., -1.,  1.,  1., -1.,  1.,  1.,  1.,  1.,
+     1.,  1.,  1., -1.,  1.,  1., -1., -1.,  1.,
+    -1.,  1.,  1., -1.,  1., -1., -1., -1., -1.,
+    -1., -1., -1., -1., -1.,  1., -1.,  1.,  1.,
+     1.,  1.,  1.,  1., -1., -1.,  1.,  1., -1.,
+     1., -1., -1.,  1.,  1.,  1.,  1., -1.,  1.,
+    -1., -1., -1.,  1., -1., -1.,  1., -1.,  1.,
+     1., -1.,  1., -1., -1.,  1., -1., -1., -1.,
+    -1.,  1., -1.,  1.,  1.,  1.,  1.,  1., -1.,
+     1.,  1.,  1., -1.,  1., -1., -1.,  1.,  1.,
+];
+
+/// Output sizes for IblMaps::generate
+#[derive(Debug, Clone, Copy)]
+pub struct IblSettings {
+    pub irradiance_size: u32,
+    pub prefiltered_size: u32,
+    /// mip 0 is roughness 0, the last mip is roughness 1
+    pub prefiltered_mip_levels: u32,
+    pub brdf_lut_size: u32,
+}
+
+/// The maps image based lighting samples: a diffuse irradiance cubemap, a specular cubemap
+/// prefiltered per roughness into its mip chain, and a split-sum BRDF lookup table
+#[derive(Debug, Clone, Copy)]
+pub struct IblMaps {
+    pub irradiance: GLuint,
+    pub prefiltered: GLuint,
+    pub prefiltered_mip_levels: u32,
+    pub brdf_lut: GLuint,
+}
+
+/// Renders a unit cube into each face of a cubemap, with each face's view looking out from the center
+pub(crate) struct CubeCapture {
+    fbo: GLuint,
+    vao: GLuint,
+    vbo: GLuint,
+}
+
+impl Default for IblSettings {
+    fn default() -> Self {
+        Self {
+            irradiance_size: 32,
+            prefiltered_size: 128,
+            prefiltered_mip_levels: 5,
+            brdf_lut_size: 512,
+        }
+    }
+}
+
+impl CubeCapture {
+    /// (look direction, up) for each face, in TEXTURE_CUBE_MAP_POSITIVE_X + i order
+    const FACES: [(Vec3, Vec3); 6] = [
+        (Vec3::X, Vec3::NEG_Y),
+        (Vec3::NEG_X, Vec3::NEG_Y),
+        (Vec3::Y, Vec3::Z),
+        (Vec3::NEG_Y, Vec3::NEG_Z),
+        (Vec3::Z, Vec3::NEG_Y),
+        (Vec3::NEG_Z, Vec3::NEG_Y),
+    ];
+
+    pub(crate) fn new() -> Self {
+        unsafe {
+            let mut fbo = 0;
+            gl::GenFramebuffers(1, &mut fbo);
+
+            let mut vao = 0;
+            gl::GenVertexArrays(1, &mut vao);
+            gl::BindVertexArray(vao);
+
+            let mut vbo = 0;
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, size_of_val(&CUBE_VERTICES) as isize, CUBE_VERTICES.as_ptr() as *const c_void, gl::STATIC_DRAW);
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, (3 * size_of::<f32>()) as GLsizei, ptr::null());
+            gl::EnableVertexAttribArray(0);
+
+            gl::BindVertexArray(0);
+
+            Self { fbo, vao, vbo }
+        }
+    }
+
+    /// Draws the cube with `program` into all six faces of one mip level of `target`.
+    /// The program's uniforms other than u_view_proj must already be set.
+    pub(crate) fn render_faces(&self, gl_state: &mut GlStateManager, program: GLuint, target: GLuint, mip: u32, size: u32) {
+        let projection = Mat4::perspective_rh_gl(90f32.to_radians(), 1., 0.1, 10.);
+
+        gl_state.scoped(|gl_state| {
+            gl_state.bind_fbo(self.fbo);
+            gl_state.viewport([0, 0, size as i32, size as i32]);
+            gl_state.scissor_test(false);
+            gl_state.depth_test(false);
+            gl_state.culling(false);
+            gl_state.blending(false);
+            gl_state.use_program(program);
+            gl_state.bind_vao(self.vao);
+
+            for (i, (dir, up)) in Self::FACES.iter().enumerate() {
+                gl_state.set_uniform(U_VIEW_PROJ, GLUniform::Mat4(projection * Mat4::look_at_rh(Vec3::ZERO, *dir, *up)));
+                unsafe {
+                    gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as GLenum, target, mip as GLint);
+                    gl::Clear(gl::COLOR_BUFFER_BIT);
+                    gl::DrawArrays(gl::TRIANGLES, 0, 36);
+                }
+            }
+        });
+    }
+
+    pub(crate) fn destroy(self, gl_state: &mut GlStateManager) {
+        gl_state.destroy_fbo(self.fbo);
+        gl_state.destroy_vbo_vec(vec![self.vbo]);
+        gl_state.destroy_vao(self.vao);
+    }
+}
+
+/// Allocates an empty RGB16F cubemap, with a full mip chain if `mipmapped`
+pub(crate) fn create_empty_cubemap(size: u32, mipmapped: bool) -> GLuint {
+    unsafe {
+        let mut tex_id = 0;
+        gl::GenTextures(1, &mut tex_id);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, tex_id);
+
+        for face in 0..6 {
+            gl::TexImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + face, 0, gl::RGB16F as i32, size as i32, size as i32, 0, gl::RGB, gl::FLOAT, ptr::null());
+        }
+
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        if mipmapped {
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
+            gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+        } else {
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        }
+
+        tex_id
+    }
+}
+
+/// Projects an equirectangular (latitude/longitude) texture onto the faces of a new RGB16F cubemap.
+/// If `mipmapped` is set the cubemap gets a full mip chain, as needed for create_prefiltered_map.
+pub fn equirectangular_to_cubemap(gl_state: &mut GlStateManager, equirectangular: GLuint, size: u32, mipmapped: bool) -> Result<GLuint> {
+    let program = compile_program(CUBE_VERTEX_SHADER, EQUIRECTANGULAR_FRAGMENT_SHADER)?;
+    let target = create_empty_cubemap(size, mipmapped);
+    let capture = CubeCapture::new();
+
+    gl_state.use_program(program);
+    gl_state.set_uniform("u_equirectangular", GLUniform::I32(0));
+    gl_state.bind_texture(0, equirectangular);
+    capture.render_faces(gl_state, program, target, 0, size);
+
+    if mipmapped {
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, target);
+            gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+        }
+    }
+
+    capture.destroy(gl_state);
+    gl_state.destroy_program(program);
+    Ok(target)
+}
+
+/// Loads an equirectangular HDR image (such as a .hdr file) and converts it into a mipmapped cubemap
+/// that skyboxes and IblMaps::generate can use directly
+pub fn load_hdr_cubemap(gl_state: &mut GlStateManager, path: impl AsRef<Path>, size: u32) -> Result<GLuint> {
+    let img = image::open(path)?;
+    let (equirectangular, _) = upload_hdr_image(&img);
+    let cubemap = equirectangular_to_cubemap(gl_state, equirectangular, size, true);
+    gl_state.destroy_texture(equirectangular);
+    cubemap
+}
+
+fn compile_with_sampling(vertex: &str, fragment: &str) -> Result<GLuint> {
+    let mut includes = ShaderIncludes::new();
+    includes.add("importance_sampling", IMPORTANCE_SAMPLING);
+    compile_program_with_includes(vertex, fragment, &includes)
+}
+
+/// Convolves an environment cubemap into a diffuse irradiance cubemap
+pub fn create_irradiance_map(gl_state: &mut GlStateManager, environment: GLuint, size: u32) -> Result<GLuint> {
+    let program = compile_program(CUBE_VERTEX_SHADER, IRRADIANCE_FRAGMENT_SHADER)?;
+    let target = create_empty_cubemap(size, false);
+    let capture = CubeCapture::new();
+
+    gl_state.use_program(program);
+    gl_state.set_uniform("u_environment", GLUniform::I32(0));
+    gl_state.bind_texture_cube(0, environment);
+    capture.render_faces(gl_state, program, target, 0, size);
+
+    capture.destroy(gl_state);
+    gl_state.destroy_program(program);
+    Ok(target)
+}
+
+/// Prefilters an environment cubemap for GGX specular lighting, storing increasing roughness in
+/// each mip level. Mipmaps are generated for the environment map, since the filter samples them.
+pub fn create_prefiltered_map(gl_state: &mut GlStateManager, environment: GLuint, size: u32, mip_levels: u32) -> Result<GLuint> {
+    let program = compile_with_sampling(CUBE_VERTEX_SHADER, PREFILTER_FRAGMENT_SHADER)?;
+    let target = create_empty_cubemap(size, true);
+    let capture = CubeCapture::new();
+
+    let mut environment_size = 0;
+    gl_state.bind_texture_cube(0, environment);
+    unsafe {
+        gl::GetTexLevelParameteriv(gl::TEXTURE_CUBE_MAP_POSITIVE_X, 0, gl::TEXTURE_WIDTH, &mut environment_size);
+        gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+    }
+
+    gl_state.use_program(program);
+    gl_state.set_uniform("u_environment", GLUniform::I32(0));
+    gl_state.set_uniform("u_resolution", GLUniform::F32(environment_size as f32));
+
+    let mip_levels = mip_levels.max(1);
+    for mip in 0..mip_levels {
+        let mip_size = (size >> mip).max(1);
+        let roughness = if mip_levels > 1 { mip as f32 / (mip_levels - 1) as f32 } else { 0. };
+        gl_state.use_program(program);
+        gl_state.set_uniform("u_roughness", GLUniform::F32(roughness));
+        capture.render_faces(gl_state, program, target, mip, mip_size);
+    }
+
+    unsafe {
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, target);
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAX_LEVEL, (mip_levels - 1) as i32);
+    }
+
+    capture.destroy(gl_state);
+    gl_state.destroy_program(program);
+    Ok(target)
+}
+
+/// Renders the split-sum BRDF integration table as an RG16F texture, indexed by (n dot v, roughness)
+pub fn create_brdf_lut(gl_state: &mut GlStateManager, size: u32) -> Result<GLuint> {
+    let program = compile_with_sampling(BRDF_VERTEX_SHADER, BRDF_FRAGMENT_SHADER)?;
+
+    unsafe {
+        let mut target = 0;
+        gl::GenTextures(1, &mut target);
+        gl::BindTexture(gl::TEXTURE_2D, target);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RG16F as i32, size as i32, size as i32, 0, gl::RG, gl::FLOAT, ptr::null());
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+
+        let mut fbo = 0;
+        let mut vao = 0;
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::GenVertexArrays(1, &mut vao);
+
+        gl_state.scoped(|gl_state| {
+            gl_state.bind_fbo(fbo);
+            gl_state.viewport([0, 0, size as i32, size as i32]);
+            gl_state.scissor_test(false);
+            gl_state.depth_test(false);
+            gl_state.blending(false);
+            gl_state.use_program(program);
+            gl_state.bind_vao(vao);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, target, 0);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        });
+
+        gl_state.destroy_fbo(fbo);
+        gl_state.destroy_vao(vao);
+        gl_state.destroy_program(program);
+        Ok(target)
+    }
+}
+
+impl IblMaps {
+    /// Generates every IBL map from an environment cubemap
+    pub fn generate(gl_state: &mut GlStateManager, environment: GLuint, settings: IblSettings) -> Result<Self> {
+        let irradiance = create_irradiance_map(gl_state, environment, settings.irradiance_size)?;
+        let prefiltered = create_prefiltered_map(gl_state, environment, settings.prefiltered_size, settings.prefiltered_mip_levels)?;
+        let brdf_lut = create_brdf_lut(gl_state, settings.brdf_lut_size)?;
+        Ok(Self {
+            irradiance,
+            prefiltered,
+            prefiltered_mip_levels: settings.prefiltered_mip_levels.max(1),
+            brdf_lut,
+        })
+    }
+
+    /// Adds the maps to the registry as the textures `{name}_irradiance`, `{name}_prefiltered` and `{name}_brdf_lut`.
+    /// The registry takes ownership of the textures, so the maps must not be destroyed afterwards.
+    pub fn register(self, registry: &mut Registry, name: &str) {
+        registry.add(format!("{name}_irradiance"), Resource::Texture(Texture::from_raw(self.irradiance, TextureTarget::CubeMap)));
+        registry.add(format!("{name}_prefiltered"), Resource::Texture(Texture::from_raw(self.prefiltered, TextureTarget::CubeMap)));
+        registry.add(format!("{name}_brdf_lut"), Resource::Texture(Texture::from_raw(self.brdf_lut, TextureTarget::Texture2D)));
+    }
+
+    pub fn destroy(&self, gl_state: &mut GlStateManager) {
+        gl_state.destroy_texture(self.irradiance);
+        gl_state.destroy_texture(self.prefiltered);
+        gl_state.destroy_texture(self.brdf_lut);
+    }
+}
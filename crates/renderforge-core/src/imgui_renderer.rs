@@ -0,0 +1,242 @@
+//! Renders dear imgui draw data through the GlStateManager. Every state change goes through the
+//! state cache inside a snapshot, so the engine's state is restored once the UI is drawn.
+
+use std::os::raw::c_void;
+use std::thread::panicking;
+
+use anyhow::Result;
+use gl::types::{GLenum, GLint, GLsizei, GLuint};
+use glam::{Mat4, Vec2};
+use image::{DynamicImage, RgbaImage};
+use imgui::{BackendFlags, Condition, Context, DrawCmd, DrawCmdParams, DrawData, DrawIdx, DrawVert, TextureId, Ui};
+
+use crate::data::{BlendMode, GLUniform, GlStateManager};
+use crate::debug_config::profiler_summary;
+use crate::semantics::U_TEXTURE;
+use crate::shader::compile_program;
+use crate::texture::{upload_image, MagFilter, MinFilter, TextureWrap, WrapMode};
+
+const IMGUI_VERTEX_SHADER: &str = r#"#version 330 core
+layout(location = 0) in vec2 a_position;
+layout(location = 1) in vec2 a_uv;
+layout(location = 2) in vec4 a_color;
+
+uniform mat4 u_projection;
+
+out vec2 v_uv;
+out vec4 v_color;
+
+void main() {
+    v_uv = a_uv;
+    v_color = a_color;
+    gl_Position = u_projection * vec4(a_position, 0.0, 1.0);
+}
+"#;
+
+const IMGUI_FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec2 v_uv;
+in vec4 v_color;
+
+uniform sampler2D u_texture;
+
+out vec4 frag_color;
+
+void main() {
+    frag_color = v_color * texture(u_texture, v_uv);
+}
+"#;
+
+const INDEX_TYPE: GLenum = if size_of::<DrawIdx>() == 2 { gl::UNSIGNED_SHORT } else { gl::UNSIGNED_INT };
+
+/// A dear imgui renderer backend. Texture ids in the draw data are gl texture ids, so images can
+/// be drawn with `TextureId::new(texture as usize)`.
+#[derive(Debug)]
+pub struct ImguiRenderer {
+    program: GLuint,
+    vao: GLuint,
+    vbo: GLuint,
+    ebo: GLuint,
+    font_texture: GLuint,
+    freed: bool,
+}
+
+impl ImguiRenderer {
+    /// Compiles the UI shader, uploads the font atlas of `context` and registers the backend with it.
+    pub fn new(gl_state: &mut GlStateManager, context: &mut Context) -> Result<Self> {
+        let program = compile_program(IMGUI_VERTEX_SHADER, IMGUI_FRAGMENT_SHADER)?;
+
+        context.set_renderer_name(Some(format!("renderforge {}", env!("CARGO_PKG_VERSION"))));
+        context.io_mut().backend_flags.insert(BackendFlags::RENDERER_HAS_VTX_OFFSET);
+
+        let previous_vao = gl_state.get_vao();
+        unsafe {
+            let mut vao = 0;
+            gl::GenVertexArrays(1, &mut vao);
+            gl_state.bind_vao(vao);
+
+            let mut vbo = 0;
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+            let mut ebo = 0;
+            gl::GenBuffers(1, &mut ebo);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+
+            let stride = size_of::<DrawVert>() as GLsizei;
+            let f_size = size_of::<f32>();
+            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, (2 * f_size) as *const c_void);
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(2, 4, gl::UNSIGNED_BYTE, gl::TRUE, stride, (4 * f_size) as *const c_void);
+            gl::EnableVertexAttribArray(2);
+
+            gl_state.bind_vao(previous_vao);
+
+            let mut renderer = Self {
+                program,
+                vao,
+                vbo,
+                ebo,
+                font_texture: 0,
+                freed: false,
+            };
+            renderer.upload_fonts(context);
+            Ok(renderer)
+        }
+    }
+
+    /// Rebuilds the font atlas texture, call after adding fonts to the context
+    pub fn reload_fonts(&mut self, gl_state: &mut GlStateManager, context: &mut Context) {
+        gl_state.destroy_texture(self.font_texture);
+        self.upload_fonts(context);
+    }
+
+    fn upload_fonts(&mut self, context: &mut Context) {
+        let fonts = context.fonts();
+        let atlas = fonts.build_rgba32_texture();
+        let img = RgbaImage::from_raw(atlas.width, atlas.height, atlas.data.to_vec())
+            .expect("imgui font atlas size does not match its data");
+        let wrap = TextureWrap::new(WrapMode::ClampToEdge, WrapMode::ClampToEdge);
+        let (texture, _) = upload_image(&DynamicImage::ImageRgba8(img), MinFilter::Linear, MagFilter::Linear, wrap);
+        fonts.tex_id = TextureId::new(texture as usize);
+        self.font_texture = texture;
+    }
+
+    pub fn get_font_texture(&self) -> GLuint {
+        self.font_texture
+    }
+
+    /// Draws a frame of UI into the bound framebuffer. The state from before the call is restored
+    /// afterwards, including the scissor and viewport.
+    pub fn render(&self, gl_state: &mut GlStateManager, draw_data: &DrawData) {
+        let scale = Vec2::from_array(draw_data.framebuffer_scale);
+        let display_pos = Vec2::from_array(draw_data.display_pos);
+        let display_size = Vec2::from_array(draw_data.display_size);
+        let fb_size = display_size * scale;
+        if fb_size.x <= 0. || fb_size.y <= 0. || draw_data.total_idx_count == 0 {
+            return;
+        }
+
+        gl_state.scoped(|state| {
+            let projection = Mat4::orthographic_rh_gl(
+                display_pos.x, display_pos.x + display_size.x,
+                display_pos.y + display_size.y, display_pos.y,
+                -1., 1.,
+            );
+            self.setup_state(state, fb_size, projection);
+
+            for draw_list in draw_data.draw_lists() {
+                let vertices = draw_list.vtx_buffer();
+                let indices = draw_list.idx_buffer();
+                unsafe {
+                    gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+                    gl::BufferData(gl::ARRAY_BUFFER, size_of_val(vertices) as isize, vertices.as_ptr() as *const _, gl::STREAM_DRAW);
+                    gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size_of_val(indices) as isize, indices.as_ptr() as *const _, gl::STREAM_DRAW);
+                }
+
+                for command in draw_list.commands() {
+                    match command {
+                        DrawCmd::Elements { count, cmd_params } => {
+                            self.draw_elements(state, count, &cmd_params, display_pos, scale, fb_size);
+                        }
+                        DrawCmd::ResetRenderState => self.setup_state(state, fb_size, projection),
+                        DrawCmd::RawCallback { callback, raw_cmd } => unsafe {
+                            callback(draw_list as *const _ as *const imgui::sys::ImDrawList, raw_cmd);
+                        },
+                    }
+                }
+            }
+        });
+    }
+
+    fn setup_state(&self, gl_state: &mut GlStateManager, fb_size: Vec2, projection: Mat4) {
+        gl_state.use_program(self.program);
+        gl_state.bind_vao(self.vao);
+        gl_state.blending(true);
+        gl_state.blend_mode(BlendMode::Alpha);
+        gl_state.culling(false);
+        gl_state.depth_test(false);
+        gl_state.scissor_test(true);
+        gl_state.viewport([0, 0, fb_size.x as i32, fb_size.y as i32]);
+        gl_state.set_uniform("u_projection", GLUniform::Mat4(projection));
+        gl_state.set_uniform(U_TEXTURE, GLUniform::I32(0));
+        unsafe {
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
+        }
+    }
+
+    fn draw_elements(&self, gl_state: &mut GlStateManager, count: usize, params: &DrawCmdParams, display_pos: Vec2, scale: Vec2, fb_size: Vec2) {
+        let [x0, y0, x1, y1] = params.clip_rect;
+        let min = ((Vec2::new(x0, y0) - display_pos) * scale).max(Vec2::ZERO);
+        let max = ((Vec2::new(x1, y1) - display_pos) * scale).min(fb_size);
+        if max.x <= min.x || max.y <= min.y {
+            return;
+        }
+        // imgui clip rects are top down, gl scissor boxes bottom up
+        gl_state.scissor_box([
+            min.x as i32,
+            (fb_size.y - max.y) as i32,
+            (max.x - min.x) as i32,
+            (max.y - min.y) as i32,
+        ]);
+        gl_state.bind_texture(0, params.texture_id.id() as GLuint);
+        unsafe {
+            gl::DrawElementsBaseVertex(
+                gl::TRIANGLES,
+                count as GLsizei,
+                INDEX_TYPE,
+                (params.idx_offset * size_of::<DrawIdx>()) as *const c_void,
+                params.vtx_offset as GLint,
+            );
+        }
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        gl_state.destroy_program(self.program);
+        gl_state.destroy_vbo_vec(vec![self.vbo, self.ebo]);
+        gl_state.destroy_vao(self.vao);
+        gl_state.destroy_texture(self.font_texture);
+        self.freed = true;
+    }
+}
+
+/// Shows the frame stats and gpu timings in a small window at the top left, for
+/// DebugConfig::profiler_overlay
+pub fn show_profiler_overlay(ui: &Ui, gl_state: &GlStateManager) {
+    let summary = profiler_summary(gl_state);
+    ui.window("Profiler")
+        .position([10., 10.], Condition::FirstUseEver)
+        .always_auto_resize(true)
+        .build(|| ui.text(summary));
+}
+
+impl Drop for ImguiRenderer {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            let mut state = GlStateManager::new();
+            self.destroy(&mut state);
+            panic!("Imgui renderer was not destroyed before dropping")
+        }
+    }
+}
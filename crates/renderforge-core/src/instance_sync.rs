@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use anyhow::Result;
+use hecs::{ChangeTracker, Component, Entity, World};
+
+use crate::errors::BufferRenderError;
+use crate::mesh::InstancedMeshData;
+
+/// Keeps packed instance data in step with the `T` components of a hecs world, re-packing only the
+/// entities whose `T` was added, changed or removed since the last sync, so static scenes cost
+/// next to nothing on the cpu. Render it with InstancedMesh::flush_synced.
+///
+/// Uses a hecs ChangeTracker, so only one InstanceSync should track a given `T` per world.
+pub struct InstanceSync<T: Component> {
+    tracker: ChangeTracker<T>,
+    /// instance slot of each tracked entity
+    slots: HashMap<Entity, usize>,
+    entities: Vec<Entity>,
+    data: Vec<f32>,
+    /// floats per instance, known once the first instance is written
+    stride: Option<usize>,
+    /// slots changed since the last take_dirty
+    dirty: Option<Range<usize>>,
+}
+
+impl<T: Component> std::fmt::Debug for InstanceSync<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstanceSync")
+            .field("instances", &self.entities.len())
+            .field("stride", &self.stride)
+            .field("dirty", &self.dirty)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Component> Default for InstanceSync<T> {
+    fn default() -> Self {
+        Self {
+            tracker: ChangeTracker::new(),
+            slots: HashMap::new(),
+            entities: Vec::new(),
+            data: Vec::new(),
+            stride: None,
+            dirty: None,
+        }
+    }
+}
+
+impl<T: InstancedMeshData + Component + Clone + PartialEq> InstanceSync<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies the changes to `T` components since the previous sync
+    pub fn sync(&mut self, world: &mut World) -> Result<()> {
+        let despawned: Vec<Entity> = self.entities.iter()
+            .copied()
+            .filter(|entity| !world.contains(*entity))
+            .collect();
+        for entity in despawned {
+            self.remove(entity);
+        }
+
+        let mut changes = self.tracker.track(world);
+        let mut written = Vec::new();
+        for (entity, _) in changes.removed() {
+            written.push((entity, None));
+        }
+        for (entity, data) in changes.added() {
+            written.push((entity, Some(Self::pack(data))));
+        }
+        for (entity, _, data) in changes.changed() {
+            written.push((entity, Some(Self::pack(data))));
+        }
+        drop(changes);
+
+        for (entity, data) in written {
+            match data {
+                Some(data) => self.write(entity, data)?,
+                None => self.remove(entity),
+            }
+        }
+        Ok(())
+    }
+
+    fn pack(data: &T) -> Vec<f32> {
+        let mut buffer = Vec::new();
+        data.write_data(&mut buffer);
+        buffer
+    }
+
+    fn write(&mut self, entity: Entity, data: Vec<f32>) -> Result<()> {
+        let stride = *self.stride.get_or_insert(data.len());
+        if data.len() != stride {
+            return Err(BufferRenderError::MalformedData.into());
+        }
+
+        let slot = match self.slots.get(&entity) {
+            Some(slot) => *slot,
+            None => {
+                self.slots.insert(entity, self.entities.len());
+                self.entities.push(entity);
+                self.data.resize(self.entities.len() * stride, 0.);
+                self.entities.len() - 1
+            }
+        };
+        self.data[slot * stride..(slot + 1) * stride].copy_from_slice(&data);
+        self.mark_dirty(slot);
+        Ok(())
+    }
+
+    /// Moves the last instance into the removed entity's slot
+    fn remove(&mut self, entity: Entity) {
+        let Some(slot) = self.slots.remove(&entity) else {
+            return;
+        };
+        let stride = self.stride.unwrap_or(0);
+        let last = self.entities.len() - 1;
+        self.entities.swap_remove(slot);
+        if slot != last {
+            self.slots.insert(self.entities[slot], slot);
+            self.data.copy_within(last * stride..(last + 1) * stride, slot * stride);
+            self.mark_dirty(slot);
+        }
+        self.data.truncate(last * stride);
+        if let Some(dirty) = &mut self.dirty {
+            dirty.end = dirty.end.min(last);
+            if dirty.start >= dirty.end {
+                self.dirty = None;
+            }
+        }
+    }
+
+    fn mark_dirty(&mut self, slot: usize) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(slot)..dirty.end.max(slot + 1),
+            None => slot..slot + 1,
+        });
+    }
+
+    /// number of tracked instances
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// # Returns
+    /// floats per instance, None until an instance has been written
+    pub fn get_stride(&self) -> Option<usize> {
+        self.stride
+    }
+
+    /// packed data of every instance, in slot order
+    pub fn get_data(&self) -> &[f32] {
+        &self.data
+    }
+
+    /// # Returns
+    /// the entity drawn by each instance slot
+    pub fn get_entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    /// Takes the range of slots changed since the last call
+    pub fn take_dirty(&mut self) -> Option<Range<usize>> {
+        self.dirty.take()
+    }
+}
@@ -1,10 +1,43 @@
 pub mod engine;
 pub mod window;
 pub mod renderer;
+pub mod particles;
+#[cfg(feature = "imgui-backend")]
+pub mod imgui_renderer;
 pub mod mesh;
+pub mod csg;
+pub mod instance_sync;
 pub mod atlas;
+pub mod glyph_atlas;
+pub mod text;
+pub mod ui_focus;
 pub mod texture;
+pub mod procedural;
 pub mod shader;
+pub mod semantics;
+pub mod material;
+pub mod material_definition;
+pub mod animation;
+pub mod transform;
+pub mod program_cache;
 pub mod data;
+pub mod frame;
+pub mod buffer;
+pub mod framebuffer;
+pub mod render_graph;
+pub mod resolution;
+pub mod recording;
+pub mod replay;
+pub mod audio;
 pub mod registry;
+pub mod streaming;
 pub mod errors;
+pub mod capabilities;
+pub mod debug_config;
+#[cfg(any(test, feature = "gl-trace"))]
+pub mod gl_trace;
+pub mod editor;
+pub mod ibl;
+pub mod lightmap;
+#[cfg(feature = "renderforge-xr")]
+pub mod xr;
@@ -0,0 +1,238 @@
+use glam::{Vec2, Vec3};
+use image::{Rgba, RgbaImage};
+
+/// A triangle to bake, with its own non-overlapping lightmap uvs
+#[derive(Debug, Clone, Copy)]
+pub struct LightmapTriangle {
+    pub positions: [Vec3; 3],
+    pub normals: [Vec3; 3],
+    pub lightmap_uvs: [Vec2; 3],
+}
+
+/// Lighting and quality settings for LightmapBaker::bake
+#[derive(Debug, Clone, Copy)]
+pub struct LightmapSettings {
+    pub size: (u32, u32),
+    /// direction the directional light shines in
+    pub light_direction: Vec3,
+    pub light_color: Vec3,
+    /// light arriving from every unoccluded direction of the hemisphere
+    pub sky_color: Vec3,
+    /// hemisphere rays per texel for the sky term, 0 disables it
+    pub sky_samples: u32,
+    /// offset along the normal for rays leaving a surface, to avoid hitting the surface itself
+    pub bias: f32,
+    /// how many texels lit texels are grown into the empty space around them, so bilinear
+    /// filtering near uv seams doesn't pick up black
+    pub dilation: u32,
+}
+
+/// Bakes direct lighting from one directional light plus sky occlusion into a lightmap on the cpu,
+/// for static scenes on hardware that can't afford dynamic lighting.
+///
+/// Every texel covered by a triangle in lightmap uv space gets a shadow ray toward the light and
+/// `sky_samples` cosine weighted rays over its hemisphere, tested against every triangle, so
+/// baking is meant to happen offline. Sample the result with the lightmapped standard shader, see
+/// mesh::compile_standard_lightmapped_program.
+#[derive(Debug, Clone)]
+pub struct LightmapBaker {
+    triangles: Vec<LightmapTriangle>,
+    settings: LightmapSettings,
+}
+
+impl Default for LightmapSettings {
+    fn default() -> Self {
+        Self {
+            size: (256, 256),
+            light_direction: Vec3::new(-0.3, -1., -0.2),
+            light_color: Vec3::ONE,
+            sky_color: Vec3::splat(0.2),
+            sky_samples: 64,
+            bias: 0.001,
+            dilation: 2,
+        }
+    }
+}
+
+impl LightmapBaker {
+    pub fn new(settings: LightmapSettings) -> Self {
+        Self {
+            triangles: Vec::new(),
+            settings,
+        }
+    }
+
+    pub fn get_settings(&self) -> &LightmapSettings {
+        &self.settings
+    }
+
+    pub fn add_triangle(&mut self, triangle: LightmapTriangle) {
+        self.triangles.push(triangle);
+    }
+
+    /// Adds a non-indexed triangle list, three entries per triangle
+    pub fn add_mesh(&mut self, positions: &[Vec3], normals: &[Vec3], lightmap_uvs: &[Vec2]) {
+        let count = positions.len().min(normals.len()).min(lightmap_uvs.len()) / 3;
+        for i in 0..count {
+            let v = i * 3;
+            self.triangles.push(LightmapTriangle {
+                positions: [positions[v], positions[v + 1], positions[v + 2]],
+                normals: [normals[v], normals[v + 1], normals[v + 2]],
+                lightmap_uvs: [lightmap_uvs[v], lightmap_uvs[v + 1], lightmap_uvs[v + 2]],
+            });
+        }
+    }
+
+    /// Bakes the lightmap. Lighting is clamped to [0, 1] and texels no triangle covers stay
+    /// transparent black (after dilation).
+    pub fn bake(&self) -> RgbaImage {
+        let (width, height) = self.settings.size;
+        let mut image = RgbaImage::new(width, height);
+        let to_light = -self.settings.light_direction.normalize_or_zero();
+
+        for triangle in &self.triangles {
+            let uvs = triangle.lightmap_uvs.map(|uv| uv * Vec2::new(width as f32, height as f32));
+            let min = uvs[0].min(uvs[1]).min(uvs[2]).floor().max(Vec2::ZERO);
+            let max = uvs[0].max(uvs[1]).max(uvs[2]).ceil().min(Vec2::new(width as f32, height as f32));
+
+            for y in min.y as u32..max.y as u32 {
+                for x in min.x as u32..max.x as u32 {
+                    let texel = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                    let Some(weights) = barycentric(texel, uvs) else {
+                        continue;
+                    };
+                    let position = triangle.positions[0] * weights.x + triangle.positions[1] * weights.y + triangle.positions[2] * weights.z;
+                    let normal = (triangle.normals[0] * weights.x + triangle.normals[1] * weights.y + triangle.normals[2] * weights.z).normalize_or_zero();
+
+                    let light = self.light_texel(position, normal, to_light).clamp(Vec3::ZERO, Vec3::ONE);
+                    image.put_pixel(x, y, Rgba([
+                        (light.x * 255.).round() as u8,
+                        (light.y * 255.).round() as u8,
+                        (light.z * 255.).round() as u8,
+                        255,
+                    ]));
+                }
+            }
+        }
+
+        for _ in 0..self.settings.dilation {
+            dilate(&mut image);
+        }
+        image
+    }
+
+    fn light_texel(&self, position: Vec3, normal: Vec3, to_light: Vec3) -> Vec3 {
+        let origin = position + normal * self.settings.bias;
+
+        let mut light = Vec3::ZERO;
+        let n_dot_l = normal.dot(to_light);
+        if n_dot_l > 0. && !self.occluded(origin, to_light) {
+            light += self.settings.light_color * n_dot_l;
+        }
+
+        let samples = self.settings.sky_samples;
+        if samples > 0 {
+            let (tangent, bitangent) = orthonormal_basis(normal);
+            let mut visible = 0;
+            for i in 0..samples {
+                let xi = hammersley(i, samples);
+                // cosine weighted, so the visible fraction is the cosine weighted sky occlusion
+                let r = xi.y.sqrt();
+                let phi = std::f32::consts::TAU * xi.x;
+                let local = Vec3::new(r * phi.cos(), r * phi.sin(), (1. - xi.y).sqrt());
+                let dir = tangent * local.x + bitangent * local.y + normal * local.z;
+                if !self.occluded(origin, dir) {
+                    visible += 1;
+                }
+            }
+            light += self.settings.sky_color * (visible as f32 / samples as f32);
+        }
+
+        light
+    }
+
+    fn occluded(&self, origin: Vec3, dir: Vec3) -> bool {
+        self.triangles.iter().any(|triangle| ray_hits_triangle(origin, dir, &triangle.positions))
+    }
+}
+
+/// # Returns
+/// the barycentric weights of `p` in the triangle, None if it lies outside
+fn barycentric(p: Vec2, [a, b, c]: [Vec2; 3]) -> Option<Vec3> {
+    let area = (b - a).perp_dot(c - a);
+    if area.abs() < f32::EPSILON {
+        return None;
+    }
+    let u = (c - b).perp_dot(p - b) / area;
+    let v = (a - c).perp_dot(p - c) / area;
+    let w = 1. - u - v;
+    // a little slack so texels on shared edges aren't missed by both triangles
+    const EDGE: f32 = -0.01;
+    (u >= EDGE && v >= EDGE && w >= EDGE).then_some(Vec3::new(u, v, w))
+}
+
+/// Möller–Trumbore ray triangle intersection, only counting hits in front of the origin
+fn ray_hits_triangle(origin: Vec3, dir: Vec3, [a, b, c]: &[Vec3; 3]) -> bool {
+    let edge1 = *b - *a;
+    let edge2 = *c - *a;
+    let p = dir.cross(edge2);
+    let det = edge1.dot(p);
+    if det.abs() < 1e-8 {
+        return false;
+    }
+    let inv_det = 1. / det;
+    let s = origin - *a;
+    let u = s.dot(p) * inv_det;
+    if !(0. ..=1.).contains(&u) {
+        return false;
+    }
+    let q = s.cross(edge1);
+    let v = dir.dot(q) * inv_det;
+    if v < 0. || u + v > 1. {
+        return false;
+    }
+    edge2.dot(q) * inv_det > 1e-5
+}
+
+fn orthonormal_basis(normal: Vec3) -> (Vec3, Vec3) {
+    let up = if normal.z.abs() < 0.999 { Vec3::Z } else { Vec3::X };
+    let tangent = up.cross(normal).normalize();
+    (tangent, normal.cross(tangent))
+}
+
+fn hammersley(i: u32, n: u32) -> Vec2 {
+    Vec2::new(i as f32 / n as f32, i.reverse_bits() as f32 * 2.328_306_4e-10)
+}
+
+/// Fills every empty texel that borders a filled one with the average of its filled neighbours
+fn dilate(image: &mut RgbaImage) {
+    let source = image.clone();
+    let (width, height) = source.dimensions();
+    for y in 0..height {
+        for x in 0..width {
+            if source.get_pixel(x, y)[3] != 0 {
+                continue;
+            }
+            let mut sum = [0u32; 3];
+            let mut count = 0;
+            for (dx, dy) in [(-1i32, 0i32), (1, 0), (0, -1), (0, 1)] {
+                let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                    continue;
+                }
+                let pixel = source.get_pixel(nx as u32, ny as u32);
+                if pixel[3] != 0 {
+                    for (total, channel) in sum.iter_mut().zip(pixel.0) {
+                        *total += channel as u32;
+                    }
+                    count += 1;
+                }
+            }
+            if count == 0 {
+                continue;
+            }
+            let [r, g, b] = sum.map(|total| (total / count) as u8);
+            image.put_pixel(x, y, Rgba([r, g, b, 255]));
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use gl::types::GLuint;
+
+use crate::data::{BlendMode, CullFace, DepthFunc, GLUniform, GlStateManager};
+use crate::texture::{SamplerDescriptor, TextureTarget};
+
+/// A texture bound by a Material
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaterialTexture {
+    pub slot: u32,
+    pub texture: GLuint,
+    pub target: TextureTarget,
+}
+
+/// Everything needed to draw with a shader: the program, the blend/depth/cull state, uniform
+/// values and textures, applied together with `apply`.
+/// The material doesn't own the program or textures.
+#[derive(Debug, Clone)]
+pub struct Material {
+    program: GLuint,
+    /// None disables blending
+    pub blend: Option<BlendMode>,
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub depth_func: DepthFunc,
+    /// None disables face culling
+    pub culling: Option<CullFace>,
+    uniforms: HashMap<String, GLUniform>,
+    textures: HashMap<String, MaterialTexture>,
+    /// sampler parameters replacing those of the texture with the same name
+    samplers: HashMap<String, SamplerDescriptor>,
+}
+
+impl Material {
+    /// Creates an opaque material: no blending, depth tested and written, back faces culled
+    pub fn new(program: GLuint) -> Self {
+        Self {
+            program,
+            blend: None,
+            depth_test: true,
+            depth_write: true,
+            depth_func: DepthFunc::Less,
+            culling: Some(CullFace::Back),
+            uniforms: HashMap::new(),
+            textures: HashMap::new(),
+            samplers: HashMap::new(),
+        }
+    }
+
+    pub fn with_blend(mut self, blend: Option<BlendMode>) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn with_depth(mut self, test: bool, write: bool) -> Self {
+        self.depth_test = test;
+        self.depth_write = write;
+        self
+    }
+
+    pub fn with_depth_func(mut self, func: DepthFunc) -> Self {
+        self.depth_func = func;
+        self
+    }
+
+    pub fn with_culling(mut self, culling: Option<CullFace>) -> Self {
+        self.culling = culling;
+        self
+    }
+
+    pub fn with_uniform(mut self, name: impl ToString, value: GLUniform) -> Self {
+        self.set_uniform(name, value);
+        self
+    }
+
+    pub fn with_texture(mut self, name: impl ToString, slot: u32, texture: GLuint, target: TextureTarget) -> Self {
+        self.set_texture(name, slot, texture, target);
+        self
+    }
+
+    pub fn with_sampler_override(mut self, name: impl ToString, sampler: SamplerDescriptor) -> Self {
+        self.set_sampler_override(name, Some(sampler));
+        self
+    }
+
+    pub fn get_program(&self) -> GLuint {
+        self.program
+    }
+
+    pub fn set_program(&mut self, program: GLuint) {
+        self.program = program;
+    }
+
+    pub fn set_uniform(&mut self, name: impl ToString, value: GLUniform) {
+        self.uniforms.insert(name.to_string(), value);
+    }
+
+    pub fn get_uniform(&self, name: &str) -> Option<&GLUniform> {
+        self.uniforms.get(name)
+    }
+
+    /// Binds `texture` to `slot` and points the sampler uniform `name` at it
+    pub fn set_texture(&mut self, name: impl ToString, slot: u32, texture: GLuint, target: TextureTarget) {
+        self.textures.insert(name.to_string(), MaterialTexture { slot, texture, target });
+    }
+
+    pub fn get_texture(&self, name: &str) -> Option<&MaterialTexture> {
+        self.textures.get(name)
+    }
+
+    /// Samples the texture `name` with different filtering, wrapping or LOD bias than it was created
+    /// with, through a shared gl sampler object. None goes back to the texture's own parameters.
+    pub fn set_sampler_override(&mut self, name: impl ToString, sampler: Option<SamplerDescriptor>) {
+        match sampler {
+            Some(sampler) => self.samplers.insert(name.to_string(), sampler),
+            None => self.samplers.remove(&name.to_string()),
+        };
+    }
+
+    pub fn get_sampler_override(&self, name: &str) -> Option<&SamplerDescriptor> {
+        self.samplers.get(name)
+    }
+
+    /// Uses the program and sets the state, uniforms and textures of the material.
+    /// Redundant changes are skipped by the state manager, so applying a material every draw is cheap.
+    pub fn apply(&self, gl_state: &mut GlStateManager) {
+        gl_state.use_program(self.program);
+
+        match self.blend {
+            Some(mode) => {
+                gl_state.blending(true);
+                gl_state.blend_mode(mode);
+            }
+            None => gl_state.blending(false),
+        }
+
+        gl_state.depth_test(self.depth_test);
+        gl_state.depth_mask(self.depth_write);
+        gl_state.depth_func(self.depth_func);
+
+        match self.culling {
+            Some(face) => {
+                gl_state.culling(true);
+                gl_state.cull_face(face);
+            }
+            None => gl_state.culling(false),
+        }
+
+        for (name, value) in &self.uniforms {
+            gl_state.set_uniform(name, value.clone());
+        }
+
+        for (name, texture) in &self.textures {
+            gl_state.set_uniform(name, GLUniform::I32(texture.slot as i32));
+            match texture.target {
+                TextureTarget::Texture1D => gl_state.bind_texture_1d(texture.slot, texture.texture),
+                TextureTarget::Texture2D => gl_state.bind_texture(texture.slot, texture.texture),
+                TextureTarget::Texture3D => gl_state.bind_texture_3d(texture.slot, texture.texture),
+                TextureTarget::CubeMap => gl_state.bind_texture_cube(texture.slot, texture.texture),
+                TextureTarget::Texture2DArray => gl_state.bind_texture_array(texture.slot, texture.texture),
+            }
+            // unbinds samplers left on the slot by other materials
+            let sampler = match self.samplers.get(name) {
+                Some(descriptor) => gl_state.get_sampler(descriptor),
+                None => 0,
+            };
+            gl_state.bind_sampler(texture.slot, sampler);
+        }
+    }
+}
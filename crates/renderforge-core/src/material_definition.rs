@@ -0,0 +1,277 @@
+//! Materials described as data instead of Rust and full GLSL files. A definition lists the
+//! material's inputs, texture slots and render state, plus GLSL snippets that are injected into a
+//! template built on the standard instanced mesh shader:
+//!
+//! ```json
+//! {
+//!     "name": "water",
+//!     "inputs": [{ "name": "u_tint", "type": "vec4", "value": [0.2, 0.5, 0.9, 0.8] }],
+//!     "textures": [{ "name": "u_waves", "slot": 0 }],
+//!     "blend": "alpha",
+//!     "depth_write": false,
+//!     "fragment": "return texture(u_waves, uv + instance_data.xy) * u_tint;"
+//! }
+//! ```
+//!
+//! The fragment snippet is the body of `vec4 material_surface(vec3 normal, vec2 uv, vec4 instance_data)`
+//! and returns the unlit surface color. The optional vertex snippet is the body of
+//! `void material_vertex(inout vec3 position, inout vec3 normal, inout vec2 uv)`, run in model space.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use gl::types::GLuint;
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use serde::Deserialize;
+
+use crate::data::{BlendMode, CullFace, DepthFunc, GLUniform};
+use crate::errors::MaterialDefinitionError;
+use crate::material::Material;
+use crate::shader::{compile_program_with_includes, inject_defines, ShaderIncludes};
+use crate::texture::TextureTarget;
+
+const TEMPLATE_VERTEX_SHADER: &str = r#"#version 330 core
+layout(location = 0) in vec3 a_position;
+layout(location = 1) in vec3 a_normal;
+layout(location = 2) in vec2 a_uv;
+layout(location = 3) in mat4 a_transform;
+layout(location = 7) in vec4 a_instance_data;
+
+uniform mat4 u_view_proj;
+#include "material_uniforms"
+
+out vec3 v_normal;
+out vec2 v_uv;
+out vec4 v_instance_data;
+
+void material_vertex(inout vec3 position, inout vec3 normal, inout vec2 uv) {
+#include "material_vertex"
+}
+
+void main() {
+    vec3 position = a_position;
+    vec3 normal = a_normal;
+    vec2 uv = a_uv;
+    material_vertex(position, normal, uv);
+    v_normal = mat3(a_transform) * normal;
+    v_uv = uv;
+    v_instance_data = a_instance_data;
+    gl_Position = u_view_proj * a_transform * vec4(position, 1.0);
+}
+"#;
+
+const TEMPLATE_FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec3 v_normal;
+in vec2 v_uv;
+in vec4 v_instance_data;
+
+uniform vec3 u_light_direction;
+#include "material_uniforms"
+
+out vec4 frag_color;
+
+vec4 material_surface(vec3 normal, vec2 uv, vec4 instance_data) {
+#include "material_fragment"
+}
+
+void main() {
+    vec3 normal = normalize(v_normal);
+    vec4 color = material_surface(normal, v_uv, v_instance_data);
+#ifndef UNLIT
+    color.rgb *= max(dot(normal, -normalize(u_light_direction)), 0.0) * 0.8 + 0.2;
+#endif
+    frag_color = color;
+}
+"#;
+
+/// A material input and its default value, uploaded as a uniform of the matching GLSL type
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum MaterialValue {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Int(i32),
+    Bool(bool),
+    /// column major
+    Mat4([f32; 16]),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MaterialInput {
+    pub name: String,
+    #[serde(flatten)]
+    pub value: MaterialValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MaterialTextureSlot {
+    pub name: String,
+    pub slot: u32,
+    #[serde(default = "default_texture_target")]
+    pub target: TextureTarget,
+}
+
+/// A material loaded from JSON, see the module documentation for the format.
+/// Render state fields default to those of Material::new.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MaterialDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub inputs: Vec<MaterialInput>,
+    #[serde(default)]
+    pub textures: Vec<MaterialTextureSlot>,
+    #[serde(default)]
+    pub blend: Option<BlendMode>,
+    #[serde(default = "default_true")]
+    pub depth_test: bool,
+    #[serde(default = "default_true")]
+    pub depth_write: bool,
+    #[serde(default = "default_depth_func")]
+    pub depth_func: DepthFunc,
+    #[serde(default = "default_culling")]
+    pub culling: Option<CullFace>,
+    /// skips the template's directional lighting
+    #[serde(default)]
+    pub unlit: bool,
+    #[serde(default)]
+    pub vertex: Option<String>,
+    pub fragment: String,
+}
+
+fn default_texture_target() -> TextureTarget {
+    TextureTarget::Texture2D
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_depth_func() -> DepthFunc {
+    DepthFunc::Less
+}
+
+fn default_culling() -> Option<CullFace> {
+    Some(CullFace::Back)
+}
+
+impl MaterialValue {
+    pub fn glsl_type(&self) -> &'static str {
+        match self {
+            Self::Float(_) => "float",
+            Self::Vec2(_) => "vec2",
+            Self::Vec3(_) => "vec3",
+            Self::Vec4(_) => "vec4",
+            Self::Int(_) => "int",
+            Self::Bool(_) => "bool",
+            Self::Mat4(_) => "mat4",
+        }
+    }
+
+    pub fn to_uniform(&self) -> GLUniform {
+        match self {
+            Self::Float(v) => GLUniform::F32(*v),
+            Self::Vec2(v) => GLUniform::Vec2(Vec2::from_array(*v)),
+            Self::Vec3(v) => GLUniform::Vec3(Vec3::from_array(*v)),
+            Self::Vec4(v) => GLUniform::Vec4(Vec4::from_array(*v)),
+            Self::Int(v) => GLUniform::I32(*v),
+            Self::Bool(v) => GLUniform::Bool(*v),
+            Self::Mat4(v) => GLUniform::Mat4(Mat4::from_cols_array(v)),
+        }
+    }
+}
+
+impl MaterialDefinition {
+    /// Parses and validates a definition
+    pub fn from_json(source: &str) -> Result<Self> {
+        let definition: Self = serde_json::from_str(source).map_err(|e| MaterialDefinitionError::Parse(e.to_string()))?;
+        definition.validate()?;
+        Ok(definition)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let mut names = Vec::new();
+        let inputs = self.inputs.iter().map(|i| &i.name);
+        for name in inputs.chain(self.textures.iter().map(|t| &t.name)) {
+            if names.contains(&name) {
+                return Err(MaterialDefinitionError::DuplicateName { material: self.name.clone(), name: name.clone() }.into());
+            }
+            names.push(name);
+        }
+        for (i, texture) in self.textures.iter().enumerate() {
+            if let Some(other) = self.textures[..i].iter().find(|t| t.slot == texture.slot) {
+                return Err(MaterialDefinitionError::SlotConflict {
+                    material: self.name.clone(),
+                    first: other.name.clone(),
+                    second: texture.name.clone(),
+                    slot: texture.slot,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// # Returns
+    /// the GLSL declarations of the inputs and texture samplers, shared by both stages
+    pub fn uniform_declarations(&self) -> String {
+        let mut out = String::new();
+        for input in &self.inputs {
+            let _ = writeln!(out, "uniform {} {};", input.value.glsl_type(), input.name);
+        }
+        for texture in &self.textures {
+            let sampler = match texture.target {
+                TextureTarget::Texture1D => "sampler1D",
+                TextureTarget::Texture2D => "sampler2D",
+                TextureTarget::Texture3D => "sampler3D",
+                TextureTarget::CubeMap => "samplerCube",
+                TextureTarget::Texture2DArray => "sampler2DArray",
+            };
+            let _ = writeln!(out, "uniform {sampler} {};", texture.name);
+        }
+        out
+    }
+
+    /// Compiles the template shader with the definition's snippets.
+    ///
+    /// # Returns
+    /// the gl program id
+    pub fn compile(&self) -> Result<GLuint> {
+        let mut includes = ShaderIncludes::new();
+        includes.add("material_uniforms", self.uniform_declarations());
+        includes.add("material_vertex", self.vertex.as_deref().unwrap_or(""));
+        includes.add("material_fragment", &self.fragment);
+        let fragment = if self.unlit {
+            inject_defines(TEMPLATE_FRAGMENT_SHADER, &["UNLIT"])
+        } else {
+            TEMPLATE_FRAGMENT_SHADER.to_string()
+        };
+        compile_program_with_includes(TEMPLATE_VERTEX_SHADER, &fragment, &includes)
+    }
+
+    /// Creates a Material drawing with `program` (from `compile`) and the definition's render state
+    /// and default inputs. Texture slots are bound to the ids in `textures` by name, 0 if missing.
+    pub fn to_material(&self, program: GLuint, textures: &HashMap<String, GLuint>) -> Material {
+        let mut material = Material::new(program)
+            .with_blend(self.blend)
+            .with_depth(self.depth_test, self.depth_write)
+            .with_depth_func(self.depth_func)
+            .with_culling(self.culling);
+        for input in &self.inputs {
+            material.set_uniform(&input.name, input.value.to_uniform());
+        }
+        for texture in &self.textures {
+            let id = textures.get(&texture.name).copied().unwrap_or(0);
+            material.set_texture(&texture.name, texture.slot, id, texture.target);
+        }
+        material
+    }
+}
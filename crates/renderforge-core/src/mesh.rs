@@ -4,27 +4,163 @@ use std::mem;
 use std::os::raw::c_void;
 use std::thread::panicking;
 use anyhow::Result;
-use gl::types::{GLint, GLsizei, GLuint};
-use glam::{Vec2, Vec3, Mat4};
+use gl::types::{GLboolean, GLenum, GLint, GLsizei, GLuint};
+use glam::{Vec2, Vec3, Vec4, Mat4};
 use crate::data::*;
 use crate::engine::Engine;
+use crate::window::ContextId;
 use crate::errors::{AttributeError, BufferRenderError};
+use crate::instance_sync::InstanceSync;
+use crate::semantics::{A_COLOR, A_NORMAL, A_POSITION, A_UV};
+use crate::shader::{compile_program_with_includes, inject_defines, PrimitiveType, ShaderIncludes, ShaderReflection};
+
+const STANDARD_INSTANCED_VERTEX_SHADER: &str = r#"#version 330 core
+layout(location = 0) in vec3 a_position;
+layout(location = 1) in vec3 a_normal;
+layout(location = 2) in vec2 a_uv;
+layout(location = 3) in mat4 a_transform;
+layout(location = 7) in vec4 a_instance_data;
+#ifdef LIGHTMAP
+layout(location = 8) in vec2 a_lightmap_uv;
+out vec2 v_lightmap_uv;
+#endif
+
+uniform mat4 u_view_proj;
+
+out vec3 v_normal;
+out vec2 v_uv;
+out vec4 v_instance_data;
+
+void main() {
+#ifdef LIGHTMAP
+    v_lightmap_uv = a_lightmap_uv;
+#endif
+    v_normal = mat3(a_transform) * a_normal;
+    v_uv = a_uv;
+    v_instance_data = a_instance_data;
+    gl_Position = u_view_proj * a_transform * vec4(a_position, 1.0);
+}
+"#;
+
+const STANDARD_INSTANCED_FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec3 v_normal;
+in vec2 v_uv;
+in vec4 v_instance_data;
+
+uniform sampler2D u_texture;
+uniform vec4 u_color;
+uniform vec3 u_light_direction;
+#ifdef LIGHTMAP
+in vec2 v_lightmap_uv;
+uniform sampler2D u_lightmap;
+#endif
+
+out vec4 frag_color;
+
+#include "instance_data"
+
+void main() {
+#ifdef LIGHTMAP
+    vec3 light = texture(u_lightmap, v_lightmap_uv).rgb;
+#else
+    float light = max(dot(normalize(v_normal), -normalize(u_light_direction)), 0.0) * 0.8 + 0.2;
+#endif
+    vec4 color = texture(u_texture, v_uv) * u_color;
+    color.rgb *= light;
+    frag_color = apply_instance_data(color, v_instance_data);
+}
+"#;
+
+/// The default `apply_instance_data`, which ignores the instance data
+pub const DEFAULT_INSTANCE_DATA_FUNCTION: &str = r#"
+vec4 apply_instance_data(vec4 color, vec4 data) {
+    return color;
+}
+"#;
+
+/// How an attribute's components are stored in the vertex buffer. Buffers are written as f32s, so
+/// packed attributes take whole f32 slots holding the packed bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AttributeFormat {
+    #[default]
+    Float,
+    /// 16 bit floats, two per f32 slot, written with `write_half`
+    Half,
+    /// signed normalized 10-10-10-2 in one f32 slot, written with `pack_rgb10a2`. For normals and
+    /// tangents, read as a vec3 or vec4 in the shader.
+    Rgb10A2,
+}
 
 #[derive(Debug, Clone)]
 pub struct LayoutMetaData {
+    /// (location, components)
     attributes: Vec<(u32, u32)>,
+    formats: Vec<AttributeFormat>,
+    /// in f32 slots
     stride: u32,
 }
 
+impl AttributeFormat {
+    /// # Returns
+    /// how many f32 slots of the vertex buffer an attribute with `components` takes
+    pub fn slots(&self, components: u32) -> u32 {
+        match self {
+            Self::Float => components,
+            Self::Half => components.div_ceil(2),
+            Self::Rgb10A2 => 1,
+        }
+    }
+
+    /// # Returns
+    /// (size, type, normalized) for glVertexAttribPointer
+    fn to_gl(self, components: u32) -> (GLint, GLenum, GLboolean) {
+        match self {
+            Self::Float => (components as GLint, gl::FLOAT, gl::FALSE),
+            Self::Half => (components as GLint, gl::HALF_FLOAT, gl::FALSE),
+            Self::Rgb10A2 => (4, gl::INT_2_10_10_10_REV, gl::TRUE),
+        }
+    }
+}
+
 impl LayoutMetaData {
     pub fn new(alignments: Vec<(u32, u32)>) -> Self {
-        let mut stride = 0;
-        for (_, s) in &alignments {
-            stride += *s;
-        }
+        let formats = vec![AttributeFormat::Float; alignments.len()];
+        Self::from_parts(alignments, formats)
+    }
+
+    /// Creates a layout of (location, components, format) attributes
+    pub fn with_formats(attributes: Vec<(u32, u32, AttributeFormat)>) -> Self {
+        let (alignments, formats) = attributes.into_iter().map(|(loc, size, format)| ((loc, size), format)).unzip();
+        Self::from_parts(alignments, formats)
+    }
+
+    fn from_parts(attributes: Vec<(u32, u32)>, formats: Vec<AttributeFormat>) -> Self {
+        let stride = attributes.iter().zip(&formats).map(|((_, size), format)| format.slots(*size)).sum();
         Self {
-            attributes: alignments,
-            stride
+            attributes,
+            formats,
+            stride,
+        }
+    }
+
+    /// # Returns
+    /// the size of one vertex in f32 slots
+    pub fn get_stride(&self) -> u32 {
+        self.stride
+    }
+
+    /// Points the attributes of the bound VAO at the bound array buffer
+    unsafe fn apply(&self, divisor: u32) {
+        let f_size = size_of::<f32>() as u32;
+        let mut pointer = 0u32;
+        for ((loc, size), format) in self.attributes.iter().zip(&self.formats) {
+            let (gl_size, ty, normalized) = format.to_gl(*size);
+            unsafe {
+                gl::VertexAttribPointer(*loc, gl_size, ty, normalized, (self.stride * f_size) as GLsizei, (pointer * f_size) as *const c_void);
+                gl::EnableVertexAttribArray(*loc);
+                gl::VertexAttribDivisor(*loc, divisor);
+            }
+            pointer += format.slots(*size);
         }
     }
 }
@@ -43,20 +179,373 @@ impl MeshLayout {
     }
 }
 
+/// How vertices are assembled into primitives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PrimitiveTopology {
+    Points,
+    /// every two vertices form a line
+    Lines,
+    /// every vertex after the first continues the line from the previous one
+    LineStrip,
+    /// a line strip that also connects the last vertex to the first
+    LineLoop,
+    /// every three vertices form a triangle
+    #[default]
+    Triangles,
+    /// every vertex after the first two forms a triangle with the two before it
+    TriangleStrip,
+    /// every vertex after the first two forms a triangle with the one before it and the first
+    TriangleFan,
+}
+
+impl PrimitiveTopology {
+    pub fn to_gl(&self) -> GLenum {
+        match self {
+            Self::Points => gl::POINTS,
+            Self::Lines => gl::LINES,
+            Self::LineStrip => gl::LINE_STRIP,
+            Self::LineLoop => gl::LINE_LOOP,
+            Self::Triangles => gl::TRIANGLES,
+            Self::TriangleStrip => gl::TRIANGLE_STRIP,
+            Self::TriangleFan => gl::TRIANGLE_FAN,
+        }
+    }
+
+    /// # Returns
+    /// the primitives a geometry stage receives when drawing with this topology
+    pub fn primitive_type(&self) -> PrimitiveType {
+        match self {
+            Self::Points => PrimitiveType::Points,
+            Self::Lines | Self::LineStrip | Self::LineLoop => PrimitiveType::Lines,
+            Self::Triangles | Self::TriangleStrip | Self::TriangleFan => PrimitiveType::Triangles,
+        }
+    }
+
+    /// Checks that `count` vertices or indices form whole primitives. Strips, loops and fans only
+    /// need enough vertices for their first primitive.
+    pub fn validate_count(&self, count: u32) -> Result<()> {
+        let complete = match self {
+            Self::Points => true,
+            Self::Lines => count.is_multiple_of(2),
+            Self::LineStrip | Self::LineLoop => count != 1,
+            Self::Triangles => count.is_multiple_of(3),
+            Self::TriangleStrip | Self::TriangleFan => count == 0 || count >= 3,
+        };
+        match (complete, self) {
+            (true, _) => Ok(()),
+            (false, Self::Triangles) => Err(BufferRenderError::IncompleteTriangleData.into()),
+            (false, topology) => Err(BufferRenderError::IncompletePrimitiveData { topology: *topology, count }.into()),
+        }
+    }
+}
+
+/// Index data of a mesh, u16 indices take half the memory for meshes under 65536 vertices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Indices {
+    /// Picks u16 indices when every index fits
+    pub fn from_u32(indices: Vec<u32>) -> Self {
+        if indices.iter().all(|i| *i <= u16::MAX as u32) {
+            Indices::U16(indices.into_iter().map(|i| i as u16).collect())
+        } else {
+            Indices::U32(indices)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U16(indices) => indices.len(),
+            Indices::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// # Returns
+    /// the largest index, None if there are no indices
+    pub fn max(&self) -> Option<u32> {
+        match self {
+            Indices::U16(indices) => indices.iter().max().map(|i| *i as u32),
+            Indices::U32(indices) => indices.iter().max().copied(),
+        }
+    }
+
+    /// # Returns
+    /// the index type for glDrawElements
+    pub fn gl_type(&self) -> GLenum {
+        match self {
+            Indices::U16(_) => gl::UNSIGNED_SHORT,
+            Indices::U32(_) => gl::UNSIGNED_INT,
+        }
+    }
+
+    /// Checks that every index points at one of `vertex_count` vertices
+    pub fn validate(&self, vertex_count: u32) -> Result<()> {
+        match self.max() {
+            Some(index) if index >= vertex_count => Err(BufferRenderError::IndexOutOfRange { index, vertex_count }.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Uploads the indices to `buffer`, replacing its contents
+    ///
+    /// # Safety
+    /// `buffer` must be a buffer of the current context. The buffer is bound to ARRAY_BUFFER for the
+    /// upload, which leaves the element buffer of the bound VAO alone.
+    unsafe fn upload(&self, buffer: GLuint, usage: GLenum) {
+        let (size, data) = match self {
+            Indices::U16(indices) => (size_of_val(indices.as_slice()), indices.as_ptr() as *const c_void),
+            Indices::U32(indices) => (size_of_val(indices.as_slice()), indices.as_ptr() as *const c_void),
+        };
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, buffer);
+            gl::BufferData(gl::ARRAY_BUFFER, size as isize, data, usage);
+        }
+    }
+}
+
+/// The six planes of a camera's view volume, for visibility tests
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// (normal, distance) pointing inwards: left, right, bottom, top, near, far
+    planes: [Vec4; 6],
+}
+
+/// Per-instance culling applied by InstancedMesh::flush, testing each instance's bounding sphere
+/// against the camera frustum and an optional maximum distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstanceCulling {
+    frustum: Frustum,
+    camera_position: Vec3,
+    max_distance: Option<f32>,
+    /// bounding sphere of the mesh in model space, scaled by each instance's transform
+    bounds_center: Vec3,
+    bounds_radius: f32,
+}
+
+impl Frustum {
+    /// Extracts the planes from an OpenGL view projection matrix
+    pub fn from_view_proj(view_proj: Mat4) -> Self {
+        let (r0, r1, r2, r3) = (view_proj.row(0), view_proj.row(1), view_proj.row(2), view_proj.row(3));
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r3 + r2, r3 - r2]
+            .map(|p| p / p.truncate().length());
+        Self { planes }
+    }
+
+    /// # Returns
+    /// false if the sphere is entirely outside the frustum
+    pub fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+        self.planes.iter().all(|p| p.truncate().dot(center) + p.w >= -radius)
+    }
+}
+
+impl InstanceCulling {
+    /// `bounds_radius` is the radius of a sphere around the model space origin containing the mesh
+    pub fn new(view_proj: Mat4, camera_position: Vec3, bounds_radius: f32) -> Self {
+        Self {
+            frustum: Frustum::from_view_proj(view_proj),
+            camera_position,
+            max_distance: None,
+            bounds_center: Vec3::ZERO,
+            bounds_radius,
+        }
+    }
+
+    /// Culls instances whose bounding sphere is further than `max_distance` from the camera
+    pub fn with_max_distance(mut self, max_distance: f32) -> Self {
+        self.max_distance = Some(max_distance);
+        self
+    }
+
+    /// Moves the bounding sphere away from the model space origin
+    pub fn with_bounds_center(mut self, center: Vec3) -> Self {
+        self.bounds_center = center;
+        self
+    }
+
+    /// Updates the camera, call whenever it moves
+    pub fn set_view(&mut self, view_proj: Mat4, camera_position: Vec3) {
+        self.frustum = Frustum::from_view_proj(view_proj);
+        self.camera_position = camera_position;
+    }
+
+    pub fn set_max_distance(&mut self, max_distance: Option<f32>) {
+        self.max_distance = max_distance;
+    }
+
+    pub fn get_frustum(&self) -> &Frustum {
+        &self.frustum
+    }
+
+    /// # Returns
+    /// whether an instance drawn with `transform` may be visible
+    pub fn is_visible(&self, transform: &Mat4) -> bool {
+        let center = transform.transform_point3(self.bounds_center);
+        let scale = transform.x_axis.truncate().length()
+            .max(transform.y_axis.truncate().length())
+            .max(transform.z_axis.truncate().length());
+        let radius = self.bounds_radius * scale;
+        if let Some(max_distance) = self.max_distance
+            && center.distance(self.camera_position) - radius > max_distance
+        {
+            return false;
+        }
+        self.frustum.intersects_sphere(center, radius)
+    }
+}
+
 pub trait InstancedMeshData {
     fn get_transform(&self) -> &Mat4;
+    /// Writes this instance in the mesh's instance layout, see `write_standard_instance` for the standard shader
     fn write_data(&self, buffer: &mut Vec<f32>);
     fn write_mesh(buffer: &mut Vec<f32>);
     fn setup_shader(engine: &mut Engine, program: GLuint);
 }
 
+/// Mesh layout of the standard instanced shader: position(3), normal(3), uv(2)
+pub fn standard_mesh_layout() -> LayoutMetaData {
+    LayoutMetaData::new(vec![(0, 3), (1, 3), (2, 2)])
+}
+
+/// Mesh layout of the lightmapped standard shader: position(3), normal(3), uv(2), lightmap uv(2)
+pub fn standard_lightmapped_mesh_layout() -> LayoutMetaData {
+    LayoutMetaData::new(vec![(0, 3), (1, 3), (2, 2), (8, 2)])
+}
+
+/// Packed mesh layout of the standard instanced shader, half the size of `standard_mesh_layout`:
+/// half float position(3), 10-10-10-2 normal, half float uv(2). Write vertices with
+/// `write_packed_vertex`.
+pub fn standard_packed_mesh_layout() -> LayoutMetaData {
+    LayoutMetaData::with_formats(vec![
+        (0, 3, AttributeFormat::Half),
+        (1, 3, AttributeFormat::Rgb10A2),
+        (2, 2, AttributeFormat::Half),
+    ])
+}
+
+/// Instance layout of the standard instanced shader: transform(16) then the custom data vec4(4).
+/// InstancedMeshData::write_data should write the instance with `write_standard_instance`.
+pub fn standard_instance_layout() -> LayoutMetaData {
+    LayoutMetaData::new(vec![(3, 4), (4, 4), (5, 4), (6, 4), (7, 4)])
+}
+
+/// Writes one instance in the standard instance layout
+pub fn write_standard_instance(transform: &Mat4, data: Vec4, buffer: &mut Vec<f32>) {
+    transform.upload_gl(buffer);
+    data.upload_gl(buffer);
+}
+
+/// Writes one vertex in `standard_packed_mesh_layout`
+pub fn write_packed_vertex(position: Vec3, normal: Vec3, uv: Vec2, buffer: &mut Vec<f32>) {
+    write_half(&position.to_array(), buffer);
+    buffer.push(pack_rgb10a2(normal.extend(0.)));
+    write_half(&uv.to_array(), buffer);
+}
+
+/// Converts to a 16 bit float, rounding to nearest even. Out of range values become infinity.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // keep NaNs NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    let (half, remainder, halfway) = if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // subnormal, shift the mantissa with its implicit leading bit into place
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        (mantissa >> shift, mantissa & ((1 << shift) - 1), 1 << (shift - 1))
+    } else {
+        (((exponent as u32) << 10) | (mantissa >> 13), mantissa & 0x1fff, 0x1000)
+    };
+    // a carry out of the mantissa correctly bumps the exponent
+    let rounded = if remainder > halfway || (remainder == halfway && half & 1 == 1) { half + 1 } else { half };
+    sign | rounded as u16
+}
+
+/// Packs two 16 bit floats into the bits of one f32 slot, `a` in the low half
+pub fn pack_half2(a: f32, b: f32) -> f32 {
+    f32::from_bits(f32_to_f16(a) as u32 | (f32_to_f16(b) as u32) << 16)
+}
+
+/// Writes `values` as AttributeFormat::Half, padding an odd count with 0
+pub fn write_half(values: &[f32], buffer: &mut Vec<f32>) {
+    for pair in values.chunks(2) {
+        buffer.push(pack_half2(pair[0], pair.get(1).copied().unwrap_or(0.)));
+    }
+}
+
+/// Packs a vector with components in -1..1 as AttributeFormat::Rgb10A2. w keeps only -1, 0 or 1.
+pub fn pack_rgb10a2(value: Vec4) -> f32 {
+    let component = |v: f32, max: f32, bits: u32| ((v.clamp(-1., 1.) * max).round() as i32 as u32) & ((1 << bits) - 1);
+    let packed = component(value.x, 511., 10)
+        | component(value.y, 511., 10) << 10
+        | component(value.z, 511., 10) << 20
+        | component(value.w, 1., 2) << 30;
+    f32::from_bits(packed)
+}
+
+/// Compiles the standard instanced shader.
+///
+/// `instance_data_function` replaces the GLSL function
+/// `vec4 apply_instance_data(vec4 color, vec4 data)`, which receives the lit fragment color and the
+/// per-instance vec4 written by `write_standard_instance`, and returns the final color (it may `discard`).
+/// Defaults to `DEFAULT_INSTANCE_DATA_FUNCTION`.
+///
+/// The shader's uniforms are u_view_proj, u_texture, u_color and u_light_direction, and its attributes
+/// are at the locations in `semantics::STANDARD_MESH_ATTRIBUTES`.
+pub fn compile_standard_instanced_program(instance_data_function: Option<&str>) -> Result<GLuint> {
+    let mut includes = ShaderIncludes::new();
+    includes.add("instance_data", instance_data_function.unwrap_or(DEFAULT_INSTANCE_DATA_FUNCTION));
+    compile_program_with_includes(STANDARD_INSTANCED_VERTEX_SHADER, STANDARD_INSTANCED_FRAGMENT_SHADER, &includes)
+}
+
+/// Compiles the standard instanced shader lit by a baked lightmap instead of u_light_direction,
+/// see lightmap::LightmapBaker. Meshes need `standard_lightmapped_mesh_layout`, and the lightmap
+/// is sampled from the u_lightmap uniform.
+pub fn compile_standard_lightmapped_program(instance_data_function: Option<&str>) -> Result<GLuint> {
+    let mut includes = ShaderIncludes::new();
+    includes.add("instance_data", instance_data_function.unwrap_or(DEFAULT_INSTANCE_DATA_FUNCTION));
+    compile_program_with_includes(
+        &inject_defines(STANDARD_INSTANCED_VERTEX_SHADER, &["LIGHTMAP"]),
+        &inject_defines(STANDARD_INSTANCED_FRAGMENT_SHADER, &["LIGHTMAP"]),
+        &includes,
+    )
+}
+
 pub trait MeshController<T: InstancedMeshData> {
     fn write_mesh(&mut self, buffer: &mut Vec<f32>);
     fn setup_shader(&mut self, engine: &mut Engine, program: GLuint);
 }
 
+/// Object safe interface for instanced meshes, used by the Registry
 pub trait InstancedMeshTrait {
+    /// Switches the mesh to the VAO of the given context, creating one if the mesh hasn't been used
+    /// in that context yet. The context must be current.
+    fn use_context(&mut self, context: ContextId);
+    /// Deletes the VAO created for the given context. The context must be current.
+    fn release_context(&mut self, gl_state: &mut GlStateManager, context: ContextId);
+}
 
+impl std::fmt::Debug for dyn InstancedMeshTrait {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("InstancedMesh")
+    }
 }
 
 #[derive(Debug)]
@@ -65,19 +554,73 @@ pub struct InstancedMesh<T: InstancedMeshData, K: MeshController<T>> {
     data_controller: Option<K>,
     shader: GLuint,
     vertex_count: u32,
+    /// indices drawn per instance, vertex_count unless set_indices was used
+    index_count: u32,
+    /// UNSIGNED_SHORT or UNSIGNED_INT
+    index_type: GLenum,
+    /// whether the index buffer was set with set_indices, rather than counting up the vertices
+    custom_indices: bool,
+    /// the largest index set with set_indices, checked against the vertex count by update_mesh
+    max_index: Option<u32>,
+    topology: PrimitiveTopology,
     layout: MeshLayout,
-    vao: GLuint,
+    /// VAOs can't be shared between contexts, so each context the mesh is used in gets its own
+    vaos: HashMap<ContextId, GLuint>,
+    context: ContextId,
     vbo: GLuint,
     indices_vbo: GLuint,
     instance_vbo: GLuint,
+    /// instances the instance buffer has room for
+    instance_capacity: usize,
+    /// whether the instance buffer holds the data of an InstanceSync, rather than flushed draws
+    holds_synced: bool,
+    culling: Option<InstanceCulling>,
+    /// draws skipped by culling in the last flush
+    culled: usize,
     freed: bool,
 }
 
-impl<T: InstancedMeshData, K: MeshController<T>> InstancedMeshTrait for InstancedMesh<T, K> {}
+impl<T: InstancedMeshData, K: MeshController<T>> InstancedMeshTrait for InstancedMesh<T, K> {
+    fn use_context(&mut self, context: ContextId) {
+        if !self.vaos.contains_key(&context) {
+            unsafe {
+                let mut vao = 0;
+                gl::GenVertexArrays(1, &mut vao);
+                gl::BindVertexArray(vao);
+                setup_vertex_attributes(&self.layout, self.vbo, self.indices_vbo, self.instance_vbo);
+                gl::BindVertexArray(0);
+                self.vaos.insert(context, vao);
+            }
+        }
+        self.context = context;
+    }
+
+    fn release_context(&mut self, gl_state: &mut GlStateManager, context: ContextId) {
+        if let Some(vao) = self.vaos.remove(&context) {
+            gl_state.destroy_vao(vao);
+        }
+    }
+}
+
+/// Points the attributes of the currently bound VAO at the mesh and instance buffers
+unsafe fn setup_vertex_attributes(layout: &MeshLayout, vbo: GLuint, indices_vbo: GLuint, instance_vbo: GLuint) {
+    unsafe {
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        layout.mesh_layout.apply(0);
+
+        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, indices_vbo);
+
+        gl::BindBuffer(gl::ARRAY_BUFFER, instance_vbo);
+        layout.instance_layout.apply(1);
+    }
+}
 
 impl<T: InstancedMeshData, K: MeshController<T>> InstancedMesh<T, K> {
 
-    pub fn new(shader_program: GLuint, vertex_count: u32, layout: MeshLayout, data_controller: Option<K>) -> Self {
+    /// Creates the mesh in the current context of `gl_state`.
+    /// Use InstancedMeshTrait::use_context (or add the mesh to the Registry) to render it in other contexts.
+    pub fn new(gl_state: &GlStateManager, shader_program: GLuint, vertex_count: u32, layout: MeshLayout, data_controller: Option<K>) -> Self {
+        let context = gl_state.get_current_context();
         unsafe {
             let mut data_controller = data_controller;
             let mut vao = 0;
@@ -104,13 +647,6 @@ impl<T: InstancedMeshData, K: MeshController<T>> InstancedMesh<T, K> {
             gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
             gl::BufferData(gl::ARRAY_BUFFER, (buffer.len() * f_size) as isize, buffer.as_ptr() as *const _, gl::STATIC_DRAW);
 
-            let mut pointer = 0u32;
-            for (loc, size) in &layout.mesh_layout.attributes {
-                gl::VertexAttribPointer(*loc, *size as GLint, gl::FLOAT, gl::FALSE, (stride * f_size as u32) as GLsizei, (pointer * f_size as u32) as *const c_void);
-                gl::EnableVertexAttribArray(*loc);gl::VertexAttribDivisor(*loc, 0);
-                pointer += *size;
-            }
-
             let mut indices_buffer = Vec::with_capacity(vertex_count as usize);
             for i in 0..vertex_count {
                 indices_buffer.push(i);
@@ -118,38 +654,157 @@ impl<T: InstancedMeshData, K: MeshController<T>> InstancedMesh<T, K> {
             gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, indices_vbo);
             gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, (indices_buffer.len() * u_size) as isize, indices_buffer.as_ptr() as *const c_void, gl::STATIC_DRAW);
 
-            gl::BindBuffer(gl::ARRAY_BUFFER, instance_vbo);
-            let stride = layout.instance_layout.stride;
-            let mut pointer = 0u32;
-            for (loc, size) in &layout.instance_layout.attributes {
-                gl::VertexAttribPointer(*loc, *size as GLint, gl::FLOAT, gl::FALSE, (stride * f_size as u32) as GLsizei, (pointer * f_size as u32) as *const c_void);
-                gl::EnableVertexAttribArray(*loc);
-                gl::VertexAttribDivisor(*loc, 1);
-                pointer += size;
-            }
+            setup_vertex_attributes(&layout, vbo, indices_vbo, instance_vbo);
             gl::BindVertexArray(0);
 
+            let mut vaos = HashMap::new();
+            vaos.insert(context, vao);
+
             Self {
                 draws: Vec::new(),
                 vertex_count,
+                index_count: vertex_count,
+                index_type: gl::UNSIGNED_INT,
+                custom_indices: false,
+                max_index: None,
+                topology: PrimitiveTopology::Triangles,
                 layout,
                 data_controller,
                 shader: shader_program,
-                vao,
+                vaos,
+                context,
                 vbo,
                 indices_vbo,
                 instance_vbo,
+                instance_capacity: 0,
+                holds_synced: false,
+                culling: None,
+                culled: 0,
                 freed: false
             }
         }
     }
 
+    /// Draws the mesh's vertices in the order of `indices` instead of one after another, so
+    /// vertices shared between triangles are stored once.
+    pub fn with_indices(mut self, indices: &Indices) -> Result<Self> {
+        self.set_indices(indices)?;
+        Ok(self)
+    }
+
+    /// Replaces the mesh's index buffer, see with_indices. Every index must be below the mesh's
+    /// vertex count and the indices must form whole primitives of the mesh's topology.
+    pub fn set_indices(&mut self, indices: &Indices) -> Result<()> {
+        indices.validate(self.vertex_count)?;
+        self.topology.validate_count(indices.len() as u32)?;
+        unsafe {
+            indices.upload(self.indices_vbo, gl::STATIC_DRAW);
+        }
+        self.index_count = indices.len() as u32;
+        self.index_type = indices.gl_type();
+        self.custom_indices = true;
+        self.max_index = indices.max();
+        Ok(())
+    }
+
+    pub fn get_vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    pub fn get_controller(&self) -> Option<&K> {
+        self.data_controller.as_ref()
+    }
+
+    /// Gives access to the controller, e.g. to change the geometry it writes before update_mesh
+    pub fn get_controller_mut(&mut self) -> Option<&mut K> {
+        self.data_controller.as_mut()
+    }
+
+    /// Writes the mesh's vertices again through the controller (or `T::write_mesh`) and uploads
+    /// them into the existing vertex buffer, for procedurally deforming geometry. The VAOs and
+    /// buffers are kept. When the vertex count changes the buffer is reallocated; without
+    /// set_indices the default indices follow the new count, while indices set with set_indices
+    /// are kept and must stay below it, otherwise nothing is uploaded and an error is returned.
+    pub fn update_mesh(&mut self, vertex_count: u32) -> Result<()> {
+        let stride = self.layout.mesh_layout.stride as usize;
+        let mut buffer = Vec::with_capacity(vertex_count as usize * stride);
+        if let Some(controller) = &mut self.data_controller {
+            controller.write_mesh(&mut buffer);
+        } else {
+            T::write_mesh(&mut buffer);
+        }
+        if buffer.len() != vertex_count as usize * stride {
+            return Err(BufferRenderError::MalformedData.into());
+        }
+        if !self.custom_indices {
+            self.topology.validate_count(vertex_count)?;
+        } else if let Some(index) = self.max_index && index >= vertex_count {
+            return Err(BufferRenderError::IndexOutOfRange { index, vertex_count }.into());
+        }
+
+        let size = size_of_val(buffer.as_slice()) as isize;
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            if vertex_count == self.vertex_count {
+                // orphan the old storage so the upload doesn't wait on draws still reading it
+                gl::BufferData(gl::ARRAY_BUFFER, size, std::ptr::null(), gl::DYNAMIC_DRAW);
+                gl::BufferSubData(gl::ARRAY_BUFFER, 0, size, buffer.as_ptr() as *const _);
+            } else {
+                gl::BufferData(gl::ARRAY_BUFFER, size, buffer.as_ptr() as *const _, gl::DYNAMIC_DRAW);
+                if !self.custom_indices {
+                    let indices = Indices::from_u32((0..vertex_count).collect());
+                    indices.upload(self.indices_vbo, gl::STATIC_DRAW);
+                    self.index_count = vertex_count;
+                    self.index_type = indices.gl_type();
+                }
+            }
+        }
+        self.vertex_count = vertex_count;
+        Ok(())
+    }
+
+    pub fn get_index_count(&self) -> u32 {
+        self.index_count
+    }
+
+    /// Draws the mesh as `topology` instead of triangles
+    pub fn with_topology(mut self, topology: PrimitiveTopology) -> Result<Self> {
+        self.set_topology(topology)?;
+        Ok(self)
+    }
+
+    /// Changes how the mesh's vertices, or indices if set, are assembled. They must form whole
+    /// primitives of the new topology.
+    pub fn set_topology(&mut self, topology: PrimitiveTopology) -> Result<()> {
+        topology.validate_count(self.index_count)?;
+        self.topology = topology;
+        Ok(())
+    }
+
+    pub fn get_topology(&self) -> PrimitiveTopology {
+        self.topology
+    }
+
+    /// Deletes the mesh buffers and the VAO of every context. VAOs of contexts other than the
+    /// current one are deleted once they are current again, see GlStateManager::destroy_vao_in.
     pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
         gl_state.destroy_vbo_box_array(Box::new([self.vbo, self.indices_vbo, self.instance_vbo]));
-        gl_state.destroy_vao(self.vao);
+        for (context, vao) in self.vaos.drain() {
+            gl_state.destroy_vao_in(context, vao);
+        }
         self.freed = true;
     }
 
+    /// Checks the mesh and instance layouts against the attributes the shader actually reads, and
+    /// that a geometry stage (if any) takes the mesh's primitives
+    pub fn validate(&self) -> Result<()> {
+        let mut layout = self.layout.mesh_layout.attributes.clone();
+        layout.extend_from_slice(&self.layout.instance_layout.attributes);
+        let reflection = ShaderReflection::reflect(self.shader);
+        reflection.validate_layout(&layout)?;
+        reflection.validate_primitive(self.topology.primitive_type())
+    }
+
     pub fn draw(&mut self, data: T) {
         self.draws.push(data);
     }
@@ -157,6 +812,115 @@ impl<T: InstancedMeshData, K: MeshController<T>> InstancedMesh<T, K> {
         self.draws.clear();
     }
 
+    /// Skips queued draws that can't be visible when flushing, None (the default) draws everything.
+    /// Synced instances aren't culled.
+    pub fn set_culling(&mut self, culling: Option<InstanceCulling>) {
+        self.culling = culling;
+    }
+
+    pub fn get_culling(&self) -> Option<&InstanceCulling> {
+        self.culling.as_ref()
+    }
+
+    pub fn get_culling_mut(&mut self) -> Option<&mut InstanceCulling> {
+        self.culling.as_mut()
+    }
+
+    /// # Returns
+    /// how many draws culling skipped in the last flush
+    pub fn get_culled_count(&self) -> usize {
+        self.culled
+    }
+
+    /// Uploads the data of every queued draw and renders all of them in a single instanced draw call.
+    /// Draws outside the culling volume, if set, are neither uploaded nor drawn.
+    pub fn flush(&mut self, engine: &mut Engine) -> Result<()> {
+        let mut draws = mem::take(&mut self.draws);
+        if let Some(culling) = &self.culling {
+            let queued = draws.len();
+            draws.retain(|data| culling.is_visible(data.get_transform()));
+            self.culled = queued - draws.len();
+        } else {
+            self.culled = 0;
+        }
+        if draws.is_empty() {
+            return Ok(());
+        }
+
+        let stride = self.layout.instance_layout.stride as usize;
+        let mut buffer = Vec::with_capacity(draws.len() * stride);
+        for data in &draws {
+            data.write_data(&mut buffer);
+        }
+        if buffer.len() != draws.len() * stride {
+            return Err(BufferRenderError::MalformedData.into());
+        }
+
+        engine.gl_state.use_program(self.shader);
+        if let Some(controller) = &mut self.data_controller {
+            controller.setup_shader(engine, self.shader);
+        } else {
+            T::setup_shader(engine, self.shader);
+        }
+        engine.gl_state.bind_vao(self.vaos[&self.context]);
+
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, (buffer.len() * size_of::<f32>()) as isize, buffer.as_ptr() as *const _, gl::STREAM_DRAW);
+            gl::DrawElementsInstanced(self.topology.to_gl(), self.index_count as GLsizei, self.index_type, std::ptr::null(), draws.len() as GLsizei);
+        }
+        self.instance_capacity = draws.len();
+        self.holds_synced = false;
+
+        Ok(())
+    }
+
+    /// Syncs `sync` with the engine's world and renders one instance per tracked entity in a single
+    /// instanced draw call. Only the instances that changed since the last call are uploaded, as
+    /// long as the mesh hasn't flushed other draws in between.
+    pub fn flush_synced(&mut self, engine: &mut Engine, sync: &mut InstanceSync<T>) -> Result<()>
+    where
+        T: hecs::Component + Clone + PartialEq,
+    {
+        sync.sync(&mut engine.ecs)?;
+        let dirty = sync.take_dirty();
+        if sync.is_empty() {
+            return Ok(());
+        }
+
+        let stride = self.layout.instance_layout.stride as usize;
+        if sync.get_stride() != Some(stride) {
+            return Err(BufferRenderError::MalformedData.into());
+        }
+
+        engine.gl_state.use_program(self.shader);
+        if let Some(controller) = &mut self.data_controller {
+            controller.setup_shader(engine, self.shader);
+        } else {
+            T::setup_shader(engine, self.shader);
+        }
+        engine.gl_state.bind_vao(self.vaos[&self.context]);
+
+        let f_size = size_of::<f32>();
+        let data = sync.get_data();
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo);
+            if !self.holds_synced || sync.len() > self.instance_capacity {
+                // grow to the next power of two so a few spawns don't reallocate every frame
+                self.instance_capacity = sync.len().next_power_of_two();
+                gl::BufferData(gl::ARRAY_BUFFER, (self.instance_capacity * stride * f_size) as isize, std::ptr::null(), gl::DYNAMIC_DRAW);
+                gl::BufferSubData(gl::ARRAY_BUFFER, 0, size_of_val(data) as isize, data.as_ptr() as *const _);
+                self.holds_synced = true;
+            } else if let Some(dirty) = dirty {
+                let floats = &data[dirty.start * stride..dirty.end * stride];
+                gl::BufferSubData(gl::ARRAY_BUFFER, (dirty.start * stride * f_size) as isize, size_of_val(floats) as isize, floats.as_ptr() as *const _);
+            }
+            gl::DrawElementsInstanced(self.topology.to_gl(), self.index_count as GLsizei, self.index_type, std::ptr::null(), sync.len() as GLsizei);
+        }
+
+        Ok(())
+    }
+
 }
 
 impl<T: InstancedMeshData, K: MeshController<T>> Drop for InstancedMesh<T, K> {
@@ -180,8 +944,16 @@ pub struct VertexRenderer<T: VertexRenderController> {
     layout: LayoutMetaData,
     vao: GLuint,
     vbo: GLuint,
+    /// element buffer, created by the first render with indices
+    ebo: GLuint,
+    /// indices queued for the next render, see put_indices
+    indices: Vec<u32>,
     program: GLuint,
+    /// vertices per patch when drawing GL_PATCHES, None draws the topology
+    patch_vertices: Option<u32>,
+    topology: PrimitiveTopology,
     _implicit: PhantomData<T>,
+    freed: bool,
 }
 
 impl<T: VertexRenderController> VertexRenderer<T> {
@@ -199,8 +971,13 @@ impl<T: VertexRenderController> VertexRenderer<T> {
                 layout,
                 vao,
                 vbo,
+                ebo: 0,
+                indices: Vec::new(),
                 program,
+                patch_vertices: None,
+                topology: PrimitiveTopology::Triangles,
                 _implicit: PhantomData,
+                freed: false,
             }
         }
     }
@@ -209,6 +986,45 @@ impl<T: VertexRenderController> VertexRenderer<T> {
         self.program = program;
     }
 
+    /// Deletes the VAO, the vertex buffer and the element buffer, if one was created
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        // deleting buffer 0 is ignored, for renderers that never drew indices
+        gl_state.destroy_vbo_vec(vec![self.vbo, self.ebo]);
+        gl_state.destroy_vao(self.vao);
+        self.freed = true;
+    }
+
+    /// Draws the buffer as GL_PATCHES of `count` vertices each, for tessellation shaders.
+    /// None goes back to drawing the topology.
+    pub fn set_patch_vertices(&mut self, count: Option<u32>) {
+        self.patch_vertices = count;
+    }
+
+    pub fn get_patch_vertices(&self) -> Option<u32> {
+        self.patch_vertices
+    }
+
+    /// Sets how the buffer is drawn when not drawing patches, triangles by default
+    pub fn set_topology(&mut self, topology: PrimitiveTopology) {
+        self.topology = topology;
+    }
+
+    pub fn get_topology(&self) -> PrimitiveTopology {
+        self.topology
+    }
+
+    /// Checks the layout against the attributes the shader actually reads, and that a geometry
+    /// stage (if any) takes the topology's primitives. When drawing patches the geometry stage
+    /// input comes from the tessellation stages, so it isn't checked.
+    pub fn validate(&self) -> Result<()> {
+        let reflection = ShaderReflection::reflect(self.program);
+        reflection.validate_layout(&self.layout.attributes)?;
+        if self.patch_vertices.is_none() {
+            reflection.validate_primitive(self.topology.primitive_type())?;
+        }
+        Ok(())
+    }
+
     pub fn put(&mut self, value: f32) -> &mut Self {
         self.buffer.push(value);
         self
@@ -235,6 +1051,14 @@ impl<T: VertexRenderController> VertexRenderer<T> {
         self
     }
 
+    /// Queues indices into the vertices put since the last render, relative to the first of them.
+    /// With indices the next render draws the indexed primitives with glDrawElements, otherwise
+    /// the vertices are drawn in order.
+    pub fn put_indices(&mut self, indices: &[u32]) -> &mut Self {
+        self.indices.extend_from_slice(indices);
+        self
+    }
+
     pub fn put_mat4(&mut self, mat: Mat4) -> &mut Self {
         let m = mat.to_cols_array();
         self.put4(m[0], m[1], m[2], m[3])
@@ -246,12 +1070,20 @@ impl<T: VertexRenderController> VertexRenderer<T> {
     pub fn render(&mut self, gl_state: &mut GlStateManager) -> Result<()> {
 
         let buf = mem::take(&mut self.buffer);
+        let indices = (!self.indices.is_empty()).then(|| Indices::from_u32(mem::take(&mut self.indices)));
 
         if !(buf.len() as u32).is_multiple_of(self.layout.stride) {
             return Err(BufferRenderError::MalformedData.into());
         }
-        if !(buf.len() as u32 / self.layout.stride).is_multiple_of(3) {
-            return Err(BufferRenderError::IncompleteTriangleData.into());
+        let vertex_count = buf.len() as u32 / self.layout.stride;
+        if let Some(indices) = &indices {
+            indices.validate(vertex_count)?;
+        }
+        let element_count = indices.as_ref().map_or(vertex_count, |indices| indices.len() as u32);
+        match self.patch_vertices {
+            Some(count) if !element_count.is_multiple_of(count) => return Err(BufferRenderError::IncompletePatchData(count).into()),
+            Some(_) => {}
+            None => self.topology.validate_count(element_count)?,
         }
 
         let f_size = size_of::<f32>();
@@ -265,16 +1097,28 @@ impl<T: VertexRenderController> VertexRenderer<T> {
             gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
             gl::BufferData(gl::ARRAY_BUFFER, (buf.len() * f_size) as isize, buf.as_ptr() as *const _, gl::STREAM_DRAW);
 
-            let mut pointer = 0u32;
+            self.layout.apply(0);
 
-            for (loc, size) in &self.layout.attributes {
-                gl::EnableVertexAttribArray(*loc);
-                gl::VertexAttribPointer(*loc, *size as GLint, gl::FLOAT, gl::FALSE, (self.layout.stride * f_size as u32) as GLsizei, (pointer * f_size as u32) as *const c_void);
-                pointer += *size;
+            let mode = match self.patch_vertices {
+                Some(count) => {
+                    gl::PatchParameteri(gl::PATCH_VERTICES, count as GLint);
+                    gl::PATCHES
+                }
+                None => self.topology.to_gl(),
+            };
+            match &indices {
+                Some(indices) => {
+                    if self.ebo == 0 {
+                        gl::GenBuffers(1, &mut self.ebo);
+                    }
+                    indices.upload(self.ebo, gl::STREAM_DRAW);
+                    // the element buffer binding is part of the bound VAO
+                    gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
+                    gl::DrawElements(mode, element_count as GLsizei, indices.gl_type(), std::ptr::null());
+                }
+                None => gl::DrawArrays(mode, 0, vertex_count as GLsizei),
             }
 
-            gl::DrawArrays(gl::TRIANGLES, 0, (buf.len() as u32 / self.layout.stride) as GLsizei);
-
             for (loc, _) in &self.layout.attributes {
                 gl::DisableVertexAttribArray(*loc);
             }
@@ -288,6 +1132,16 @@ impl<T: VertexRenderController> VertexRenderer<T> {
 
 }
 
+impl<T: VertexRenderController> Drop for VertexRenderer<T> {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            let mut state = GlStateManager::new();
+            self.destroy(&mut state);
+            panic!("Vertex renderer was not destroyed before dropping")
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Vertex {
     parts: Vec<(u8, u8, Vec<f32>)>
@@ -297,6 +1151,11 @@ pub trait BufferFormat {
     fn get_vertex(&self) -> Vertex;
     fn stride(&self) -> usize;
     fn get_sizes(&self) -> Vec<u8>;
+    /// (attribute name, location) of every attribute of the format, see ShaderReflection::validate_semantics.
+    /// Formats without attribute names return nothing.
+    fn get_semantics(&self) -> Vec<(String, u32)> {
+        Vec::new()
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -351,6 +1210,19 @@ impl BufferFormat for SimpleBufferFormat {
         v
     }
 
+    fn get_semantics(&self) -> Vec<(String, u32)> {
+        let mut names = vec![A_POSITION];
+
+        if self.color { names.push(A_COLOR) }
+        if self.normal { names.push(A_NORMAL) }
+        if self.uv { names.push(A_UV) }
+
+        names.into_iter()
+            .enumerate()
+            .map(|(loc, name)| (name.to_string(), loc as u32))
+            .collect()
+    }
+
 }
 impl BufferFormat for ArbitraryBufferFormat {
     fn get_vertex(&self) -> Vertex {
@@ -384,6 +1256,13 @@ impl BufferFormat for ArbitraryBufferFormat {
 
         v
     }
+
+    fn get_semantics(&self) -> Vec<(String, u32)> {
+        self.attributes.iter()
+            .enumerate()
+            .map(|(loc, (name, _, _))| (name.clone(), loc as u32))
+            .collect()
+    }
 }
 
 impl Vertex {
@@ -435,6 +1314,7 @@ pub struct BufferBuilder<F: BufferFormat> {
     shader: GLuint,
     uniforms: HashMap<String, GLUniform>,
     samplers: HashMap<String, (u32, GLuint)>,
+    topology: PrimitiveTopology,
 }
 
 impl<F: BufferFormat> BufferBuilder<F> {
@@ -447,6 +1327,7 @@ impl<F: BufferFormat> BufferBuilder<F> {
             shader,
             uniforms: HashMap::new(),
             samplers: HashMap::new(),
+            topology: PrimitiveTopology::Triangles,
         }
     }
 
@@ -465,14 +1346,39 @@ impl<F: BufferFormat> BufferBuilder<F> {
         self.samplers.insert(name.to_string(), (slot, tex));
     }
 
+    pub fn with_topology(mut self, topology: PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Sets how the vertices are assembled when rendering, triangles by default
+    pub fn set_topology(&mut self, topology: PrimitiveTopology) {
+        self.topology = topology;
+    }
+
+    pub fn get_topology(&self) -> PrimitiveTopology {
+        self.topology
+    }
+
+    /// Checks the vertex format against the attributes the shader actually reads, both their sizes
+    /// and their names, and that a geometry stage (if any) takes the topology's primitives
+    pub fn validate(&self) -> Result<()> {
+        let layout: Vec<(u32, u32)> = self.format.get_sizes().iter()
+            .enumerate()
+            .map(|(loc, size)| (loc as u32, *size as u32))
+            .collect();
+        let reflection = ShaderReflection::reflect(self.shader);
+        reflection.validate_layout(&layout)?;
+        reflection.validate_semantics(&self.format.get_semantics())?;
+        reflection.validate_primitive(self.topology.primitive_type())
+    }
+
     pub fn render(&mut self, gl_state: &mut GlStateManager) -> Result<()> {
 
         self.push_vertex();
 
         let stride = self.format.stride();
-        if !(self.data.len() / stride).is_multiple_of(3) {
-            return Err(BufferRenderError::IncompleteTriangleData.into())
-        }
+        self.topology.validate_count((self.data.len() / stride) as u32)?;
 
         unsafe {
             let mut vao = 0;
@@ -507,7 +1413,7 @@ impl<F: BufferFormat> BufferBuilder<F> {
 
             gl_state.use_program(self.shader);
             for (name, uni) in &self.uniforms {
-                gl_state.set_uniform(name, *uni);
+                gl_state.set_uniform(name, uni.clone());
             }
             
             for (name, (slot, tex)) in &self.samplers {
@@ -515,7 +1421,7 @@ impl<F: BufferFormat> BufferBuilder<F> {
                 gl_state.bind_texture(*slot, *tex);
             }
 
-            gl::DrawArrays(gl::TRIANGLES, 0, (self.data.len() / stride) as GLsizei);
+            gl::DrawArrays(self.topology.to_gl(), 0, (self.data.len() / stride) as GLsizei);
 
             gl::BindVertexArray(0);
             gl::DeleteBuffers(1, &vbo);
@@ -602,3 +1508,50 @@ impl BufferBuilder<ArbitraryBufferFormat> {
 
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gl_trace;
+
+    struct Point;
+
+    impl InstancedMeshData for Point {
+        fn get_transform(&self) -> &Mat4 {
+            &Mat4::IDENTITY
+        }
+        fn write_data(&self, _buffer: &mut Vec<f32>) {}
+        fn write_mesh(_buffer: &mut Vec<f32>) {}
+        fn setup_shader(_engine: &mut Engine, _program: GLuint) {}
+    }
+
+    /// Writes the given number of 3 component vertices
+    struct Vertices(u32);
+
+    impl MeshController<Point> for Vertices {
+        fn write_mesh(&mut self, buffer: &mut Vec<f32>) {
+            buffer.resize(self.0 as usize * 3, 0.);
+        }
+        fn setup_shader(&mut self, _engine: &mut Engine, _program: GLuint) {}
+    }
+
+    #[test]
+    fn shrinking_below_the_indices_is_rejected() {
+        gl_trace::load();
+        let mut gl_state = GlStateManager::new();
+        let layout = MeshLayout::new(LayoutMetaData::new(vec![(0, 3)]), LayoutMetaData::new(vec![]));
+        let mut mesh = InstancedMesh::new(&gl_state, 0, 6, layout, Some(Vertices(6)))
+            .with_indices(&Indices::from_u32(vec![0, 1, 5, 2, 3, 4]))
+            .unwrap();
+
+        mesh.get_controller_mut().unwrap().0 = 4;
+        let err = mesh.update_mesh(4).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(BufferRenderError::IndexOutOfRange { index: 5, vertex_count: 4 })));
+        assert_eq!(mesh.get_vertex_count(), 6);
+
+        mesh.set_indices(&Indices::from_u32(vec![0, 1, 2, 1, 2, 3])).unwrap();
+        mesh.update_mesh(4).unwrap();
+        assert_eq!(mesh.get_vertex_count(), 4);
+        mesh.destroy(&mut gl_state);
+    }
+}
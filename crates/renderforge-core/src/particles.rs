@@ -0,0 +1,304 @@
+use std::mem;
+use std::os::raw::c_void;
+use std::thread::panicking;
+
+use anyhow::Result;
+use gl::types::{GLint, GLsizei, GLuint};
+use glam::{Mat4, Vec2, Vec3};
+
+use crate::data::{BlendMode, Color, GLUniform, GlStateManager};
+use crate::semantics::{U_TEXTURE, U_VIEW_PROJ};
+use crate::shader::compile_program;
+
+const PARTICLE_VERTEX_SHADER: &str = r#"#version 330 core
+layout(location = 0) in vec3 a_position;
+layout(location = 1) in float a_size;
+layout(location = 2) in vec4 a_color;
+
+uniform mat4 u_view_proj;
+uniform float u_viewport_height;
+uniform float u_projection_scale;
+
+out vec4 v_color;
+out float v_depth;
+
+void main() {
+    gl_Position = u_view_proj * vec4(a_position, 1.0);
+    // world space size to pixels, the same scaling the projection applies to the y axis
+    gl_PointSize = a_size * u_projection_scale * u_viewport_height * 0.5 / gl_Position.w;
+    v_color = a_color;
+    v_depth = gl_Position.w;
+}
+"#;
+
+const PARTICLE_FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec4 v_color;
+in float v_depth;
+
+uniform sampler2D u_texture;
+uniform bool u_textured;
+uniform sampler2D u_scene_depth;
+uniform bool u_soft;
+uniform vec2 u_viewport_origin;
+uniform vec2 u_viewport_size;
+uniform vec2 u_depth_range;
+uniform float u_fade_distance;
+
+out vec4 frag_color;
+
+float linear_depth(float depth) {
+    float z = depth * 2.0 - 1.0;
+    return 2.0 * u_depth_range.x * u_depth_range.y / (u_depth_range.y + u_depth_range.x - z * (u_depth_range.y - u_depth_range.x));
+}
+
+void main() {
+    vec4 color = v_color;
+    if (u_textured) {
+        color *= texture(u_texture, gl_PointCoord);
+    } else {
+        // round points with a soft edge
+        float distance = length(gl_PointCoord - 0.5) * 2.0;
+        color.a *= 1.0 - smoothstep(0.8, 1.0, distance);
+    }
+    if (u_soft) {
+        float scene = linear_depth(texture(u_scene_depth, (gl_FragCoord.xy - u_viewport_origin) / u_viewport_size).r);
+        color.a *= clamp((scene - v_depth) / u_fade_distance, 0.0, 1.0);
+    }
+    if (color.a <= 0.0) {
+        discard;
+    }
+    frag_color = color;
+}
+"#;
+
+/// position(3), size(1), color(4)
+const PARTICLE_VERTEX_SIZE: usize = 8;
+
+const SCENE_DEPTH_SLOT: u32 = 1;
+
+/// A particle drawn as one point sprite
+#[derive(Debug, Clone, Copy)]
+pub struct Particle {
+    pub position: Vec3,
+    /// diameter in world units
+    pub size: f32,
+    pub color: Color,
+}
+
+/// Fades particles out where they get close to the scene behind them, instead of cutting hard
+/// lines where they intersect geometry. Needs a perspective projection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoftParticles {
+    /// scene depth, see FramebufferBuilder::with_depth_texture. It must not be attached to the
+    /// framebuffer the particles render into.
+    pub depth_texture: GLuint,
+    /// near and far planes of the projection the scene depth was rendered with
+    pub near: f32,
+    pub far: f32,
+    /// distance in front of the scene over which particles fade out
+    pub fade_distance: f32,
+}
+
+/// Draws particles as point sprites in one draw call. Particles are depth tested without writing
+/// depth, and sorted back to front unless the blend mode is additive.
+#[derive(Debug)]
+pub struct ParticleRenderer {
+    particles: Vec<Particle>,
+    view: Mat4,
+    projection: Mat4,
+    blend_mode: BlendMode,
+    texture: Option<GLuint>,
+    soft: Option<SoftParticles>,
+    program: GLuint,
+    vao: GLuint,
+    vbo: GLuint,
+    freed: bool,
+}
+
+impl Particle {
+    pub fn new(position: Vec3, size: f32) -> Self {
+        Self {
+            position,
+            size,
+            color: Color::rgb(1., 1., 1.),
+        }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+impl SoftParticles {
+    pub fn new(depth_texture: GLuint, near: f32, far: f32, fade_distance: f32) -> Self {
+        Self { depth_texture, near, far, fade_distance }
+    }
+
+    /// Takes the near and far planes from an OpenGL perspective projection, like
+    /// Mat4::perspective_rh_gl
+    pub fn from_projection(depth_texture: GLuint, projection: &Mat4, fade_distance: f32) -> Self {
+        let (a, b) = (projection.z_axis.z, projection.w_axis.z);
+        Self {
+            depth_texture,
+            near: b / (a - 1.),
+            far: b / (a + 1.),
+            fade_distance,
+        }
+    }
+}
+
+impl ParticleRenderer {
+    /// Compiles the particle shader and creates the gl objects used by the renderer.
+    pub fn new() -> Result<Self> {
+        let program = compile_program(PARTICLE_VERTEX_SHADER, PARTICLE_FRAGMENT_SHADER)?;
+
+        unsafe {
+            let mut vao = 0;
+            gl::GenVertexArrays(1, &mut vao);
+            gl::BindVertexArray(vao);
+
+            let mut vbo = 0;
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+            let f_size = size_of::<f32>();
+            let stride = (PARTICLE_VERTEX_SIZE * f_size) as GLsizei;
+            let mut pointer = 0;
+            for (loc, size) in [(0u32, 3usize), (1, 1), (2, 4)] {
+                gl::VertexAttribPointer(loc, size as GLint, gl::FLOAT, gl::FALSE, stride, (pointer * f_size) as *const c_void);
+                gl::EnableVertexAttribArray(loc);
+                pointer += size;
+            }
+
+            gl::BindVertexArray(0);
+
+            Ok(Self {
+                particles: Vec::new(),
+                view: Mat4::IDENTITY,
+                projection: Mat4::IDENTITY,
+                blend_mode: BlendMode::Alpha,
+                texture: None,
+                soft: None,
+                program,
+                vao,
+                vbo,
+                freed: false,
+            })
+        }
+    }
+
+    pub fn set_view(&mut self, view: Mat4, projection: Mat4) {
+        self.view = view;
+        self.projection = projection;
+    }
+
+    /// Sets the blend mode used when rendering, defaults to BlendMode::Alpha
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    pub fn get_blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    /// Samples `texture` across each point, None draws round dots
+    pub fn set_texture(&mut self, texture: Option<GLuint>) {
+        self.texture = texture;
+    }
+
+    pub fn set_soft_particles(&mut self, soft: Option<SoftParticles>) {
+        self.soft = soft;
+    }
+
+    pub fn get_soft_particles(&self) -> Option<&SoftParticles> {
+        self.soft.as_ref()
+    }
+
+    pub fn get_soft_particles_mut(&mut self) -> Option<&mut SoftParticles> {
+        self.soft.as_mut()
+    }
+
+    pub fn draw(&mut self, particle: Particle) {
+        self.particles.push(particle);
+    }
+
+    pub fn cancel_draws(&mut self) {
+        self.particles.clear();
+    }
+
+    /// Draws every queued particle into the bound framebuffer
+    pub fn render(&mut self, gl_state: &mut GlStateManager) -> Result<()> {
+        let mut particles = mem::take(&mut self.particles);
+        if particles.is_empty() {
+            return Ok(());
+        }
+        if self.blend_mode != BlendMode::Additive {
+            let view = self.view;
+            particles.sort_by(|a, b| view.transform_point3(a.position).z.total_cmp(&view.transform_point3(b.position).z));
+        }
+
+        let mut buffer = Vec::with_capacity(particles.len() * PARTICLE_VERTEX_SIZE);
+        for particle in &particles {
+            buffer.extend_from_slice(&particle.position.to_array());
+            buffer.push(particle.size);
+            buffer.extend_from_slice(&particle.color.to_array());
+        }
+
+        let viewport = gl_state.get_viewport();
+        // the scene depth covers the viewport, which may be offset, e.g. one view of several
+        let viewport_origin = Vec2::new(viewport[0] as f32, viewport[1] as f32);
+        let viewport_size = Vec2::new(viewport[2].max(1) as f32, viewport[3].max(1) as f32);
+
+        gl_state.use_program(self.program);
+        gl_state.bind_vao(self.vao);
+        gl_state.blending(true);
+        gl_state.blend_mode(self.blend_mode);
+        gl_state.depth_mask(false);
+        gl_state.program_point_size(true);
+
+        gl_state.set_uniform(U_VIEW_PROJ, GLUniform::Mat4(self.projection * self.view));
+        gl_state.set_uniform("u_viewport_height", GLUniform::F32(viewport_size.y));
+        gl_state.set_uniform("u_projection_scale", GLUniform::F32(self.projection.y_axis.y));
+        gl_state.set_uniform(U_TEXTURE, GLUniform::I32(0));
+        gl_state.set_uniform("u_textured", GLUniform::Bool(self.texture.is_some()));
+        if let Some(texture) = self.texture {
+            gl_state.bind_texture(0, texture);
+        }
+
+        gl_state.set_uniform("u_soft", GLUniform::Bool(self.soft.is_some()));
+        if let Some(soft) = self.soft {
+            gl_state.bind_texture(SCENE_DEPTH_SLOT, soft.depth_texture);
+            gl_state.set_uniform("u_scene_depth", GLUniform::I32(SCENE_DEPTH_SLOT as i32));
+            gl_state.set_uniform("u_viewport_origin", GLUniform::Vec2(viewport_origin));
+            gl_state.set_uniform("u_viewport_size", GLUniform::Vec2(viewport_size));
+            gl_state.set_uniform("u_depth_range", GLUniform::Vec2(Vec2::new(soft.near, soft.far)));
+            gl_state.set_uniform("u_fade_distance", GLUniform::F32(soft.fade_distance.max(1e-4)));
+        }
+
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, (buffer.len() * size_of::<f32>()) as isize, buffer.as_ptr() as *const _, gl::STREAM_DRAW);
+            gl::DrawArrays(gl::POINTS, 0, particles.len() as GLsizei);
+        }
+
+        Ok(())
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        gl_state.destroy_program(self.program);
+        gl_state.destroy_vbo_vec(vec![self.vbo]);
+        gl_state.destroy_vao(self.vao);
+        self.freed = true;
+    }
+}
+
+impl Drop for ParticleRenderer {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            let mut state = GlStateManager::new();
+            self.destroy(&mut state);
+            panic!("Particle renderer was not destroyed before dropping")
+        }
+    }
+}
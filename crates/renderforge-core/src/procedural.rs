@@ -0,0 +1,294 @@
+use glam::Vec2;
+use image::{DynamicImage, GrayImage, Luma, Rgba, RgbaImage};
+
+use crate::data::Color;
+
+/// Settings shared by the noise generators
+#[derive(Debug, Clone, Copy)]
+pub struct NoiseSettings {
+    pub size: (u32, u32),
+    pub seed: u32,
+    /// noise features across the image width (the height scales to match)
+    pub frequency: f32,
+    /// layers of fractal noise, each at double the frequency and half the amplitude of the last
+    pub octaves: u32,
+    /// whether the image wraps seamlessly at its edges. Tiling rounds the frequency to whole
+    /// features across the image, and is ignored by simplex noise.
+    pub tiling: bool,
+}
+
+impl Default for NoiseSettings {
+    fn default() -> Self {
+        Self {
+            size: (256, 256),
+            seed: 0,
+            frequency: 8.,
+            octaves: 1,
+            tiling: true,
+        }
+    }
+}
+
+/// Perlin gradient noise, normalized to the full 0-255 range
+pub fn perlin_noise(settings: NoiseSettings) -> DynamicImage {
+    fractal(settings, |p, period, seed| perlin(p, period, seed) * 0.5 + 0.5)
+}
+
+/// Simplex noise, normalized to the full 0-255 range. Never tiles.
+pub fn simplex_noise(settings: NoiseSettings) -> DynamicImage {
+    fractal(NoiseSettings { tiling: false, ..settings }, |p, _, seed| simplex(p, seed) * 0.5 + 0.5)
+}
+
+/// Worley (cellular) noise, the distance to the nearest of one random point per cell.
+/// Bright at the points, dark at cell borders when inverted, as is common for caustics and cracks.
+pub fn worley_noise(settings: NoiseSettings, invert: bool) -> DynamicImage {
+    fractal(settings, |p, period, seed| {
+        let distance = worley(p, period, seed).min(1.);
+        if invert { 1. - distance } else { distance }
+    })
+}
+
+/// Blue noise threshold map made with the void and cluster method: every value from 0 to 255
+/// appears equally often, and pixels of similar value are spread evenly, which makes it ideal for
+/// dithering and sample jittering. Always tiles. Generation is O(n²) in the pixel count, so keep
+/// the size small (64x64 is typical).
+pub fn blue_noise(size: (u32, u32), seed: u32) -> DynamicImage {
+    let (width, height) = (size.0.max(1) as usize, size.1.max(1) as usize);
+    let count = width * height;
+    const SIGMA: f32 = 1.9;
+
+    // gaussian energy each set pixel adds around it, wrapping at the edges
+    let splat = |energy: &mut [f32], index: usize, sign: f32| {
+        let (x, y) = ((index % width) as i32, (index / width) as i32);
+        for (i, e) in energy.iter_mut().enumerate() {
+            let dx = wrapped_distance(x, (i % width) as i32, width as i32);
+            let dy = wrapped_distance(y, (i / width) as i32, height as i32);
+            *e += sign * (-((dx * dx + dy * dy) as f32) / (2. * SIGMA * SIGMA)).exp();
+        }
+    };
+    let extreme = |energy: &[f32], set: &[bool], want_set: bool, tightest: bool| {
+        (0..count)
+            .filter(|i| set[*i] == want_set)
+            .max_by(|a, b| {
+                let ordering = energy[*a].total_cmp(&energy[*b]);
+                if tightest { ordering } else { ordering.reverse() }
+            })
+            .unwrap()
+    };
+
+    // random initial pattern of about a tenth of the pixels
+    let mut set = vec![false; count];
+    let mut energy = vec![0.; count];
+    let initial = (count / 10).max(1);
+    let mut state = seed;
+    let mut placed = 0;
+    while placed < initial {
+        state = hash(state.wrapping_add(placed as u32 * 7919));
+        let index = state as usize % count;
+        if !set[index] {
+            set[index] = true;
+            splat(&mut energy, index, 1.);
+            placed += 1;
+        }
+    }
+
+    // spread the initial pattern out by moving the tightest cluster into the largest void
+    loop {
+        let cluster = extreme(&energy, &set, true, true);
+        set[cluster] = false;
+        splat(&mut energy, cluster, -1.);
+        let void = extreme(&energy, &set, false, false);
+        set[void] = true;
+        splat(&mut energy, void, 1.);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0usize; count];
+    let mut phase_set = set.clone();
+    let mut phase_energy = energy.clone();
+    // ranks below the initial pattern: remove the tightest clusters first
+    for r in (0..initial).rev() {
+        let cluster = extreme(&phase_energy, &phase_set, true, true);
+        phase_set[cluster] = false;
+        splat(&mut phase_energy, cluster, -1.);
+        rank[cluster] = r;
+    }
+    // ranks above: fill the largest voids first
+    for r in initial..count {
+        let void = extreme(&energy, &set, false, false);
+        set[void] = true;
+        splat(&mut energy, void, 1.);
+        rank[void] = r;
+    }
+
+    let image = GrayImage::from_fn(width as u32, height as u32, |x, y| {
+        let r = rank[y as usize * width + x as usize];
+        Luma([(r * 256 / count) as u8])
+    });
+    DynamicImage::ImageLuma8(image)
+}
+
+/// A checkerboard of `cell_size` pixel squares, starting with `a` in the top left
+pub fn checkerboard(size: (u32, u32), cell_size: u32, a: Color, b: Color) -> DynamicImage {
+    let cell_size = cell_size.max(1);
+    let (a, b) = (color_to_rgba(a), color_to_rgba(b));
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(size.0, size.1, |x, y| {
+        if (x / cell_size + y / cell_size).is_multiple_of(2) { a } else { b }
+    }))
+}
+
+/// A gradient from `from` to `to` along the direction `angle` (radians, 0 runs left to right)
+pub fn linear_gradient(size: (u32, u32), angle: f32, from: Color, to: Color) -> DynamicImage {
+    let dir = Vec2::from_angle(angle);
+    let half = Vec2::new(size.0 as f32, size.1 as f32) / 2.;
+    // project the corners to find the range the gradient has to cover
+    let extent = half.x * dir.x.abs() + half.y * dir.y.abs();
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(size.0, size.1, |x, y| {
+        let p = Vec2::new(x as f32 + 0.5, y as f32 + 0.5) - half;
+        let t = (p.dot(dir) / extent.max(f32::EPSILON)) * 0.5 + 0.5;
+        color_to_rgba(lerp_color(from, to, t))
+    }))
+}
+
+/// A gradient from `inner` at the center to `outer` at the closest edge and beyond
+pub fn radial_gradient(size: (u32, u32), inner: Color, outer: Color) -> DynamicImage {
+    let half = Vec2::new(size.0 as f32, size.1 as f32) / 2.;
+    let radius = half.min_element().max(f32::EPSILON);
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(size.0, size.1, |x, y| {
+        let p = Vec2::new(x as f32 + 0.5, y as f32 + 0.5) - half;
+        color_to_rgba(lerp_color(inner, outer, p.length() / radius))
+    }))
+}
+
+/// Sums octaves of a noise function over the image. The noise function gets the sample position
+/// in feature space, the tiling period (if tiling) and a per-octave seed, and returns 0 to 1.
+fn fractal(settings: NoiseSettings, noise: impl Fn(Vec2, Option<(i32, i32)>, u32) -> f32) -> DynamicImage {
+    let (width, height) = (settings.size.0.max(1), settings.size.1.max(1));
+    let mut frequency = if settings.tiling { settings.frequency.round().max(1.) } else { settings.frequency };
+    let aspect = height as f32 / width as f32;
+
+    let mut values = vec![0f32; (width * height) as usize];
+    let mut amplitude = 1.;
+    let mut total = 0.;
+    for octave in 0..settings.octaves.max(1) {
+        let period = settings.tiling.then(|| (frequency as i32, (frequency * aspect).round().max(1.) as i32));
+        let scale = match period {
+            Some((px, py)) => Vec2::new(px as f32 / width as f32, py as f32 / height as f32),
+            None => Vec2::splat(frequency / width as f32),
+        };
+        let seed = hash(settings.seed.wrapping_add(octave.wrapping_mul(0x9E37_79B9)));
+        for (i, value) in values.iter_mut().enumerate() {
+            let p = Vec2::new((i as u32 % width) as f32, (i as u32 / width) as f32) * scale;
+            *value += noise(p, period, seed) * amplitude;
+        }
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.;
+    }
+
+    let image = GrayImage::from_fn(width, height, |x, y| {
+        let v = values[(y * width + x) as usize] / total;
+        Luma([(v.clamp(0., 1.) * 255.).round() as u8])
+    });
+    DynamicImage::ImageLuma8(image)
+}
+
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^ (x >> 16)
+}
+
+fn hash2(x: i32, y: i32, seed: u32) -> u32 {
+    hash((x as u32).wrapping_mul(0x8da6_b343) ^ (y as u32).wrapping_mul(0xd816_3841) ^ seed)
+}
+
+fn wrap(v: i32, period: Option<i32>) -> i32 {
+    match period {
+        Some(p) => v.rem_euclid(p),
+        None => v,
+    }
+}
+
+fn wrapped_distance(a: i32, b: i32, period: i32) -> i32 {
+    let d = (a - b).abs();
+    d.min(period - d)
+}
+
+fn gradient(x: i32, y: i32, seed: u32) -> Vec2 {
+    let angle = hash2(x, y, seed) as f32 / u32::MAX as f32 * std::f32::consts::TAU;
+    Vec2::from_angle(angle)
+}
+
+/// roughly -1 to 1
+fn perlin(p: Vec2, period: Option<(i32, i32)>, seed: u32) -> f32 {
+    let cell = p.floor();
+    let f = p - cell;
+    let (cx, cy) = (cell.x as i32, cell.y as i32);
+    let corner = |dx: i32, dy: i32| {
+        let g = gradient(wrap(cx + dx, period.map(|p| p.0)), wrap(cy + dy, period.map(|p| p.1)), seed);
+        g.dot(f - Vec2::new(dx as f32, dy as f32))
+    };
+    let fade = |t: f32| t * t * t * (t * (t * 6. - 15.) + 10.);
+    let (u, v) = (fade(f.x), fade(f.y));
+    let top = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * u;
+    let bottom = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * u;
+    (top + (bottom - top) * v) * std::f32::consts::SQRT_2
+}
+
+/// roughly -1 to 1
+fn simplex(p: Vec2, seed: u32) -> f32 {
+    const F2: f32 = 0.366_025_42;
+    const G2: f32 = 0.211_324_87;
+    let skewed = (p + Vec2::splat((p.x + p.y) * F2)).floor();
+    let origin = skewed - Vec2::splat((skewed.x + skewed.y) * G2);
+    let d0 = p - origin;
+    let step = if d0.x > d0.y { Vec2::X } else { Vec2::Y };
+    let d1 = d0 - step + Vec2::splat(G2);
+    let d2 = d0 - Vec2::ONE + Vec2::splat(2. * G2);
+    let (i, j) = (skewed.x as i32, skewed.y as i32);
+
+    let contribution = |d: Vec2, x: i32, y: i32| {
+        let t = 0.5 - d.length_squared();
+        if t < 0. { 0. } else { t.powi(4) * gradient(x, y, seed).dot(d) }
+    };
+    70. * (contribution(d0, i, j)
+        + contribution(d1, i + step.x as i32, j + step.y as i32)
+        + contribution(d2, i + 1, j + 1))
+}
+
+/// distance to the nearest feature point, in cells
+fn worley(p: Vec2, period: Option<(i32, i32)>, seed: u32) -> f32 {
+    let cell = p.floor();
+    let (cx, cy) = (cell.x as i32, cell.y as i32);
+    let mut nearest = f32::MAX;
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            let (x, y) = (cx + dx, cy + dy);
+            let h = hash2(wrap(x, period.map(|p| p.0)), wrap(y, period.map(|p| p.1)), seed);
+            let offset = Vec2::new((h & 0xffff) as f32 / 65535., (h >> 16) as f32 / 65535.);
+            let point = Vec2::new(x as f32, y as f32) + offset;
+            nearest = nearest.min(point.distance(p));
+        }
+    }
+    nearest
+}
+
+fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    let t = t.clamp(0., 1.);
+    let (a, b) = (a.to_array(), b.to_array());
+    Color::rgba(
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+        a[3] + (b[3] - a[3]) * t,
+    )
+}
+
+fn color_to_rgba(color: Color) -> Rgba<u8> {
+    Rgba(color.to_array().map(|c| (c.clamp(0., 1.) * 255.).round() as u8))
+}
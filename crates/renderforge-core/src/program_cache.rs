@@ -0,0 +1,146 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::os::raw::c_void;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use gl::types::{GLenum, GLint, GLsizei, GLuint};
+
+use crate::capabilities::driver_string;
+use crate::shader::{compile_stages, relink_program, ShaderStage};
+
+/// Caches linked program binaries on disk, keyed by a hash of the shader sources and the driver,
+/// so programs only have to be compiled from source the first time (or after a driver update).
+/// Entries that fail to load are deleted and the program is compiled from source instead.
+#[derive(Debug, Clone)]
+pub struct ProgramCache {
+    dir: PathBuf,
+    driver: String,
+}
+
+impl ProgramCache {
+    /// Creates the cache directory if needed. A gl context must be current, since the driver is
+    /// part of every cache key.
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            driver: driver_string(),
+        })
+    }
+
+    pub fn get_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Same as shader::compile_program, but loads the program from the cache when possible
+    pub fn compile_program(&self, vertex: &str, fragment: &str) -> Result<GLuint> {
+        self.compile_program_stages(&[(ShaderStage::Vertex, vertex), (ShaderStage::Fragment, fragment)])
+    }
+
+    /// Same as shader::compile_program_stages, but loads the program from the cache when possible
+    pub fn compile_program_stages(&self, stages: &[(ShaderStage, &str)]) -> Result<GLuint> {
+        let path = self.entry_path(stages);
+
+        if let Some(program) = load_binary(&path) {
+            return Ok(program);
+        }
+        // stale or corrupt
+        let _ = fs::remove_file(&path);
+
+        let program = link_retrievable(stages)?;
+        // failing to write the cache only costs compile time on the next run
+        let _ = store_binary(program, &path);
+        Ok(program)
+    }
+
+    /// Deletes every cache entry
+    pub fn clear(&self) -> Result<()> {
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "bin") {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn entry_path(&self, stages: &[(ShaderStage, &str)]) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        self.driver.hash(&mut hasher);
+        for (stage, source) in stages {
+            stage.hash(&mut hasher);
+            source.hash(&mut hasher);
+        }
+        self.dir.join(format!("{:016x}.bin", hasher.finish()))
+    }
+}
+
+/// Compiles and links the stages with PROGRAM_BINARY_RETRIEVABLE_HINT set, which has to happen before linking
+fn link_retrievable(stages: &[(ShaderStage, &str)]) -> Result<GLuint> {
+    let shaders = compile_stages(stages)?;
+
+    let res = unsafe {
+        let program = gl::CreateProgram();
+        gl::ProgramParameteri(program, gl::PROGRAM_BINARY_RETRIEVABLE_HINT, gl::TRUE as GLint);
+        match relink_program(program, &shaders) {
+            Ok(()) => Ok(program),
+            Err(e) => {
+                gl::DeleteProgram(program);
+                Err(e)
+            }
+        }
+    };
+
+    unsafe {
+        for shader in shaders {
+            gl::DeleteShader(shader);
+        }
+    }
+    res
+}
+
+/// Cache files are the binary format as a little endian u32, followed by the binary
+fn load_binary(path: &Path) -> Option<GLuint> {
+    let data = fs::read(path).ok()?;
+    if data.len() <= 4 {
+        return None;
+    }
+    let format = GLenum::from_le_bytes(data[..4].try_into().ok()?);
+    let binary = &data[4..];
+
+    unsafe {
+        let program = gl::CreateProgram();
+        gl::ProgramBinary(program, format, binary.as_ptr() as *const c_void, binary.len() as GLsizei);
+
+        let mut status = 0;
+        gl::GetProgramiv(program, gl::LINK_STATUS, &mut status);
+        if status == gl::FALSE as GLint {
+            gl::DeleteProgram(program);
+            return None;
+        }
+        Some(program)
+    }
+}
+
+fn store_binary(program: GLuint, path: &Path) -> Result<()> {
+    unsafe {
+        let mut len = 0;
+        gl::GetProgramiv(program, gl::PROGRAM_BINARY_LENGTH, &mut len);
+        if len <= 0 {
+            return Ok(());
+        }
+
+        let mut data = vec![0u8; 4 + len as usize];
+        let mut format = 0;
+        let mut written = 0;
+        gl::GetProgramBinary(program, len, &mut written, &mut format, data[4..].as_mut_ptr() as *mut c_void);
+        data[..4].copy_from_slice(&format.to_le_bytes());
+        data.truncate(4 + written.max(0) as usize);
+
+        fs::write(path, data)?;
+        Ok(())
+    }
+}
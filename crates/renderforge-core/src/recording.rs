@@ -0,0 +1,145 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use image::RgbaImage;
+
+use crate::data::GlStateManager;
+
+/// A frame captured by a FrameRecorder
+#[derive(Debug, Clone)]
+pub struct RecordedFrame {
+    /// time since the recording started
+    pub time: Duration,
+    pub image: RgbaImage,
+}
+
+/// Records the framebuffer at a fixed frame rate using GlStateManager::read_pixels_async, so
+/// recording doesn't stall rendering. Frames arrive a frame or two after they are captured,
+/// once the gpu has finished copying them.
+///
+/// Typical use: `start()`, then `capture(gl_state, viewport)` after rendering every frame, then
+/// `stop()` and save the frames once `is_finished()`.
+#[derive(Debug)]
+pub struct FrameRecorder {
+    fps: f32,
+    max_duration: Option<Duration>,
+    started: Option<Instant>,
+    next_capture: Duration,
+    /// captures started that haven't been read back yet
+    pending: Rc<Cell<usize>>,
+    frames: Rc<RefCell<Vec<RecordedFrame>>>,
+}
+
+impl FrameRecorder {
+    /// Records at `fps` frames per second with no duration limit
+    pub fn new(fps: f32) -> Self {
+        Self {
+            fps: fps.max(0.001),
+            max_duration: None,
+            started: None,
+            next_capture: Duration::ZERO,
+            pending: Rc::new(Cell::new(0)),
+            frames: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// Stops recording on its own after `duration`
+    pub fn with_max_duration(mut self, duration: Duration) -> Self {
+        self.max_duration = Some(duration);
+        self
+    }
+
+    pub fn get_fps(&self) -> f32 {
+        self.fps
+    }
+
+    /// Starts a new recording, dropping the frames of the previous one
+    pub fn start(&mut self) {
+        self.started = Some(Instant::now());
+        self.next_capture = Duration::ZERO;
+        self.frames.borrow_mut().clear();
+    }
+
+    pub fn stop(&mut self) {
+        self.started = None;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// Whether the recording has stopped and every captured frame has been read back
+    pub fn is_finished(&self) -> bool {
+        !self.is_recording() && self.pending.get() == 0
+    }
+
+    /// Captures `rect` ([x, y, width, height]) of the bound framebuffer if a frame is due.
+    /// Call once per frame after rendering, before swapping buffers.
+    pub fn capture(&mut self, gl_state: &mut GlStateManager, rect: [i32; 4]) {
+        let Some(started) = self.started else {
+            return;
+        };
+        let time = started.elapsed();
+        if let Some(max_duration) = self.max_duration && time > max_duration {
+            self.stop();
+            return;
+        }
+        if time < self.next_capture {
+            return;
+        }
+
+        // skip missed frames instead of bunching them up
+        let interval = Duration::from_secs_f32(1. / self.fps);
+        while self.next_capture <= time {
+            self.next_capture += interval;
+        }
+
+        self.pending.set(self.pending.get() + 1);
+        let pending = Rc::clone(&self.pending);
+        let frames = Rc::clone(&self.frames);
+        gl_state.read_pixels_async(rect, move |image| {
+            pending.set(pending.get() - 1);
+            frames.borrow_mut().push(RecordedFrame { time, image });
+        });
+    }
+
+    pub fn get_frame_count(&self) -> usize {
+        self.frames.borrow().len()
+    }
+
+    /// Takes the frames read back so far, in capture order
+    pub fn take_frames(&mut self) -> Vec<RecordedFrame> {
+        let mut frames = std::mem::take(&mut *self.frames.borrow_mut());
+        frames.sort_by_key(|frame| frame.time);
+        frames
+    }
+
+    /// Encodes the frames read back so far as a looping gif, timed by when they were captured.
+    /// The frames are kept, so the recording can be saved again.
+    #[cfg(feature = "gif-recording")]
+    pub fn save_gif(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        use image::codecs::gif::{GifEncoder, Repeat};
+        use image::{Delay, Frame};
+
+        let mut frames = self.frames.borrow().clone();
+        if frames.is_empty() {
+            return Err(crate::errors::RecordingError::NoFrames.into());
+        }
+        frames.sort_by_key(|frame| frame.time);
+
+        let file = std::fs::File::create(path)?;
+        let mut encoder = GifEncoder::new(std::io::BufWriter::new(file));
+        encoder.set_repeat(Repeat::Infinite)?;
+        let last_delay = Duration::from_secs_f32(1. / self.fps);
+        for i in 0..frames.len() {
+            let delay = match frames.get(i + 1) {
+                Some(next) => next.time - frames[i].time,
+                None => last_delay,
+            };
+            let image = std::mem::take(&mut frames[i].image);
+            encoder.encode_frame(Frame::from_parts(image, 0, 0, Delay::from_saturating_duration(delay)))?;
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,12 @@
 use std::collections::HashMap;
 use std::hash::Hash;
 
-use anyhow::Result;
-
-use crate::mesh::{InstancedMesh, InstancedMeshData, InstancedMeshTrait, MeshController};
-use crate::window::Window;
+use crate::audio::AudioUniforms;
+use crate::data::GlStateManager;
+use crate::mesh::InstancedMeshTrait;
+use crate::streaming::StreamedTexture;
+use crate::texture::Texture;
+use crate::window::{ContextId, Window};
 
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -14,17 +16,35 @@ pub enum ResourceIdentifier {
     InstancedMesh(String),
     Window(String),
     VertexBuffer(String),
+    Audio(String),
+    StreamedTexture(String),
+}
+
+impl ResourceIdentifier {
+    /// Whether the resource is made only of gl objects that can be shared between contexts.
+    /// Instanced meshes share their buffers but need a VAO per context, which the Registry creates
+    /// when switching contexts.
+    pub fn is_shareable(&self) -> bool {
+        match self {
+            Self::Texture(..) | Self::Atlas(..) | Self::VertexBuffer(..) | Self::Audio(..) | Self::StreamedTexture(..) => true,
+            Self::InstancedMesh(..) | Self::Window(..) => false,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum Resource {
+    Texture(Texture),
     InstancedMesh(Box<dyn InstancedMeshTrait>),
-    Window(Window)
+    Window(Window),
+    Audio(Box<AudioUniforms>),
+    StreamedTexture(StreamedTexture),
 }
 
 #[derive(Debug)]
 pub struct Registry {
-   resources: HashMap<ResourceIdentifier, Resource> 
+   resources: HashMap<ResourceIdentifier, Resource>,
+   current_context: ContextId,
 }
 
 impl Default for Registry {
@@ -37,6 +57,7 @@ impl Registry {
     pub fn new() -> Self {
         Self {
             resources: HashMap::new(),
+            current_context: ContextId::default(),
         }
     }
 
@@ -48,11 +69,59 @@ impl Registry {
         self.resources.get_mut(id)
     }
 
-    pub fn add(&mut self, id: impl ToString, resource: Resource) {
+    /// Removes a resource from the registry, handing ownership of it back to the caller.
+    /// Textures and meshes still need to be destroyed afterwards.
+    pub fn remove(&mut self, id: &ResourceIdentifier) -> Option<Resource> {
+        self.resources.remove(id)
+    }
+
+    pub fn get_current_context(&self) -> ContextId {
+        self.current_context
+    }
+
+    /// Tells the registry which context is current, must be called after making a different context current.
+    /// Per-context objects of registered resources (like mesh VAOs) are created for the context if
+    /// they don't exist yet.
+    pub fn set_current_context(&mut self, context: ContextId) {
+        self.current_context = context;
+        for resource in self.resources.values_mut() {
+            if let Resource::InstancedMesh(mesh) = resource {
+                mesh.use_context(context);
+            }
+        }
+    }
+
+    /// Deletes the per-context objects made for a context, call this while the context is still current
+    /// before destroying it.
+    pub fn release_context(&mut self, gl_state: &mut GlStateManager, context: ContextId) {
+        for resource in self.resources.values_mut() {
+            if let Resource::InstancedMesh(mesh) = resource {
+                mesh.release_context(gl_state, context);
+            }
+        }
+    }
+
+    /// Polls every registered audio source and uploads its analysis for shaders, see AudioUniforms
+    pub fn update_audio(&mut self, gl_state: &mut GlStateManager) {
+        for resource in self.resources.values_mut() {
+            if let Resource::Audio(audio) = resource {
+                audio.update(gl_state);
+            }
+        }
+    }
+
+    pub fn add(&mut self, id: impl ToString, mut resource: Resource) {
+        if let Resource::InstancedMesh(mesh) = &mut resource {
+            mesh.use_context(self.current_context);
+        }
+
         let id = id.to_string();
         let loc = match &resource {
+            Resource::Texture(..) => ResourceIdentifier::Texture(id),
             Resource::Window(..) => ResourceIdentifier::Window(id),
             Resource::InstancedMesh(..) => ResourceIdentifier::InstancedMesh(id),
+            Resource::Audio(..) => ResourceIdentifier::Audio(id),
+            Resource::StreamedTexture(..) => ResourceIdentifier::StreamedTexture(id),
         };
 
         self.resources.insert(loc, resource);
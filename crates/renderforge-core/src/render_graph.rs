@@ -0,0 +1,591 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::rc::Rc;
+
+use anyhow::Result;
+use glam::Mat4;
+
+use crate::data::{DepthFunc, GlStateManager};
+use crate::errors::RenderGraphError;
+use crate::framebuffer::{Framebuffer, FramebufferBuilder};
+use crate::particles::{ParticleRenderer, SoftParticles};
+use crate::renderer::ColorGrading;
+
+type PassFn = Box<dyn FnMut(&mut GlStateManager, &HashMap<String, Framebuffer>) -> Result<()>>;
+
+/// Which half of a forward pass is being rendered, see RenderGraph::add_forward_pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForwardPhase {
+    /// Depth only, color writes are masked off. Draw with a cheap variant of the shaders
+    /// (e.g. a ShaderVariants variant with a `DEPTH_ONLY` define that skips lighting).
+    DepthPrepass,
+    /// Fully shaded. After a depth pre-pass the depth test is `DepthFunc::Equal` with depth writes off,
+    /// so only the visible fragment of each pixel is shaded.
+    Shaded,
+}
+
+/// The view a RenderGraph is currently rendering, read by passes through a ViewHandle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewInfo {
+    /// which view is rendering, e.g. 0 for the left eye and 1 for the right
+    pub index: usize,
+    /// number of views rendered this frame, 1 outside of execute_views
+    pub count: usize,
+    pub view: Mat4,
+    pub projection: Mat4,
+}
+
+/// A shared handle to the graph's current view, for passes to capture when they are added
+#[derive(Debug, Clone)]
+pub struct ViewHandle {
+    view: Rc<Cell<ViewInfo>>,
+}
+
+/// Which post-processing passes a camera applies, see RenderGraph::add_post_pass. Every effect
+/// is enabled unless disabled here.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PostProcessStack {
+    disabled: HashSet<String>,
+    color_grading: Option<ColorGrading>,
+}
+
+/// A view rendered by RenderGraph::execute_cameras, with its own output and post-processing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+    pub view: Mat4,
+    pub projection: Mat4,
+    /// where passes without a target render, None for the default framebuffer
+    pub target: Option<String>,
+    /// part of the default framebuffer the camera renders to, e.g. a minimap in a corner
+    pub viewport: Option<[i32; 4]>,
+    pub post_process: PostProcessStack,
+}
+
+/// Where passes that render to the default framebuffer end up in RenderGraph::execute_views
+#[derive(Debug, Clone, PartialEq)]
+pub enum MultiViewOutput {
+    /// views are laid out left to right in the default framebuffer of the given size, each getting
+    /// an equal slice as its viewport
+    SideBySide { size: (u32, u32) },
+    /// each view renders into its own named target, e.g. the swapchain images of a headset
+    Targets(Vec<String>),
+}
+
+/// Where passes without a target render
+enum DefaultTarget {
+    /// the default framebuffer, optionally confined to a viewport
+    Framebuffer(Option<[i32; 4]>),
+    Named(String),
+}
+
+/// A named step of a RenderGraph
+pub struct RenderPass {
+    name: String,
+    /// None renders to the default framebuffer
+    target: Option<String>,
+    /// targets the pass samples, see RenderGraph::set_pass_inputs
+    inputs: Vec<String>,
+    /// for post-processing passes, the target copied to the pass's target when the current
+    /// PostProcessStack disables the pass
+    post_input: Option<String>,
+    execute: PassFn,
+}
+
+/// A target created by the graph, which may share its framebuffer with other transient targets
+#[derive(Debug)]
+struct TransientTarget {
+    name: String,
+    builder: FramebufferBuilder,
+}
+
+/// Runs render passes in the order they were added, binding each pass's target beforehand and
+/// restoring the gl state afterwards
+#[derive(Debug, Default)]
+pub struct RenderGraph {
+    targets: HashMap<String, Framebuffer>,
+    passes: Vec<RenderPass>,
+    view: Rc<Cell<ViewInfo>>,
+    transients: Vec<TransientTarget>,
+    /// framebuffers backing the transient targets, with the builder each was made from
+    pool: Vec<(FramebufferBuilder, Framebuffer)>,
+    /// every target by name as passes see it, aliases of the persistent targets and of the pool
+    bindings: HashMap<String, Framebuffer>,
+    /// targets or passes changed since the transient targets were assigned to the pool
+    dirty: bool,
+    /// the post-processing of the camera being rendered, or set_post_process outside of
+    /// execute_cameras
+    post_process: Rc<RefCell<PostProcessStack>>,
+}
+
+impl Default for ViewInfo {
+    fn default() -> Self {
+        Self {
+            index: 0,
+            count: 1,
+            view: Mat4::IDENTITY,
+            projection: Mat4::IDENTITY,
+        }
+    }
+}
+
+impl ViewInfo {
+    pub fn view_proj(&self) -> Mat4 {
+        self.projection * self.view
+    }
+}
+
+impl PostProcessStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Skips the post-processing pass named `effect`, e.g. `"bloom"` for a UI camera
+    pub fn with_disabled(mut self, effect: impl ToString) -> Self {
+        self.disabled.insert(effect.to_string());
+        self
+    }
+
+    pub fn with_color_grading(mut self, color_grading: ColorGrading) -> Self {
+        self.color_grading = Some(color_grading);
+        self
+    }
+
+    pub fn set_enabled(&mut self, effect: &str, enabled: bool) {
+        if enabled {
+            self.disabled.remove(effect);
+        } else {
+            self.disabled.insert(effect.to_string());
+        }
+    }
+
+    pub fn is_enabled(&self, effect: &str) -> bool {
+        !self.disabled.contains(effect)
+    }
+
+    /// # Returns
+    /// the grading a color grading pass should apply for this camera, None to use its own
+    pub fn get_color_grading(&self) -> Option<ColorGrading> {
+        self.color_grading
+    }
+}
+
+impl Camera {
+    /// A camera rendering to the default framebuffer with every post-processing pass
+    pub fn new(view: Mat4, projection: Mat4) -> Self {
+        Self {
+            view,
+            projection,
+            target: None,
+            viewport: None,
+            post_process: PostProcessStack::default(),
+        }
+    }
+
+    pub fn with_target(mut self, target: impl ToString) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    pub fn with_viewport(mut self, viewport: [i32; 4]) -> Self {
+        self.viewport = Some(viewport);
+        self
+    }
+
+    pub fn with_post_process(mut self, post_process: PostProcessStack) -> Self {
+        self.post_process = post_process;
+        self
+    }
+}
+
+impl ViewHandle {
+    pub fn get(&self) -> ViewInfo {
+        self.view.get()
+    }
+}
+
+impl std::fmt::Debug for RenderPass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RenderPass")
+            .field("name", &self.name)
+            .field("target", &self.target)
+            .field("inputs", &self.inputs)
+            .field("post_input", &self.post_input)
+            .finish()
+    }
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a framebuffer passes can render into, replacing (and returning) any target with the same name
+    pub fn add_target(&mut self, name: impl ToString, framebuffer: Framebuffer) -> Option<Framebuffer> {
+        let name = name.to_string();
+        self.transients.retain(|t| t.name != name);
+        self.dirty = true;
+        self.targets.insert(name, framebuffer)
+    }
+
+    /// Adds a target whose framebuffer the graph creates on the first execute. Transient targets
+    /// built alike that aren't used by overlapping ranges of passes share one framebuffer, so a
+    /// chain of post-processing passes needs two framebuffers instead of one per pass. A transient
+    /// target is used from the first to the last pass that renders into it or declares it as an
+    /// input (see set_pass_inputs), and its contents are undefined outside of that range.
+    ///
+    /// # Returns
+    /// the persistent target with the same name, which is replaced
+    pub fn add_transient_target(&mut self, name: impl ToString, builder: FramebufferBuilder) -> Option<Framebuffer> {
+        let name = name.to_string();
+        self.transients.retain(|t| t.name != name);
+        self.transients.push(TransientTarget {
+            name: name.clone(),
+            builder,
+        });
+        self.dirty = true;
+        self.targets.remove(&name)
+    }
+
+    /// # Returns
+    /// a persistent target, or a transient target once the graph has executed
+    pub fn get_target(&self, name: &str) -> Option<&Framebuffer> {
+        self.targets.get(name).or_else(|| self.bindings.get(name))
+    }
+
+    /// # Returns
+    /// a persistent target, e.g. to point it at another texture with Framebuffer::set_external_color
+    pub fn get_target_mut(&mut self, name: &str) -> Option<&mut Framebuffer> {
+        self.targets.get_mut(name)
+    }
+
+    /// Removes a persistent target, or forgets a transient target, returning None
+    pub fn remove_target(&mut self, name: &str) -> Option<Framebuffer> {
+        self.transients.retain(|t| t.name != name);
+        self.dirty = true;
+        self.targets.remove(name)
+    }
+
+    /// # Returns
+    /// how many framebuffers back the transient targets, after the last execute
+    pub fn get_transient_framebuffer_count(&self) -> usize {
+        self.pool.len()
+    }
+
+    /// Creates a handle passes can capture to read the view being rendered, which changes per eye
+    /// in execute_views
+    pub fn get_view_handle(&self) -> ViewHandle {
+        ViewHandle {
+            view: Rc::clone(&self.view),
+        }
+    }
+
+    /// Sets the view execute renders with
+    pub fn set_view(&mut self, view: Mat4, projection: Mat4) {
+        self.view.set(ViewInfo {
+            index: 0,
+            count: 1,
+            view,
+            projection,
+        });
+    }
+
+    /// Adds a pass rendering into the named target, or the default framebuffer if target is None.
+    /// The pass receives every target of the graph, so it can sample earlier passes' output.
+    pub fn add_pass(
+        &mut self,
+        name: impl ToString,
+        target: Option<&str>,
+        execute: impl FnMut(&mut GlStateManager, &HashMap<String, Framebuffer>) -> Result<()> + 'static,
+    ) {
+        self.passes.push(RenderPass {
+            name: name.to_string(),
+            target: target.map(|t| t.to_string()),
+            inputs: Vec::new(),
+            post_input: None,
+            execute: Box::new(execute),
+        });
+        self.dirty = true;
+    }
+
+    /// Adds a post-processing pass reading `input` and rendering into the named target, or the
+    /// default framebuffer if target is None. The pass is named after its effect: when the
+    /// camera's PostProcessStack disables it, color attachment 0 of `input` is copied to the
+    /// target instead, so the following passes of the chain still find an image there. The pass
+    /// receives the stack of the camera being rendered, e.g. for its color grading.
+    pub fn add_post_pass(
+        &mut self,
+        name: impl ToString,
+        input: &str,
+        target: Option<&str>,
+        mut execute: impl FnMut(&mut GlStateManager, &HashMap<String, Framebuffer>, &PostProcessStack) -> Result<()> + 'static,
+    ) {
+        let post_process = Rc::clone(&self.post_process);
+        self.add_pass(name, target, move |gl_state, targets| execute(gl_state, targets, &post_process.borrow()));
+        if let Some(pass) = self.passes.last_mut() {
+            pass.inputs = vec![input.to_string()];
+            pass.post_input = Some(input.to_string());
+        }
+    }
+
+    /// Sets the post-processing execute and execute_views render with
+    pub fn set_post_process(&mut self, post_process: PostProcessStack) {
+        *self.post_process.borrow_mut() = post_process;
+    }
+
+    pub fn get_post_process(&self) -> PostProcessStack {
+        self.post_process.borrow().clone()
+    }
+
+    /// Declares the targets a pass samples, which keeps transient inputs alive until the pass has
+    /// run, see add_transient_target. For forward passes with a depth pre-pass both halves get
+    /// the inputs. Inputs that are persistent targets are allowed and change nothing.
+    pub fn set_pass_inputs(&mut self, name: &str, inputs: &[&str]) {
+        let prepass = format!("{name}_depth_prepass");
+        for pass in self.passes.iter_mut().filter(|p| p.name == name || p.name == prepass) {
+            pass.inputs = inputs.iter().map(|input| input.to_string()).collect();
+        }
+        self.dirty = true;
+    }
+
+    /// Adds a forward rendering pass. With `depth_prepass`, the scene is first drawn depth only into
+    /// the target (pass `{name}_depth_prepass`), then drawn again shaded with `DepthFunc::Equal`,
+    /// which cuts overdraw on fill rate bound scenes. `execute` runs once per phase and must submit
+    /// the same geometry with the same vertex transforms both times for the equal test to pass.
+    pub fn add_forward_pass(
+        &mut self,
+        name: impl ToString,
+        target: Option<&str>,
+        depth_prepass: bool,
+        execute: impl FnMut(&mut GlStateManager, &HashMap<String, Framebuffer>, ForwardPhase) -> Result<()> + 'static,
+    ) {
+        let name = name.to_string();
+        if !depth_prepass {
+            let mut execute = execute;
+            self.add_pass(name, target, move |gl_state, targets| execute(gl_state, targets, ForwardPhase::Shaded));
+            return;
+        }
+
+        let execute = Rc::new(RefCell::new(execute));
+
+        let prepass = Rc::clone(&execute);
+        self.add_pass(format!("{name}_depth_prepass"), target, move |gl_state, targets| {
+            gl_state.color_mask([false; 4]);
+            gl_state.depth_test(true);
+            gl_state.depth_mask(true);
+            gl_state.depth_func(DepthFunc::Less);
+            (prepass.borrow_mut())(gl_state, targets, ForwardPhase::DepthPrepass)
+        });
+
+        self.add_pass(name, target, move |gl_state, targets| {
+            gl_state.depth_test(true);
+            gl_state.depth_mask(false);
+            gl_state.depth_func(DepthFunc::Equal);
+            (execute.borrow_mut())(gl_state, targets, ForwardPhase::Shaded)
+        });
+    }
+
+    /// Adds a pass rendering the particles queued on `particles` with the graph's current view.
+    /// With `depth_source`, particles are faded against the depth texture of that target (see
+    /// FramebufferBuilder::with_depth_texture), keeping the renderer's soft particle fade distance
+    /// or fading over 1 unit if it has none. The depth source must not be the pass's own target.
+    pub fn add_particle_pass(&mut self, name: impl ToString, target: Option<&str>, depth_source: Option<&str>, particles: Rc<RefCell<ParticleRenderer>>) {
+        let name = name.to_string();
+        let pass = name.clone();
+        let depth_source = depth_source.map(|s| s.to_string());
+        let inputs: Vec<String> = depth_source.iter().cloned().collect();
+        let view = self.get_view_handle();
+        self.add_pass(name, target, move |gl_state, targets| {
+            let info = view.get();
+            let mut particles = particles.borrow_mut();
+            particles.set_view(info.view, info.projection);
+            if let Some(source) = &depth_source {
+                let depth_texture = targets
+                    .get(source)
+                    .and_then(Framebuffer::get_depth_texture)
+                    .ok_or_else(|| RenderGraphError::MissingDepthTexture { pass: pass.clone(), target: source.clone() })?;
+                let fade_distance = particles.get_soft_particles().map_or(1., |s| s.fade_distance);
+                particles.set_soft_particles(Some(SoftParticles::from_projection(depth_texture, &info.projection, fade_distance)));
+            }
+            gl_state.depth_test(true);
+            particles.render(gl_state)
+        });
+        if let Some(pass) = self.passes.last_mut() {
+            pass.inputs = inputs;
+        }
+    }
+
+    /// Removes a pass, for forward passes with a depth pre-pass both halves are removed
+    pub fn remove_pass(&mut self, name: &str) {
+        let prepass = format!("{name}_depth_prepass");
+        self.passes.retain(|p| p.name != name && p.name != prepass);
+        self.dirty = true;
+    }
+
+    /// Assigns every transient target to a pooled framebuffer, reusing a framebuffer built alike
+    /// once the passes using its previous target are done, and rebuilds the bindings passes see
+    fn allocate(&mut self, gl_state: &mut GlStateManager) -> Result<()> {
+        // (first pass, last pass, target) of every transient target that is used
+        let mut lifetimes: Vec<(usize, usize, &TransientTarget)> = self.transients.iter()
+            .filter_map(|transient| {
+                let mut uses = self.passes.iter().enumerate()
+                    .filter(|(_, pass)| pass.target.as_ref() == Some(&transient.name) || pass.inputs.contains(&transient.name))
+                    .map(|(i, _)| i);
+                let first = uses.next()?;
+                let last = uses.next_back().unwrap_or(first);
+                Some((first, last, transient))
+            })
+            .collect();
+        lifetimes.sort_by_key(|(first, _, _)| *first);
+
+        let mut unused = mem::take(&mut self.pool);
+        // last pass using each pooled framebuffer
+        let mut busy_until = Vec::new();
+        let mut assignments = Vec::with_capacity(lifetimes.len());
+        for (first, last, transient) in lifetimes {
+            let free = self.pool.iter().zip(&busy_until)
+                .position(|((builder, _), busy)| *builder == transient.builder && *busy < first);
+            let index = match free {
+                Some(index) => index,
+                None => {
+                    let framebuffer = match unused.iter().position(|(builder, _)| *builder == transient.builder) {
+                        Some(i) => unused.swap_remove(i).1,
+                        None => transient.builder.clone().build(gl_state)?,
+                    };
+                    self.pool.push((transient.builder.clone(), framebuffer));
+                    busy_until.push(0);
+                    self.pool.len() - 1
+                }
+            };
+            busy_until[index] = last;
+            assignments.push((transient.name.clone(), index));
+        }
+        for (_, mut framebuffer) in unused {
+            framebuffer.destroy(gl_state);
+        }
+
+        self.bindings = self.targets.iter()
+            .map(|(name, framebuffer)| (name.clone(), framebuffer.alias()))
+            .chain(assignments.into_iter().map(|(name, index)| (name, self.pool[index].1.alias())))
+            .collect();
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Runs every pass. After a pass finishes, mipmaps are regenerated for its target's
+    /// attachments that ask for it.
+    pub fn execute(&mut self, gl_state: &mut GlStateManager) -> Result<()> {
+        self.run_passes(gl_state, &DefaultTarget::Framebuffer(None))
+    }
+
+    /// Runs every pass once per view, one view after another, with each view's (view, projection)
+    /// set on the view handle. Targets are shared between views, so passes see the same frame
+    /// resources, while passes rendering to the default framebuffer are redirected per `output`.
+    pub fn execute_views(&mut self, gl_state: &mut GlStateManager, views: &[(Mat4, Mat4)], output: &MultiViewOutput) -> Result<()> {
+        let previous = self.view.get();
+        let count = views.len();
+        let result = views.iter().enumerate().try_for_each(|(index, (view, projection))| {
+            self.view.set(ViewInfo { index, count, view: *view, projection: *projection });
+            let default = match output {
+                MultiViewOutput::SideBySide { size: (width, height) } => {
+                    let slice = (*width as usize / count.max(1)) as i32;
+                    DefaultTarget::Framebuffer(Some([slice * index as i32, 0, slice, *height as i32]))
+                }
+                MultiViewOutput::Targets(names) => match names.get(index) {
+                    Some(name) => DefaultTarget::Named(name.clone()),
+                    None => DefaultTarget::Framebuffer(None),
+                },
+            };
+            self.run_passes(gl_state, &default)
+        });
+        self.view.set(previous);
+        result
+    }
+
+    /// Runs every pass once per camera, in order, with the camera's view set on the view handle and
+    /// its PostProcessStack deciding which post-processing passes run. Passes without a target
+    /// render to the camera's target or viewport, so e.g. a UI camera drawn last overlays the
+    /// scene while a minimap camera renders into its own corner.
+    pub fn execute_cameras(&mut self, gl_state: &mut GlStateManager, cameras: &[Camera]) -> Result<()> {
+        let previous_view = self.view.get();
+        let previous_post = self.get_post_process();
+        let count = cameras.len();
+        let result = cameras.iter().enumerate().try_for_each(|(index, camera)| {
+            self.view.set(ViewInfo { index, count, view: camera.view, projection: camera.projection });
+            *self.post_process.borrow_mut() = camera.post_process.clone();
+            let default = match &camera.target {
+                Some(name) => DefaultTarget::Named(name.clone()),
+                None => DefaultTarget::Framebuffer(camera.viewport),
+            };
+            self.run_passes(gl_state, &default)
+        });
+        self.view.set(previous_view);
+        self.set_post_process(previous_post);
+        result
+    }
+
+    fn run_passes(&mut self, gl_state: &mut GlStateManager, default: &DefaultTarget) -> Result<()> {
+        if self.dirty {
+            self.allocate(gl_state)?;
+        }
+        for pass in &mut self.passes {
+            let target_name = match (&pass.target, default) {
+                (Some(name), _) | (None, DefaultTarget::Named(name)) => Some(name),
+                (None, DefaultTarget::Framebuffer(_)) => None,
+            };
+            let target = match target_name {
+                Some(name) => Some(self.bindings.get(name).ok_or_else(|| RenderGraphError::MissingTarget {
+                    pass: pass.name.clone(),
+                    target: name.clone(),
+                })?),
+                None => None,
+            };
+            // a disabled post-processing pass passes its input through
+            let skipped_input = match &pass.post_input {
+                Some(input) if !self.post_process.borrow().is_enabled(&pass.name) => Some(self.bindings.get(input).ok_or_else(|| RenderGraphError::MissingTarget {
+                    pass: pass.name.clone(),
+                    target: input.clone(),
+                })?),
+                _ => None,
+            };
+
+            gl_state.scoped(|gl_state| {
+                match (target, default) {
+                    (Some(framebuffer), _) => framebuffer.bind(gl_state),
+                    (None, DefaultTarget::Framebuffer(Some(rect))) => {
+                        // scissor to the view's slice so clears don't wipe the other views
+                        gl_state.bind_fbo(0);
+                        gl_state.viewport(*rect);
+                        gl_state.scissor_test(true);
+                        gl_state.scissor_box(*rect);
+                    }
+                    (None, _) => gl_state.bind_fbo(0),
+                }
+                match skipped_input {
+                    Some(input) => {
+                        input.blit_to_bound(gl_state, 0, gl_state.get_viewport());
+                        Ok(())
+                    }
+                    None => (pass.execute)(gl_state, &self.bindings),
+                }
+            })?;
+
+            if let Some(framebuffer) = target {
+                framebuffer.finish_pass();
+            }
+        }
+        Ok(())
+    }
+
+    /// Destroys every target framebuffer, including the framebuffers of transient targets
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        for (_, mut framebuffer) in self.targets.drain() {
+            framebuffer.destroy(gl_state);
+        }
+        for (_, mut framebuffer) in self.pool.drain(..) {
+            framebuffer.destroy(gl_state);
+        }
+        self.bindings.clear();
+        self.dirty = true;
+    }
+}
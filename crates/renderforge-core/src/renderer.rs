@@ -0,0 +1,635 @@
+use std::collections::HashSet;
+use std::mem;
+use std::os::raw::c_void;
+use std::thread::panicking;
+
+use anyhow::Result;
+use gl::types::{GLint, GLsizei, GLuint};
+use glam::{Mat4, Vec2};
+
+use crate::atlas::{AtlasArray, AtlasRect, AtlasTextureIdentifier, NineSlice};
+use crate::data::{BlendMode, Color, GLUniform, GlStateManager};
+use crate::semantics::{U_PROJECTION, U_TEXTURE};
+use crate::shader::compile_program;
+
+const SPRITE_VERTEX_SHADER: &str = r#"#version 330 core
+layout(location = 0) in vec2 a_position;
+layout(location = 1) in vec2 a_uv;
+layout(location = 2) in vec4 a_color;
+layout(location = 3) in float a_layer;
+
+uniform mat4 u_projection;
+
+out vec2 v_uv;
+out vec4 v_color;
+out float v_layer;
+
+void main() {
+    v_uv = a_uv;
+    v_color = a_color;
+    v_layer = a_layer;
+    gl_Position = u_projection * vec4(a_position, 0.0, 1.0);
+}
+"#;
+
+const SPRITE_FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec2 v_uv;
+in vec4 v_color;
+in float v_layer;
+
+uniform sampler2D u_texture;
+uniform sampler2DArray u_texture_array;
+uniform bool u_array;
+uniform sampler1D u_palette;
+uniform sampler3D u_lut;
+uniform int u_grading;
+uniform float u_lut_size;
+
+out vec4 frag_color;
+
+void main() {
+    vec4 color = u_array ? texture(u_texture_array, vec3(v_uv, v_layer)) : texture(u_texture, v_uv);
+
+    if (u_grading == 1) {
+        int index = min(int(color.r * 255.0 + 0.5), textureSize(u_palette, 0) - 1);
+        vec4 entry = texelFetch(u_palette, index, 0);
+        color = vec4(entry.rgb, entry.a * color.a);
+    }
+
+    color *= v_color;
+
+    if (u_grading == 2) {
+        vec3 scale = vec3((u_lut_size - 1.0) / u_lut_size);
+        vec3 offset = vec3(0.5 / u_lut_size);
+        color.rgb = texture(u_lut, clamp(color.rgb, 0.0, 1.0) * scale + offset).rgb;
+    }
+
+    frag_color = color;
+}
+"#;
+
+/// position(2), uv(2), color(4), array layer(1)
+const SPRITE_VERTEX_SIZE: usize = 9;
+
+const PALETTE_SLOT: u32 = 1;
+const LUT_SLOT: u32 = 2;
+const ARRAY_SLOT: u32 = 3;
+
+/// (texture, is a texture array, clip rect) shared by the sprites of one draw call
+type RunKey = (GLuint, bool, Option<[f32; 4]>);
+
+/// Color transform applied to every sprite in a SpriteBatch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorGrading {
+    None,
+    /// Indexed palette swap. The red channel of the sprite's texture selects an entry of a palette
+    /// texture created with `upload_palette`, the sprite's alpha is kept.
+    Palette(GLuint),
+    /// 3D LUT color grading with a texture created with `upload_lut`
+    Lut {
+        texture: GLuint,
+        size: u32,
+    },
+}
+
+/// Handle to a layer of SortingLayers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SortingLayer(u32);
+
+/// Named sprite layers drawn in a global order, every sprite in a layer is drawn after every sprite
+/// in the layers before it.
+/// Starts with the layers background, world, foreground and ui, in that order.
+#[derive(Debug, Clone)]
+pub struct SortingLayers {
+    /// (name, handle) in draw order
+    layers: Vec<(String, SortingLayer)>,
+    /// layers that keep float positions when the batch snaps to pixels
+    smooth: HashSet<SortingLayer>,
+    next_id: u32,
+}
+
+/// Maps sprite corners to device pixels and back through the batch projection
+#[derive(Debug, Clone, Copy)]
+struct PixelSnap {
+    projection: Mat4,
+    inverse: Mat4,
+    viewport: Vec2,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sprite {
+    pub texture: GLuint,
+    pub position: Vec2,
+    pub size: Vec2,
+    /// (u0, v0, u1, v1)
+    pub uvs: (f32, f32, f32, f32),
+    /// the uv rect holds the texture rotated 90 degrees clockwise, see AtlasRect::is_rotated
+    pub rotated: bool,
+    /// clockwise rotation in radians (with y pointing down) about the sprite's center
+    pub rotation: f32,
+    /// layer to sample when `texture` is a texture array, None for 2D textures
+    pub array_layer: Option<u32>,
+    /// (x, y, width, height) in the same space as `position`, the sprite is cut off outside it.
+    /// Consecutive sprites with the same clip share a draw call.
+    pub clip: Option<[f32; 4]>,
+    pub color: Color,
+    pub layer: SortingLayer,
+    /// order within the layer, lower is drawn first. Sprites with the same order keep submission order.
+    pub order: i32,
+}
+
+#[derive(Debug)]
+pub struct SpriteBatch {
+    sprites: Vec<Sprite>,
+    projection: Mat4,
+    color_grading: ColorGrading,
+    blend_mode: BlendMode,
+    sorting_layers: SortingLayers,
+    pixel_snapping: bool,
+    program: GLuint,
+    vao: GLuint,
+    vbo: GLuint,
+    freed: bool,
+}
+
+impl ColorGrading {
+    fn mode(&self) -> i32 {
+        match self {
+            Self::None => 0,
+            Self::Palette(..) => 1,
+            Self::Lut { .. } => 2,
+        }
+    }
+}
+
+impl SortingLayer {
+    pub const BACKGROUND: Self = Self(0);
+    pub const WORLD: Self = Self(1);
+    pub const FOREGROUND: Self = Self(2);
+    pub const UI: Self = Self(3);
+}
+
+impl Default for SortingLayers {
+    fn default() -> Self {
+        Self {
+            layers: vec![
+                ("background".to_string(), SortingLayer::BACKGROUND),
+                ("world".to_string(), SortingLayer::WORLD),
+                ("foreground".to_string(), SortingLayer::FOREGROUND),
+                ("ui".to_string(), SortingLayer::UI),
+            ],
+            smooth: HashSet::new(),
+            next_id: 4,
+        }
+    }
+}
+
+impl SortingLayers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a layer drawn after every existing layer
+    pub fn add_layer(&mut self, name: impl ToString) -> SortingLayer {
+        let layer = self.next_layer();
+        self.layers.push((name.to_string(), layer));
+        layer
+    }
+
+    /// Adds a layer drawn just before `before`
+    ///
+    /// # Returns
+    /// None if `before` doesn't exist
+    pub fn insert_layer_before(&mut self, name: impl ToString, before: SortingLayer) -> Option<SortingLayer> {
+        let index = self.layers.iter().position(|(_, l)| *l == before)?;
+        let layer = self.next_layer();
+        self.layers.insert(index, (name.to_string(), layer));
+        Some(layer)
+    }
+
+    /// Moves an existing layer so it's drawn just before `before`
+    pub fn move_layer_before(&mut self, layer: SortingLayer, before: SortingLayer) {
+        if let Some(index) = self.layers.iter().position(|(_, l)| *l == layer) {
+            let entry = self.layers.remove(index);
+            let index = self.layers.iter().position(|(_, l)| *l == before).unwrap_or(self.layers.len());
+            self.layers.insert(index, entry);
+        }
+    }
+
+    pub fn get_layer(&self, name: &str) -> Option<SortingLayer> {
+        self.layers.iter().find(|(n, _)| n == name).map(|(_, l)| *l)
+    }
+
+    pub fn get_name(&self, layer: SortingLayer) -> Option<&str> {
+        self.layers.iter().find(|(_, l)| *l == layer).map(|(n, _)| n.as_str())
+    }
+
+    /// Smooth layers keep their float positions when the batch has pixel snapping enabled,
+    /// for layers with smooth motion
+    pub fn set_smooth(&mut self, layer: SortingLayer, smooth: bool) {
+        if smooth {
+            self.smooth.insert(layer);
+        } else {
+            self.smooth.remove(&layer);
+        }
+    }
+
+    pub fn is_smooth(&self, layer: SortingLayer) -> bool {
+        self.smooth.contains(&layer)
+    }
+
+    /// # Returns
+    /// the position of the layer in the draw order, unknown layers are drawn last
+    pub fn rank(&self, layer: SortingLayer) -> usize {
+        self.layers.iter().position(|(_, l)| *l == layer).unwrap_or(self.layers.len())
+    }
+
+    fn next_layer(&mut self) -> SortingLayer {
+        let layer = SortingLayer(self.next_id);
+        self.next_id += 1;
+        layer
+    }
+}
+
+impl PixelSnap {
+    fn new(projection: Mat4, viewport: [i32; 4]) -> Self {
+        Self {
+            projection,
+            inverse: projection.inverse(),
+            viewport: Vec2::new(viewport[2] as f32, viewport[3] as f32),
+        }
+    }
+
+    /// Rounds a point to the nearest device pixel corner, so scaled or translated projections still
+    /// land sprite edges on pixel boundaries
+    fn snap(&self, point: Vec2) -> Vec2 {
+        let ndc = self.projection.project_point3(point.extend(0.));
+        let pixel = ((ndc.truncate() * 0.5 + 0.5) * self.viewport).round();
+        let snapped = (pixel / self.viewport - 0.5) * 2.;
+        self.inverse.project_point3(snapped.extend(ndc.z)).truncate()
+    }
+}
+
+/// # Returns
+/// the scissor box ([x, y, width, height] in framebuffer pixels from the bottom left) covering a
+/// clip rect given in the space the projection is applied to
+fn clip_to_scissor(projection: Mat4, viewport: [i32; 4], clip: [f32; 4]) -> [i32; 4] {
+    let to_pixels = |point: Vec2| {
+        let ndc = projection.project_point3(point.extend(0.)).truncate();
+        Vec2::new(viewport[0] as f32, viewport[1] as f32) + (ndc * 0.5 + 0.5) * Vec2::new(viewport[2] as f32, viewport[3] as f32)
+    };
+    let a = to_pixels(Vec2::new(clip[0], clip[1]));
+    let b = to_pixels(Vec2::new(clip[0] + clip[2], clip[1] + clip[3]));
+    // projections commonly flip y, so either corner can be the bottom left
+    let min = a.min(b).round();
+    let max = a.max(b).round();
+    [min.x as i32, min.y as i32, (max.x - min.x) as i32, (max.y - min.y) as i32]
+}
+
+impl Sprite {
+    pub fn new(texture: GLuint, position: Vec2, size: Vec2) -> Self {
+        Self {
+            texture,
+            position,
+            size,
+            uvs: (0., 0., 1., 1.),
+            rotated: false,
+            rotation: 0.,
+            array_layer: None,
+            clip: None,
+            color: Color::rgb(1., 1., 1.),
+            layer: SortingLayer::WORLD,
+            order: 0,
+        }
+    }
+
+    /// Creates a sprite showing a single texture from an atlas, sized to the texture's pixel size.
+    /// `position` is the top left of the image as it was added, trimmed textures are offset from it.
+    pub fn from_atlas(atlas_texture: GLuint, rect: &AtlasRect, position: Vec2) -> Self {
+        let (w, h) = rect.get_size();
+        let (u, v, uw, vh) = rect.uvs();
+        let (offset_x, offset_y) = rect.get_trim_offset();
+        Self {
+            texture: atlas_texture,
+            position: position + Vec2::new(offset_x as f32, offset_y as f32),
+            size: Vec2::new(w as f32, h as f32),
+            uvs: (u, v, u + uw, v + vh),
+            rotated: rect.is_rotated(),
+            rotation: 0.,
+            array_layer: None,
+            clip: None,
+            color: Color::rgb(1., 1., 1.),
+            layer: SortingLayer::WORLD,
+            order: 0,
+        }
+    }
+
+    /// Splits an atlas texture into nine sprites covering `size` at `position`, in row major order.
+    /// Corners keep their pixel size, edges stretch along their length and the center fills the
+    /// rest. When `size` is smaller than the borders they shrink to fit.
+    pub fn nine_slice(atlas_texture: GLuint, rect: &AtlasRect, insets: NineSlice, position: Vec2, size: Vec2) -> [Self; 9] {
+        let (w, h) = rect.get_size();
+        let (w, h) = (w as f32, h as f32);
+        let left = (insets.left as f32).min(w);
+        let right = (insets.right as f32).min(w - left);
+        let top = (insets.top as f32).min(h);
+        let bottom = (insets.bottom as f32).min(h - top);
+
+        let fit = |a: f32, b: f32, extent: f32| if a + b > extent { extent / (a + b) } else { 1. };
+        let scale_x = fit(left, right, size.x);
+        let scale_y = fit(top, bottom, size.y);
+        let xs = [0., left * scale_x, size.x - right * scale_x, size.x];
+        let ys = [0., top * scale_y, size.y - bottom * scale_y, size.y];
+        let ss = [0., left / w, 1. - right / w, 1.];
+        let ts = [0., top / h, 1. - bottom / h, 1.];
+
+        let (u, v, uw, vh) = rect.uvs();
+        std::array::from_fn(|i| {
+            let (column, row) = (i % 3, i / 3);
+            let (s0, s1, t0, t1) = (ss[column], ss[column + 1], ts[row], ts[row + 1]);
+            // a rotated texture's rows run along the stored rect's columns, see write_vertices
+            let (s0, s1, t0, t1) = if rect.is_rotated() { (1. - t1, 1. - t0, s0, s1) } else { (s0, s1, t0, t1) };
+            let mut sprite = Self::new(
+                atlas_texture,
+                position + Vec2::new(xs[column], ys[row]),
+                Vec2::new(xs[column + 1] - xs[column], ys[row + 1] - ys[row]),
+            ).with_uvs((u + s0 * uw, v + t0 * vh, u + s1 * uw, v + t1 * vh));
+            sprite.rotated = rect.is_rotated();
+            sprite
+        })
+    }
+
+    /// Creates a sprite showing a texture from an AtlasArray, see from_atlas
+    pub fn from_atlas_array(array: &AtlasArray, id: &AtlasTextureIdentifier, position: Vec2) -> Option<Self> {
+        let (layer, rect) = array.get_layer_and_rect(id)?;
+        Some(Self::from_atlas(array.get_id(), &rect, position).with_array_layer(Some(layer)))
+    }
+
+    /// Samples `layer` of the sprite's texture as a texture array, None for 2D textures
+    pub fn with_array_layer(mut self, layer: Option<u32>) -> Self {
+        self.array_layer = layer;
+        self
+    }
+
+    /// Rotates the sprite clockwise by `rotation` radians about its center
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Clips the sprite to (x, y, width, height), e.g. the visible area of a scrolling list
+    pub fn with_clip(mut self, clip: Option<[f32; 4]>) -> Self {
+        self.clip = clip;
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: (f32, f32, f32, f32)) -> Self {
+        self.uvs = uvs;
+        self
+    }
+
+    pub fn with_layer(mut self, layer: SortingLayer) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+
+    fn write_vertices(&self, buffer: &mut Vec<f32>, snap: Option<&PixelSnap>) {
+        let mut min = self.position;
+        let mut max = self.position + self.size;
+        if let Some(snap) = snap {
+            // snapping both corners keeps edges crisp, the size may change by up to a pixel
+            min = snap.snap(min);
+            max = snap.snap(max);
+        }
+        let (x0, y0) = (min.x, min.y);
+        let (x1, y1) = (max.x, max.y);
+        let (u0, v0, u1, v1) = self.uvs;
+        let c = self.color.to_array();
+        let layer = self.array_layer.unwrap_or(0) as f32;
+        let center = (min + max) * 0.5;
+        let turn = Vec2::from_angle(self.rotation);
+        let place = |x: f32, y: f32| {
+            if self.rotation == 0. {
+                return (x, y);
+            }
+            let p = center + turn.rotate(Vec2::new(x, y) - center);
+            (p.x, p.y)
+        };
+        let uv = |s: f32, t: f32| {
+            // rotated clockwise, the sprite's left edge runs along the top of the uv rect
+            let (s, t) = if self.rotated { (1. - t, s) } else { (s, t) };
+            (u0 + (u1 - u0) * s, v0 + (v1 - v0) * t)
+        };
+
+        for (x, y, (u, v)) in [
+            (x0, y0, uv(0., 0.)),
+            (x1, y0, uv(1., 0.)),
+            (x1, y1, uv(1., 1.)),
+            (x0, y0, uv(0., 0.)),
+            (x1, y1, uv(1., 1.)),
+            (x0, y1, uv(0., 1.)),
+        ] {
+            let (x, y) = place(x, y);
+            buffer.extend_from_slice(&[x, y, u, v]);
+            buffer.extend_from_slice(&c);
+            buffer.push(layer);
+        }
+    }
+}
+
+impl SpriteBatch {
+
+    /// Compiles the built-in sprite shader and creates the gl objects used by the batch.
+    pub fn new() -> Result<Self> {
+        let program = compile_program(SPRITE_VERTEX_SHADER, SPRITE_FRAGMENT_SHADER)?;
+
+        unsafe {
+            let mut vao = 0;
+            gl::GenVertexArrays(1, &mut vao);
+            gl::BindVertexArray(vao);
+
+            let mut vbo = 0;
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+            let f_size = size_of::<f32>();
+            let stride = (SPRITE_VERTEX_SIZE * f_size) as GLsizei;
+            let mut pointer = 0;
+            for (loc, size) in [(0u32, 2usize), (1, 2), (2, 4), (3, 1)] {
+                gl::VertexAttribPointer(loc, size as GLint, gl::FLOAT, gl::FALSE, stride, (pointer * f_size) as *const c_void);
+                gl::EnableVertexAttribArray(loc);
+                pointer += size;
+            }
+
+            gl::BindVertexArray(0);
+
+            Ok(Self {
+                sprites: Vec::new(),
+                projection: Mat4::IDENTITY,
+                color_grading: ColorGrading::None,
+                blend_mode: BlendMode::Alpha,
+                sorting_layers: SortingLayers::default(),
+                pixel_snapping: false,
+                program,
+                vao,
+                vbo,
+                freed: false,
+            })
+        }
+    }
+
+    pub fn set_projection(&mut self, projection: Mat4) {
+        self.projection = projection;
+    }
+
+    pub fn set_color_grading(&mut self, color_grading: ColorGrading) {
+        self.color_grading = color_grading;
+    }
+
+    pub fn get_color_grading(&self) -> ColorGrading {
+        self.color_grading
+    }
+
+    /// Sets the blend mode used when rendering the batch, defaults to BlendMode::Alpha
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    pub fn get_blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    pub fn get_sorting_layers(&self) -> &SortingLayers {
+        &self.sorting_layers
+    }
+
+    pub fn get_sorting_layers_mut(&mut self) -> &mut SortingLayers {
+        &mut self.sorting_layers
+    }
+
+    pub fn set_sorting_layers(&mut self, sorting_layers: SortingLayers) {
+        self.sorting_layers = sorting_layers;
+    }
+
+    /// Snaps sprite corners to device pixels when rendering, which stops UI and pixel art from
+    /// shimmering at fractional positions. Layers marked smooth in the sorting layers are not snapped.
+    /// Device pixels are taken from the viewport in GlStateManager, so it must match the render target.
+    pub fn set_pixel_snapping(&mut self, enabled: bool) {
+        self.pixel_snapping = enabled;
+    }
+
+    pub fn get_pixel_snapping(&self) -> bool {
+        self.pixel_snapping
+    }
+
+    pub fn draw(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    pub fn cancel_draws(&mut self) {
+        self.sprites.clear();
+    }
+
+    /// Draws every queued sprite ordered by sorting layer, then order in layer, then submission order,
+    /// splitting draw calls only when the texture or clip rect changes. Sprites on different layers
+    /// of one texture array share a draw call. Clip rects are intersected with any scissor already
+    /// pushed on the state manager.
+    pub fn render(&mut self, gl_state: &mut GlStateManager) -> Result<()> {
+        let mut sprites = mem::take(&mut self.sprites);
+        if sprites.is_empty() {
+            return Ok(());
+        }
+        // stable, so submission order is kept within the same layer and order
+        sprites.sort_by_key(|s| (self.sorting_layers.rank(s.layer), s.order));
+
+        let snap = self.pixel_snapping.then(|| PixelSnap::new(self.projection, gl_state.get_viewport()));
+
+        let mut buffer = Vec::with_capacity(sprites.len() * 6 * SPRITE_VERTEX_SIZE);
+        let mut runs: Vec<(RunKey, usize, usize)> = Vec::new();
+        for sprite in &sprites {
+            let snap = snap.as_ref().filter(|_| !self.sorting_layers.is_smooth(sprite.layer));
+            sprite.write_vertices(&mut buffer, snap);
+            let key = (sprite.texture, sprite.array_layer.is_some(), sprite.clip);
+            match runs.last_mut() {
+                Some((run_key, _, count)) if *run_key == key => *count += 6,
+                _ => runs.push((key, (buffer.len() / SPRITE_VERTEX_SIZE) - 6, 6)),
+            }
+        }
+
+        gl_state.use_program(self.program);
+        gl_state.bind_vao(self.vao);
+        gl_state.blend_mode(self.blend_mode);
+
+        gl_state.set_uniform(U_PROJECTION, GLUniform::Mat4(self.projection));
+        gl_state.set_uniform(U_TEXTURE, GLUniform::I32(0));
+        gl_state.set_uniform("u_texture_array", GLUniform::I32(ARRAY_SLOT as i32));
+        gl_state.set_uniform("u_palette", GLUniform::I32(PALETTE_SLOT as i32));
+        gl_state.set_uniform("u_lut", GLUniform::I32(LUT_SLOT as i32));
+        gl_state.set_uniform("u_grading", GLUniform::I32(self.color_grading.mode()));
+
+        match self.color_grading {
+            ColorGrading::None => {}
+            ColorGrading::Palette(palette) => {
+                gl_state.bind_texture_1d(PALETTE_SLOT, palette);
+            }
+            ColorGrading::Lut { texture, size } => {
+                gl_state.bind_texture_3d(LUT_SLOT, texture);
+                gl_state.set_uniform("u_lut_size", GLUniform::F32(size as f32));
+            }
+        }
+
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, (buffer.len() * size_of::<f32>()) as isize, buffer.as_ptr() as *const _, gl::STREAM_DRAW);
+        }
+
+        let viewport = gl_state.get_viewport();
+        for ((tex, array, clip), start, count) in runs {
+            if let Some(clip) = clip {
+                gl_state.push_scissor(clip_to_scissor(self.projection, viewport, clip));
+            }
+            if array {
+                gl_state.bind_texture_array(ARRAY_SLOT, tex);
+            } else {
+                gl_state.bind_texture(0, tex);
+            }
+            gl_state.set_uniform("u_array", GLUniform::Bool(array));
+            unsafe {
+                gl::DrawArrays(gl::TRIANGLES, start as GLsizei, count as GLsizei);
+            }
+            if clip.is_some() {
+                gl_state.pop_scissor();
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        gl_state.destroy_program(self.program);
+        gl_state.destroy_vbo_vec(vec![self.vbo]);
+        gl_state.destroy_vao(self.vao);
+        self.freed = true;
+    }
+
+}
+
+impl Drop for SpriteBatch {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            let mut state = GlStateManager::new();
+            self.destroy(&mut state);
+            panic!("Sprite batch was not destroyed before dropping")
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::mem;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::errors::ReplayError;
+
+/// Input the engine can record and replay. Convert window events into these and queue them with
+/// Engine::push_event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    KeyPressed(u32),
+    KeyReleased(u32),
+    MouseMoved { x: f32, y: f32 },
+    MousePressed(u32),
+    MouseReleased(u32),
+    Scroll { x: f32, y: f32 },
+    Resized { width: u32, height: u32 },
+    Text(char),
+    GamepadPressed { gamepad: u32, button: u32 },
+    GamepadReleased { gamepad: u32, button: u32 },
+}
+
+/// The delta time and input of one frame
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayFrame {
+    /// seconds since the previous frame
+    pub delta: f32,
+    pub events: Vec<InputEvent>,
+}
+
+/// Where the engine loop gets each frame's delta time and input from
+#[derive(Debug, Default)]
+pub enum Replay {
+    /// real time and the queued events
+    #[default]
+    Live,
+    /// real time and the queued events, also recorded for saving with save_recording
+    Recording(Vec<ReplayFrame>),
+    /// frames from a recording, ignoring real time and queued events
+    Playing {
+        frames: Vec<ReplayFrame>,
+        next: usize,
+    },
+}
+
+impl InputEvent {
+    fn write(&self, out: &mut String) {
+        let _ = match self {
+            Self::KeyPressed(key) => writeln!(out, "key_pressed {key}"),
+            Self::KeyReleased(key) => writeln!(out, "key_released {key}"),
+            Self::MouseMoved { x, y } => writeln!(out, "mouse_moved {x} {y}"),
+            Self::MousePressed(button) => writeln!(out, "mouse_pressed {button}"),
+            Self::MouseReleased(button) => writeln!(out, "mouse_released {button}"),
+            Self::Scroll { x, y } => writeln!(out, "scroll {x} {y}"),
+            Self::Resized { width, height } => writeln!(out, "resized {width} {height}"),
+            Self::Text(c) => writeln!(out, "text {}", *c as u32),
+            Self::GamepadPressed { gamepad, button } => writeln!(out, "gamepad_pressed {gamepad} {button}"),
+            Self::GamepadReleased { gamepad, button } => writeln!(out, "gamepad_released {gamepad} {button}"),
+        };
+    }
+
+    fn parse(kind: &str, args: &[&str]) -> Option<Self> {
+        let f = |i: usize| args.get(i)?.parse::<f32>().ok();
+        let u = |i: usize| args.get(i)?.parse::<u32>().ok();
+        Some(match kind {
+            "key_pressed" => Self::KeyPressed(u(0)?),
+            "key_released" => Self::KeyReleased(u(0)?),
+            "mouse_moved" => Self::MouseMoved { x: f(0)?, y: f(1)? },
+            "mouse_pressed" => Self::MousePressed(u(0)?),
+            "mouse_released" => Self::MouseReleased(u(0)?),
+            "scroll" => Self::Scroll { x: f(0)?, y: f(1)? },
+            "resized" => Self::Resized { width: u(0)?, height: u(1)? },
+            "text" => Self::Text(char::from_u32(u(0)?)?),
+            "gamepad_pressed" => Self::GamepadPressed { gamepad: u(0)?, button: u(1)? },
+            "gamepad_released" => Self::GamepadReleased { gamepad: u(0)?, button: u(1)? },
+            _ => return None,
+        })
+    }
+}
+
+impl Replay {
+    /// Loads a recording made with save_recording for playback
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut frames: Vec<ReplayFrame> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let invalid = || ReplayError::InvalidLine { line: i + 1, content: line.to_string() };
+            if parts[0] == "frame" {
+                let delta = parts.get(1).and_then(|d| d.parse().ok()).ok_or_else(invalid)?;
+                frames.push(ReplayFrame { delta, events: Vec::new() });
+            } else {
+                let event = InputEvent::parse(parts[0], &parts[1..]).ok_or_else(invalid)?;
+                frames.last_mut().ok_or(ReplayError::EventBeforeFrame { line: i + 1 })?.events.push(event);
+            }
+        }
+        Ok(Self::Playing { frames, next: 0 })
+    }
+
+    /// Starts recording, replacing the current mode
+    pub fn start_recording(&mut self) {
+        *self = Self::Recording(Vec::new());
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self, Self::Recording(..))
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self, Self::Playing { .. })
+    }
+
+    /// Whether a playback has used up all its frames
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Playing { frames, next } if *next >= frames.len())
+    }
+
+    /// Writes the frames recorded so far to a file that load can read back
+    pub fn save_recording(&self, path: impl AsRef<Path>) -> Result<()> {
+        let Self::Recording(frames) = self else {
+            return Err(ReplayError::NotRecording.into());
+        };
+        let mut out = String::from("# RenderForge input replay\n");
+        for frame in frames {
+            let _ = writeln!(out, "frame {}", frame.delta);
+            for event in &frame.events {
+                event.write(&mut out);
+            }
+        }
+        fs::write(path, out)?;
+        Ok(())
+    }
+
+    /// Decides the delta time and input of the next frame: the live values when live or recording
+    /// (recording them), the next recorded frame when playing. A finished playback returns empty
+    /// frames with no time passing.
+    pub fn next_frame(&mut self, delta: f32, events: &mut Vec<InputEvent>) -> ReplayFrame {
+        match self {
+            Self::Live => ReplayFrame { delta, events: mem::take(events) },
+            Self::Recording(frames) => {
+                let frame = ReplayFrame { delta, events: mem::take(events) };
+                frames.push(frame.clone());
+                frame
+            }
+            Self::Playing { frames, next } => {
+                events.clear();
+                let frame = frames.get(*next).cloned().unwrap_or_default();
+                *next += 1;
+                frame
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::Result;
+use gl::types::GLuint;
+
+use crate::data::GlStateManager;
+use crate::frame::FRAME_TIMING;
+use crate::framebuffer::{AttachmentFormat, Framebuffer};
+
+/// scale changes are quantized to this step, so the target isn't reallocated every frame
+const SCALE_STEP: f32 = 0.05;
+/// how far the smoothed scale has to drift from the current scale before it changes, just over
+/// half a step so the quantized scale always moves once it does
+const SCALE_HYSTERESIS: f32 = SCALE_STEP * 0.6;
+
+/// Renders the scene into an internal target scaled to between min_scale and max_scale of the
+/// output size, adjusting the scale from gpu frame times to hold a target frame time, then
+/// upscales the result to the output.
+///
+/// Typically enabled with Engine::enable_dynamic_resolution, which calls update_frame every
+/// frame; render the scene into `target(..)`, then `upscale(..)` it to the window.
+#[derive(Debug)]
+pub struct DynamicResolution {
+    scale: f32,
+    /// the damped ideal scale, which `scale` follows in steps once it drifts far enough
+    smoothed: f32,
+    min_scale: f32,
+    max_scale: f32,
+    target_frame_ms: f64,
+    format: AttachmentFormat,
+    target: Option<Framebuffer>,
+}
+
+impl DynamicResolution {
+    /// Starts at full resolution, scaling between 50% and 100% to hold `target_frame_ms`
+    pub fn new(target_frame_ms: f64) -> Self {
+        Self {
+            scale: 1.,
+            smoothed: 1.,
+            min_scale: 0.5,
+            max_scale: 1.,
+            target_frame_ms,
+            format: AttachmentFormat::Rgba8,
+            target: None,
+        }
+    }
+
+    pub fn with_scale_range(mut self, min_scale: f32, max_scale: f32) -> Self {
+        self.min_scale = min_scale;
+        self.max_scale = max_scale.max(min_scale);
+        self.scale = self.scale.clamp(self.min_scale, self.max_scale);
+        self.smoothed = self.scale;
+        self
+    }
+
+    pub fn with_format(mut self, format: AttachmentFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn get_scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Fixes the scale, until the next update
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.clamp(self.min_scale, self.max_scale);
+        self.smoothed = self.scale;
+    }
+
+    pub fn set_target_frame_ms(&mut self, target_frame_ms: f64) {
+        self.target_frame_ms = target_frame_ms;
+    }
+
+    /// Adjusts the scale from a measured gpu frame time. Frame time is roughly proportional to the
+    /// pixel count, so the ideal scale is sqrt(target / measured) of the current one. A smoothed
+    /// scale follows the ideal, damped to avoid oscillating, and the scale steps to it once the two
+    /// are more than SCALE_HYSTERESIS apart, so small but steady misses still move the scale.
+    pub fn update(&mut self, gpu_frame_ms: f64) {
+        if gpu_frame_ms <= 0. {
+            return;
+        }
+        let ideal = self.scale * (self.target_frame_ms / gpu_frame_ms).sqrt() as f32;
+        self.smoothed = (self.smoothed + (ideal - self.smoothed) * 0.25).clamp(self.min_scale, self.max_scale);
+        if (self.smoothed - self.scale).abs() > SCALE_HYSTERESIS {
+            let quantized = (self.smoothed / SCALE_STEP).round() * SCALE_STEP;
+            self.scale = quantized.clamp(self.min_scale, self.max_scale);
+        }
+    }
+
+    /// Updates from the latest result of a gpu timer started with GlStateManager::begin_gpu_timer
+    pub fn update_from_timer(&mut self, gl_state: &GlStateManager, timer: &str) {
+        if let Some(ms) = gl_state.get_gpu_timings().get(timer) {
+            self.update(*ms);
+        }
+    }
+
+    /// Updates from the gpu time of the last finished frame, see frame::FRAME_TIMING
+    pub fn update_frame(&mut self, gl_state: &GlStateManager) {
+        self.update_from_timer(gl_state, FRAME_TIMING);
+    }
+
+    /// # Returns
+    /// the internal resolution for an output size at the current scale
+    pub fn get_scaled_size(&self, output_size: (u32, u32)) -> (u32, u32) {
+        (
+            ((output_size.0 as f32 * self.scale).round() as u32).max(1),
+            ((output_size.1 as f32 * self.scale).round() as u32).max(1),
+        )
+    }
+
+    /// # Returns
+    /// the internal target for this frame, recreated if the scaled size changed
+    pub fn target(&mut self, gl_state: &mut GlStateManager, output_size: (u32, u32)) -> Result<&Framebuffer> {
+        let size = self.get_scaled_size(output_size);
+        if let Some(target) = &mut self.target
+            && target.get_size() != size
+        {
+            target.destroy(gl_state);
+            self.target = None;
+        }
+        if self.target.is_none() {
+            let target = Framebuffer::builder(size.0, size.1)
+                .with_color_attachment(self.format)
+                .with_depth()
+                .build(gl_state)?;
+            self.target = Some(target);
+        }
+        Ok(self.target.as_ref().unwrap())
+    }
+
+    /// Stretches the internal target over an output framebuffer (0 for the window) with linear filtering
+    pub fn upscale(&self, gl_state: &mut GlStateManager, output_fbo: GLuint, output_size: (u32, u32)) {
+        let Some(target) = &self.target else {
+            return;
+        };
+        let (width, height) = target.get_size();
+        let previous = gl_state.get_fbo();
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, target.get_id());
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, output_fbo);
+            gl::BlitFramebuffer(
+                0, 0, width as i32, height as i32,
+                0, 0, output_size.0 as i32, output_size.1 as i32,
+                gl::COLOR_BUFFER_BIT, gl::LINEAR,
+            );
+            // blitting changed both bindings behind the state manager's back
+            gl::BindFramebuffer(gl::FRAMEBUFFER, previous);
+        }
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        if let Some(mut target) = self.target.take() {
+            target.destroy(gl_state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steady_overshoot_lowers_the_scale() {
+        let mut resolution = DynamicResolution::new(10.);
+        for _ in 0..20 {
+            // frame time follows the pixel count, 10% over the target at full resolution
+            let scale = resolution.get_scale() as f64;
+            resolution.update(11. * scale * scale);
+        }
+        assert_eq!(resolution.get_scale(), 0.95);
+    }
+
+    #[test]
+    fn frames_on_target_keep_the_scale() {
+        let mut resolution = DynamicResolution::new(10.);
+        for _ in 0..20 {
+            resolution.update(10.2);
+        }
+        assert_eq!(resolution.get_scale(), 1.);
+    }
+}
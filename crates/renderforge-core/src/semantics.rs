@@ -0,0 +1,40 @@
+//! Standard names and locations for the data the built-in shaders and vertex formats share.
+//! Custom shaders using these names get their data from the engine without extra setup, and
+//! ShaderReflection::validate_semantics catches attributes declared at the wrong location.
+
+pub const A_POSITION: &str = "a_position";
+pub const A_NORMAL: &str = "a_normal";
+pub const A_UV: &str = "a_uv";
+pub const A_COLOR: &str = "a_color";
+/// per-instance model matrix, takes 4 locations
+pub const A_TRANSFORM: &str = "a_transform";
+pub const A_INSTANCE_DATA: &str = "a_instance_data";
+pub const A_LIGHTMAP_UV: &str = "a_lightmap_uv";
+/// every standard attribute name above
+pub const ATTRIBUTE_NAMES: [&str; 7] = [A_POSITION, A_NORMAL, A_UV, A_COLOR, A_TRANSFORM, A_INSTANCE_DATA, A_LIGHTMAP_UV];
+
+pub const U_MODEL: &str = "u_model";
+pub const U_VIEW_PROJ: &str = "u_view_proj";
+pub const U_INVERSE_VIEW_PROJ: &str = "u_inverse_view_proj";
+/// orthographic projection of 2D renderers
+pub const U_PROJECTION: &str = "u_projection";
+pub const U_TEXTURE: &str = "u_texture";
+pub const U_COLOR: &str = "u_color";
+pub const U_LIGHT_DIRECTION: &str = "u_light_direction";
+pub const U_LIGHTMAP: &str = "u_lightmap";
+
+/// (attribute, location) of the standard instanced mesh shader, see mesh::standard_mesh_layout
+pub const STANDARD_MESH_ATTRIBUTES: [(&str, u32); 5] = [
+    (A_POSITION, 0),
+    (A_NORMAL, 1),
+    (A_UV, 2),
+    (A_TRANSFORM, 3),
+    (A_INSTANCE_DATA, 7),
+];
+
+/// (attribute, location) of the sprite batch shader
+pub const SPRITE_ATTRIBUTES: [(&str, u32); 3] = [
+    (A_POSITION, 0),
+    (A_UV, 1),
+    (A_COLOR, 2),
+];
@@ -0,0 +1,955 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::OnceLock;
+use std::thread::panicking;
+
+use anyhow::Result;
+use gl::types::{GLchar, GLenum, GLint, GLuint};
+
+use crate::capabilities::{has_extension, shader_capabilities};
+use crate::data::GlStateManager;
+use crate::errors::ShaderError;
+use crate::semantics::ATTRIBUTE_NAMES;
+
+const SHADER_BINARY_FORMAT_SPIR_V: GLenum = 0x9551;
+const SPIRV_MAGIC: u32 = 0x07230203;
+/// most defines ShaderVariantsBuilder::with_permutations takes, each one doubles the programs compiled
+pub const MAX_PERMUTATION_DEFINES: usize = 8;
+
+type SpecializeShaderFn = extern "system" fn(GLuint, *const GLchar, GLuint, *const GLuint, *const GLuint);
+
+/// glSpecializeShader isn't part of the GL 4.5 bindings, so it's loaded by load_spirv_support
+static SPECIALIZE_SHADER: OnceLock<Option<SpecializeShaderFn>> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveUniform {
+    /// array uniforms are reported without the trailing `[0]`
+    pub name: String,
+    pub location: GLint,
+    pub gl_type: GLenum,
+    /// array length, 1 for non-array uniforms
+    pub size: GLint,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveAttribute {
+    pub name: String,
+    pub location: GLint,
+    pub gl_type: GLenum,
+    pub size: GLint,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveUniformBlock {
+    pub name: String,
+    pub index: GLuint,
+    pub binding: GLint,
+    /// size of the block's buffer data in bytes
+    pub data_size: GLint,
+}
+
+/// Everything the linker kept active in a program
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ShaderReflection {
+    /// uniforms in the default block, including samplers
+    pub uniforms: Vec<ActiveUniform>,
+    pub samplers: Vec<ActiveUniform>,
+    pub attributes: Vec<ActiveAttribute>,
+    pub uniform_blocks: Vec<ActiveUniformBlock>,
+    /// input primitive of the geometry stage, None if the program has no geometry shader
+    pub geometry_input: Option<PrimitiveType>,
+}
+
+/// A precompiled SPIR-V shader stage, with GLSL source to use when the context can't load SPIR-V
+#[derive(Debug, Clone, Copy)]
+pub struct SpirvSource<'a> {
+    pub binary: &'a [u8],
+    pub entry_point: &'a str,
+    /// (constant id, value) pairs
+    pub specialization: &'a [(u32, u32)],
+    pub fallback_glsl: Option<&'a str>,
+}
+
+/// A virtual filesystem of shader sources that can be pulled into other shaders with
+/// `#include "name"`, so shared code doesn't have to be copied into every shader.
+#[derive(Debug, Clone, Default)]
+pub struct ShaderIncludes {
+    files: HashMap<String, String>,
+}
+
+/// A linked vertex + fragment program that keeps its sources around so it can be relinked in place,
+/// e.g. when hot reloading shader files.
+#[derive(Debug)]
+pub struct ShaderProgram {
+    id: GLuint,
+    vertex: String,
+    geometry: Option<String>,
+    fragment: String,
+    freed: bool,
+}
+
+/// Builds a ShaderVariants, see ShaderVariants::builder
+#[derive(Debug, Clone)]
+pub struct ShaderVariantsBuilder {
+    vertex: String,
+    fragment: String,
+    variants: Vec<Vec<String>>,
+}
+
+/// One vertex + fragment program compiled several times with different sets of `#define`s,
+/// looked up by the set of defines at draw time.
+/// Defines are either a bare name (`USE_NORMAL_MAP`) or a name and value (`MAX_LIGHTS 8`).
+#[derive(Debug)]
+pub struct ShaderVariants {
+    variants: HashMap<Vec<String>, GLuint>,
+    freed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderStage {
+    Vertex,
+    TessControl,
+    TessEvaluation,
+    Geometry,
+    Fragment,
+}
+
+/// Primitive types a geometry shader can take as input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrimitiveType {
+    Points,
+    Lines,
+    LinesAdjacency,
+    Triangles,
+    TrianglesAdjacency,
+}
+
+impl ShaderStage {
+    pub fn to_gl(&self) -> GLenum {
+        match self {
+            Self::Vertex => gl::VERTEX_SHADER,
+            Self::TessControl => gl::TESS_CONTROL_SHADER,
+            Self::TessEvaluation => gl::TESS_EVALUATION_SHADER,
+            Self::Geometry => gl::GEOMETRY_SHADER,
+            Self::Fragment => gl::FRAGMENT_SHADER,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Vertex => "vertex",
+            Self::TessControl => "tessellation control",
+            Self::TessEvaluation => "tessellation evaluation",
+            Self::Geometry => "geometry",
+            Self::Fragment => "fragment",
+        }
+    }
+}
+
+impl PrimitiveType {
+    pub fn to_gl(&self) -> GLenum {
+        match self {
+            Self::Points => gl::POINTS,
+            Self::Lines => gl::LINES,
+            Self::LinesAdjacency => gl::LINES_ADJACENCY,
+            Self::Triangles => gl::TRIANGLES,
+            Self::TrianglesAdjacency => gl::TRIANGLES_ADJACENCY,
+        }
+    }
+
+    pub fn from_gl(primitive: GLenum) -> Option<Self> {
+        match primitive {
+            gl::POINTS => Some(Self::Points),
+            gl::LINES => Some(Self::Lines),
+            gl::LINES_ADJACENCY => Some(Self::LinesAdjacency),
+            gl::TRIANGLES => Some(Self::Triangles),
+            gl::TRIANGLES_ADJACENCY => Some(Self::TrianglesAdjacency),
+            _ => None,
+        }
+    }
+}
+
+impl ShaderIncludes {
+    pub fn new() -> Self {
+        Self {
+            files: HashMap::new(),
+        }
+    }
+
+    /// Adds a file, replacing any file with the same name
+    pub fn add(&mut self, name: impl ToString, source: impl ToString) {
+        self.files.insert(name.to_string(), source.to_string());
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.files.get(name).map(|s| s.as_str())
+    }
+
+    /// Replaces every `#include "name"` (or `#include <name>`) line with the contents of the named
+    /// file, expanding includes inside included files as well.
+    pub fn preprocess(&self, source: &str) -> Result<String> {
+        let mut out = String::with_capacity(source.len());
+        let mut stack = Vec::new();
+        self.expand(source, &mut stack, &mut out)?;
+        Ok(out)
+    }
+
+    fn expand(&self, source: &str, stack: &mut Vec<String>, out: &mut String) -> Result<()> {
+        for line in source.lines() {
+            let Some(directive) = line.trim_start().strip_prefix("#include") else {
+                out.push_str(line);
+                out.push('\n');
+                continue;
+            };
+
+            let name = parse_include_name(directive)
+                .ok_or_else(|| ShaderError::MalformedInclude(line.trim().to_string()))?;
+
+            if stack.iter().any(|n| n == name) {
+                return Err(ShaderError::RecursiveInclude(name.to_string()).into());
+            }
+
+            let included = self.get(name).ok_or_else(|| ShaderError::MissingInclude(name.to_string()))?;
+
+            stack.push(name.to_string());
+            self.expand(included, stack, out)?;
+            stack.pop();
+        }
+        Ok(())
+    }
+}
+
+fn parse_include_name(directive: &str) -> Option<&str> {
+    let directive = directive.trim();
+    let (open, close) = match directive.chars().next()? {
+        '"' => ('"', '"'),
+        '<' => ('<', '>'),
+        _ => return None,
+    };
+    let rest = directive.strip_prefix(open)?;
+    let end = rest.find(close)?;
+    if !rest[end + 1..].trim().is_empty() {
+        return None;
+    }
+    Some(&rest[..end])
+}
+
+impl ShaderProgram {
+    pub fn new(vertex: impl ToString, fragment: impl ToString) -> Result<Self> {
+        let vertex = vertex.to_string();
+        let fragment = fragment.to_string();
+        let id = compile_program(&vertex, &fragment)?;
+        Ok(Self {
+            id,
+            vertex,
+            geometry: None,
+            fragment,
+            freed: false,
+        })
+    }
+
+    /// Creates a program with a geometry stage between the vertex and fragment shaders
+    pub fn new_with_geometry(vertex: impl ToString, geometry: impl ToString, fragment: impl ToString) -> Result<Self> {
+        let vertex = vertex.to_string();
+        let geometry = geometry.to_string();
+        let fragment = fragment.to_string();
+        let id = compile_program_with_geometry(&vertex, &geometry, &fragment)?;
+        Ok(Self {
+            id,
+            vertex,
+            geometry: Some(geometry),
+            fragment,
+            freed: false,
+        })
+    }
+
+    pub fn get_id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn get_vertex_source(&self) -> &str {
+        &self.vertex
+    }
+
+    pub fn get_geometry_source(&self) -> Option<&str> {
+        self.geometry.as_deref()
+    }
+
+    pub fn get_fragment_source(&self) -> &str {
+        &self.fragment
+    }
+
+    /// Recompiles the program from new sources, keeping the same gl id, and invalidates the uniforms
+    /// GlStateManager has cached for it. The geometry stage, if any, is kept.
+    /// The new sources are only stored once the program links. If linking fails the previous sources
+    /// are kept and the program is left unlinked.
+    pub fn relink(&mut self, gl_state: &mut GlStateManager, vertex: impl ToString, fragment: impl ToString) -> Result<()> {
+        let vertex = vertex.to_string();
+        let fragment = fragment.to_string();
+
+        let mut stages = vec![(ShaderStage::Vertex, vertex.as_str()), (ShaderStage::Fragment, fragment.as_str())];
+        if let Some(geometry) = &self.geometry {
+            stages.push((ShaderStage::Geometry, geometry));
+        }
+        let shaders = compile_stages(&stages)?;
+
+        let res = relink_program(self.id, &shaders);
+
+        unsafe {
+            for shader in shaders {
+                gl::DeleteShader(shader);
+            }
+        }
+
+        gl_state.invalidate_uniforms(self.id);
+        res?;
+        self.vertex = vertex;
+        self.fragment = fragment;
+        Ok(())
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        gl_state.destroy_program(self.id);
+        self.freed = true;
+    }
+}
+
+impl Drop for ShaderProgram {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            let mut state = GlStateManager::new();
+            self.destroy(&mut state);
+            panic!("Shader program was not destroyed before dropping")
+        }
+    }
+}
+
+impl ShaderVariantsBuilder {
+    /// Adds a variant compiled with the given defines. The order of the defines doesn't matter.
+    pub fn with_variant(mut self, defines: &[&str]) -> Self {
+        self.variants.push(variant_key(defines));
+        self
+    }
+
+    /// Adds every combination of the given defines, including the variant with none of them.
+    /// At most MAX_PERMUTATION_DEFINES defines are accepted, since the variants double with each.
+    pub fn with_permutations(mut self, defines: &[&str]) -> Result<Self> {
+        if defines.len() > MAX_PERMUTATION_DEFINES {
+            return Err(ShaderError::TooManyPermutations {
+                count: defines.len(),
+                max: MAX_PERMUTATION_DEFINES,
+            }.into());
+        }
+        for mask in 0..(1u32 << defines.len()) {
+            let selected: Vec<&str> = defines.iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .map(|(_, d)| *d)
+                .collect();
+            self.variants.push(variant_key(&selected));
+        }
+        Ok(self)
+    }
+
+    /// Compiles every variant. If any variant fails, the already compiled ones are deleted.
+    pub fn build(self) -> Result<ShaderVariants> {
+        let mut variants = ShaderVariants {
+            variants: HashMap::new(),
+            freed: false,
+        };
+        for key in self.variants {
+            if variants.variants.contains_key(&key) {
+                continue;
+            }
+            let vertex = inject_defines(&self.vertex, &key);
+            let fragment = inject_defines(&self.fragment, &key);
+            match compile_program(&vertex, &fragment) {
+                Ok(program) => {
+                    variants.variants.insert(key, program);
+                }
+                Err(e) => {
+                    variants.destroy(&mut GlStateManager::new());
+                    return Err(e);
+                }
+            }
+        }
+        Ok(variants)
+    }
+}
+
+impl ShaderVariants {
+    pub fn builder(vertex: impl ToString, fragment: impl ToString) -> ShaderVariantsBuilder {
+        ShaderVariantsBuilder {
+            vertex: vertex.to_string(),
+            fragment: fragment.to_string(),
+            variants: Vec::new(),
+        }
+    }
+
+    /// # Returns
+    /// the program compiled with exactly these defines, in any order
+    pub fn get(&self, defines: &[&str]) -> Option<GLuint> {
+        self.variants.get(&variant_key(defines)).copied()
+    }
+
+    /// # Returns
+    /// (defines, program) for every variant
+    pub fn iter(&self) -> impl Iterator<Item = (&[String], GLuint)> {
+        self.variants.iter().map(|(k, v)| (k.as_slice(), *v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.variants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.variants.is_empty()
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        for (_, program) in self.variants.drain() {
+            gl_state.destroy_program(program);
+        }
+        self.freed = true;
+    }
+}
+
+impl Drop for ShaderVariants {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            let mut state = GlStateManager::new();
+            self.destroy(&mut state);
+            panic!("Shader variants were not destroyed before dropping")
+        }
+    }
+}
+
+fn variant_key(defines: &[&str]) -> Vec<String> {
+    let mut key: Vec<String> = defines.iter().map(|d| d.trim().to_string()).collect();
+    key.sort();
+    key.dedup();
+    key
+}
+
+/// Inserts a `#define` line for each define right after the `#version` directive, or at the top of
+/// the source if it has none
+pub fn inject_defines(source: &str, defines: &[impl AsRef<str>]) -> String {
+    let mut block = String::new();
+    for define in defines {
+        block.push_str("#define ");
+        block.push_str(define.as_ref());
+        block.push('\n');
+    }
+
+    let version_end = source.lines()
+        .next()
+        .filter(|line| line.trim_start().starts_with("#version"))
+        .map(|line| (line.len() + 1).min(source.len()));
+
+    match version_end {
+        Some(end) => {
+            let mut out = String::with_capacity(source.len() + block.len() + 1);
+            out.push_str(&source[..end]);
+            if !out.ends_with('\n') {
+                out.push('\n');
+            }
+            out.push_str(&block);
+            out.push_str(&source[end..]);
+            out
+        }
+        None => block + source,
+    }
+}
+
+/// Adds the context's `#version` directive when the source has none, then the defines of
+/// ShaderCapabilities::defines, so one source can adapt to desktop GL and GLSL ES targets
+pub fn with_capability_defines(source: &str) -> String {
+    let capabilities = shader_capabilities();
+    let has_version = source.lines()
+        .next()
+        .is_some_and(|line| line.trim_start().starts_with("#version"));
+    if has_version {
+        inject_defines(source, &capabilities.defines())
+    } else {
+        inject_defines(&(capabilities.version_header() + source), &capabilities.defines())
+    }
+}
+
+impl ShaderReflection {
+    /// Queries the active uniforms, attributes and uniform blocks of a linked program
+    pub fn reflect(program: GLuint) -> Self {
+        let mut reflection = Self::default();
+
+        unsafe {
+            let mut count = 0;
+            let mut max_len = 0;
+            gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
+            gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_len);
+            for i in 0..count as GLuint {
+                let mut name = vec![0u8; max_len.max(1) as usize];
+                let mut len = 0;
+                let mut size = 0;
+                let mut gl_type = 0;
+                gl::GetActiveUniform(program, i, max_len, &mut len, &mut size, &mut gl_type, name.as_mut_ptr() as *mut GLchar);
+                name.truncate(len as usize);
+                let cname = CString::new(name.clone()).unwrap();
+                let location = gl::GetUniformLocation(program, cname.as_ptr());
+
+                // uniforms that live in a uniform block have no location
+                if location == -1 {
+                    continue;
+                }
+
+                let name = String::from_utf8_lossy(&name);
+                let uniform = ActiveUniform {
+                    name: name.strip_suffix("[0]").unwrap_or(&name).to_string(),
+                    location,
+                    gl_type,
+                    size,
+                };
+                if is_sampler_type(gl_type) {
+                    reflection.samplers.push(uniform.clone());
+                }
+                reflection.uniforms.push(uniform);
+            }
+
+            gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTES, &mut count);
+            gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, &mut max_len);
+            for i in 0..count as GLuint {
+                let mut name = vec![0u8; max_len.max(1) as usize];
+                let mut len = 0;
+                let mut size = 0;
+                let mut gl_type = 0;
+                gl::GetActiveAttrib(program, i, max_len, &mut len, &mut size, &mut gl_type, name.as_mut_ptr() as *mut GLchar);
+                name.truncate(len as usize);
+                let cname = CString::new(name.clone()).unwrap();
+                let location = gl::GetAttribLocation(program, cname.as_ptr());
+
+                // built-ins like gl_VertexID are reported without a location
+                if location == -1 {
+                    continue;
+                }
+
+                reflection.attributes.push(ActiveAttribute {
+                    name: String::from_utf8_lossy(&name).to_string(),
+                    location,
+                    gl_type,
+                    size,
+                });
+            }
+
+            gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCKS, &mut count);
+            gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCK_MAX_NAME_LENGTH, &mut max_len);
+            for index in 0..count as GLuint {
+                let mut name = vec![0u8; max_len.max(1) as usize];
+                let mut len = 0;
+                let mut binding = 0;
+                let mut data_size = 0;
+                gl::GetActiveUniformBlockName(program, index, max_len, &mut len, name.as_mut_ptr() as *mut GLchar);
+                name.truncate(len as usize);
+                gl::GetActiveUniformBlockiv(program, index, gl::UNIFORM_BLOCK_BINDING, &mut binding);
+                gl::GetActiveUniformBlockiv(program, index, gl::UNIFORM_BLOCK_DATA_SIZE, &mut data_size);
+
+                reflection.uniform_blocks.push(ActiveUniformBlock {
+                    name: String::from_utf8_lossy(&name).to_string(),
+                    index,
+                    binding,
+                    data_size,
+                });
+            }
+
+            // GEOMETRY_INPUT_TYPE is an error on programs without a geometry stage. link_program
+            // leaves the shaders attached for this, programs loaded from a binary have none and
+            // are reported without a geometry stage.
+            gl::GetProgramiv(program, gl::ATTACHED_SHADERS, &mut count);
+            let mut shaders = vec![0; count.max(0) as usize];
+            gl::GetAttachedShaders(program, count, ptr::null_mut(), shaders.as_mut_ptr());
+            let has_geometry = shaders.iter().any(|shader| {
+                let mut stage = 0;
+                gl::GetShaderiv(*shader, gl::SHADER_TYPE, &mut stage);
+                stage as GLenum == gl::GEOMETRY_SHADER
+            });
+            if has_geometry {
+                let mut input = 0;
+                gl::GetProgramiv(program, gl::GEOMETRY_INPUT_TYPE, &mut input);
+                reflection.geometry_input = PrimitiveType::from_gl(input as GLenum);
+            }
+        }
+
+        reflection
+    }
+
+    pub fn get_uniform(&self, name: &str) -> Option<&ActiveUniform> {
+        self.uniforms.iter().find(|u| u.name == name)
+    }
+
+    pub fn get_attribute(&self, name: &str) -> Option<&ActiveAttribute> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+
+    pub fn get_uniform_block(&self, name: &str) -> Option<&ActiveUniformBlock> {
+        self.uniform_blocks.iter().find(|b| b.name == name)
+    }
+
+    /// Checks a vertex layout of (location, component count) pairs against the program's attributes.
+    /// Fails if an attribute the shader reads is missing from the layout, or is given the wrong
+    /// number of components. Layout entries the shader doesn't use are allowed.
+    pub fn validate_layout(&self, layout: &[(u32, u32)]) -> Result<()> {
+        for attr in &self.attributes {
+            let (columns, components) = attribute_shape(attr.gl_type);
+            let columns = columns * attr.size.max(1) as u32;
+
+            for column in 0..columns {
+                let location = attr.location as u32 + column;
+                let Some((_, found)) = layout.iter().find(|(loc, _)| *loc == location) else {
+                    return Err(ShaderError::UnboundAttribute { name: attr.name.clone(), location }.into());
+                };
+                if *found != components {
+                    return Err(ShaderError::AttributeSizeMismatch { location, expected: components, found: *found }.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the program's attributes against (name, location) pairs a vertex format provides.
+    /// Fails if a named attribute is declared at another location, or if an attribute with a
+    /// standard name (see semantics) sits at a location the format gives a different name, which
+    /// usually means the layout and GLSL disagree about what is where. Other attributes with names
+    /// the format doesn't know are allowed.
+    pub fn validate_semantics(&self, semantics: &[(impl AsRef<str>, u32)]) -> Result<()> {
+        for attr in &self.attributes {
+            let location = attr.location as u32;
+            if let Some((_, expected)) = semantics.iter().find(|(name, _)| name.as_ref() == attr.name) {
+                if *expected != location {
+                    return Err(ShaderError::SemanticMismatch { name: attr.name.clone(), expected: *expected, found: location }.into());
+                }
+            } else if ATTRIBUTE_NAMES.contains(&attr.name.as_str())
+                && let Some((expected, _)) = semantics.iter().find(|(_, loc)| *loc == location) {
+                return Err(ShaderError::SemanticConflict { name: attr.name.clone(), location, expected: expected.as_ref().to_string() }.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the program's geometry stage, if it has one, takes the primitives a mesh is drawn with
+    pub fn validate_primitive(&self, primitive: PrimitiveType) -> Result<()> {
+        match self.geometry_input {
+            Some(input) if input != primitive => Err(ShaderError::PrimitiveMismatch { expected: input, found: primitive }.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn is_sampler_type(gl_type: GLenum) -> bool {
+    matches!(gl_type,
+        gl::SAMPLER_1D | gl::SAMPLER_2D | gl::SAMPLER_3D | gl::SAMPLER_CUBE
+        | gl::SAMPLER_1D_SHADOW | gl::SAMPLER_2D_SHADOW | gl::SAMPLER_CUBE_SHADOW
+        | gl::SAMPLER_1D_ARRAY | gl::SAMPLER_2D_ARRAY | gl::SAMPLER_1D_ARRAY_SHADOW | gl::SAMPLER_2D_ARRAY_SHADOW
+        | gl::SAMPLER_2D_MULTISAMPLE | gl::SAMPLER_2D_MULTISAMPLE_ARRAY | gl::SAMPLER_CUBE_MAP_ARRAY
+        | gl::SAMPLER_BUFFER | gl::SAMPLER_2D_RECT | gl::SAMPLER_2D_RECT_SHADOW
+        | gl::INT_SAMPLER_1D | gl::INT_SAMPLER_2D | gl::INT_SAMPLER_3D | gl::INT_SAMPLER_CUBE
+        | gl::INT_SAMPLER_1D_ARRAY | gl::INT_SAMPLER_2D_ARRAY | gl::INT_SAMPLER_BUFFER
+        | gl::UNSIGNED_INT_SAMPLER_1D | gl::UNSIGNED_INT_SAMPLER_2D | gl::UNSIGNED_INT_SAMPLER_3D
+        | gl::UNSIGNED_INT_SAMPLER_CUBE | gl::UNSIGNED_INT_SAMPLER_1D_ARRAY
+        | gl::UNSIGNED_INT_SAMPLER_2D_ARRAY | gl::UNSIGNED_INT_SAMPLER_BUFFER
+    )
+}
+
+/// # Returns
+/// (attribute locations used, components per location)
+fn attribute_shape(gl_type: GLenum) -> (u32, u32) {
+    match gl_type {
+        gl::FLOAT_VEC2 | gl::INT_VEC2 | gl::UNSIGNED_INT_VEC2 | gl::DOUBLE_VEC2 => (1, 2),
+        gl::FLOAT_VEC3 | gl::INT_VEC3 | gl::UNSIGNED_INT_VEC3 | gl::DOUBLE_VEC3 => (1, 3),
+        gl::FLOAT_VEC4 | gl::INT_VEC4 | gl::UNSIGNED_INT_VEC4 | gl::DOUBLE_VEC4 => (1, 4),
+        gl::FLOAT_MAT2 => (2, 2),
+        gl::FLOAT_MAT2x3 => (2, 3),
+        gl::FLOAT_MAT2x4 => (2, 4),
+        gl::FLOAT_MAT3 => (3, 3),
+        gl::FLOAT_MAT3x2 => (3, 2),
+        gl::FLOAT_MAT3x4 => (3, 4),
+        gl::FLOAT_MAT4 => (4, 4),
+        gl::FLOAT_MAT4x2 => (4, 2),
+        gl::FLOAT_MAT4x3 => (4, 3),
+        _ => (1, 1),
+    }
+}
+
+/// Compiles a single shader stage, returning the gl shader id.
+/// the shader object is deleted if compilation fails.
+/// The context's capability defines are injected first, see with_capability_defines.
+pub fn compile_shader(stage: ShaderStage, source: &str) -> Result<GLuint> {
+    let src = CString::new(with_capability_defines(source)).map_err(|_| ShaderError::InvalidSource)?;
+    unsafe {
+        let shader = gl::CreateShader(stage.to_gl());
+        gl::ShaderSource(shader, 1, &src.as_ptr(), ptr::null());
+        gl::CompileShader(shader);
+        check_compile_status(shader, stage)?;
+        Ok(shader)
+    }
+}
+
+/// Loads glSpecializeShader, which SPIR-V shaders need. Call this once after gl::load_with, with the
+/// same loader function.
+pub fn load_spirv_support(mut loader: impl FnMut(&'static str) -> *const c_void) {
+    SPECIALIZE_SHADER.get_or_init(|| {
+        let mut f = loader("glSpecializeShader");
+        if f.is_null() {
+            f = loader("glSpecializeShaderARB");
+        }
+        if f.is_null() {
+            None
+        } else {
+            Some(unsafe { std::mem::transmute::<*const c_void, SpecializeShaderFn>(f) })
+        }
+    });
+}
+
+/// Whether SPIR-V shaders can be loaded in the current context.
+/// Always false if load_spirv_support hasn't been called.
+pub fn spirv_supported() -> bool {
+    matches!(SPECIALIZE_SHADER.get(), Some(Some(_))) && has_extension("GL_ARB_gl_spirv")
+}
+
+/// Creates a shader stage from a SPIR-V binary, returning the gl shader id.
+/// Does not fall back to GLSL, see compile_program_spirv for that.
+pub fn load_spirv_shader(stage: ShaderStage, binary: &[u8], entry_point: &str, specialization: &[(u32, u32)]) -> Result<GLuint> {
+    let Some(Some(specialize)) = SPECIALIZE_SHADER.get() else {
+        return Err(ShaderError::SpirvUnsupported.into());
+    };
+    if binary.len() < 4 || !binary.len().is_multiple_of(4) {
+        return Err(ShaderError::InvalidSpirv.into());
+    }
+    let magic = u32::from_le_bytes([binary[0], binary[1], binary[2], binary[3]]);
+    if magic != SPIRV_MAGIC && magic.swap_bytes() != SPIRV_MAGIC {
+        return Err(ShaderError::InvalidSpirv.into());
+    }
+
+    let entry_point = CString::new(entry_point).map_err(|_| ShaderError::InvalidSource)?;
+    let (indices, values): (Vec<GLuint>, Vec<GLuint>) = specialization.iter().copied().unzip();
+
+    unsafe {
+        let shader = gl::CreateShader(stage.to_gl());
+        gl::ShaderBinary(1, &shader, SHADER_BINARY_FORMAT_SPIR_V, binary.as_ptr() as *const c_void, binary.len() as GLint);
+        specialize(shader, entry_point.as_ptr(), indices.len() as GLuint, indices.as_ptr(), values.as_ptr());
+        check_compile_status(shader, stage)?;
+        Ok(shader)
+    }
+}
+
+/// Builds a vertex + fragment program from SPIR-V binaries. If the context can't load SPIR-V, the
+/// GLSL fallbacks are compiled instead.
+///
+/// # Returns
+/// the gl program id
+pub fn compile_program_spirv(vertex: &SpirvSource, fragment: &SpirvSource) -> Result<GLuint> {
+    if !spirv_supported() {
+        return match (vertex.fallback_glsl, fragment.fallback_glsl) {
+            (Some(vs), Some(fs)) => compile_program(vs, fs),
+            _ => Err(ShaderError::SpirvUnsupported.into()),
+        };
+    }
+
+    let vs = load_spirv_shader(ShaderStage::Vertex, vertex.binary, vertex.entry_point, vertex.specialization)?;
+    let fs = match load_spirv_shader(ShaderStage::Fragment, fragment.binary, fragment.entry_point, fragment.specialization) {
+        Ok(fs) => fs,
+        Err(e) => {
+            unsafe { gl::DeleteShader(vs) };
+            return Err(e);
+        }
+    };
+
+    let program = link_program(&[vs, fs]);
+
+    unsafe {
+        gl::DeleteShader(vs);
+        gl::DeleteShader(fs);
+    }
+
+    program
+}
+
+/// deletes the shader if it failed to compile
+unsafe fn check_compile_status(shader: GLuint, stage: ShaderStage) -> Result<()> {
+    unsafe {
+        let mut status = 0;
+        gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut status);
+        if status == gl::FALSE as GLint {
+            let mut len = 0;
+            gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
+            let mut log = vec![0u8; len.max(1) as usize];
+            gl::GetShaderInfoLog(shader, len, ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+            gl::DeleteShader(shader);
+            return Err(ShaderError::Compile {
+                stage: stage.name(),
+                log: String::from_utf8_lossy(&log).trim_end_matches('\0').to_string(),
+            }.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Links already compiled shaders into a program.
+/// The shaders stay attached, so reflection can tell which stages the program has, and are freed
+/// with the program once deleted.
+pub fn link_program(shaders: &[GLuint]) -> Result<GLuint> {
+    let program = unsafe { gl::CreateProgram() };
+    if let Err(e) = relink_program(program, shaders) {
+        unsafe { gl::DeleteProgram(program) };
+        return Err(e);
+    }
+    Ok(program)
+}
+
+/// Links already compiled shaders into an existing program, replacing its executable.
+/// Uniform locations can change when relinking, so any cached uniforms for the program must be
+/// invalidated with GlState::invalidate_uniforms afterwards.
+/// The shaders linked before are detached, the new ones stay attached, see link_program.
+pub fn relink_program(program: GLuint, shaders: &[GLuint]) -> Result<()> {
+    unsafe {
+        let mut count = 0;
+        gl::GetProgramiv(program, gl::ATTACHED_SHADERS, &mut count);
+        let mut attached = vec![0; count.max(0) as usize];
+        gl::GetAttachedShaders(program, count, ptr::null_mut(), attached.as_mut_ptr());
+        for shader in attached {
+            gl::DetachShader(program, shader);
+        }
+        for shader in shaders {
+            gl::AttachShader(program, *shader);
+        }
+        gl::LinkProgram(program);
+
+        let mut status = 0;
+        gl::GetProgramiv(program, gl::LINK_STATUS, &mut status);
+        if status == gl::FALSE as GLint {
+            let mut len = 0;
+            gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut len);
+            let mut log = vec![0u8; len.max(1) as usize];
+            gl::GetProgramInfoLog(program, len, ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+            return Err(ShaderError::Link(String::from_utf8_lossy(&log).trim_end_matches('\0').to_string()).into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Compiles and links a vertex + fragment shader pair.
+///
+/// # Returns
+/// the gl program id
+pub fn compile_program(vertex: &str, fragment: &str) -> Result<GLuint> {
+    let (vs, fs) = compile_pair(vertex, fragment)?;
+
+    let program = link_program(&[vs, fs]);
+
+    unsafe {
+        gl::DeleteShader(vs);
+        gl::DeleteShader(fs);
+    }
+
+    program
+}
+
+/// Compiles and links a vertex + geometry + fragment program.
+///
+/// # Returns
+/// the gl program id
+pub fn compile_program_with_geometry(vertex: &str, geometry: &str, fragment: &str) -> Result<GLuint> {
+    compile_program_stages(&[
+        (ShaderStage::Vertex, vertex),
+        (ShaderStage::Geometry, geometry),
+        (ShaderStage::Fragment, fragment),
+    ])
+}
+
+/// Compiles and links a program with tessellation stages, to be drawn as patches
+/// (see VertexRenderer::set_patch_vertices). The control stage is optional, without it the
+/// tessellation levels come from glPatchParameterfv.
+///
+/// # Returns
+/// the gl program id
+pub fn compile_program_with_tessellation(vertex: &str, control: Option<&str>, evaluation: &str, fragment: &str) -> Result<GLuint> {
+    let mut stages = vec![(ShaderStage::Vertex, vertex)];
+    if let Some(control) = control {
+        stages.push((ShaderStage::TessControl, control));
+    }
+    stages.push((ShaderStage::TessEvaluation, evaluation));
+    stages.push((ShaderStage::Fragment, fragment));
+    compile_program_stages(&stages)
+}
+
+/// Compiles every (stage, source) pair and links them into a program.
+///
+/// # Returns
+/// the gl program id
+pub fn compile_program_stages(stages: &[(ShaderStage, &str)]) -> Result<GLuint> {
+    let shaders = compile_stages(stages)?;
+
+    let program = link_program(&shaders);
+
+    unsafe {
+        for shader in shaders {
+            gl::DeleteShader(shader);
+        }
+    }
+
+    program
+}
+
+/// Compiles every stage, deleting the already compiled shaders if one fails
+pub(crate) fn compile_stages(stages: &[(ShaderStage, &str)]) -> Result<Vec<GLuint>> {
+    let mut shaders = Vec::with_capacity(stages.len());
+    for (stage, source) in stages {
+        match compile_shader(*stage, source) {
+            Ok(shader) => shaders.push(shader),
+            Err(e) => {
+                unsafe {
+                    for shader in shaders {
+                        gl::DeleteShader(shader);
+                    }
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(shaders)
+}
+
+fn compile_pair(vertex: &str, fragment: &str) -> Result<(GLuint, GLuint)> {
+    let vs = compile_shader(ShaderStage::Vertex, vertex)?;
+    match compile_shader(ShaderStage::Fragment, fragment) {
+        Ok(fs) => Ok((vs, fs)),
+        Err(e) => {
+            unsafe { gl::DeleteShader(vs) };
+            Err(e)
+        }
+    }
+}
+
+/// Assigns a program's uniform block to a binding point, so it reads from whatever
+/// UniformBuffer is bound there
+pub fn bind_uniform_block(program: GLuint, name: &str, binding: u32) -> Result<()> {
+    let cstr = CString::new(name).map_err(|_| ShaderError::MissingUniformBlock(name.to_string()))?;
+    unsafe {
+        let index = gl::GetUniformBlockIndex(program, cstr.as_ptr());
+        if index == gl::INVALID_INDEX {
+            return Err(ShaderError::MissingUniformBlock(name.to_string()).into());
+        }
+        gl::UniformBlockBinding(program, index, binding);
+    }
+    Ok(())
+}
+
+/// Expands `#include` directives in both sources using `includes`, then compiles and links them.
+///
+/// # Returns
+/// the gl program id
+pub fn compile_program_with_includes(vertex: &str, fragment: &str, includes: &ShaderIncludes) -> Result<GLuint> {
+    let vertex = includes.preprocess(vertex)?;
+    let fragment = includes.preprocess(fragment)?;
+    compile_program(&vertex, &fragment)
+}
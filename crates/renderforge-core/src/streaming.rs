@@ -0,0 +1,253 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use gl::types::GLuint;
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::data::GlStateManager;
+use crate::registry::{Registry, Resource, ResourceIdentifier};
+use crate::texture::{MagFilter, MinFilter, PboUpload, Texture, TextureWrap, WrapMode};
+
+/// A texture registered by TextureStreamer::request, which shows the streamer's placeholder until
+/// the real texture has been decoded and uploaded
+#[derive(Debug)]
+pub struct StreamedTexture {
+    placeholder: GLuint,
+    state: StreamState,
+}
+
+#[derive(Debug)]
+pub enum StreamState {
+    /// decoding or uploading
+    Pending,
+    Ready(Texture),
+    /// decoding failed, the placeholder stays in use
+    Failed(String),
+}
+
+/// Identifies a streamed texture in the Registry
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StreamHandle {
+    id: ResourceIdentifier,
+}
+
+#[derive(Debug)]
+struct StreamJob {
+    name: String,
+    path: PathBuf,
+}
+
+#[derive(Debug)]
+struct StreamRequest {
+    name: String,
+    min_filter: MinFilter,
+    mag_filter: MagFilter,
+    texture_wrap: TextureWrap,
+}
+
+/// Loads textures in the background: images are decoded on worker threads, then uploaded on the
+/// gl thread a budgeted number of bytes per frame (see PboUpload). Requested textures are
+/// registered right away as Resource::StreamedTexture, showing a placeholder until they're ready.
+#[derive(Debug)]
+pub struct TextureStreamer {
+    jobs: Option<Sender<StreamJob>>,
+    decoded: Receiver<(String, Result<DynamicImage, String>)>,
+    workers: Vec<JoinHandle<()>>,
+    requests: Vec<StreamRequest>,
+    uploads: VecDeque<(String, PboUpload)>,
+    placeholder: Texture,
+    budget: usize,
+}
+
+impl StreamedTexture {
+    /// # Returns
+    /// the texture to draw with, the placeholder until the streamed texture is ready
+    pub fn get_id(&self) -> GLuint {
+        match &self.state {
+            StreamState::Ready(texture) => texture.get_id(),
+            _ => self.placeholder,
+        }
+    }
+
+    pub fn get_state(&self) -> &StreamState {
+        &self.state
+    }
+
+    pub fn is_ready(&self) -> bool {
+        matches!(self.state, StreamState::Ready(..))
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        if let StreamState::Ready(texture) = &mut self.state {
+            texture.destroy(gl_state);
+        }
+    }
+}
+
+impl StreamHandle {
+    pub fn get_id(&self) -> &ResourceIdentifier {
+        &self.id
+    }
+
+    pub fn get<'a>(&self, registry: &'a Registry) -> Option<&'a StreamedTexture> {
+        match registry.get(&self.id) {
+            Some(Resource::StreamedTexture(texture)) => Some(texture),
+            _ => None,
+        }
+    }
+
+    /// Whether the texture finished streaming, false if it is pending, failed or was removed
+    pub fn is_ready(&self, registry: &Registry) -> bool {
+        self.get(registry).is_some_and(StreamedTexture::is_ready)
+    }
+
+    /// # Returns
+    /// the texture to draw with, the placeholder while pending
+    pub fn get_texture_id(&self, registry: &Registry) -> Option<GLuint> {
+        self.get(registry).map(StreamedTexture::get_id)
+    }
+}
+
+impl TextureStreamer {
+    /// Starts `workers` decoding threads. At most `budget` bytes of pixels are uploaded per update.
+    pub fn new(workers: usize, budget: usize) -> Self {
+        let (job_sender, job_receiver) = channel::<StreamJob>();
+        let (decoded_sender, decoded) = channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let workers = (0..workers.max(1))
+            .map(|_| {
+                let jobs = Arc::clone(&job_receiver);
+                let decoded = decoded_sender.clone();
+                thread::spawn(move || {
+                    loop {
+                        // the lock is only held while waiting for a job, not while decoding
+                        let job = jobs.lock().unwrap().recv();
+                        let Ok(job) = job else {
+                            break;
+                        };
+                        let image = image::open(&job.path).map_err(|e| e.to_string());
+                        if decoded.send((job.name, image)).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        // magenta and black checkers, so missing textures stand out
+        let placeholder = RgbaImage::from_fn(8, 8, |x, y| {
+            if (x / 4 + y / 4).is_multiple_of(2) { Rgba([255, 0, 255, 255]) } else { Rgba([0, 0, 0, 255]) }
+        });
+        let placeholder = Texture::from_image(
+            &DynamicImage::ImageRgba8(placeholder),
+            MinFilter::Nearest,
+            MagFilter::Nearest,
+            TextureWrap::new(WrapMode::Repeat, WrapMode::Repeat),
+        );
+
+        Self {
+            jobs: Some(job_sender),
+            decoded,
+            workers,
+            requests: Vec::new(),
+            uploads: VecDeque::new(),
+            placeholder,
+            budget,
+        }
+    }
+
+    pub fn get_placeholder(&self) -> &Texture {
+        &self.placeholder
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+    }
+
+    /// # Returns
+    /// how many textures are still decoding or uploading
+    pub fn get_pending_count(&self) -> usize {
+        self.requests.len() + self.uploads.len()
+    }
+
+    /// Queues `path` for decoding and registers it as `name`, showing the placeholder until ready.
+    /// Replaces a streamed texture already registered under `name`, which must have been removed and
+    /// destroyed first if it was ready.
+    pub fn request(&mut self, registry: &mut Registry, name: impl ToString, path: impl Into<PathBuf>, min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> StreamHandle {
+        let name = name.to_string();
+        registry.add(&name, Resource::StreamedTexture(StreamedTexture {
+            placeholder: self.placeholder.get_id(),
+            state: StreamState::Pending,
+        }));
+        self.requests.retain(|r| r.name != name);
+        self.requests.push(StreamRequest { name: name.clone(), min_filter, mag_filter, texture_wrap });
+        if let Some(jobs) = &self.jobs {
+            let _ = jobs.send(StreamJob { name: name.clone(), path: path.into() });
+        }
+        StreamHandle { id: ResourceIdentifier::StreamedTexture(name) }
+    }
+
+    /// Starts uploads for newly decoded images, uploads up to the budget, and swaps finished
+    /// textures into the registry. Call once per frame on the gl thread.
+    pub fn update(&mut self, gl_state: &mut GlStateManager, registry: &mut Registry) {
+        while let Ok((name, image)) = self.decoded.try_recv() {
+            let Some(index) = self.requests.iter().position(|r| r.name == name) else {
+                continue;
+            };
+            let request = self.requests.remove(index);
+            match image {
+                Ok(image) => {
+                    let upload = PboUpload::new(gl_state, &image, request.min_filter, request.mag_filter, request.texture_wrap);
+                    self.uploads.push_back((name, upload));
+                }
+                Err(error) => {
+                    if let Some(Resource::StreamedTexture(texture)) = registry.get_mut(&ResourceIdentifier::StreamedTexture(name)) {
+                        texture.state = StreamState::Failed(error);
+                    }
+                }
+            }
+        }
+
+        // oldest first, so textures finish one after another instead of all at the end
+        let mut budget = self.budget;
+        for (_, upload) in &mut self.uploads {
+            if budget == 0 {
+                break;
+            }
+            let remaining = upload.get_remaining_bytes();
+            upload.upload_step(gl_state, budget);
+            budget = budget.saturating_sub(remaining - upload.get_remaining_bytes());
+        }
+
+        let mut still_uploading = VecDeque::new();
+        for (name, upload) in self.uploads.drain(..) {
+            match upload.try_finish() {
+                Ok(mut ready) => match registry.get_mut(&ResourceIdentifier::StreamedTexture(name)) {
+                    Some(Resource::StreamedTexture(texture)) => texture.state = StreamState::Ready(ready),
+                    // removed while streaming
+                    _ => ready.destroy(gl_state),
+                },
+                Err(upload) => still_uploading.push_back((name, upload)),
+            }
+        }
+        self.uploads = still_uploading;
+    }
+
+    /// Stops the workers and deletes the placeholder and unfinished uploads. Streamed textures in the
+    /// registry keep working once ready, but show a deleted placeholder otherwise.
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        for (_, mut upload) in self.uploads.drain(..) {
+            upload.destroy(gl_state);
+        }
+        self.requests.clear();
+        self.placeholder.destroy(gl_state);
+    }
+}
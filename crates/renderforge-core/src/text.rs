@@ -0,0 +1,292 @@
+//! Text layout with glyphs from a GlyphAtlas, along straight lines or curved paths. Coordinates
+//! are in pixels with y pointing down, like sprites.
+
+use std::f32::consts::FRAC_PI_2;
+
+use anyhow::Result;
+use glam::Vec2;
+
+use crate::data::{Color, GlStateManager};
+use crate::glyph_atlas::{Glyph, GlyphAtlas, GlyphKey};
+use crate::renderer::{Sprite, SpriteBatch};
+
+/// Curve text is laid out along, the baseline runs on the path and glyphs stand on its left side
+/// (above it for a path running left to right)
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextPath {
+    /// a straight baseline without an end
+    Line { start: Vec2, direction: Vec2 },
+    /// a circular arc starting at `start_angle` (radians, 0 pointing right), running `sweep`
+    /// radians clockwise on screen, or counterclockwise when negative. Text on the outside of a
+    /// circle runs clockwise, text on the inside counterclockwise.
+    Arc { center: Vec2, radius: f32, start_angle: f32, sweep: f32 },
+    /// a Catmull-Rom spline through the points
+    Spline(Vec<Vec2>),
+}
+
+/// How text is placed along a path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathTextStyle {
+    /// the application's id for the font, see GlyphKey. Characters the font doesn't have come
+    /// from its fallbacks, see GlyphAtlas::set_fallbacks.
+    pub font: u32,
+    /// pixel size of the glyphs
+    pub size: u32,
+    /// extra space after every glyph
+    pub letter_spacing: f32,
+    /// distance along the path where the text starts
+    pub offset: f32,
+    /// spreads glyphs on the inside of curves and tightens them on the outside, so the spacing
+    /// looks even at the middle of the glyphs rather than at the baseline
+    pub compensate_spacing: bool,
+    pub color: Color,
+}
+
+/// A glyph placed by layout_on_path
+#[derive(Debug, Clone, Copy)]
+pub struct PlacedGlyph {
+    /// the glyph's key, with the font it was resolved to
+    pub key: GlyphKey,
+    pub glyph: Glyph,
+    /// center of the glyph's bitmap
+    pub center: Vec2,
+    /// clockwise rotation in radians, the direction of the path at the glyph
+    pub rotation: f32,
+}
+
+/// Segments each spline span is flattened into
+const SPLINE_SUBDIVISIONS: usize = 16;
+/// Distance over which the curvature of a spline is measured
+const CURVATURE_STEP: f32 = 2.;
+/// Height above the baseline that spacing compensation keeps even, relative to the pixel size,
+/// about the middle of lowercase letters
+const COMPENSATION_HEIGHT: f32 = 0.33;
+
+impl PathTextStyle {
+    pub fn new(font: u32, size: u32) -> Self {
+        Self {
+            font,
+            size,
+            letter_spacing: 0.,
+            offset: 0.,
+            compensate_spacing: true,
+            color: Color::rgb(1., 1., 1.),
+        }
+    }
+
+    pub fn with_letter_spacing(mut self, letter_spacing: f32) -> Self {
+        self.letter_spacing = letter_spacing;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: f32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_spacing_compensation(mut self, enabled: bool) -> Self {
+        self.compensate_spacing = enabled;
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+impl PlacedGlyph {
+    /// # Returns
+    /// a rotated sprite drawing the glyph from the layer of `atlas` holding it, None for glyphs
+    /// without pixels
+    pub fn to_sprite(&self, atlas: &GlyphAtlas, color: Color) -> Option<Sprite> {
+        let rect = self.glyph.rect?;
+        let (w, h) = rect.get_size();
+        let size = Vec2::new(w as f32, h as f32);
+        Some(Sprite::from_atlas(atlas.get_id(), &rect, self.center - size * 0.5)
+            .with_array_layer(Some(self.glyph.layer))
+            .with_rotation(self.rotation)
+            .with_color(color))
+    }
+}
+
+/// A path flattened for sampling by distance
+enum Sampler {
+    Line { start: Vec2, direction: Vec2 },
+    Arc { center: Vec2, radius: f32, start_angle: f32, sweep: f32 },
+    Polyline { points: Vec<Vec2>, distances: Vec<f32> },
+}
+
+impl Sampler {
+    fn new(path: &TextPath) -> Self {
+        match path {
+            TextPath::Line { start, direction } => Sampler::Line {
+                start: *start,
+                direction: direction.try_normalize().unwrap_or(Vec2::X),
+            },
+            TextPath::Arc { center, radius, start_angle, sweep } => Sampler::Arc {
+                center: *center,
+                radius: radius.max(f32::EPSILON),
+                start_angle: *start_angle,
+                sweep: *sweep,
+            },
+            TextPath::Spline(points) => {
+                let points = flatten_spline(points);
+                let mut distances = Vec::with_capacity(points.len());
+                let mut total = 0.;
+                for (i, point) in points.iter().enumerate() {
+                    if i > 0 {
+                        total += point.distance(points[i - 1]);
+                    }
+                    distances.push(total);
+                }
+                Sampler::Polyline { points, distances }
+            }
+        }
+    }
+
+    fn length(&self) -> f32 {
+        match self {
+            Sampler::Line { .. } => f32::INFINITY,
+            Sampler::Arc { radius, sweep, .. } => radius * sweep.abs(),
+            Sampler::Polyline { distances, .. } => distances.last().copied().unwrap_or(0.),
+        }
+    }
+
+    /// # Returns
+    /// the point at `distance` along the path and the direction of the path there, as a clockwise
+    /// angle from the x axis
+    fn sample(&self, distance: f32) -> (Vec2, f32) {
+        match self {
+            Sampler::Line { start, direction } => (*start + *direction * distance, direction.to_angle()),
+            Sampler::Arc { center, radius, start_angle, sweep } => {
+                let turn = sweep.signum();
+                let angle = start_angle + turn * distance / radius;
+                (*center + Vec2::from_angle(angle) * *radius, angle + turn * FRAC_PI_2)
+            }
+            Sampler::Polyline { points, distances, .. } => {
+                if points.len() < 2 {
+                    return (points.first().copied().unwrap_or(Vec2::ZERO), 0.);
+                }
+                let i = distances.partition_point(|d| *d <= distance).clamp(1, points.len() - 1);
+                let (a, b) = (points[i - 1], points[i]);
+                let span = (distances[i] - distances[i - 1]).max(f32::EPSILON);
+                let t = ((distance - distances[i - 1]) / span).clamp(0., 1.);
+                (a.lerp(b, t), (b - a).to_angle())
+            }
+        }
+    }
+
+    /// # Returns
+    /// how fast the direction turns clockwise per unit of distance, 1 / radius on a clockwise arc
+    fn curvature(&self, distance: f32) -> f32 {
+        match self {
+            Sampler::Line { .. } => 0.,
+            Sampler::Arc { radius, sweep, .. } => sweep.signum() / radius,
+            Sampler::Polyline { .. } => {
+                let (_, before) = self.sample(distance - CURVATURE_STEP);
+                let (_, after) = self.sample(distance + CURVATURE_STEP);
+                let turn = Vec2::from_angle(before).angle_to(Vec2::from_angle(after));
+                turn / (2. * CURVATURE_STEP)
+            }
+        }
+    }
+}
+
+/// Flattens a Catmull-Rom spline through `points` into a polyline
+fn flatten_spline(points: &[Vec2]) -> Vec<Vec2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let mut flat = Vec::with_capacity((points.len() - 1) * SPLINE_SUBDIVISIONS + 1);
+    for i in 0..points.len() - 1 {
+        let p0 = points[i.saturating_sub(1)];
+        let (p1, p2) = (points[i], points[i + 1]);
+        let p3 = points[(i + 2).min(points.len() - 1)];
+        for step in 0..SPLINE_SUBDIVISIONS {
+            let t = step as f32 / SPLINE_SUBDIVISIONS as f32;
+            let (t2, t3) = (t * t, t * t * t);
+            flat.push(0.5 * (2. * p1 + (p2 - p0) * t + (2. * p0 - 5. * p1 + 4. * p2 - p3) * t2 + (3. * p1 - p0 - 3. * p2 + p3) * t3));
+        }
+    }
+    flat.push(points[points.len() - 1]);
+    flat
+}
+
+/// Places the glyphs of `text` along `path`, each rotated to follow the path at its center.
+/// Glyphs that neither the font nor its fallbacks have are skipped, and glyphs past the end of
+/// the path are dropped.
+/// The glyphs' rects are valid for the atlas generation after the call.
+pub fn layout_on_path(atlas: &mut GlyphAtlas, gl_state: &mut GlStateManager, text: &str, path: &TextPath, style: &PathTextStyle) -> Result<Vec<PlacedGlyph>> {
+    let sampler = Sampler::new(path);
+    loop {
+        // packing a new glyph can repack the atlas pages, moving the glyphs placed before it.
+        // Every glyph is cached after the first pass, so the next one leaves the atlas alone.
+        let generation = atlas.get_generation();
+        let placed = place_glyphs(atlas, gl_state, text, &sampler, style)?;
+        if atlas.get_generation() == generation {
+            return Ok(placed);
+        }
+    }
+}
+
+fn place_glyphs(atlas: &mut GlyphAtlas, gl_state: &mut GlStateManager, text: &str, sampler: &Sampler, style: &PathTextStyle) -> Result<Vec<PlacedGlyph>> {
+    let length = sampler.length();
+    let compensation_height = style.size as f32 * COMPENSATION_HEIGHT;
+
+    let mut placed = Vec::with_capacity(text.len());
+    let mut pen = style.offset;
+    for character in text.chars() {
+        let key = GlyphKey {
+            font: style.font,
+            character,
+            size: style.size,
+        };
+        let Some((key, glyph)) = atlas.resolve(gl_state, key)? else {
+            continue;
+        };
+        let metrics = glyph.metrics;
+        let (w, h) = (metrics.size.0 as f32, metrics.size.1 as f32);
+
+        let middle = pen + metrics.bearing.x + w * 0.5;
+        if middle > length {
+            break;
+        }
+        let (point, rotation) = sampler.sample(middle);
+        // glyphs stand on the left of the direction of travel, which is up for text running right
+        let down = Vec2::from_angle(rotation).perp();
+        placed.push(PlacedGlyph {
+            key,
+            glyph,
+            center: point + down * (metrics.bearing.y + h * 0.5),
+            rotation,
+        });
+
+        let mut advance = metrics.advance + style.letter_spacing;
+        if style.compensate_spacing {
+            // the baseline is shorter than the glyph middles on the outside of a curve and longer
+            // on the inside
+            let scale = 1. + sampler.curvature(middle) * compensation_height;
+            advance /= scale.max(0.1);
+        }
+        pen += advance;
+    }
+    Ok(placed)
+}
+
+/// Lays out text along `path` and queues a sprite per visible glyph on `batch`, see layout_on_path
+pub fn draw_text_on_path(
+    batch: &mut SpriteBatch,
+    atlas: &mut GlyphAtlas,
+    gl_state: &mut GlStateManager,
+    text: &str,
+    path: &TextPath,
+    style: &PathTextStyle,
+) -> Result<()> {
+    for glyph in layout_on_path(atlas, gl_state, text, path, style)? {
+        if let Some(sprite) = glyph.to_sprite(atlas, style.color) {
+            batch.draw(sprite);
+        }
+    }
+    Ok(())
+}
@@ -1,9 +1,31 @@
+use std::collections::HashMap;
+use std::sync::atomic::{self, AtomicBool};
+use std::thread::panicking;
+
+use anyhow::Result;
 use gl::types::{GLenum, GLuint};
-use image::{DynamicImage, GenericImageView};
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::atlas::{AtlasRect, AtlasSet, AtlasTextureIdentifier};
+use crate::data::{Color, GlStateManager};
+use crate::errors::TextureError;
 
-use crate::data::Color;
+#[cfg(feature = "bindless-textures")]
+mod bindless;
+mod containers;
+mod convert;
+mod pbo;
+mod sampler;
+#[cfg(feature = "bindless-textures")]
+pub use bindless::{bindless_defines, bindless_supported, load_bindless_support, BindlessTexture, BINDLESS_GLSL};
+pub use containers::{load_texture_container, ContainerFormat, TextureContainer};
+pub use convert::{extract_channel, grayscale_to_rgba, pack_channels, Channel};
+pub use pbo::PboUpload;
+pub use sampler::{Sampler, SamplerDescriptor};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum MinFilter {
     LinearLinear,
     LinearNearest,
@@ -13,13 +35,14 @@ pub enum MinFilter {
     Linear,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum MagFilter {
     Nearest,
     Linear,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum WrapMode {
     Repeat,
     Mirror,
@@ -27,22 +50,146 @@ pub enum WrapMode {
     ClampToBorder,
 }
 
-#[derive(Debug, Clone)]
+/// Block compressed formats for upload_compressed. Not every driver supports every format, see
+/// capabilities::supported_compressed_formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompressedFormat {
+    /// BC1 / DXT1 without alpha
+    Bc1Rgb,
+    /// BC1 / DXT1 with 1 bit alpha
+    Bc1Rgba,
+    /// BC2 / DXT3
+    Bc2,
+    /// BC3 / DXT5
+    Bc3,
+    /// BC4 single channel
+    Bc4,
+    /// BC5 two channel, commonly used for normal maps
+    Bc5,
+    /// BC6H unsigned float HDR
+    Bc6h,
+    Bc7,
+    Bc7Srgb,
+    Etc2Rgb,
+    Etc2RgbA1,
+    Etc2Rgba,
+    EacR11,
+    EacRg11,
+}
+
+/// How upload_image_with_format stores an image's pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    /// 8 bits per channel, RGB or RGBA depending on whether the image has alpha
+    Unorm8,
+    /// 8 bits per channel RGBA with sRGB encoded color, decoded to linear when sampled.
+    /// Use for color textures (albedo, sprites), not for data like normal maps.
+    Srgb8,
+    /// 8 bit single channel from the image's luminance, for glyph sheets and masks.
+    /// Samples as (r, 0, 0, 1) unless swizzled, see upload_image_swizzled.
+    R8,
+    /// half float RGBA, enough range for most HDR images at half the memory of Rgba32F
+    Rgba16F,
+    Rgba32F,
+}
+
+/// Where a channel sampled from a texture comes from, see Texture::set_swizzle and
+/// upload_image_swizzled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Swizzle {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    Zero,
+    One,
+}
+
+/// The kind of texture a texture id refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextureTarget {
+    Texture1D,
+    Texture2D,
+    Texture3D,
+    CubeMap,
+    /// layers of same-sized 2D images, sampled with `texture(sampler2DArray, vec3(uv, layer))`
+    Texture2DArray,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct TextureWrap {
     wrap_s: WrapMode,
     wrap_t: WrapMode,
+    /// only used by 3D textures
+    wrap_r: WrapMode,
     border_color: [f32; 4],
 }
 
+/// Builds a Texture with the Texture2DArray target out of same-sized images and atlas pages
+#[derive(Debug)]
+pub struct TextureArrayBuilder {
+    size: (u32, u32),
+    min_filter: MinFilter,
+    mag_filter: MagFilter,
+    layers: Vec<ArrayLayer>,
+}
 
-/// 
-/// Takes an image::DynamicImage and creates an RGB or RGBA format gl texture and uploads it.
+#[derive(Debug)]
+enum ArrayLayer {
+    Image(DynamicImage),
+    /// an atlas page texture, copied on the gpu and deleted once the array is built
+    Page(GLuint),
+}
+
+/// An owned gl texture along with its size, format and filtering.
+/// Must be destroyed with Texture::destroy before it is dropped.
+#[derive(Debug)]
+pub struct Texture {
+    id: GLuint,
+    target: TextureTarget,
+    size: (u32, u32),
+    /// number of slices of 3D textures or layers of array textures, 1 for other targets
+    depth: u32,
+    /// gl internal format
+    format: GLenum,
+    min_filter: MinFilter,
+    mag_filter: MagFilter,
+    freed: bool,
+}
+
+
+static ASSUME_SRGB_SOURCES: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether images without an explicit format are treated as sRGB encoded, so they are
+/// decoded to linear when sampled and lighting math happens in linear space. Pair this with
+/// GlStateManager::framebuffer_srgb, see Engine::set_assume_srgb_sources which sets both.
+pub fn set_assume_srgb_sources(enabled: bool) {
+    ASSUME_SRGB_SOURCES.store(enabled, atomic::Ordering::Relaxed);
+}
+
+pub fn assumes_srgb_sources() -> bool {
+    ASSUME_SRGB_SOURCES.load(atomic::Ordering::Relaxed)
+}
+
+///
+/// Takes an image::DynamicImage and creates an RGB or RGBA format gl texture and uploads it,
+/// or an sRGB texture if set_assume_srgb_sources is enabled.
 /// if min_filter is one of LinearLinear, LinearNearest, NearestLinear, NearestNearest, then
 /// mipmaps are generated as well.
 ///
 /// # Returns
 /// (gl id, (texture width, texture height))
 pub fn upload_image(img: &DynamicImage, min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> (GLuint, (u32, u32)) {
+    upload_image_with_format(img, ImageFormat::default_for_images(), min_filter, mag_filter, texture_wrap)
+}
+
+/// Like upload_image, but stores the image in `format`. Floating point formats keep the full range
+/// of HDR images (.hdr and .exr files open as 32 bit float images).
+///
+/// # Returns
+/// (gl id, (texture width, texture height))
+pub fn upload_image_with_format(img: &DynamicImage, format: ImageFormat, min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> (GLuint, (u32, u32)) {
     unsafe {
 
         let mut tex_id = 0;
@@ -50,45 +197,973 @@ pub fn upload_image(img: &DynamicImage, min_filter: MinFilter, mag_filter: MagFi
         gl::GenTextures(1, &mut tex_id);
         gl::BindTexture(gl::TEXTURE_2D, tex_id);
 
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, texture_wrap.wrap_s.to_gl() as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, texture_wrap.wrap_t.to_gl() as i32);
+        texture_wrap.apply(gl::TEXTURE_2D);
 
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter.to_gl() as i32);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag_filter.to_gl() as i32);
 
         let size = img.dimensions();
-        let data;
-        let format = if img.has_alpha() {
-            let img = img.to_rgba8();
-            data = img.into_raw();
-            gl::RGBA
-        } else {
-            let img = img.to_rgb8();
-            data = img.into_raw();
-            gl::RGB
-        };
+        match format {
+            ImageFormat::Unorm8 => {
+                let data;
+                let format = if img.has_alpha() {
+                    let img = img.to_rgba8();
+                    data = img.into_raw();
+                    gl::RGBA
+                } else {
+                    let img = img.to_rgb8();
+                    data = img.into_raw();
+                    gl::RGB
+                };
+
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    format as i32,
+                    size.0 as i32,
+                    size.1 as i32,
+                    0,
+                    format,
+                    gl::UNSIGNED_BYTE,
+                    data.as_ptr() as *const _,
+                );
+            }
+            ImageFormat::Srgb8 => {
+                let data = img.to_rgba8().into_raw();
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    gl::SRGB8_ALPHA8 as i32,
+                    size.0 as i32,
+                    size.1 as i32,
+                    0,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    data.as_ptr() as *const _,
+                );
+            }
+            ImageFormat::R8 => {
+                let data = img.to_luma8().into_raw();
+                // rows of single bytes aren't 4 byte aligned
+                gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    gl::R8 as i32,
+                    size.0 as i32,
+                    size.1 as i32,
+                    0,
+                    gl::RED,
+                    gl::UNSIGNED_BYTE,
+                    data.as_ptr() as *const _,
+                );
+                gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            }
+            ImageFormat::Rgba16F | ImageFormat::Rgba32F => {
+                let data = img.to_rgba32f().into_raw();
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    format.to_gl() as i32,
+                    size.0 as i32,
+                    size.1 as i32,
+                    0,
+                    gl::RGBA,
+                    gl::FLOAT,
+                    data.as_ptr() as *const _,
+                );
+            }
+        }
+
+        if !matches!(min_filter, MinFilter::Nearest | MinFilter::Linear) {
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
+
+        (tex_id, size)
+
+    }
+}
+
+/// Like upload_image_with_format, but remaps the channels shaders read, `swizzle[i]` supplying
+/// channel `i`. e.g. an R8 glyph sheet with `Swizzle::WHITE_FROM_RED` samples as white text with
+/// the glyph coverage as alpha.
+///
+/// # Returns
+/// (gl id, (texture width, texture height))
+pub fn upload_image_swizzled(img: &DynamicImage, format: ImageFormat, swizzle: [Swizzle; 4], min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> (GLuint, (u32, u32)) {
+    let (id, size) = upload_image_with_format(img, format, min_filter, mag_filter, texture_wrap);
+    let mask = swizzle.map(|s| s.to_gl() as i32);
+    unsafe {
+        gl::TexParameteriv(gl::TEXTURE_2D, gl::TEXTURE_SWIZZLE_RGBA, mask.as_ptr());
+    }
+    (id, size)
+}
+
+/// Opens a Radiance .hdr or OpenEXR .exr image (or any other format the image crate reads) and
+/// uploads it as a floating point texture
+pub fn load_hdr_texture(path: impl AsRef<std::path::Path>, format: ImageFormat, min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> Result<Texture> {
+    let img = image::open(path)?;
+    Ok(Texture::from_image_with_format(&img, format, min_filter, mag_filter, texture_wrap))
+}
+
+
+/// Uploads an image as a floating point RGB16F texture with linear filtering and clamped edges,
+/// keeping the full range of HDR images (such as radiance .hdr files).
+///
+/// # Returns
+/// (gl id, (texture width, texture height))
+pub fn upload_hdr_image(img: &DynamicImage) -> (GLuint, (u32, u32)) {
+    let size = img.dimensions();
+    let data = img.to_rgb32f().into_raw();
+
+    unsafe {
+        let mut tex_id = 0;
+
+        gl::GenTextures(1, &mut tex_id);
+        gl::BindTexture(gl::TEXTURE_2D, tex_id);
+
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
 
         gl::TexImage2D(
             gl::TEXTURE_2D,
             0,
-            format as i32,
+            gl::RGB16F as i32,
             size.0 as i32,
             size.1 as i32,
             0,
-            format,
+            gl::RGB,
+            gl::FLOAT,
+            data.as_ptr() as *const _,
+        );
+
+        (tex_id, size)
+    }
+}
+
+
+/// Uploads a stack of images of the same size as the depth slices of an RGBA 3D texture, first image
+/// at depth 0. If min_filter is a mipmapped filter, mipmaps are generated, which with
+/// MinFilter::LinearLinear gives trilinear filtering across slices.
+///
+/// # Returns
+/// (gl id, (width, height, depth))
+pub fn upload_image_3d(slices: &[DynamicImage], min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> Result<(GLuint, (u32, u32, u32))> {
+    let Some(first) = slices.first() else {
+        return Err(TextureError::MismatchedSlices.into());
+    };
+    let (width, height) = first.dimensions();
+    if slices.iter().any(|slice| slice.dimensions() != (width, height)) {
+        return Err(TextureError::MismatchedSlices.into());
+    }
+    let depth = slices.len() as u32;
+
+    let mut data = Vec::with_capacity((width * height * depth * 4) as usize);
+    for slice in slices {
+        data.extend_from_slice(&slice.to_rgba8());
+    }
+
+    unsafe {
+        let mut tex_id = 0;
+
+        gl::GenTextures(1, &mut tex_id);
+        gl::BindTexture(gl::TEXTURE_3D, tex_id);
+
+        texture_wrap.apply(gl::TEXTURE_3D);
+
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MIN_FILTER, min_filter.to_gl() as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MAG_FILTER, mag_filter.to_gl() as i32);
+
+        gl::TexImage3D(
+            gl::TEXTURE_3D,
+            0,
+            gl::RGBA8 as i32,
+            width as i32,
+            height as i32,
+            depth as i32,
+            0,
+            gl::RGBA,
             gl::UNSIGNED_BYTE,
             data.as_ptr() as *const _,
         );
 
         if !matches!(min_filter, MinFilter::Nearest | MinFilter::Linear) {
-            gl::GenerateMipmap(gl::TEXTURE_2D);
+            gl::GenerateMipmap(gl::TEXTURE_3D);
         }
 
-        (tex_id, size)
+        Ok((tex_id, (width, height, depth)))
+    }
+}
 
+/// Uploads six square images of the same size as the faces of a cubemap, in +X, -X, +Y, -Y, +Z, -Z order.
+/// Edges are clamped so seams don't bleed between faces, and mipmaps are generated for mipmapped min filters.
+///
+/// # Returns
+/// (gl id, face size)
+pub fn upload_cubemap(faces: &[DynamicImage; 6], min_filter: MinFilter, mag_filter: MagFilter) -> Result<(GLuint, u32)> {
+    let size = faces[0].width();
+    if faces.iter().any(|face| face.dimensions() != (size, size)) {
+        return Err(TextureError::MismatchedCubemapFaces.into());
+    }
+
+    unsafe {
+        let mut tex_id = 0;
+
+        gl::GenTextures(1, &mut tex_id);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, tex_id);
+
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, min_filter.to_gl() as i32);
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, mag_filter.to_gl() as i32);
+
+        for (i, face) in faces.iter().enumerate() {
+            let data = face.to_rgba8().into_raw();
+            gl::TexImage2D(
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as GLenum,
+                0,
+                gl::RGBA as i32,
+                size as i32,
+                size as i32,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                data.as_ptr() as *const _,
+            );
+        }
+
+        if !matches!(min_filter, MinFilter::Nearest | MinFilter::Linear) {
+            gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+        }
+
+        Ok((tex_id, size))
+    }
+}
+
+/// Splits a cross layout image into cubemap faces and uploads them with upload_cubemap.
+/// Takes either a horizontal cross (4x3 faces, -X +Z +X -Z across the middle row) or a
+/// vertical cross (3x4 faces, with -Z below -Y and upside down).
+///
+/// # Returns
+/// (gl id, face size)
+pub fn upload_cubemap_cross(img: &DynamicImage, min_filter: MinFilter, mag_filter: MagFilter) -> Result<(GLuint, u32)> {
+    let (width, height) = img.dimensions();
+    let horizontal = width * 3 == height * 4 && width.is_multiple_of(4);
+    let vertical = width * 4 == height * 3 && width.is_multiple_of(3);
+    if (!horizontal && !vertical) || width == 0 {
+        return Err(TextureError::InvalidCubemapCross { width, height }.into());
+    }
+
+    let size = if horizontal { width / 4 } else { width / 3 };
+    let face = |col: u32, row: u32| img.crop_imm(col * size, row * size, size, size);
+    let faces = if horizontal {
+        [face(2, 1), face(0, 1), face(1, 0), face(1, 2), face(1, 1), face(3, 1)]
+    } else {
+        [face(2, 1), face(0, 1), face(1, 0), face(1, 2), face(1, 1), face(1, 3).rotate180()]
+    };
+    upload_cubemap(&faces, min_filter, mag_filter)
+}
+
+
+/// Uploads pre-compressed texture data, with one entry in `levels` per mip level starting at the full
+/// size. Mipmaps can't be generated for compressed data, so a mipmapped min filter only samples the
+/// given levels.
+///
+/// # Returns
+/// (gl id, (texture width, texture height))
+pub fn upload_compressed(levels: &[&[u8]], size: (u32, u32), format: CompressedFormat, min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> Result<(GLuint, (u32, u32))> {
+    if levels.is_empty() {
+        return Err(TextureError::CompressedSizeMismatch { level: 0, expected: format.level_size(size), found: 0 }.into());
+    }
+    for (level, data) in levels.iter().enumerate() {
+        let expected = format.level_size(mip_size(size, level as u32));
+        if data.len() != expected {
+            return Err(TextureError::CompressedSizeMismatch { level: level as u32, expected, found: data.len() }.into());
+        }
+    }
+
+    unsafe {
+        let mut tex_id = 0;
+
+        gl::GenTextures(1, &mut tex_id);
+        gl::BindTexture(gl::TEXTURE_2D, tex_id);
+
+        texture_wrap.apply(gl::TEXTURE_2D);
+
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter.to_gl() as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag_filter.to_gl() as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, levels.len() as i32 - 1);
+
+        for (level, data) in levels.iter().enumerate() {
+            let (width, height) = mip_size(size, level as u32);
+            gl::CompressedTexImage2D(
+                gl::TEXTURE_2D,
+                level as i32,
+                format.to_gl(),
+                width as i32,
+                height as i32,
+                0,
+                data.len() as i32,
+                data.as_ptr() as *const _,
+            );
+        }
+
+        Ok((tex_id, size))
     }
 }
 
+/// Uploads hand-authored mip levels as a 2D RGBA texture, `levels[0]` being the full size image and
+/// each following level half the size of the previous one (rounded down, at least 1).
+/// Mipmaps are not generated, only the given levels are sampled.
+///
+/// # Returns
+/// (gl id, (texture width, texture height))
+pub fn upload_image_mips(levels: &[DynamicImage], min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> Result<(GLuint, (u32, u32))> {
+    let Some(first) = levels.first() else {
+        return Err(TextureError::MipSizeMismatch { level: 0, expected: (1, 1), found: (0, 0) }.into());
+    };
+    let size = first.dimensions();
+    for (level, img) in levels.iter().enumerate() {
+        let expected = mip_size(size, level as u32);
+        if img.dimensions() != expected {
+            return Err(TextureError::MipSizeMismatch { level: level as u32, expected, found: img.dimensions() }.into());
+        }
+    }
+
+    unsafe {
+        let mut tex_id = 0;
+
+        gl::GenTextures(1, &mut tex_id);
+        gl::BindTexture(gl::TEXTURE_2D, tex_id);
+
+        texture_wrap.apply(gl::TEXTURE_2D);
+
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter.to_gl() as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag_filter.to_gl() as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, levels.len() as i32 - 1);
+
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        for (level, img) in levels.iter().enumerate() {
+            let (width, height) = img.dimensions();
+            let data = img.to_rgba8().into_raw();
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                level as i32,
+                gl::RGBA8 as i32,
+                width as i32,
+                height as i32,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                data.as_ptr() as *const _,
+            );
+        }
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+        Ok((tex_id, size))
+    }
+}
+
+/// size of a mip level, halving down to 1x1
+pub(crate) fn mip_size(size: (u32, u32), level: u32) -> (u32, u32) {
+    (size.0.checked_shr(level).unwrap_or(0).max(1), size.1.checked_shr(level).unwrap_or(0).max(1))
+}
+
+
+/// Uploads a list of colors as a 1D RGBA texture, for use as a palette in indexed palette swapping.
+/// Palette entry `i` is selected by sprite texels whose red channel is `i / 255`.
+pub fn upload_palette(colors: &[Color]) -> GLuint {
+    let mut data = Vec::with_capacity(colors.len() * 4);
+    for color in colors {
+        data.extend_from_slice(&color.to_array());
+    }
+
+    unsafe {
+        let mut tex_id = 0;
+
+        gl::GenTextures(1, &mut tex_id);
+        gl::BindTexture(gl::TEXTURE_1D, tex_id);
+
+        gl::TexParameteri(gl::TEXTURE_1D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_1D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_1D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+
+        gl::TexImage1D(
+            gl::TEXTURE_1D,
+            0,
+            gl::RGBA8 as i32,
+            colors.len() as i32,
+            0,
+            gl::RGBA,
+            gl::FLOAT,
+            data.as_ptr() as *const _,
+        );
+
+        tex_id
+    }
+}
+
+///
+/// Takes a color grading LUT in the common horizontal strip layout (`size` slices of `size`x`size`
+/// laid out left to right, blue increasing per slice) and uploads it as a 3D texture.
+///
+/// # Returns
+/// (gl id, LUT size)
+pub fn upload_lut(img: &DynamicImage) -> Result<(GLuint, u32)> {
+    let (width, height) = img.dimensions();
+    if width != height * height {
+        return Err(TextureError::InvalidLutDimensions { width, height }.into());
+    }
+
+    let size = height;
+    let strip = img.to_rgb8();
+    let mut data = Vec::with_capacity((size * size * size * 3) as usize);
+    for b in 0..size {
+        for g in 0..size {
+            for r in 0..size {
+                data.extend_from_slice(&strip.get_pixel(r + b * size, g).0);
+            }
+        }
+    }
+
+    unsafe {
+        let mut tex_id = 0;
+
+        gl::GenTextures(1, &mut tex_id);
+        gl::BindTexture(gl::TEXTURE_3D, tex_id);
+
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexImage3D(
+            gl::TEXTURE_3D,
+            0,
+            gl::RGB8 as i32,
+            size as i32,
+            size as i32,
+            size as i32,
+            0,
+            gl::RGB,
+            gl::UNSIGNED_BYTE,
+            data.as_ptr() as *const _,
+        );
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+        Ok((tex_id, size))
+    }
+}
+
+
+impl Texture {
+    /// Uploads an image as a 2D texture, see upload_image
+    pub fn from_image(img: &DynamicImage, min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> Self {
+        Self::from_image_with_format(img, ImageFormat::default_for_images(), min_filter, mag_filter, texture_wrap)
+    }
+
+    /// Uploads an image as a 2D texture in `format`, see upload_image_with_format
+    pub fn from_image_with_format(img: &DynamicImage, image_format: ImageFormat, min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> Self {
+        let format = match image_format {
+            ImageFormat::Unorm8 if img.has_alpha() => gl::RGBA,
+            ImageFormat::Unorm8 => gl::RGB,
+            _ => image_format.to_gl(),
+        };
+        let (id, size) = upload_image_with_format(img, image_format, min_filter, mag_filter, texture_wrap);
+        Self {
+            id,
+            target: TextureTarget::Texture2D,
+            size,
+            depth: 1,
+            format,
+            min_filter,
+            mag_filter,
+            freed: false,
+        }
+    }
+
+    /// Uploads an image as a 2D texture with remapped channels, see upload_image_swizzled
+    pub fn from_image_swizzled(img: &DynamicImage, image_format: ImageFormat, swizzle: [Swizzle; 4], min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> Self {
+        let texture = Self::from_image_with_format(img, image_format, min_filter, mag_filter, texture_wrap);
+        let mask = swizzle.map(|s| s.to_gl() as i32);
+        unsafe {
+            gl::TexParameteriv(gl::TEXTURE_2D, gl::TEXTURE_SWIZZLE_RGBA, mask.as_ptr());
+        }
+        texture
+    }
+
+    /// Uploads hand-authored mip levels, see upload_image_mips
+    pub fn from_mips(levels: &[DynamicImage], min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> Result<Self> {
+        let (id, size) = upload_image_mips(levels, min_filter, mag_filter, texture_wrap)?;
+        Ok(Self {
+            id,
+            target: TextureTarget::Texture2D,
+            size,
+            depth: 1,
+            format: gl::RGBA8,
+            min_filter,
+            mag_filter,
+            freed: false,
+        })
+    }
+
+    /// Uploads a stack of images as a 3D texture, see upload_image_3d
+    pub fn from_slices(slices: &[DynamicImage], min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> Result<Self> {
+        let (id, (width, height, depth)) = upload_image_3d(slices, min_filter, mag_filter, texture_wrap)?;
+        Ok(Self {
+            id,
+            target: TextureTarget::Texture3D,
+            size: (width, height),
+            depth,
+            format: gl::RGBA8,
+            min_filter,
+            mag_filter,
+            freed: false,
+        })
+    }
+
+    /// Uploads pre-compressed mip levels, see upload_compressed
+    pub fn from_compressed(levels: &[&[u8]], size: (u32, u32), format: CompressedFormat, min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> Result<Self> {
+        let (id, size) = upload_compressed(levels, size, format, min_filter, mag_filter, texture_wrap)?;
+        Ok(Self {
+            id,
+            target: TextureTarget::Texture2D,
+            size,
+            depth: 1,
+            format: format.to_gl(),
+            min_filter,
+            mag_filter,
+            freed: false,
+        })
+    }
+
+    /// Uploads six faces as a cubemap texture, see upload_cubemap
+    pub fn from_cubemap(faces: &[DynamicImage; 6], min_filter: MinFilter, mag_filter: MagFilter) -> Result<Self> {
+        let (id, size) = upload_cubemap(faces, min_filter, mag_filter)?;
+        Ok(Self::cubemap(id, size, min_filter, mag_filter))
+    }
+
+    /// Uploads a cross layout image as a cubemap texture, see upload_cubemap_cross
+    pub fn from_cubemap_cross(img: &DynamicImage, min_filter: MinFilter, mag_filter: MagFilter) -> Result<Self> {
+        let (id, size) = upload_cubemap_cross(img, min_filter, mag_filter)?;
+        Ok(Self::cubemap(id, size, min_filter, mag_filter))
+    }
+
+    fn cubemap(id: GLuint, size: u32, min_filter: MinFilter, mag_filter: MagFilter) -> Self {
+        Self {
+            id,
+            target: TextureTarget::CubeMap,
+            size: (size, size),
+            depth: 1,
+            format: gl::RGBA,
+            min_filter,
+            mag_filter,
+            freed: false,
+        }
+    }
+
+    /// Takes ownership of an existing texture, reading its size and format back from gl.
+    /// Filters are assumed to be the gl defaults until set with set_filters.
+    pub fn from_raw(id: GLuint, target: TextureTarget) -> Self {
+        // cubemap levels are queried per face
+        let level_target = match target {
+            TextureTarget::CubeMap => gl::TEXTURE_CUBE_MAP_POSITIVE_X,
+            _ => target.to_gl(),
+        };
+        let (mut width, mut height, mut depth, mut format) = (0, 0, 0, 0);
+        unsafe {
+            gl::BindTexture(target.to_gl(), id);
+            gl::GetTexLevelParameteriv(level_target, 0, gl::TEXTURE_WIDTH, &mut width);
+            gl::GetTexLevelParameteriv(level_target, 0, gl::TEXTURE_HEIGHT, &mut height);
+            gl::GetTexLevelParameteriv(level_target, 0, gl::TEXTURE_DEPTH, &mut depth);
+            gl::GetTexLevelParameteriv(level_target, 0, gl::TEXTURE_INTERNAL_FORMAT, &mut format);
+        }
+        Self {
+            id,
+            target,
+            size: (width as u32, height as u32),
+            depth: depth.max(1) as u32,
+            format: format as GLenum,
+            min_filter: MinFilter::NearestLinear,
+            mag_filter: MagFilter::Linear,
+            freed: false,
+        }
+    }
+
+    /// Gives up ownership of the gl texture without deleting it, the inverse of from_raw
+    pub fn into_raw(mut self) -> GLuint {
+        self.freed = true;
+        self.id
+    }
+
+    pub fn get_id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn get_target(&self) -> TextureTarget {
+        self.target
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub fn get_depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// # Returns
+    /// the gl internal format
+    pub fn get_format(&self) -> GLenum {
+        self.format
+    }
+
+    pub fn get_min_filter(&self) -> MinFilter {
+        self.min_filter
+    }
+
+    pub fn get_mag_filter(&self) -> MagFilter {
+        self.mag_filter
+    }
+
+    /// Changes the filtering of the texture. Switching to a mipmapped min filter doesn't generate
+    /// mipmaps, textures uploaded without them must have them generated separately.
+    pub fn set_filters(&mut self, gl_state: &mut GlStateManager, min_filter: MinFilter, mag_filter: MagFilter) {
+        self.bind(gl_state, 0);
+        let target = self.target.to_gl();
+        unsafe {
+            gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, min_filter.to_gl() as i32);
+            gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, mag_filter.to_gl() as i32);
+        }
+        self.min_filter = min_filter;
+        self.mag_filter = mag_filter;
+    }
+
+    /// Limits sampling to mip levels `base..=max`, e.g. to stop atlas textures from reaching
+    /// levels small enough that neighbouring sprites bleed together
+    pub fn set_mip_levels(&mut self, gl_state: &mut GlStateManager, base: u32, max: u32) {
+        self.bind(gl_state, 0);
+        let target = self.target.to_gl();
+        unsafe {
+            gl::TexParameteri(target, gl::TEXTURE_BASE_LEVEL, base as i32);
+            gl::TexParameteri(target, gl::TEXTURE_MAX_LEVEL, max.max(base) as i32);
+        }
+    }
+
+    /// Clamps the level of detail sampling picks between `min_lod` and `max_lod`, which unlike
+    /// set_mip_levels also allows fractional limits
+    pub fn set_lod_range(&mut self, gl_state: &mut GlStateManager, min_lod: f32, max_lod: f32) {
+        self.bind(gl_state, 0);
+        let target = self.target.to_gl();
+        unsafe {
+            gl::TexParameterf(target, gl::TEXTURE_MIN_LOD, min_lod);
+            gl::TexParameterf(target, gl::TEXTURE_MAX_LOD, max_lod);
+        }
+    }
+
+    /// Offsets the level of detail sampling picks, positive values pick smaller (blurrier) mips
+    pub fn set_lod_bias(&mut self, gl_state: &mut GlStateManager, bias: f32) {
+        self.bind(gl_state, 0);
+        unsafe {
+            gl::TexParameterf(self.target.to_gl(), gl::TEXTURE_LOD_BIAS, bias);
+        }
+    }
+
+    /// Changes the color sampled outside the texture by edges using WrapMode::ClampToBorder
+    pub fn set_border_color(&mut self, gl_state: &mut GlStateManager, color: [f32; 4]) {
+        self.bind(gl_state, 0);
+        unsafe {
+            gl::TexParameterfv(self.target.to_gl(), gl::TEXTURE_BORDER_COLOR, color.as_ptr());
+        }
+    }
+
+    /// Changes the wrap modes and border color of the texture
+    pub fn set_wrap(&mut self, gl_state: &mut GlStateManager, texture_wrap: TextureWrap) {
+        self.bind(gl_state, 0);
+        texture_wrap.apply(self.target.to_gl());
+    }
+
+    /// Remaps the channels shaders read from the texture, `swizzle[i]` supplying channel `i`.
+    /// e.g. `[Red; 4]` lets a single channel texture sample as `.rrrr`.
+    pub fn set_swizzle(&mut self, gl_state: &mut GlStateManager, swizzle: [Swizzle; 4]) {
+        self.bind(gl_state, 0);
+        let mask = swizzle.map(|s| s.to_gl() as i32);
+        unsafe {
+            gl::TexParameteriv(self.target.to_gl(), gl::TEXTURE_SWIZZLE_RGBA, mask.as_ptr());
+        }
+    }
+
+    /// Replaces the pixels of a 2D texture starting at (x, y) with `img`, without reallocating the
+    /// texture. Mipmaps are regenerated if the min filter uses them.
+    pub fn update_region(&mut self, gl_state: &mut GlStateManager, x: u32, y: u32, img: &DynamicImage) -> Result<()> {
+        if self.target != TextureTarget::Texture2D {
+            return Err(TextureError::UnsupportedRegionTarget.into());
+        }
+        let (width, height) = img.dimensions();
+        if x + width > self.size.0 || y + height > self.size.1 {
+            return Err(TextureError::RegionOutOfBounds {
+                x, y, width, height,
+                texture_width: self.size.0,
+                texture_height: self.size.1,
+            }.into());
+        }
+
+        self.bind(gl_state, 0);
+        unsafe {
+            if matches!(self.format, gl::RGBA16F | gl::RGBA32F) {
+                let data = img.to_rgba32f().into_raw();
+                gl::TexSubImage2D(gl::TEXTURE_2D, 0, x as i32, y as i32, width as i32, height as i32, gl::RGBA, gl::FLOAT, data.as_ptr() as *const _);
+            } else {
+                let data = img.to_rgba8().into_raw();
+                gl::TexSubImage2D(gl::TEXTURE_2D, 0, x as i32, y as i32, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, data.as_ptr() as *const _);
+            }
+
+            if !matches!(self.min_filter, MinFilter::Nearest | MinFilter::Linear) {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the pixels of `layer` of a 2D array texture starting at (x, y) with `img`, see
+    /// update_region
+    pub fn update_layer_region(&mut self, gl_state: &mut GlStateManager, layer: u32, x: u32, y: u32, img: &DynamicImage) -> Result<()> {
+        if self.target != TextureTarget::Texture2DArray {
+            return Err(TextureError::UnsupportedRegionTarget.into());
+        }
+        if layer >= self.depth {
+            return Err(TextureError::LayerOutOfRange { layer, layers: self.depth }.into());
+        }
+        let (width, height) = img.dimensions();
+        if x + width > self.size.0 || y + height > self.size.1 {
+            return Err(TextureError::RegionOutOfBounds {
+                x, y, width, height,
+                texture_width: self.size.0,
+                texture_height: self.size.1,
+            }.into());
+        }
+
+        self.bind(gl_state, 0);
+        unsafe {
+            let data = img.to_rgba8().into_raw();
+            gl::TexSubImage3D(gl::TEXTURE_2D_ARRAY, 0, x as i32, y as i32, layer as i32, width as i32, height as i32, 1, gl::RGBA, gl::UNSIGNED_BYTE, data.as_ptr() as *const _);
+
+            if !matches!(self.min_filter, MinFilter::Nearest | MinFilter::Linear) {
+                gl::GenerateMipmap(gl::TEXTURE_2D_ARRAY);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads mip level 0 back to the CPU. 1D and 2D textures are read with glGetTexImage, layered
+    /// textures (arrays, 3D textures and cubemaps) return their first layer, see download_layer.
+    pub fn download(&self, gl_state: &mut GlStateManager) -> Result<RgbaImage> {
+        match self.target {
+            TextureTarget::Texture1D | TextureTarget::Texture2D => {
+                let (width, height) = (self.size.0, self.size.1.max(1));
+                let mut data = vec![0u8; (width * height * 4) as usize];
+                self.bind(gl_state, 0);
+                unsafe {
+                    gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+                    gl::GetTexImage(self.target.to_gl(), 0, gl::RGBA, gl::UNSIGNED_BYTE, data.as_mut_ptr() as *mut _);
+                    gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
+                }
+                Ok(RgbaImage::from_raw(width, height, data).unwrap())
+            }
+            _ => self.download_layer(gl_state, 0),
+        }
+    }
+
+    /// Reads one layer of mip level 0 back to the CPU by attaching it to a temporary framebuffer.
+    /// The layer is the array layer, the 3D slice, or the cubemap face (+X, -X, +Y, -Y, +Z, -Z).
+    pub fn download_layer(&self, gl_state: &mut GlStateManager, layer: u32) -> Result<RgbaImage> {
+        let layers = match self.target {
+            TextureTarget::CubeMap => 6,
+            _ => self.depth,
+        };
+        if layer >= layers {
+            return Err(TextureError::LayerOutOfRange { layer, layers }.into());
+        }
+        let (width, height) = (self.size.0, self.size.1.max(1));
+        let mut data = vec![0u8; (width * height * 4) as usize];
+        unsafe {
+            let mut fbo = 0;
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fbo);
+            match self.target {
+                TextureTarget::Texture1D => gl::FramebufferTexture1D(gl::READ_FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_1D, self.id, 0),
+                TextureTarget::Texture2D => gl::FramebufferTexture2D(gl::READ_FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.id, 0),
+                TextureTarget::CubeMap => gl::FramebufferTexture2D(gl::READ_FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_CUBE_MAP_POSITIVE_X + layer, self.id, 0),
+                TextureTarget::Texture3D | TextureTarget::Texture2DArray => gl::FramebufferTextureLayer(gl::READ_FRAMEBUFFER, gl::COLOR_ATTACHMENT0, self.id, 0, layer as i32),
+            }
+            let status = gl::CheckFramebufferStatus(gl::READ_FRAMEBUFFER);
+            if status == gl::FRAMEBUFFER_COMPLETE {
+                gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+                gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+                gl::ReadPixels(0, 0, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, data.as_mut_ptr() as *mut _);
+                gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
+            }
+            // put back the framebuffer the state cache thinks is bound
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, gl_state.get_fbo());
+            gl::DeleteFramebuffers(1, &fbo);
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                return Err(TextureError::UnreadableTexture(status).into());
+            }
+        }
+        Ok(RgbaImage::from_raw(width, height, data).unwrap())
+    }
+
+    /// Binds the texture to a texture unit with the bind call matching its target
+    pub fn bind(&self, gl_state: &mut GlStateManager, slot: u32) {
+        match self.target {
+            TextureTarget::Texture1D => gl_state.bind_texture_1d(slot, self.id),
+            TextureTarget::Texture2D => gl_state.bind_texture(slot, self.id),
+            TextureTarget::Texture3D => gl_state.bind_texture_3d(slot, self.id),
+            TextureTarget::CubeMap => gl_state.bind_texture_cube(slot, self.id),
+            TextureTarget::Texture2DArray => gl_state.bind_texture_array(slot, self.id),
+        }
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        if !self.freed {
+            gl_state.destroy_texture(self.id);
+            self.freed = true;
+        }
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            let mut state = GlStateManager::new();
+            self.destroy(&mut state);
+            panic!("Texture was not destroyed before dropping")
+        }
+    }
+}
+
+impl TextureArrayBuilder {
+    /// Starts an array of `size` layers. Layers are clamped to their edges, and get mipmaps if
+    /// min_filter is a mipmapped filter.
+    pub fn new(size: (u32, u32), min_filter: MinFilter, mag_filter: MagFilter) -> Self {
+        Self {
+            size,
+            min_filter,
+            mag_filter,
+            layers: Vec::new(),
+        }
+    }
+
+    /// # Returns
+    /// the layer index of the image
+    pub fn add_layer(&mut self, img: DynamicImage) -> Result<u32> {
+        let (width, height) = img.dimensions();
+        if (width, height) != self.size {
+            return Err(TextureError::LayerSizeMismatch { width, height }.into());
+        }
+        self.layers.push(ArrayLayer::Image(img));
+        Ok(self.layers.len() as u32 - 1)
+    }
+
+    /// Adds every page of an atlas set as consecutive layers, so overflowed atlases can be bound as
+    /// one texture. The pages' textures are copied into the array and deleted by build.
+    ///
+    /// # Returns
+    /// the layer and rect of every texture in the set
+    pub fn add_atlas_set(&mut self, atlas_set: AtlasSet) -> Result<HashMap<AtlasTextureIdentifier, (u32, AtlasRect)>> {
+        let mut rects = HashMap::new();
+        for atlas in atlas_set.into_atlases() {
+            let (width, height) = atlas.get_size();
+            if (width, height) != self.size {
+                return Err(TextureError::LayerSizeMismatch { width, height }.into());
+            }
+            let layer = self.layers.len() as u32;
+            let (tex_id, position_data) = atlas.into_parts();
+            rects.extend(position_data.into_iter().map(|(id, rect)| (id, (layer, rect))));
+            self.layers.push(ArrayLayer::Page(tex_id));
+        }
+        Ok(rects)
+    }
+
+    pub fn get_layer_count(&self) -> u32 {
+        self.layers.len() as u32
+    }
+
+    pub fn build(self, gl_state: &mut GlStateManager) -> Texture {
+        let (width, height) = self.size;
+        let depth = self.layers.len().max(1) as u32;
+        let mipmapped = !matches!(self.min_filter, MinFilter::Nearest | MinFilter::Linear);
+        let levels = if mipmapped { width.max(height).max(1).ilog2() + 1 } else { 1 };
+
+        let mut tex_id = 0;
+        unsafe {
+            gl::GenTextures(1, &mut tex_id);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, tex_id);
+
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MIN_FILTER, self.min_filter.to_gl() as i32);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MAG_FILTER, self.mag_filter.to_gl() as i32);
+
+            gl::TexStorage3D(gl::TEXTURE_2D_ARRAY, levels as i32, gl::RGBA8, width as i32, height as i32, depth as i32);
+        }
+
+        for (layer, source) in self.layers.into_iter().enumerate() {
+            match source {
+                ArrayLayer::Image(img) => unsafe {
+                    let data = img.to_rgba8().into_raw();
+                    gl::BindTexture(gl::TEXTURE_2D_ARRAY, tex_id);
+                    gl::TexSubImage3D(
+                        gl::TEXTURE_2D_ARRAY,
+                        0,
+                        0,
+                        0,
+                        layer as i32,
+                        width as i32,
+                        height as i32,
+                        1,
+                        gl::RGBA,
+                        gl::UNSIGNED_BYTE,
+                        data.as_ptr() as *const _,
+                    );
+                },
+                ArrayLayer::Page(page) => {
+                    unsafe {
+                        gl::CopyImageSubData(
+                            page, gl::TEXTURE_2D, 0, 0, 0, 0,
+                            tex_id, gl::TEXTURE_2D_ARRAY, 0, 0, 0, layer as i32,
+                            width as i32, height as i32, 1,
+                        );
+                    }
+                    gl_state.destroy_texture(page);
+                }
+            }
+        }
+
+        if mipmapped {
+            unsafe {
+                gl::BindTexture(gl::TEXTURE_2D_ARRAY, tex_id);
+                gl::GenerateMipmap(gl::TEXTURE_2D_ARRAY);
+            }
+        }
+
+        Texture {
+            id: tex_id,
+            target: TextureTarget::Texture2DArray,
+            size: self.size,
+            depth,
+            format: gl::RGBA8,
+            min_filter: self.min_filter,
+            mag_filter: self.mag_filter,
+            freed: false,
+        }
+    }
+}
 
 impl MinFilter {
     pub fn to_gl(&self) -> GLenum {
@@ -112,6 +1187,110 @@ impl MagFilter {
     }
 }
 
+// S3TC isn't core gl, so its formats aren't in the bindings
+const COMPRESSED_RGB_S3TC_DXT1: GLenum = 0x83F0;
+const COMPRESSED_RGBA_S3TC_DXT1: GLenum = 0x83F1;
+const COMPRESSED_RGBA_S3TC_DXT3: GLenum = 0x83F2;
+const COMPRESSED_RGBA_S3TC_DXT5: GLenum = 0x83F3;
+
+impl CompressedFormat {
+    pub const ALL: [CompressedFormat; 14] = [
+        Self::Bc1Rgb, Self::Bc1Rgba, Self::Bc2, Self::Bc3, Self::Bc4, Self::Bc5, Self::Bc6h, Self::Bc7,
+        Self::Bc7Srgb, Self::Etc2Rgb, Self::Etc2RgbA1, Self::Etc2Rgba, Self::EacR11, Self::EacRg11,
+    ];
+
+    pub fn to_gl(&self) -> GLenum {
+        match self {
+            Self::Bc1Rgb => COMPRESSED_RGB_S3TC_DXT1,
+            Self::Bc1Rgba => COMPRESSED_RGBA_S3TC_DXT1,
+            Self::Bc2 => COMPRESSED_RGBA_S3TC_DXT3,
+            Self::Bc3 => COMPRESSED_RGBA_S3TC_DXT5,
+            Self::Bc4 => gl::COMPRESSED_RED_RGTC1,
+            Self::Bc5 => gl::COMPRESSED_RG_RGTC2,
+            Self::Bc6h => gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+            Self::Bc7 => gl::COMPRESSED_RGBA_BPTC_UNORM,
+            Self::Bc7Srgb => gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+            Self::Etc2Rgb => gl::COMPRESSED_RGB8_ETC2,
+            Self::Etc2RgbA1 => gl::COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            Self::Etc2Rgba => gl::COMPRESSED_RGBA8_ETC2_EAC,
+            Self::EacR11 => gl::COMPRESSED_R11_EAC,
+            Self::EacRg11 => gl::COMPRESSED_RG11_EAC,
+        }
+    }
+
+    pub fn from_gl(format: GLenum) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.to_gl() == format)
+    }
+
+    /// bytes per 4x4 block
+    pub fn block_bytes(&self) -> usize {
+        match self {
+            Self::Bc1Rgb | Self::Bc1Rgba | Self::Bc4 | Self::Etc2Rgb | Self::Etc2RgbA1 | Self::EacR11 => 8,
+            _ => 16,
+        }
+    }
+
+    /// # Returns
+    /// the byte size of one compressed image of `size`
+    pub fn level_size(&self, size: (u32, u32)) -> usize {
+        size.0.div_ceil(4) as usize * size.1.div_ceil(4) as usize * self.block_bytes()
+    }
+}
+
+impl ImageFormat {
+    /// The format upload_image and Texture::from_image use, Srgb8 if set_assume_srgb_sources is
+    /// enabled and Unorm8 otherwise
+    pub fn default_for_images() -> Self {
+        if assumes_srgb_sources() { Self::Srgb8 } else { Self::Unorm8 }
+    }
+
+    /// # Returns
+    /// the gl internal format, the sized RGBA8 for Unorm8
+    pub fn to_gl(&self) -> GLenum {
+        match self {
+            Self::Unorm8 => gl::RGBA8,
+            Self::Srgb8 => gl::SRGB8_ALPHA8,
+            Self::R8 => gl::R8,
+            Self::Rgba16F => gl::RGBA16F,
+            Self::Rgba32F => gl::RGBA32F,
+        }
+    }
+}
+
+impl Swizzle {
+    /// channels read as stored
+    pub const IDENTITY: [Swizzle; 4] = [Swizzle::Red, Swizzle::Green, Swizzle::Blue, Swizzle::Alpha];
+    /// every channel reads red, grayscale images as `.rrrr`
+    pub const RRRR: [Swizzle; 4] = [Swizzle::Red; 4];
+    /// white with red as alpha, for single channel glyph and mask textures tinted by vertex color
+    pub const WHITE_FROM_RED: [Swizzle; 4] = [Swizzle::One, Swizzle::One, Swizzle::One, Swizzle::Red];
+    /// red as gray with full alpha, for single channel images shown as grayscale
+    pub const GRAY_FROM_RED: [Swizzle; 4] = [Swizzle::Red, Swizzle::Red, Swizzle::Red, Swizzle::One];
+
+    pub fn to_gl(&self) -> GLenum {
+        match self {
+            Self::Red => gl::RED,
+            Self::Green => gl::GREEN,
+            Self::Blue => gl::BLUE,
+            Self::Alpha => gl::ALPHA,
+            Self::Zero => gl::ZERO,
+            Self::One => gl::ONE,
+        }
+    }
+}
+
+impl TextureTarget {
+    pub fn to_gl(&self) -> GLenum {
+        match self {
+            Self::Texture1D => gl::TEXTURE_1D,
+            Self::Texture2D => gl::TEXTURE_2D,
+            Self::Texture3D => gl::TEXTURE_3D,
+            Self::CubeMap => gl::TEXTURE_CUBE_MAP,
+            Self::Texture2DArray => gl::TEXTURE_2D_ARRAY,
+        }
+    }
+}
+
 impl WrapMode {
     pub fn to_gl(&self) -> GLenum {
         match self {
@@ -128,10 +1307,17 @@ impl TextureWrap {
         Self {
             wrap_s,
             wrap_t,
+            wrap_r: wrap_t,
             border_color: [0., 0., 0., 0.],
         }
     }
 
+    /// Sets the wrap mode along the depth of 3D textures, which defaults to wrap_t
+    pub fn with_wrap_r(mut self, wrap_r: WrapMode) -> Self {
+        self.wrap_r = wrap_r;
+        self
+    }
+
     pub fn with_border_color(mut self, color: [f32; 4]) -> Self {
         self.border_color = color;
         self
@@ -141,5 +1327,15 @@ impl TextureWrap {
         self.border_color = color;
     }
 
+    /// Sets the wrap modes and border color on the texture bound to `target`
+    pub(crate) fn apply(&self, target: GLenum) {
+        unsafe {
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_S, self.wrap_s.to_gl() as i32);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_T, self.wrap_t.to_gl() as i32);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_R, self.wrap_r.to_gl() as i32);
+            gl::TexParameterfv(target, gl::TEXTURE_BORDER_COLOR, self.border_color.as_ptr());
+        }
+    }
+
 }
 
@@ -0,0 +1,150 @@
+use std::os::raw::c_void;
+use std::sync::OnceLock;
+use std::thread::panicking;
+
+use gl::types::{GLint, GLuint, GLuint64};
+
+use crate::capabilities::has_extension;
+use crate::data::{GLUniform, GlStateManager};
+
+/// Add to shaders sampling BindlessTextures, after the `#version` line. With the `BINDLESS` define
+/// (see bindless_defines) every sampler uniform takes a texture handle instead of a slot, without
+/// it the shader compiles as usual.
+pub const BINDLESS_GLSL: &str = r#"#ifdef BINDLESS
+#extension GL_ARB_bindless_texture : require
+layout(bindless_sampler) uniform;
+#endif
+"#;
+
+type GetTextureHandleFn = extern "system" fn(GLuint) -> GLuint64;
+type HandleFn = extern "system" fn(GLuint64);
+type UniformHandleFn = extern "system" fn(GLint, GLuint64);
+
+#[derive(Debug, Clone, Copy)]
+struct BindlessFns {
+    get_texture_handle: GetTextureHandleFn,
+    make_resident: HandleFn,
+    make_non_resident: HandleFn,
+    uniform_handle: UniformHandleFn,
+}
+
+/// ARB_bindless_texture isn't part of the GL 4.5 bindings, so it's loaded by load_bindless_support
+static BINDLESS: OnceLock<Option<BindlessFns>> = OnceLock::new();
+
+/// A texture made resident through ARB_bindless_texture, so shaders can sample it without binding
+/// it to a slot. Falls back to slot binding when the extension is missing.
+#[derive(Debug)]
+pub struct BindlessTexture {
+    texture: GLuint,
+    /// None when falling back to slots
+    handle: Option<GLuint64>,
+    freed: bool,
+}
+
+/// Loads the ARB_bindless_texture functions. Call this once after gl::load_with, with the same
+/// loader function.
+pub fn load_bindless_support(mut loader: impl FnMut(&'static str) -> *const c_void) {
+    BINDLESS.get_or_init(|| {
+        let functions = [
+            loader("glGetTextureHandleARB"),
+            loader("glMakeTextureHandleResidentARB"),
+            loader("glMakeTextureHandleNonResidentARB"),
+            loader("glUniformHandleui64ARB"),
+        ];
+        if functions.iter().any(|f| f.is_null()) {
+            return None;
+        }
+        unsafe {
+            Some(BindlessFns {
+                get_texture_handle: std::mem::transmute::<*const c_void, GetTextureHandleFn>(functions[0]),
+                make_resident: std::mem::transmute::<*const c_void, HandleFn>(functions[1]),
+                make_non_resident: std::mem::transmute::<*const c_void, HandleFn>(functions[2]),
+                uniform_handle: std::mem::transmute::<*const c_void, UniformHandleFn>(functions[3]),
+            })
+        }
+    });
+}
+
+/// Whether textures can be made resident in the current context.
+/// Always false if load_bindless_support hasn't been called.
+pub fn bindless_supported() -> bool {
+    bindless_fns().is_some()
+}
+
+/// # Returns
+/// `["BINDLESS"]` if bindless textures are supported, for inject_defines on shaders using
+/// BINDLESS_GLSL
+pub fn bindless_defines() -> &'static [&'static str] {
+    if bindless_supported() { &["BINDLESS"] } else { &[] }
+}
+
+fn bindless_fns() -> Option<BindlessFns> {
+    static EXTENSION: OnceLock<bool> = OnceLock::new();
+    let fns = (*BINDLESS.get()?)?;
+    EXTENSION.get_or_init(|| has_extension("GL_ARB_bindless_texture")).then_some(fns)
+}
+
+impl BindlessTexture {
+    /// Makes `texture` resident if bindless textures are supported. The texture's parameters can't
+    /// change while it's resident, and it must outlive this handle.
+    pub fn new(texture: GLuint) -> Self {
+        let handle = bindless_fns().map(|fns| {
+            let handle = (fns.get_texture_handle)(texture);
+            (fns.make_resident)(handle);
+            handle
+        });
+        Self {
+            texture,
+            handle,
+            freed: false,
+        }
+    }
+
+    pub fn get_texture(&self) -> GLuint {
+        self.texture
+    }
+
+    /// # Returns
+    /// the resident handle, None when falling back to slot binding
+    pub fn get_handle(&self) -> Option<GLuint64> {
+        self.handle
+    }
+
+    pub fn is_resident(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    /// Points the sampler uniform `name` of the current program at the texture. Resident textures
+    /// set the handle directly, otherwise the texture is bound to `slot`.
+    pub fn bind(&self, gl_state: &mut GlStateManager, name: &str, slot: u32) {
+        match (self.handle, bindless_fns()) {
+            (Some(handle), Some(fns)) => {
+                let uniform = gl_state.get_uniform_handle(name);
+                if uniform.is_active() {
+                    (fns.uniform_handle)(uniform.get_location(), handle);
+                }
+            }
+            _ => {
+                gl_state.bind_texture(slot, self.texture);
+                gl_state.set_uniform(name, GLUniform::I32(slot as i32));
+            }
+        }
+    }
+
+    /// Makes the texture non-resident, the texture itself isn't deleted
+    pub fn destroy(&mut self) {
+        if let (Some(handle), Some(fns)) = (self.handle.take(), bindless_fns()) {
+            (fns.make_non_resident)(handle);
+        }
+        self.freed = true;
+    }
+}
+
+impl Drop for BindlessTexture {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            self.destroy();
+            panic!("Bindless texture was not destroyed before dropping")
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use std::path::Path;
+
+use anyhow::Result;
+use gl::types::GLenum;
+
+use crate::errors::TextureError;
+use crate::texture::{mip_size, CompressedFormat, MagFilter, MinFilter, Texture, TextureTarget, TextureWrap};
+
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+
+/// Pixel formats a TextureContainer can hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerFormat {
+    Compressed(CompressedFormat),
+    Rgba8,
+    /// byte order used by most uncompressed DDS files
+    Bgra8,
+}
+
+/// A texture read from a GPU oriented container file (KTX2 or DDS), with its whole mip chain,
+/// array layers and cubemap faces still in their stored (possibly compressed) form
+#[derive(Debug, Clone)]
+pub struct TextureContainer {
+    pub format: ContainerFormat,
+    pub size: (u32, u32),
+    /// array layers, 1 for non-array textures
+    pub layers: u32,
+    /// 6 for cubemaps, 1 otherwise
+    pub faces: u32,
+    /// `levels[level][layer * faces + face]`, starting at the full size level
+    pub levels: Vec<Vec<Vec<u8>>>,
+}
+
+impl ContainerFormat {
+    /// # Returns
+    /// the byte size of one image of `size`
+    pub fn image_size(&self, size: (u32, u32)) -> usize {
+        match self {
+            Self::Compressed(format) => format.level_size(size),
+            Self::Rgba8 | Self::Bgra8 => size.0 as usize * size.1 as usize * 4,
+        }
+    }
+
+    /// # Returns
+    /// (internal format, pixel format) for uncompressed formats
+    fn to_gl(self) -> (GLenum, GLenum) {
+        match self {
+            Self::Compressed(format) => (format.to_gl(), 0),
+            Self::Rgba8 => (gl::RGBA8, gl::RGBA),
+            Self::Bgra8 => (gl::RGBA8, gl::BGRA),
+        }
+    }
+}
+
+impl TextureContainer {
+    /// Reads a KTX2 or DDS file, telling them apart by their magic bytes
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        if bytes.starts_with(&KTX2_IDENTIFIER) {
+            Self::from_ktx2(&bytes)
+        } else if bytes.starts_with(DDS_MAGIC) {
+            Self::from_dds(&bytes)
+        } else {
+            Err(TextureError::InvalidContainer("not a KTX2 or DDS file".to_string()).into())
+        }
+    }
+
+    /// Parses a KTX2 file. Supercompressed (Basis Universal, zstd) and 3D textures are not supported.
+    pub fn from_ktx2(bytes: &[u8]) -> Result<Self> {
+        if !bytes.starts_with(&KTX2_IDENTIFIER) {
+            return Err(TextureError::InvalidContainer("missing KTX2 identifier".to_string()).into());
+        }
+        let vk_format = read_u32(bytes, 12)?;
+        let width = read_u32(bytes, 20)?;
+        let height = read_u32(bytes, 24)?.max(1);
+        let depth = read_u32(bytes, 28)?;
+        let layers = read_u32(bytes, 32)?.max(1);
+        let faces = read_u32(bytes, 36)?.max(1);
+        let level_count = read_u32(bytes, 40)?.max(1);
+        let supercompression = read_u32(bytes, 44)?;
+
+        if supercompression != 0 {
+            return Err(TextureError::UnsupportedContainerFormat(format!("KTX2 supercompression scheme {supercompression}")).into());
+        }
+        if depth > 1 {
+            return Err(TextureError::UnsupportedContainerFormat("KTX2 3D textures".to_string()).into());
+        }
+        let format = vk_format_to_container(vk_format)
+            .ok_or_else(|| TextureError::UnsupportedContainerFormat(format!("KTX2 vkFormat {vk_format}")))?;
+        check_level_count((width, height), level_count)?;
+        let images = image_count(layers, faces)?;
+
+        let mut levels = Vec::with_capacity(level_count as usize);
+        for level in 0..level_count {
+            let index = 80 + level as usize * 24;
+            let offset = read_u64(bytes, index)?;
+            let length = read_u64(bytes, index + 8)?;
+            let data = offset.checked_add(length)
+                .and_then(|end| bytes.get(usize::try_from(offset).ok()?..usize::try_from(end).ok()?))
+                .ok_or_else(|| TextureError::InvalidContainer(format!("KTX2 level {level} is out of bounds")))?;
+
+            let image_size = checked_image_size(format, mip_size((width, height), level))?;
+            if image_size.checked_mul(images).is_none_or(|needed| data.len() < needed) {
+                return Err(TextureError::InvalidContainer(format!("KTX2 level {level} is too short")).into());
+            }
+            levels.push(data.chunks_exact(image_size).take(images).map(|image| image.to_vec()).collect());
+        }
+
+        Ok(Self {
+            format,
+            size: (width, height),
+            layers,
+            faces,
+            levels,
+        })
+    }
+
+    /// Parses a DDS file, including the DX10 header extension for BC6H/BC7 and texture arrays.
+    /// Volume textures are not supported.
+    pub fn from_dds(bytes: &[u8]) -> Result<Self> {
+        if !bytes.starts_with(DDS_MAGIC) {
+            return Err(TextureError::InvalidContainer("missing DDS magic".to_string()).into());
+        }
+        let height = read_u32(bytes, 12)?;
+        let width = read_u32(bytes, 16)?;
+        let level_count = read_u32(bytes, 28)?.max(1);
+        let pixel_flags = read_u32(bytes, 80)?;
+        let four_cc = bytes.get(84..88).ok_or_else(|| TextureError::InvalidContainer("DDS header is too short".to_string()))?;
+        let bit_count = read_u32(bytes, 88)?;
+        let red_mask = read_u32(bytes, 92)?;
+        let caps2 = read_u32(bytes, 112)?;
+
+        const DDPF_FOURCC: u32 = 0x4;
+        const DDPF_RGB: u32 = 0x40;
+        const DDSCAPS2_CUBEMAP: u32 = 0x200;
+        const DDSCAPS2_VOLUME: u32 = 0x200000;
+        const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+        if caps2 & DDSCAPS2_VOLUME != 0 {
+            return Err(TextureError::UnsupportedContainerFormat("DDS volume textures".to_string()).into());
+        }
+
+        let mut faces = if caps2 & DDSCAPS2_CUBEMAP != 0 { 6 } else { 1 };
+        let mut layers = 1;
+        let mut data_start = 128;
+
+        let format = if pixel_flags & DDPF_FOURCC != 0 && four_cc == b"DX10" {
+            let dxgi_format = read_u32(bytes, 128)?;
+            let misc_flags = read_u32(bytes, 136)?;
+            layers = read_u32(bytes, 140)?.max(1);
+            if misc_flags & DDS_RESOURCE_MISC_TEXTURECUBE != 0 {
+                faces = 6;
+            }
+            data_start = 148;
+            dxgi_format_to_container(dxgi_format)
+                .ok_or_else(|| TextureError::UnsupportedContainerFormat(format!("DXGI format {dxgi_format}")))?
+        } else if pixel_flags & DDPF_FOURCC != 0 {
+            let format = match four_cc {
+                b"DXT1" => CompressedFormat::Bc1Rgba,
+                b"DXT3" => CompressedFormat::Bc2,
+                b"DXT5" => CompressedFormat::Bc3,
+                b"ATI1" | b"BC4U" => CompressedFormat::Bc4,
+                b"ATI2" | b"BC5U" => CompressedFormat::Bc5,
+                _ => return Err(TextureError::UnsupportedContainerFormat(format!("DDS FourCC {}", String::from_utf8_lossy(four_cc))).into()),
+            };
+            ContainerFormat::Compressed(format)
+        } else if pixel_flags & DDPF_RGB != 0 && bit_count == 32 {
+            match red_mask {
+                0x00ff0000 => ContainerFormat::Bgra8,
+                0x000000ff => ContainerFormat::Rgba8,
+                _ => return Err(TextureError::UnsupportedContainerFormat(format!("DDS RGB masks with red mask 0x{red_mask:x}")).into()),
+            }
+        } else {
+            return Err(TextureError::UnsupportedContainerFormat("DDS pixel format".to_string()).into());
+        };
+
+        check_level_count((width, height), level_count)?;
+        let image_count = image_count(layers, faces)?;
+        let level_sizes = (0..level_count)
+            .map(|level| checked_image_size(format, mip_size((width, height), level)))
+            .collect::<Result<Vec<_>>>()?;
+
+        // check the file holds every image before allocating for them
+        let too_short = || TextureError::InvalidContainer("DDS data is too short".to_string());
+        let chain_size = level_sizes.iter().try_fold(0usize, |total, size| total.checked_add(*size)).ok_or_else(too_short)?;
+        if chain_size.checked_mul(image_count).and_then(|size| size.checked_add(data_start)).is_none_or(|end| end > bytes.len()) {
+            return Err(too_short().into());
+        }
+
+        // dds stores each image's whole mip chain before the next image
+        let mut levels = vec![Vec::with_capacity(image_count); level_count as usize];
+        let mut offset = data_start;
+        for _ in 0..image_count {
+            for (images, image_size) in levels.iter_mut().zip(&level_sizes) {
+                images.push(bytes[offset..offset + image_size].to_vec());
+                offset += image_size;
+            }
+        }
+
+        Ok(Self {
+            format,
+            size: (width, height),
+            layers,
+            faces,
+            levels,
+        })
+    }
+
+    /// # Returns
+    /// the kind of texture upload creates
+    pub fn get_target(&self) -> TextureTarget {
+        if self.layers > 1 {
+            TextureTarget::Texture2DArray
+        } else if self.faces == 6 {
+            TextureTarget::CubeMap
+        } else {
+            TextureTarget::Texture2D
+        }
+    }
+
+    /// Uploads every level, layer and face into a new texture. Cubemap arrays are not supported.
+    /// Only the stored mip levels are sampled, so mipmapped min filters need the file to have mips.
+    pub fn upload(&self, min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> Result<Texture> {
+        if self.layers > 1 && self.faces > 1 {
+            return Err(TextureError::UnsupportedContainerFormat("cubemap arrays".to_string()).into());
+        }
+        let target = self.get_target();
+        let gl_target = target.to_gl();
+        let (internal, pixel_format) = self.format.to_gl();
+
+        let mut tex_id = 0;
+        unsafe {
+            gl::GenTextures(1, &mut tex_id);
+            gl::BindTexture(gl_target, tex_id);
+
+            texture_wrap.apply(gl_target);
+            gl::TexParameteri(gl_target, gl::TEXTURE_MIN_FILTER, min_filter.to_gl() as i32);
+            gl::TexParameteri(gl_target, gl::TEXTURE_MAG_FILTER, mag_filter.to_gl() as i32);
+            gl::TexParameteri(gl_target, gl::TEXTURE_MAX_LEVEL, self.levels.len() as i32 - 1);
+
+            for (level, images) in self.levels.iter().enumerate() {
+                let (width, height) = mip_size(self.size, level as u32);
+                match target {
+                    TextureTarget::Texture2DArray => {
+                        let data = images.concat();
+                        if let ContainerFormat::Compressed(_) = self.format {
+                            gl::CompressedTexImage3D(gl_target, level as i32, internal, width as i32, height as i32, self.layers as i32, 0, data.len() as i32, data.as_ptr() as *const _);
+                        } else {
+                            gl::TexImage3D(gl_target, level as i32, internal as i32, width as i32, height as i32, self.layers as i32, 0, pixel_format, gl::UNSIGNED_BYTE, data.as_ptr() as *const _);
+                        }
+                    }
+                    _ => {
+                        for (face, data) in images.iter().enumerate() {
+                            let image_target = if target == TextureTarget::CubeMap {
+                                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as GLenum
+                            } else {
+                                gl_target
+                            };
+                            if let ContainerFormat::Compressed(_) = self.format {
+                                gl::CompressedTexImage2D(image_target, level as i32, internal, width as i32, height as i32, 0, data.len() as i32, data.as_ptr() as *const _);
+                            } else {
+                                gl::TexImage2D(image_target, level as i32, internal as i32, width as i32, height as i32, 0, pixel_format, gl::UNSIGNED_BYTE, data.as_ptr() as *const _);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Texture {
+            id: tex_id,
+            target,
+            size: self.size,
+            depth: self.layers,
+            format: internal,
+            min_filter,
+            mag_filter,
+            freed: false,
+        })
+    }
+}
+
+/// Loads a KTX2 or DDS file straight into a texture, see TextureContainer
+pub fn load_texture_container(path: impl AsRef<Path>, min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> Result<Texture> {
+    TextureContainer::load(path)?.upload(min_filter, mag_filter, texture_wrap)
+}
+
+/// Fails if a file claims more mip levels than halving `size` down to 1x1 gives
+fn check_level_count(size: (u32, u32), level_count: u32) -> Result<()> {
+    let max_levels = u32::BITS - size.0.max(size.1).max(1).leading_zeros();
+    if level_count > max_levels {
+        return Err(TextureError::InvalidContainer(format!("{level_count} mip levels for a {}x{} texture", size.0, size.1)).into());
+    }
+    Ok(())
+}
+
+/// # Returns
+/// the number of images in each level, layers times faces
+fn image_count(layers: u32, faces: u32) -> Result<usize> {
+    layers.checked_mul(faces)
+        .map(|count| count as usize)
+        .ok_or_else(|| TextureError::InvalidContainer(format!("{layers} layers of {faces} faces")).into())
+}
+
+/// ContainerFormat::image_size, failing instead of overflowing on sizes read from a file
+fn checked_image_size(format: ContainerFormat, size: (u32, u32)) -> Result<usize> {
+    let (columns, rows, bytes) = match format {
+        ContainerFormat::Compressed(format) => (size.0.div_ceil(4), size.1.div_ceil(4), format.block_bytes()),
+        ContainerFormat::Rgba8 | ContainerFormat::Bgra8 => (size.0, size.1, 4),
+    };
+    (columns as usize).checked_mul(rows as usize)
+        .and_then(|blocks| blocks.checked_mul(bytes))
+        .ok_or_else(|| TextureError::InvalidContainer(format!("{}x{} image is too large", size.0, size.1)).into())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    bytes.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| TextureError::InvalidContainer("header is too short".to_string()).into())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64> {
+    bytes.get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| TextureError::InvalidContainer("header is too short".to_string()).into())
+}
+
+fn vk_format_to_container(vk_format: u32) -> Option<ContainerFormat> {
+    let format = match vk_format {
+        37 => return Some(ContainerFormat::Rgba8),
+        44 => return Some(ContainerFormat::Bgra8),
+        131 => CompressedFormat::Bc1Rgb,
+        133 => CompressedFormat::Bc1Rgba,
+        135 => CompressedFormat::Bc2,
+        137 => CompressedFormat::Bc3,
+        139 => CompressedFormat::Bc4,
+        141 => CompressedFormat::Bc5,
+        143 => CompressedFormat::Bc6h,
+        145 => CompressedFormat::Bc7,
+        146 => CompressedFormat::Bc7Srgb,
+        147 => CompressedFormat::Etc2Rgb,
+        149 => CompressedFormat::Etc2RgbA1,
+        151 => CompressedFormat::Etc2Rgba,
+        153 => CompressedFormat::EacR11,
+        155 => CompressedFormat::EacRg11,
+        _ => return None,
+    };
+    Some(ContainerFormat::Compressed(format))
+}
+
+fn dxgi_format_to_container(dxgi_format: u32) -> Option<ContainerFormat> {
+    let format = match dxgi_format {
+        28 => return Some(ContainerFormat::Rgba8),
+        87 => return Some(ContainerFormat::Bgra8),
+        71 => CompressedFormat::Bc1Rgba,
+        74 => CompressedFormat::Bc2,
+        77 => CompressedFormat::Bc3,
+        80 => CompressedFormat::Bc4,
+        83 => CompressedFormat::Bc5,
+        95 => CompressedFormat::Bc6h,
+        98 => CompressedFormat::Bc7,
+        99 => CompressedFormat::Bc7Srgb,
+        _ => return None,
+    };
+    Some(ContainerFormat::Compressed(format))
+}
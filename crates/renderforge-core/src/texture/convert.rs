@@ -0,0 +1,69 @@
+use anyhow::Result;
+use image::{DynamicImage, GenericImageView, GrayImage, Luma, Rgba, RgbaImage};
+
+use crate::errors::TextureError;
+
+/// A channel of an image for the cpu-side conversion helpers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    R,
+    G,
+    B,
+    A,
+    /// the image converted to grayscale, for single channel sources stored as RGB
+    Luma,
+}
+
+impl Channel {
+    fn read(&self, pixel: Rgba<u8>) -> u8 {
+        let [r, g, b, a] = pixel.0;
+        match self {
+            Self::R => r,
+            Self::G => g,
+            Self::B => b,
+            Self::A => a,
+            // Rec. 709 weights, matching image's own grayscale conversion
+            Self::Luma => ((r as u32 * 2126 + g as u32 * 7152 + b as u32 * 722) / 10000) as u8,
+        }
+    }
+}
+
+/// Expands a grayscale image to RGBA, copying the gray value into red, green and blue.
+/// With `alpha_from_gray` the gray value also becomes the alpha (e.g. for masks), otherwise the
+/// image's own alpha is kept.
+pub fn grayscale_to_rgba(img: &DynamicImage, alpha_from_gray: bool) -> RgbaImage {
+    let gray = img.to_luma_alpha8();
+    RgbaImage::from_fn(gray.width(), gray.height(), |x, y| {
+        let [l, a] = gray.get_pixel(x, y).0;
+        Rgba([l, l, l, if alpha_from_gray { l } else { a }])
+    })
+}
+
+/// Copies one channel of an image into a single channel image
+pub fn extract_channel(img: &DynamicImage, channel: Channel) -> GrayImage {
+    let (width, height) = img.dimensions();
+    GrayImage::from_fn(width, height, |x, y| Luma([channel.read(img.get_pixel(x, y))]))
+}
+
+/// Packs channels of several images into one RGBA image, e.g. ambient occlusion, roughness and
+/// metalness maps into the R, G and B of a single texture. `sources[i]` fills output channel `i`,
+/// channels without a source are filled with `defaults[i]`. All sources must be the same size.
+pub fn pack_channels(sources: [Option<(&DynamicImage, Channel)>; 4], defaults: [u8; 4]) -> Result<RgbaImage> {
+    let Some(size) = sources.iter().flatten().map(|(img, _)| img.dimensions()).next() else {
+        return Err(TextureError::MismatchedChannelSources.into());
+    };
+    if sources.iter().flatten().any(|(img, _)| img.dimensions() != size) {
+        return Err(TextureError::MismatchedChannelSources.into());
+    }
+
+    let sources = sources.map(|source| source.map(|(img, channel)| (img.to_rgba8(), channel)));
+    Ok(RgbaImage::from_fn(size.0, size.1, |x, y| {
+        let mut pixel = defaults;
+        for (out, source) in pixel.iter_mut().zip(&sources) {
+            if let Some((img, channel)) = source {
+                *out = channel.read(*img.get_pixel(x, y));
+            }
+        }
+        Rgba(pixel)
+    }))
+}
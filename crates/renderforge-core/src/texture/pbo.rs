@@ -0,0 +1,201 @@
+use std::ptr;
+use std::thread::panicking;
+
+use gl::types::{GLsizeiptr, GLsync, GLuint};
+use image::{DynamicImage, GenericImageView};
+
+use crate::data::GlStateManager;
+use crate::texture::{ImageFormat, MagFilter, MinFilter, Texture, TextureTarget, TextureWrap};
+
+/// Uploads an image to a 2D texture through a pixel buffer object, a few rows at a time, so large
+/// textures can be streamed over several frames without stalling the render thread.
+///
+/// Call upload_step once per frame with a byte budget until it returns true, then poll try_finish
+/// until the gpu has finished copying and the texture is handed over.
+#[derive(Debug)]
+pub struct PboUpload {
+    texture: Texture,
+    pbo: GLuint,
+    data: Vec<u8>,
+    row_bytes: usize,
+    rows_uploaded: u32,
+    fence: Option<GLsync>,
+    freed: bool,
+}
+
+impl PboUpload {
+    /// Allocates the texture and the pixel buffer, no pixels are uploaded until upload_step.
+    /// The image is stored in ImageFormat::default_for_images, as 8 bit RGBA.
+    pub fn new(gl_state: &mut GlStateManager, img: &DynamicImage, min_filter: MinFilter, mag_filter: MagFilter, texture_wrap: TextureWrap) -> Self {
+        let size = img.dimensions();
+        let format = match ImageFormat::default_for_images() {
+            ImageFormat::Srgb8 => ImageFormat::Srgb8,
+            _ => ImageFormat::Unorm8,
+        };
+        let data = img.to_rgba8().into_raw();
+        let mut texture = Texture {
+            id: 0,
+            target: TextureTarget::Texture2D,
+            size,
+            depth: 1,
+            format: format.to_gl(),
+            min_filter,
+            mag_filter,
+            freed: false,
+        };
+        let mut pbo = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture.id);
+            texture.bind(gl_state, 0);
+            texture_wrap.apply(gl::TEXTURE_2D);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter.to_gl() as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag_filter.to_gl() as i32);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, format.to_gl() as i32, size.0 as i32, size.1 as i32, 0, gl::RGBA, gl::UNSIGNED_BYTE, ptr::null());
+
+            gl::GenBuffers(1, &mut pbo);
+            gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, pbo);
+            gl::BufferData(gl::PIXEL_UNPACK_BUFFER, data.len() as GLsizeiptr, ptr::null(), gl::STREAM_DRAW);
+            gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+        }
+        Self {
+            texture,
+            pbo,
+            data,
+            row_bytes: size.0 as usize * 4,
+            rows_uploaded: 0,
+            fence: None,
+            freed: false,
+        }
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        self.texture.size
+    }
+
+    /// # Returns
+    /// the fraction of rows handed to the gpu so far, from 0 to 1
+    pub fn get_progress(&self) -> f32 {
+        self.rows_uploaded as f32 / self.texture.size.1.max(1) as f32
+    }
+
+    /// # Returns
+    /// how many bytes of pixels are left to submit
+    pub fn get_remaining_bytes(&self) -> usize {
+        (self.texture.size.1 - self.rows_uploaded) as usize * self.row_bytes
+    }
+
+    /// Copies up to `max_bytes` of pixels (at least one row) into the pixel buffer and starts their
+    /// transfer into the texture.
+    ///
+    /// # Returns
+    /// whether every row has been submitted
+    pub fn upload_step(&mut self, gl_state: &mut GlStateManager, max_bytes: usize) -> bool {
+        let height = self.texture.size.1;
+        if self.rows_uploaded >= height {
+            return true;
+        }
+        let rows = ((max_bytes / self.row_bytes.max(1)) as u32).clamp(1, height - self.rows_uploaded);
+        let offset = self.rows_uploaded as usize * self.row_bytes;
+        let len = rows as usize * self.row_bytes;
+
+        self.texture.bind(gl_state, 0);
+        unsafe {
+            gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, self.pbo);
+            // rows never overlap, so the range can be written without waiting on earlier transfers
+            let mapped = gl::MapBufferRange(
+                gl::PIXEL_UNPACK_BUFFER,
+                offset as isize,
+                len as GLsizeiptr,
+                gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_RANGE_BIT | gl::MAP_UNSYNCHRONIZED_BIT,
+            );
+            if mapped.is_null() {
+                gl::BufferSubData(gl::PIXEL_UNPACK_BUFFER, offset as isize, len as GLsizeiptr, self.data[offset..].as_ptr() as *const _);
+            } else {
+                ptr::copy_nonoverlapping(self.data[offset..].as_ptr(), mapped as *mut u8, len);
+                gl::UnmapBuffer(gl::PIXEL_UNPACK_BUFFER);
+            }
+            // with an unpack buffer bound the pixel pointer is an offset into it
+            gl::TexSubImage2D(
+                gl::TEXTURE_2D,
+                0,
+                0,
+                self.rows_uploaded as i32,
+                self.texture.size.0 as i32,
+                rows as i32,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                offset as *const _,
+            );
+            gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+        }
+        self.rows_uploaded += rows;
+
+        if self.rows_uploaded >= height {
+            unsafe {
+                if !matches!(self.texture.min_filter, MinFilter::Nearest | MinFilter::Linear) {
+                    gl::GenerateMipmap(gl::TEXTURE_2D);
+                }
+                self.fence = Some(gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0));
+            }
+            // the pixels live in the pixel buffer now
+            self.data = Vec::new();
+            return true;
+        }
+        false
+    }
+
+    /// Whether every row was submitted and the gpu has finished copying them, never blocks
+    pub fn is_ready(&self) -> bool {
+        match self.fence {
+            Some(fence) => unsafe {
+                let status = gl::ClientWaitSync(fence, 0, 0);
+                status == gl::ALREADY_SIGNALED || status == gl::CONDITION_SATISFIED
+            },
+            None => false,
+        }
+    }
+
+    /// Hands over the texture once is_ready, releasing the pixel buffer.
+    /// Returns the upload back if it isn't ready yet.
+    pub fn try_finish(mut self) -> Result<Texture, Self> {
+        if !self.is_ready() {
+            return Err(self);
+        }
+        self.release_transfer();
+        self.freed = true;
+        let id = self.texture.id;
+        self.texture.freed = true;
+        let mut texture = Texture::from_raw(id, TextureTarget::Texture2D);
+        texture.min_filter = self.texture.min_filter;
+        texture.mag_filter = self.texture.mag_filter;
+        Ok(texture)
+    }
+
+    /// Deletes the pixel buffer and the unfinished texture
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        if !self.freed {
+            self.release_transfer();
+            self.texture.destroy(gl_state);
+            self.freed = true;
+        }
+    }
+
+    fn release_transfer(&mut self) {
+        unsafe {
+            if let Some(fence) = self.fence.take() {
+                gl::DeleteSync(fence);
+            }
+            gl::DeleteBuffers(1, &self.pbo);
+        }
+    }
+}
+
+impl Drop for PboUpload {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            let mut state = GlStateManager::new();
+            self.destroy(&mut state);
+            panic!("PBO upload was not destroyed before dropping")
+        }
+    }
+}
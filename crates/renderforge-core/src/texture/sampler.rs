@@ -0,0 +1,187 @@
+use std::hash::{Hash, Hasher};
+use std::thread::panicking;
+
+use gl::types::{GLenum, GLuint};
+
+use crate::data::{DepthFunc, GlStateManager};
+use crate::texture::{MagFilter, MinFilter, TextureWrap, WrapMode};
+
+/// GL_TEXTURE_MAX_ANISOTROPY, core in GL 4.6 and the same value as the older extension's constant
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+
+/// Everything a Sampler is made from. Samplers with equal descriptors are interchangeable, see
+/// GlStateManager::get_sampler to share them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplerDescriptor {
+    min_filter: MinFilter,
+    mag_filter: MagFilter,
+    wrap: TextureWrap,
+    min_lod: f32,
+    max_lod: f32,
+    lod_bias: f32,
+    max_anisotropy: f32,
+    compare: Option<DepthFunc>,
+}
+
+/// A gl sampler object, which overrides the sampling parameters of whatever texture is bound to
+/// the same slot
+#[derive(Debug)]
+pub struct Sampler {
+    id: GLuint,
+    descriptor: SamplerDescriptor,
+    freed: bool,
+}
+
+impl SamplerDescriptor {
+    /// Clamps to edge with the full LOD range and no depth comparison
+    pub fn new(min_filter: MinFilter, mag_filter: MagFilter) -> Self {
+        Self {
+            min_filter,
+            mag_filter,
+            wrap: TextureWrap::new(WrapMode::ClampToEdge, WrapMode::ClampToEdge),
+            min_lod: -1000.,
+            max_lod: 1000.,
+            lod_bias: 0.,
+            max_anisotropy: 1.,
+            compare: None,
+        }
+    }
+
+    pub fn with_wrap(mut self, wrap: TextureWrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Limits sampling to the mip levels between min_lod and max_lod
+    pub fn with_lod_range(mut self, min_lod: f32, max_lod: f32) -> Self {
+        self.min_lod = min_lod;
+        self.max_lod = max_lod;
+        self
+    }
+
+    pub fn with_lod_bias(mut self, bias: f32) -> Self {
+        self.lod_bias = bias;
+        self
+    }
+
+    /// Anisotropic filtering for textures seen at grazing angles, 1 disables it. Values are clamped
+    /// to the driver maximum (usually 16), and ignored by drivers without anisotropic filtering.
+    pub fn with_anisotropy(mut self, max_anisotropy: f32) -> Self {
+        self.max_anisotropy = max_anisotropy.max(1.);
+        self
+    }
+
+    /// Compares depth texture texels against the reference coordinate instead of returning them,
+    /// for `sampler2DShadow` shadow map lookups
+    pub fn with_compare(mut self, func: DepthFunc) -> Self {
+        self.compare = Some(func);
+        self
+    }
+
+    pub fn get_min_filter(&self) -> MinFilter {
+        self.min_filter
+    }
+
+    pub fn get_mag_filter(&self) -> MagFilter {
+        self.mag_filter
+    }
+
+    pub fn get_wrap(&self) -> &TextureWrap {
+        &self.wrap
+    }
+
+    pub fn get_lod_bias(&self) -> f32 {
+        self.lod_bias
+    }
+
+    pub fn get_max_anisotropy(&self) -> f32 {
+        self.max_anisotropy
+    }
+
+    pub fn get_compare(&self) -> Option<DepthFunc> {
+        self.compare
+    }
+}
+
+impl Eq for SamplerDescriptor {}
+
+impl Hash for SamplerDescriptor {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.min_filter.hash(state);
+        self.mag_filter.hash(state);
+        self.wrap.wrap_s.hash(state);
+        self.wrap.wrap_t.hash(state);
+        self.wrap.wrap_r.hash(state);
+        for value in self.wrap.border_color.iter().chain([&self.min_lod, &self.max_lod, &self.lod_bias, &self.max_anisotropy]) {
+            value.to_bits().hash(state);
+        }
+        self.compare.hash(state);
+    }
+}
+
+impl Sampler {
+    pub fn new(descriptor: &SamplerDescriptor) -> Self {
+        let mut id = 0;
+        unsafe {
+            gl::GenSamplers(1, &mut id);
+            gl::SamplerParameteri(id, gl::TEXTURE_MIN_FILTER, descriptor.min_filter.to_gl() as i32);
+            gl::SamplerParameteri(id, gl::TEXTURE_MAG_FILTER, descriptor.mag_filter.to_gl() as i32);
+            gl::SamplerParameteri(id, gl::TEXTURE_WRAP_S, descriptor.wrap.wrap_s.to_gl() as i32);
+            gl::SamplerParameteri(id, gl::TEXTURE_WRAP_T, descriptor.wrap.wrap_t.to_gl() as i32);
+            gl::SamplerParameteri(id, gl::TEXTURE_WRAP_R, descriptor.wrap.wrap_r.to_gl() as i32);
+            gl::SamplerParameterfv(id, gl::TEXTURE_BORDER_COLOR, descriptor.wrap.border_color.as_ptr());
+            gl::SamplerParameterf(id, gl::TEXTURE_MIN_LOD, descriptor.min_lod);
+            gl::SamplerParameterf(id, gl::TEXTURE_MAX_LOD, descriptor.max_lod);
+            gl::SamplerParameterf(id, gl::TEXTURE_LOD_BIAS, descriptor.lod_bias);
+            if descriptor.max_anisotropy > 1. {
+                let mut max = 1.;
+                gl::GetFloatv(TEXTURE_MAX_ANISOTROPY, &mut max);
+                // drivers without anisotropic filtering report an error and leave max at 1
+                while gl::GetError() != gl::NO_ERROR {}
+                gl::SamplerParameterf(id, TEXTURE_MAX_ANISOTROPY, descriptor.max_anisotropy.min(max.max(1.)));
+                while gl::GetError() != gl::NO_ERROR {}
+            }
+            match descriptor.compare {
+                Some(func) => {
+                    gl::SamplerParameteri(id, gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE as i32);
+                    gl::SamplerParameteri(id, gl::TEXTURE_COMPARE_FUNC, func.to_gl() as i32);
+                }
+                None => gl::SamplerParameteri(id, gl::TEXTURE_COMPARE_MODE, gl::NONE as i32),
+            }
+        }
+        Self {
+            id,
+            descriptor: descriptor.clone(),
+            freed: false,
+        }
+    }
+
+    pub fn get_id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn get_descriptor(&self) -> &SamplerDescriptor {
+        &self.descriptor
+    }
+
+    pub fn bind(&self, gl_state: &mut GlStateManager, slot: u32) {
+        gl_state.bind_sampler(slot, self.id);
+    }
+
+    pub fn destroy(&mut self, gl_state: &mut GlStateManager) {
+        if !self.freed {
+            gl_state.destroy_sampler(self.id);
+            self.freed = true;
+        }
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        if !self.freed && !panicking() {
+            let mut state = GlStateManager::new();
+            self.destroy(&mut state);
+            panic!("Sampler was not destroyed before dropping")
+        }
+    }
+}
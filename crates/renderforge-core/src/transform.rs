@@ -0,0 +1,158 @@
+//! Smooths entities updated at a fixed timestep (e.g. by physics) when rendering at a different
+//! rate. After each fixed step record_transforms saves the GlobalTransform of every entity with
+//! an InterpolatedTransform. Before rendering, interpolate_transforms blends the last two steps
+//! with FixedTimestep::get_alpha. Render code then reads InterpolatedTransform::get_matrix
+//! instead of the GlobalTransform.
+
+use anyhow::Result;
+use glam::Mat4;
+use hecs::World;
+
+use crate::errors::TimestepError;
+
+/// World space transform of an entity, written by game or physics code
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlobalTransform(pub Mat4);
+
+impl Default for GlobalTransform {
+    fn default() -> Self {
+        Self(Mat4::IDENTITY)
+    }
+}
+
+/// The GlobalTransform of an entity at the previous and current fixed step, and the blend of the
+/// two for the frame being rendered
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterpolatedTransform {
+    previous: Mat4,
+    current: Mat4,
+    interpolated: Mat4,
+}
+
+impl InterpolatedTransform {
+    /// Starts at rest at `transform`, so a new entity doesn't slide in from the origin
+    pub fn new(transform: &GlobalTransform) -> Self {
+        Self {
+            previous: transform.0,
+            current: transform.0,
+            interpolated: transform.0,
+        }
+    }
+
+    /// Moves to `transform` without interpolating, for teleports
+    pub fn snap(&mut self, transform: &GlobalTransform) {
+        *self = Self::new(transform);
+    }
+
+    /// # Returns
+    /// the transform to render with this frame
+    pub fn get_matrix(&self) -> Mat4 {
+        self.interpolated
+    }
+
+    pub fn get_previous(&self) -> Mat4 {
+        self.previous
+    }
+
+    pub fn get_current(&self) -> Mat4 {
+        self.current
+    }
+
+    fn record(&mut self, transform: &GlobalTransform) {
+        self.previous = self.current;
+        self.current = transform.0;
+    }
+
+    /// Blends translation and scale linearly and rotation spherically, `alpha` 0 is the previous
+    /// step and 1 the current one
+    fn interpolate(&mut self, alpha: f32) {
+        let (scale_a, rotation_a, translation_a) = self.previous.to_scale_rotation_translation();
+        let (scale_b, rotation_b, translation_b) = self.current.to_scale_rotation_translation();
+        self.interpolated = Mat4::from_scale_rotation_translation(
+            scale_a.lerp(scale_b, alpha),
+            rotation_a.slerp(rotation_b, alpha),
+            translation_a.lerp(translation_b, alpha),
+        );
+    }
+}
+
+/// Splits frame times into fixed steps, carrying the remainder over to the next frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedTimestep {
+    step: f32,
+    accumulator: f32,
+    /// steps run in one frame at most, so a long hitch doesn't spiral into ever longer frames
+    pub max_steps: u32,
+}
+
+impl FixedTimestep {
+    /// `step` is in seconds, e.g. 1. / 60. Fails unless it is positive and finite.
+    pub fn new(step: f32) -> Result<Self> {
+        if !(step > 0. && step.is_finite()) {
+            return Err(TimestepError::InvalidStep(step).into());
+        }
+        Ok(Self {
+            step,
+            accumulator: 0.,
+            max_steps: 8,
+        })
+    }
+
+    pub fn with_max_steps(mut self, max_steps: u32) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn get_step(&self) -> f32 {
+        self.step
+    }
+
+    /// Adds a frame's delta time.
+    ///
+    /// # Returns
+    /// how many fixed steps to run this frame
+    pub fn advance(&mut self, delta_time: f32) -> u32 {
+        self.accumulator += delta_time.max(0.);
+        let steps = (self.accumulator / self.step) as u32;
+        self.accumulator -= steps as f32 * self.step;
+        if steps > self.max_steps {
+            // drop the time that can't be caught up with
+            self.accumulator = 0.;
+            return self.max_steps;
+        }
+        steps
+    }
+
+    /// # Returns
+    /// how far the current frame is between the last fixed step and the next, in [0, 1)
+    pub fn get_alpha(&self) -> f32 {
+        (self.accumulator / self.step).clamp(0., 1.)
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self {
+            step: 1. / 60.,
+            accumulator: 0.,
+            max_steps: 8,
+        }
+    }
+}
+
+/// Saves the GlobalTransform of every interpolated entity, run after each fixed step. The engine's
+/// main loop does so when it has a fixed update, see Engine::set_fixed_update.
+pub fn record_transforms(world: &mut World) {
+    for (_, (transform, interpolated)) in world.query_mut::<(&GlobalTransform, &mut InterpolatedTransform)>() {
+        interpolated.record(transform);
+    }
+}
+
+/// Blends the last two recorded steps of every interpolated entity, run once per frame before
+/// rendering with FixedTimestep::get_alpha. The engine's main loop does so when it has a fixed
+/// update.
+pub fn interpolate_transforms(world: &mut World, alpha: f32) {
+    for (_, interpolated) in world.query_mut::<&mut InterpolatedTransform>() {
+        interpolated.interpolate(alpha);
+    }
+}
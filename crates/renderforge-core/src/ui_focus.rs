@@ -0,0 +1,300 @@
+//! Keyboard and gamepad navigation for UI, so menus work without a mouse. Widgets register their
+//! rects with a FocusNavigator, NavBindings maps the frame's input (Engine::events, so replays
+//! drive menus too) to NavActions, and the navigator moves the focus to the nearest widget in the
+//! pressed direction or activates the focused one. Coordinates are in pixels with y pointing
+//! down, like sprites.
+
+use std::collections::HashMap;
+
+use gl::types::GLuint;
+use glam::Vec2;
+
+use crate::data::Color;
+use crate::renderer::{SortingLayer, Sprite, SpriteBatch};
+use crate::replay::InputEvent;
+
+/// A navigation input, mapped from raw input by NavBindings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NavAction {
+    Up,
+    Down,
+    Left,
+    Right,
+    Activate,
+    Cancel,
+}
+
+/// A raw input that can trigger a NavAction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NavInput {
+    Key(u32),
+    /// a button of any gamepad
+    GamepadButton(u32),
+}
+
+/// Maps raw input to NavActions, starts empty since key and button codes depend on the windowing
+/// library
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NavBindings {
+    bindings: HashMap<NavInput, NavAction>,
+}
+
+/// What a FocusNavigator did in response to a NavAction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FocusEvent {
+    Moved { from: Option<u32>, to: u32 },
+    /// the focused widget was activated, e.g. a button pressed
+    Activated(u32),
+    /// cancel was pressed with the given widget focused, e.g. to close the menu
+    Cancelled(Option<u32>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Focusable {
+    id: u32,
+    /// (x, y, width, height)
+    rect: [f32; 4],
+    enabled: bool,
+}
+
+/// Tracks the focused widget of a menu and moves it between widgets
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FocusNavigator {
+    /// in registration order, which decides the first widget focused
+    widgets: Vec<Focusable>,
+    focused: Option<u32>,
+}
+
+/// How the focused widget is highlighted, see FocusNavigator::draw_focus
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FocusOutline {
+    /// a texture to stretch over the outline's edges, usually a white pixel
+    pub texture: GLuint,
+    pub color: Color,
+    pub thickness: f32,
+    /// space between the widget and the outline
+    pub padding: f32,
+    pub layer: SortingLayer,
+    pub order: i32,
+}
+
+/// How much the sideways offset of a widget counts against it when moving the focus, relative to
+/// its distance in the direction of movement
+const SIDEWAYS_WEIGHT: f32 = 2.;
+
+impl NavAction {
+    /// # Returns
+    /// the screen direction of the directional actions, None for activate and cancel
+    pub fn direction(&self) -> Option<Vec2> {
+        match self {
+            Self::Up => Some(Vec2::NEG_Y),
+            Self::Down => Some(Vec2::Y),
+            Self::Left => Some(Vec2::NEG_X),
+            Self::Right => Some(Vec2::X),
+            Self::Activate | Self::Cancel => None,
+        }
+    }
+}
+
+impl NavBindings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_binding(mut self, input: NavInput, action: NavAction) -> Self {
+        self.bind(input, action);
+        self
+    }
+
+    /// Binds `input` to `action`, replacing its previous action. An action can have several inputs.
+    pub fn bind(&mut self, input: NavInput, action: NavAction) {
+        self.bindings.insert(input, action);
+    }
+
+    pub fn unbind(&mut self, input: NavInput) {
+        self.bindings.remove(&input);
+    }
+
+    pub fn get_action(&self, input: NavInput) -> Option<NavAction> {
+        self.bindings.get(&input).copied()
+    }
+
+    /// # Returns
+    /// the actions of the bound keys and buttons pressed in `events`, in order
+    pub fn map_events(&self, events: &[InputEvent]) -> Vec<NavAction> {
+        events.iter()
+            .filter_map(|event| match event {
+                InputEvent::KeyPressed(key) => Some(NavInput::Key(*key)),
+                InputEvent::GamepadPressed { button, .. } => Some(NavInput::GamepadButton(*button)),
+                _ => None,
+            })
+            .filter_map(|input| self.get_action(input))
+            .collect()
+    }
+}
+
+impl FocusNavigator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a focusable widget, or moves it if `id` is already registered. `rect` is
+    /// (x, y, width, height).
+    pub fn set_widget(&mut self, id: u32, rect: [f32; 4]) {
+        match self.widgets.iter_mut().find(|w| w.id == id) {
+            Some(widget) => widget.rect = rect,
+            None => self.widgets.push(Focusable { id, rect, enabled: true }),
+        }
+    }
+
+    /// Removes a widget, clearing the focus if it had it
+    pub fn remove_widget(&mut self, id: u32) {
+        self.widgets.retain(|w| w.id != id);
+        if self.focused == Some(id) {
+            self.focused = None;
+        }
+    }
+
+    /// Disabled widgets are skipped by navigation and lose the focus
+    pub fn set_enabled(&mut self, id: u32, enabled: bool) {
+        if let Some(widget) = self.widgets.iter_mut().find(|w| w.id == id) {
+            widget.enabled = enabled;
+        }
+        if !enabled && self.focused == Some(id) {
+            self.focused = None;
+        }
+    }
+
+    /// Forgets every widget and the focus, e.g. when switching menus
+    pub fn clear(&mut self) {
+        self.widgets.clear();
+        self.focused = None;
+    }
+
+    /// Focuses a widget directly, e.g. the default button of a menu that just opened
+    ///
+    /// # Returns
+    /// false if the widget isn't registered or is disabled
+    pub fn focus(&mut self, id: u32) -> bool {
+        let focusable = self.widgets.iter().any(|w| w.id == id && w.enabled);
+        if focusable {
+            self.focused = Some(id);
+        }
+        focusable
+    }
+
+    pub fn get_focused(&self) -> Option<u32> {
+        self.focused
+    }
+
+    /// # Returns
+    /// the (x, y, width, height) of the focused widget
+    pub fn get_focused_rect(&self) -> Option<[f32; 4]> {
+        let focused = self.focused?;
+        self.widgets.iter().find(|w| w.id == focused).map(|w| w.rect)
+    }
+
+    /// Applies an action. Directions move the focus to the closest enabled widget whose center lies
+    /// that way, preferring widgets in line with the focused one. Without a focus any direction
+    /// focuses the first enabled widget.
+    pub fn handle(&mut self, action: NavAction) -> Option<FocusEvent> {
+        let Some(direction) = action.direction() else {
+            return match action {
+                NavAction::Activate => self.focused.map(FocusEvent::Activated),
+                _ => Some(FocusEvent::Cancelled(self.focused)),
+            };
+        };
+        let from = self.focused;
+        let to = match self.get_focused_rect() {
+            Some(rect) => self.nearest(center(rect), direction)?,
+            None => self.widgets.iter().find(|w| w.enabled)?.id,
+        };
+        self.focused = Some(to);
+        Some(FocusEvent::Moved { from, to })
+    }
+
+    /// Maps the frame's input with `bindings` and applies each action in order
+    pub fn update(&mut self, bindings: &NavBindings, events: &[InputEvent]) -> Vec<FocusEvent> {
+        bindings.map_events(events)
+            .into_iter()
+            .filter_map(|action| self.handle(action))
+            .collect()
+    }
+
+    fn nearest(&self, origin: Vec2, direction: Vec2) -> Option<u32> {
+        self.widgets.iter()
+            .filter(|w| w.enabled && Some(w.id) != self.focused)
+            .filter_map(|w| {
+                let offset = center(w.rect) - origin;
+                let ahead = offset.dot(direction);
+                let sideways = offset.dot(direction.perp()).abs();
+                (ahead > f32::EPSILON).then_some((w.id, ahead + sideways * SIDEWAYS_WEIGHT))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(id, _)| id)
+    }
+
+    /// Queues a frame around the focused widget on `batch`, nothing if no widget is focused
+    pub fn draw_focus(&self, batch: &mut SpriteBatch, outline: &FocusOutline) {
+        let Some([x, y, w, h]) = self.get_focused_rect() else {
+            return;
+        };
+        let (p, t) = (outline.padding, outline.thickness);
+        let (x, y, w, h) = (x - p - t, y - p - t, w + 2. * (p + t), h + 2. * (p + t));
+        let edges = [
+            (Vec2::new(x, y), Vec2::new(w, t)),
+            (Vec2::new(x, y + h - t), Vec2::new(w, t)),
+            (Vec2::new(x, y + t), Vec2::new(t, h - 2. * t)),
+            (Vec2::new(x + w - t, y + t), Vec2::new(t, h - 2. * t)),
+        ];
+        for (position, size) in edges {
+            batch.draw(Sprite::new(outline.texture, position, size)
+                .with_color(outline.color)
+                .with_layer(outline.layer)
+                .with_order(outline.order));
+        }
+    }
+}
+
+impl FocusOutline {
+    /// A white 2 pixel outline on the ui layer
+    pub fn new(texture: GLuint) -> Self {
+        Self {
+            texture,
+            color: Color::rgb(1., 1., 1.),
+            thickness: 2.,
+            padding: 2.,
+            layer: SortingLayer::UI,
+            order: i32::MAX,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_thickness(mut self, thickness: f32) -> Self {
+        self.thickness = thickness;
+        self
+    }
+
+    pub fn with_padding(mut self, padding: f32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn with_layer(mut self, layer: SortingLayer) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+}
+
+fn center(rect: [f32; 4]) -> Vec2 {
+    Vec2::new(rect[0] + rect[2] * 0.5, rect[1] + rect[3] * 0.5)
+}
@@ -1,12 +1,33 @@
 
 
+
+/// Identifies a gl context. Textures and buffers can be shared between contexts, but container
+/// objects like VAOs and FBOs only exist in the context that created them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ContextId(pub u32);
+
 #[derive(Debug)]
 pub struct Window {
     size: (u64, u64),
     position: (i64, i64)
 }
 
-
+impl Window {
+    pub fn new(size: (u64, u64), position: (i64, i64)) -> Self {
+        Self {
+            size,
+            position,
+        }
+    }
+
+    pub fn get_size(&self) -> (u64, u64) {
+        self.size
+    }
+
+    pub fn get_position(&self) -> (i64, i64) {
+        self.position
+    }
+}
 
 
 
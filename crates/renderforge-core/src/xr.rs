@@ -0,0 +1,227 @@
+//! OpenXR rendering, behind the renderforge-xr feature. RenderForge doesn't load an OpenXR runtime
+//! itself: the application implements XrBackend over its OpenXR binding (e.g. the openxr crate)
+//! and XrSession drives it every frame. Each eye gets a render graph target that renders into
+//! the swapchain image acquired for the frame, the graph runs once per eye with
+//! RenderGraph::execute_views, and the head and controller poses end up in Engine::xr_poses.
+
+use anyhow::Result;
+use gl::types::GLuint;
+use glam::{Mat4, Quat, Vec3, Vec4};
+
+use crate::engine::Engine;
+use crate::errors::XrError;
+use crate::framebuffer::{AttachmentFormat, Framebuffer};
+use crate::render_graph::{MultiViewOutput, RenderGraph};
+
+/// The OpenXR calls an XrSession needs, implemented by the application over its OpenXR binding
+pub trait XrBackend {
+    /// Creates the session with a graphics binding for the current gl context
+    /// (XrGraphicsBindingOpenGL*), and a color swapchain per eye
+    ///
+    /// # Returns
+    /// the size of each eye's swapchain images, in view order
+    fn create_session(&mut self) -> Result<Vec<(u32, u32)>>;
+    /// Waits for and begins the next frame (xrWaitFrame and xrBeginFrame), locating the views and
+    /// controllers at its predicted display time
+    fn begin_frame(&mut self) -> Result<XrFrame>;
+    /// Acquires and waits for the next image of `eye`'s swapchain
+    ///
+    /// # Returns
+    /// the gl texture of the image
+    fn acquire_image(&mut self, eye: usize) -> Result<GLuint>;
+    fn release_image(&mut self, eye: usize) -> Result<()>;
+    /// Submits the frame (xrEndFrame), with a projection layer of its views if it was rendered
+    fn end_frame(&mut self, frame: &XrFrame, rendered: bool) -> Result<()>;
+    /// Ends and destroys the session and its swapchains
+    fn destroy_session(&mut self);
+}
+
+/// A position and orientation in the runtime's reference space
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Pose {
+    pub position: Vec3,
+    pub orientation: Quat,
+}
+
+/// Angles in radians from the view direction to each edge of a view, like XrFovf. Left and down
+/// are usually negative.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Fov {
+    pub angle_left: f32,
+    pub angle_right: f32,
+    pub angle_up: f32,
+    pub angle_down: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct XrView {
+    pub pose: Pose,
+    pub fov: Fov,
+}
+
+/// Tracked poses of the current frame, None while a device isn't tracked
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct XrPoses {
+    pub head: Option<Pose>,
+    pub left_hand: Option<Pose>,
+    pub right_hand: Option<Pose>,
+}
+
+/// What XrBackend::begin_frame located for a frame
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct XrFrame {
+    /// XrFrameState::shouldRender, false e.g. while the headset is off
+    pub should_render: bool,
+    /// the predicted display time in the runtime's nanoseconds
+    pub display_time: i64,
+    /// one view per eye, in the order of create_session's sizes
+    pub views: Vec<XrView>,
+    pub poses: XrPoses,
+}
+
+/// An OpenXR session rendering through a RenderGraph, see the module docs
+pub struct XrSession<B: XrBackend> {
+    backend: B,
+    /// names of the eye targets in the render graph
+    targets: Vec<String>,
+    near: f32,
+    far: f32,
+}
+
+impl Pose {
+    /// # Returns
+    /// the transform from the pose's space to the reference space
+    pub fn to_matrix(&self) -> Mat4 {
+        Mat4::from_rotation_translation(self.orientation, self.position)
+    }
+}
+
+impl Fov {
+    /// # Returns
+    /// the asymmetric gl projection matrix of the view
+    pub fn projection(&self, near: f32, far: f32) -> Mat4 {
+        let (left, right) = (self.angle_left.tan(), self.angle_right.tan());
+        let (up, down) = (self.angle_up.tan(), self.angle_down.tan());
+        let (width, height) = (right - left, up - down);
+        Mat4::from_cols(
+            Vec4::new(2. / width, 0., 0., 0.),
+            Vec4::new(0., 2. / height, 0., 0.),
+            Vec4::new((right + left) / width, (up + down) / height, -(far + near) / (far - near), -1.),
+            Vec4::new(0., 0., -2. * far * near / (far - near), 0.),
+        )
+    }
+}
+
+impl XrView {
+    /// # Returns
+    /// the (view, projection) of the eye, as given to RenderGraph::execute_views
+    pub fn matrices(&self, near: f32, far: f32) -> (Mat4, Mat4) {
+        (self.pose.to_matrix().inverse(), self.fov.projection(near, far))
+    }
+}
+
+impl<B: XrBackend> XrSession<B> {
+    /// Creates the session on the current gl context and adds a depth buffered target per eye to
+    /// `graph`, named "xr_eye_0", "xr_eye_1" and so on. Passes rendering to the default
+    /// framebuffer end up in the eye targets, others can render into them by name.
+    pub fn new(mut backend: B, engine: &mut Engine, graph: &mut RenderGraph) -> Result<Self> {
+        let sizes = backend.create_session()?;
+        let mut targets: Vec<String> = Vec::with_capacity(sizes.len());
+        for (eye, (width, height)) in sizes.into_iter().enumerate() {
+            let framebuffer = Framebuffer::builder(width, height)
+                .with_color_attachment(AttachmentFormat::Rgba8)
+                .with_depth()
+                .build(&mut engine.gl_state);
+            let framebuffer = match framebuffer {
+                Ok(framebuffer) => framebuffer,
+                Err(e) => {
+                    for name in &targets {
+                        if let Some(mut target) = graph.remove_target(name) {
+                            target.destroy(&mut engine.gl_state);
+                        }
+                    }
+                    backend.destroy_session();
+                    return Err(e);
+                }
+            };
+            let name = format!("xr_eye_{eye}");
+            if let Some(mut replaced) = graph.add_target(&name, framebuffer) {
+                replaced.destroy(&mut engine.gl_state);
+            }
+            targets.push(name);
+        }
+        Ok(Self {
+            backend,
+            targets,
+            near: 0.05,
+            far: 1000.,
+        })
+    }
+
+    /// Sets the near and far planes of the eyes' projections, 0.05 and 1000 by default
+    pub fn with_clip_planes(mut self, near: f32, far: f32) -> Self {
+        self.near = near;
+        self.far = far;
+        self
+    }
+
+    pub fn get_targets(&self) -> &[String] {
+        &self.targets
+    }
+
+    pub fn get_backend(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    /// Runs one XR frame: stores the frame's poses in engine.xr_poses, points each eye target at
+    /// its acquired swapchain image, runs `graph` once per eye and submits the frame. Call this
+    /// once per frame in place of RenderGraph::execute.
+    ///
+    /// # Returns
+    /// false if the runtime didn't want the frame rendered
+    pub fn render_frame(&mut self, engine: &mut Engine, graph: &mut RenderGraph) -> Result<bool> {
+        let frame = self.backend.begin_frame()?;
+        engine.xr_poses = frame.poses;
+        if !frame.should_render {
+            self.backend.end_frame(&frame, false)?;
+            return Ok(false);
+        }
+        if frame.views.len() != self.targets.len() {
+            self.backend.end_frame(&frame, false)?;
+            return Err(XrError::ViewCountMismatch { expected: self.targets.len(), found: frame.views.len() }.into());
+        }
+
+        let mut acquired = 0;
+        let mut result = Ok(());
+        for (eye, name) in self.targets.iter().enumerate() {
+            result = self.backend.acquire_image(eye).and_then(|image| {
+                acquired += 1;
+                let target = graph.get_target_mut(name).ok_or_else(|| XrError::MissingTarget(name.clone()))?;
+                target.set_external_color(&mut engine.gl_state, image)
+            });
+            if result.is_err() {
+                break;
+            }
+        }
+        if result.is_ok() {
+            let views: Vec<_> = frame.views.iter().map(|view| view.matrices(self.near, self.far)).collect();
+            result = graph.execute_views(&mut engine.gl_state, &views, &MultiViewOutput::Targets(self.targets.clone()));
+        }
+        for eye in 0..acquired {
+            self.backend.release_image(eye)?;
+        }
+        self.backend.end_frame(&frame, result.is_ok())?;
+        result.map(|_| true)
+    }
+
+    /// Removes and destroys the eye targets and ends the session
+    pub fn destroy(&mut self, engine: &mut Engine, graph: &mut RenderGraph) {
+        for name in self.targets.drain(..) {
+            if let Some(mut target) = graph.remove_target(&name) {
+                target.destroy(&mut engine.gl_state);
+            }
+        }
+        engine.xr_poses = XrPoses::default();
+        self.backend.destroy_session();
+    }
+}